@@ -1,36 +1,29 @@
-use crate::{DownloadId, TopicId};
+use crate::{AudioFormat, Bitrate, DownloadId, TopicId};
 use scraper::error::SelectorErrorKind;
 use scraper::{Html, Selector};
-
-const AUDIO_FORMAT_PRIORITY: [&str; 4] = ["FLAC", "MP3", "ALAC", "AAC"];
-const AUDIO_BITRATE_PRIORITY: [&str; 3] = ["lossless", "320 kbps", "256 kbps"];
+use std::cmp::Reverse;
+use tracing::{debug, warn};
 
 const CAPTCHA_IS_REQUIRED_TEXT: &str = "введите код подтверждения";
 const INCORRECT_PASSWORD_TEXT: &str = "неверный пароль";
 const SUCCESSFUL_LOGIN_TEXT: &str = "log-out-icon";
 
-fn get_search_result_priority(result: &TopicData) -> usize {
-    let format_priority = AUDIO_FORMAT_PRIORITY
-        .iter()
-        .enumerate()
-        .find_map(|(i, format)| {
-            if result.title.contains(format) {
-                Some(i)
-            } else {
-                None
-            }
-        })
+/// Priority taken off the search results mentioning a trusted keyword.
+const TRUSTED_KEYWORD_BONUS: usize = 10;
+/// Priority added to the search results mentioning an avoided keyword,
+/// enough to rank them below any result without one.
+const AVOIDED_KEYWORD_PENALTY: usize = 200;
+
+/// Rank of the search result by its audio format, bitrate, seeds and title keywords.
+/// Lower is better.
+pub fn get_search_result_priority(result: &TopicData, keyword_weights: &KeywordWeights) -> usize {
+    let format_priority = result
+        .format
+        .and_then(|format| AudioFormat::ALL.iter().position(|f| *f == format))
         .unwrap_or(10);
-    let bitrate_priority = AUDIO_BITRATE_PRIORITY
-        .iter()
-        .enumerate()
-        .find_map(|(i, bitrate)| {
-            if result.title.contains(bitrate) {
-                Some(i)
-            } else {
-                None
-            }
-        })
+    let bitrate_priority = result
+        .bitrate
+        .and_then(|bitrate| Bitrate::ALL.iter().position(|b| *b == bitrate))
         .unwrap_or(10);
     let seeds_priority = match result.seeds_number {
         x if x == 0 => 10,
@@ -40,7 +33,61 @@ fn get_search_result_priority(result: &TopicData) -> usize {
         _ => 0,
     };
 
-    format_priority * 5 + bitrate_priority * 10 + seeds_priority
+    let priority = format_priority * 5 + bitrate_priority * 10 + seeds_priority;
+
+    if keyword_weights.is_avoided(&result.title) {
+        priority + AVOIDED_KEYWORD_PENALTY
+    } else if keyword_weights.is_trusted(&result.title) {
+        priority.saturating_sub(TRUSTED_KEYWORD_BONUS)
+    } else {
+        priority
+    }
+}
+
+/// The most preferred audio format mentioned in the topic title.
+pub(crate) fn parse_audio_format(title: &str) -> Option<AudioFormat> {
+    AudioFormat::ALL
+        .into_iter()
+        .find(|format| title.contains(format.label()))
+}
+
+/// The most preferred bitrate mentioned in the topic title.
+pub(crate) fn parse_bitrate(title: &str) -> Option<Bitrate> {
+    Bitrate::ALL
+        .into_iter()
+        .find(|bitrate| title.contains(bitrate.label()))
+}
+
+/// Release year following the album title, like `Artist - Album - 1996, FLAC`.
+pub(crate) fn parse_year(title: &str) -> Option<u16> {
+    title.split(" - ").skip(1).find_map(|part| {
+        let digits = part
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>();
+
+        match digits.len() {
+            4 => digits
+                .parse::<u16>()
+                .ok()
+                .filter(|year| (1900..2100).contains(year)),
+            _ => None,
+        }
+    })
+}
+
+/// Whether the release is a single audio image with a cue sheet, like `FLAC (image+.cue)`.
+/// Tracks of such releases can't be selected for download one by one.
+pub(crate) fn is_image_release(title: &str) -> bool {
+    let title: String = title
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+
+    title.contains("image+.cue")
+        || title.contains("image(.cue)")
+        || (title.contains(".cue") && !title.contains("tracks"))
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -49,17 +96,125 @@ pub enum ParseError {
     SelectorError(#[from] SelectorErrorKind<'static>),
 }
 
+/// Forum categories search results are taken from, matched by case-insensitive substrings.
+#[derive(Clone, Debug)]
+pub struct CategoryFilter {
+    /// Category must contain one of these, any category is allowed if empty.
+    pub include: Vec<String>,
+    /// Category must not contain any of these.
+    pub exclude: Vec<String>,
+}
+
+impl Default for CategoryFilter {
+    fn default() -> Self {
+        Self {
+            include: vec!["loss".to_string()],
+            exclude: vec![],
+        }
+    }
+}
+
+impl CategoryFilter {
+    pub(crate) fn matches(&self, category: &str) -> bool {
+        let category = category.to_lowercase();
+        let contains = |pattern: &String| category.contains(&pattern.to_lowercase());
+
+        (self.include.is_empty() || self.include.iter().any(contains))
+            && !self.exclude.iter().any(contains)
+    }
+}
+
+/// Keywords of the topic titles, e.g. names of known uploaders or "sample",
+/// matched by case-insensitive substrings to move the search results up or down.
+#[derive(Clone, Debug, Default)]
+pub struct KeywordWeights {
+    /// Results mentioning any of these are preferred.
+    pub trusted: Vec<String>,
+    /// Results mentioning any of these are tried last.
+    pub avoided: Vec<String>,
+}
+
+impl KeywordWeights {
+    fn is_trusted(&self, title: &str) -> bool {
+        contains_any(title, &self.trusted)
+    }
+
+    fn is_avoided(&self, title: &str) -> bool {
+        contains_any(title, &self.avoided)
+    }
+}
+
+fn contains_any(title: &str, keywords: &[String]) -> bool {
+    let title = title.to_lowercase();
+
+    keywords
+        .iter()
+        .filter(|keyword| !keyword.is_empty())
+        .any(|keyword| title.contains(&keyword.to_lowercase()))
+}
+
 #[derive(Debug, PartialEq)]
 pub struct TopicData {
     pub title: String,
     pub topic_id: TopicId,
     pub download_id: DownloadId,
     pub seeds_number: u64,
+    /// Total size of the release, zero if unknown.
+    pub size_bytes: u64,
+    /// Forum category the topic belongs to.
+    pub category: String,
+    pub format: Option<AudioFormat>,
+    pub bitrate: Option<Bitrate>,
+    pub year: Option<u16>,
 }
 
-pub(crate) fn parse_search_results(raw_html: &str) -> Result<Vec<TopicData>, ParseError> {
+pub(crate) fn parse_search_results(
+    raw_html: &str,
+    category_filter: &CategoryFilter,
+    keyword_weights: &KeywordWeights,
+) -> Result<Vec<TopicData>, ParseError> {
     let html = Html::parse_document(raw_html);
 
+    let mut results = parse_results_table(&html, category_filter)?;
+
+    if results.is_empty() {
+        results = parse_results_loosely(&html, category_filter)?;
+
+        if !results.is_empty() {
+            warn!(
+                "Search results have been found by the fallback parser only, \
+                the page layout has probably changed"
+            );
+        }
+    } else {
+        debug!("Search results have been found by the primary parser");
+    }
+
+    results.retain(|r| !is_image_release(&r.title));
+
+    sort_search_results(&mut results, keyword_weights);
+
+    Ok(results)
+}
+
+/// Sorts search results by their priority. Ties are broken by more seeds, then larger size,
+/// then lower topic id, so the same results always come in the same order.
+pub(crate) fn sort_search_results(results: &mut [TopicData], keyword_weights: &KeywordWeights) {
+    results.sort_by_key(|result| {
+        (
+            get_search_result_priority(result, keyword_weights),
+            Reverse(result.seeds_number),
+            Reverse(result.size_bytes),
+            *result.topic_id,
+        )
+    });
+}
+
+// Relies on the exact column layout of the search results table.
+fn parse_results_table(
+    html: &Html,
+    category_filter: &CategoryFilter,
+) -> Result<Vec<TopicData>, ParseError> {
     let table_row_selector = Selector::parse(r#"table.forumline tr"#)?;
     let table_entries = html.select(&table_row_selector);
 
@@ -67,19 +222,15 @@ pub(crate) fn parse_search_results(raw_html: &str) -> Result<Vec<TopicData>, Par
     let td_selector = Selector::parse(r#"td"#)?;
     let seeds_selector = Selector::parse(r#"b.seedmed"#)?;
 
-    let mut results: Vec<_> = table_entries
+    let results = table_entries
         .skip(1)
         .filter(|el| el.children().filter(|el| el.value().is_element()).count() == 10)
         .filter_map(|el| {
             let columns = el.select(&td_selector).collect::<Vec<_>>();
             let link = columns[3].select(&href_selector).next()?;
-            let category_str = columns[2]
-                .select(&href_selector)
-                .next()?
-                .inner_html()
-                .to_lowercase();
+            let category = columns[2].select(&href_selector).next()?.inner_html();
 
-            if !category_str.contains("loss") {
+            if !category_filter.matches(&category) {
                 return None;
             }
 
@@ -101,6 +252,11 @@ pub(crate) fn parse_search_results(raw_html: &str) -> Result<Vec<TopicData>, Par
                 .parse::<u64>()
                 .ok()?
                 .into();
+            let size_bytes = columns[5]
+                .value()
+                .attr("data-ts_text")
+                .and_then(|size| size.parse::<u64>().ok())
+                .unwrap_or_default();
             let seeds_number = columns[6]
                 .select(&seeds_selector)
                 .next()?
@@ -111,21 +267,138 @@ pub(crate) fn parse_search_results(raw_html: &str) -> Result<Vec<TopicData>, Par
                 .into();
 
             Some(TopicData {
+                format: parse_audio_format(&title),
+                bitrate: parse_bitrate(&title),
+                year: parse_year(&title),
                 title,
                 topic_id,
                 download_id,
                 seeds_number,
+                size_bytes,
+                category,
             })
         })
-        .filter(|r| !r.title.contains("image+.cue"))
         .collect();
 
-    // Sort search results by the search result priority
-    results.sort_by(|a, b| get_search_result_priority(a).cmp(&get_search_result_priority(b)));
+    Ok(results)
+}
+
+// Finds the topic fields by their own markup wherever they are in the row.
+// Download id is the same as topic id on RuTracker, so it's used when the link is missing.
+fn parse_results_loosely(
+    html: &Html,
+    category_filter: &CategoryFilter,
+) -> Result<Vec<TopicData>, ParseError> {
+    let row_selector = Selector::parse(r#"tr"#)?;
+    let title_selector = Selector::parse(r#"a[data-topic_id]"#)?;
+    let category_selector = Selector::parse(r#"a[href^="tracker.php?f="]"#)?;
+    let download_selector = Selector::parse(r#"a[href^="dl.php?t="]"#)?;
+    let size_selector = Selector::parse(r#"td[data-ts_text].tor-size"#)?;
+    let seeds_selector = Selector::parse(r#"b.seedmed"#)?;
+
+    let results = html
+        .select(&row_selector)
+        .filter_map(|el| {
+            let link = el.select(&title_selector).next()?;
+            let category = el
+                .select(&category_selector)
+                .next()
+                .map(|el| el.inner_html())
+                .unwrap_or_default();
+
+            if !category_filter.matches(&category) {
+                return None;
+            }
+
+            let title = link.inner_html().to_string();
+            let topic_id = link.value().attr("data-topic_id")?.parse::<u64>().ok()?;
+            let download_id = el
+                .select(&download_selector)
+                .next()
+                .and_then(|el| el.value().attr("href"))
+                .and_then(|href| href.replace("dl.php?t=", "").parse::<u64>().ok())
+                .unwrap_or(topic_id);
+            let size_bytes = el
+                .select(&size_selector)
+                .next()
+                .and_then(|el| el.value().attr("data-ts_text"))
+                .and_then(|size| size.parse::<u64>().ok())
+                .unwrap_or_default();
+            let seeds_number = el
+                .select(&seeds_selector)
+                .next()
+                .and_then(|el| el.inner_html().trim().parse::<u64>().ok())
+                .unwrap_or_default();
+
+            Some(TopicData {
+                format: parse_audio_format(&title),
+                bitrate: parse_bitrate(&title),
+                year: parse_year(&title),
+                title,
+                topic_id: topic_id.into(),
+                download_id: download_id.into(),
+                seeds_number,
+                size_bytes,
+                category,
+            })
+        })
+        .collect();
 
     Ok(results)
 }
 
+/// Topic from its own page. Returns `None` if there is no such topic.
+/// Unlike search results, topics are not filtered by category or by release type.
+pub(crate) fn parse_topic(raw_html: &str, topic_id: u64) -> Result<Option<TopicData>, ParseError> {
+    let html = Html::parse_document(raw_html);
+
+    let title_selector = Selector::parse(r#"a#topic-title"#)?;
+    let category_selector = Selector::parse(r#"td.t-breadcrumb-top a[href^="viewforum.php?f="]"#)?;
+    let download_selector = Selector::parse(r#"a.dl-link[href^="dl.php?t="]"#)?;
+    let size_selector = Selector::parse(r#"span#tor-size-humn[title]"#)?;
+    let seeds_selector = Selector::parse(r#"span.seed b"#)?;
+
+    let title = match html.select(&title_selector).next() {
+        // Title is broken up by `<wbr>` tags.
+        Some(link) => link.text().collect::<String>().trim().to_string(),
+        None => return Ok(None),
+    };
+    let category = html
+        .select(&category_selector)
+        .next_back()
+        .map(|el| el.inner_html())
+        .unwrap_or_default();
+    let download_id = html
+        .select(&download_selector)
+        .next()
+        .and_then(|el| el.value().attr("href"))
+        .and_then(|href| href.replace("dl.php?t=", "").parse::<u64>().ok())
+        .unwrap_or(topic_id);
+    let size_bytes = html
+        .select(&size_selector)
+        .next()
+        .and_then(|el| el.value().attr("title"))
+        .and_then(|size| size.parse::<u64>().ok())
+        .unwrap_or_default();
+    let seeds_number = html
+        .select(&seeds_selector)
+        .next()
+        .and_then(|el| el.inner_html().trim().parse::<u64>().ok())
+        .unwrap_or_default();
+
+    Ok(Some(TopicData {
+        format: parse_audio_format(&title),
+        bitrate: parse_bitrate(&title),
+        year: parse_year(&title),
+        title,
+        topic_id: topic_id.into(),
+        download_id: download_id.into(),
+        seeds_number,
+        size_bytes,
+        category,
+    }))
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum AuthError {
     #[error("Captcha verification is required.")]
@@ -151,3 +424,34 @@ pub(crate) fn parse_and_validate_auth_state(raw_html: &str) -> Result<(), AuthEr
 
     Ok(())
 }
+
+const FORM_TOKEN_KEY: &str = "form_token:";
+
+/// Blanks out the session-bound form token, so the page can be shared for debugging.
+pub(crate) fn redact_form_token(raw_html: &str) -> String {
+    let mut redacted = String::with_capacity(raw_html.len());
+    let mut rest = raw_html;
+
+    while let Some(key_start) = rest.find(FORM_TOKEN_KEY) {
+        let value_start = key_start + FORM_TOKEN_KEY.len();
+        redacted.push_str(&rest[..value_start]);
+        rest = &rest[value_start..];
+
+        let quote = match rest.trim_start().chars().next() {
+            Some(quote @ ('\'' | '"')) => quote,
+            _ => continue,
+        };
+        let open = rest.find(quote).expect("Quote should be there");
+
+        match rest[open + 1..].find(quote) {
+            Some(close) => {
+                redacted.push_str(&rest[..=open]);
+                rest = &rest[open + 1 + close..];
+            }
+            None => break,
+        }
+    }
+
+    redacted.push_str(rest);
+    redacted
+}
@@ -1,10 +1,15 @@
 use crate::rutracker::parser::{
-    parse_and_validate_auth_state, parse_search_results, AuthError, ParseError,
+    parse_and_validate_auth_state, parse_search_results, parse_topic, redact_form_token, AuthError,
+    CategoryFilter, KeywordWeights, ParseError,
 };
 use crate::TopicData;
 use reqwest::redirect::Policy;
-use reqwest::{Client, StatusCode};
+use reqwest::{Client, ClientBuilder, StatusCode};
 use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
+use tracing::{info, warn};
 
 const RU_TRACKER_HOST: &str = "https://rutracker.net";
 const MAGIC_LOGIN_WORD: &str = "вход";
@@ -21,13 +26,111 @@ pub enum RuTrackerClientError {
     BadStatus(StatusCode),
 }
 
+impl RuTrackerClientError {
+    /// Whether the same request might succeed later. Rejected credentials and
+    /// captcha verification won't go away by themselves, so they aren't transient.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            RuTrackerClientError::ReqwestError(_) => true,
+            RuTrackerClientError::BadStatus(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            RuTrackerClientError::AuthError(AuthError::UnknownAuthError) => true,
+            RuTrackerClientError::AuthError(_) | RuTrackerClientError::ParseError(_) => false,
+        }
+    }
+
+    /// Whether RuTracker couldn't be reached at all, as opposed to responding with an error.
+    pub fn is_connectivity_error(&self) -> bool {
+        match self {
+            RuTrackerClientError::ReqwestError(error) => error.is_connect() || error.is_timeout(),
+            _ => false,
+        }
+    }
+}
+
+/// Keeps the client from crawling RuTracker too aggressively, which might get the IP blocked.
+#[derive(Clone, Debug)]
+pub struct RequestLimits {
+    /// Maximum number of requests in flight at the same time.
+    pub max_concurrent_requests: usize,
+    /// Minimum delay between starting two consecutive requests.
+    pub min_delay: Duration,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrent_requests: 2,
+            min_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+struct Throttle {
+    permits: Semaphore,
+    min_delay: Duration,
+    last_request_at: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    fn new(limits: &RequestLimits) -> Self {
+        Self {
+            permits: Semaphore::new(limits.max_concurrent_requests.max(1)),
+            min_delay: limits.min_delay,
+            last_request_at: Mutex::new(None),
+        }
+    }
+
+    async fn acquire(&self) -> SemaphorePermit<'_> {
+        let permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("Throttle semaphore should never be closed");
+
+        // Lock is held while waiting, so the requests start one by one.
+        let mut last_request_at = self.last_request_at.lock().await;
+        if let Some(elapsed) = last_request_at.map(|instant| instant.elapsed()) {
+            if elapsed < self.min_delay {
+                tokio::time::sleep(self.min_delay - elapsed).await;
+            }
+        }
+        last_request_at.replace(Instant::now());
+
+        permit
+    }
+}
+
 pub struct RuTrackerClient {
     client: Client,
+    host: String,
+    throttle: Throttle,
+    category_filter: CategoryFilter,
+    keyword_weights: KeywordWeights,
+    html_dump_directory: Option<PathBuf>,
 }
 
 impl RuTrackerClient {
-    pub async fn create(username: &str, password: &str) -> Result<Self, RuTrackerClientError> {
-        let client = Client::builder()
+    /// Logs in to RuTracker using the HTTP client built from `client_builder`,
+    /// which is expected to carry the user agent and timeouts.
+    pub async fn create(
+        client_builder: ClientBuilder,
+        username: &str,
+        password: &str,
+    ) -> Result<Self, RuTrackerClientError> {
+        Self::create_on_host(client_builder, RU_TRACKER_HOST, username, password).await
+    }
+
+    /// Logs in to a RuTracker mirror, e.g. `https://rutracker.org`, which shares
+    /// the accounts, topics and downloads with the main host.
+    pub async fn create_on_host(
+        client_builder: ClientBuilder,
+        host: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<Self, RuTrackerClientError> {
+        let client = client_builder
             .redirect(Policy::limited(10))
             .cookie_store(true)
             .build()
@@ -47,7 +150,7 @@ impl RuTrackerClient {
         };
 
         let response = client
-            .post(format!("{}/forum/login.php", RU_TRACKER_HOST))
+            .post(format!("{}/forum/login.php", host))
             .form(&form)
             .send()
             .await?;
@@ -56,7 +159,60 @@ impl RuTrackerClient {
 
         parse_and_validate_auth_state(&raw_html)?;
 
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            host: host.to_string(),
+            throttle: Throttle::new(&RequestLimits::default()),
+            category_filter: CategoryFilter::default(),
+            keyword_weights: KeywordWeights::default(),
+            html_dump_directory: None,
+        })
+    }
+
+    pub fn with_request_limits(self, request_limits: RequestLimits) -> Self {
+        Self {
+            throttle: Throttle::new(&request_limits),
+            ..self
+        }
+    }
+
+    pub fn with_category_filter(self, category_filter: CategoryFilter) -> Self {
+        Self {
+            category_filter,
+            ..self
+        }
+    }
+
+    pub fn with_keyword_weights(self, keyword_weights: KeywordWeights) -> Self {
+        Self {
+            keyword_weights,
+            ..self
+        }
+    }
+
+    pub fn keyword_weights(&self) -> &KeywordWeights {
+        &self.keyword_weights
+    }
+
+    /// Search pages without any results are written into the directory,
+    /// so the parser can be fixed when RuTracker changes its markup.
+    pub fn with_html_dump_directory(self, html_dump_directory: Option<PathBuf>) -> Self {
+        Self {
+            html_dump_directory,
+            ..self
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn create_with_host(host: &str, request_limits: RequestLimits) -> Self {
+        Self {
+            client: Client::new(),
+            host: host.to_string(),
+            throttle: Throttle::new(&request_limits),
+            category_filter: CategoryFilter::default(),
+            keyword_weights: KeywordWeights::default(),
+            html_dump_directory: None,
+        }
     }
 
     pub async fn search_music(
@@ -72,30 +228,58 @@ impl RuTrackerClient {
             nm: query_str.to_string(),
         };
 
+        let _permit = self.throttle.acquire().await;
         let response = self
             .client
-            .get(format!("{}/forum/tracker.php", RU_TRACKER_HOST))
+            .get(format!("{}/forum/tracker.php", self.host))
             .query(&query)
             .send()
             .await?;
+        let status = response.status();
+
+        let raw_html = response.text().await?;
+
+        parse_and_validate_auth_state(&raw_html)?;
+
+        let results =
+            parse_search_results(&raw_html, &self.category_filter, &self.keyword_weights)?;
+
+        if results.is_empty() && status == StatusCode::OK {
+            if let Some(directory) = &self.html_dump_directory {
+                dump_html(directory, &raw_html).await;
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Returns `None` if there is no topic with the id.
+    pub async fn get_topic(
+        &self,
+        topic_id: u64,
+    ) -> Result<Option<TopicData>, RuTrackerClientError> {
+        let _permit = self.throttle.acquire().await;
+        let response = self
+            .client
+            .get(format!("{}/forum/viewtopic.php?t={}", self.host, topic_id))
+            .send()
+            .await?;
 
         let raw_html = response.text().await?;
 
         parse_and_validate_auth_state(&raw_html)?;
 
-        Ok(parse_search_results(&raw_html)?)
+        Ok(parse_topic(&raw_html, topic_id)?)
     }
 
     pub async fn download_torrent(
         &self,
         download_id: u64,
     ) -> Result<Vec<u8>, RuTrackerClientError> {
+        let _permit = self.throttle.acquire().await;
         let response = self
             .client
-            .get(format!(
-                "{}/forum/dl.php?t={}",
-                RU_TRACKER_HOST, download_id
-            ))
+            .get(format!("{}/forum/dl.php?t={}", self.host, download_id))
             .send()
             .await?;
         let status = response.status();
@@ -110,11 +294,8 @@ impl RuTrackerClient {
     }
 
     pub async fn check_connection(&self) -> Result<(), RuTrackerClientError> {
-        let response = self
-            .client
-            .get(format!("{}", RU_TRACKER_HOST))
-            .send()
-            .await?;
+        let _permit = self.throttle.acquire().await;
+        let response = self.client.get(&self.host).send().await?;
         let status = response.status();
 
         if status != StatusCode::OK {
@@ -127,3 +308,22 @@ impl RuTrackerClient {
         Ok(())
     }
 }
+
+// Only the response body is written: cookies and credentials are never part of it.
+async fn dump_html(directory: &Path, raw_html: &str) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = directory.join(format!("search-{}.html", timestamp));
+
+    let result = match tokio::fs::create_dir_all(directory).await {
+        Ok(()) => tokio::fs::write(&path, redact_form_token(raw_html)).await,
+        Err(error) => Err(error),
+    };
+
+    match result {
+        Ok(()) => info!(?path, "Search page without results has been dumped"),
+        Err(error) => warn!(?error, ?path, "Unable to dump search page"),
+    }
+}
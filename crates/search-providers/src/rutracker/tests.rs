@@ -1,10 +1,26 @@
-use crate::rutracker::parser::parse_search_results;
-use crate::{DownloadId, TopicData, TopicId};
+use crate::rutracker::parser::{
+    get_search_result_priority, is_image_release, parse_search_results, parse_topic,
+    redact_form_token, sort_search_results,
+};
+use crate::{
+    AudioFormat, AuthError, Bitrate, CategoryFilter, DownloadId, KeywordWeights, RequestLimits,
+    RuTrackerClient, RuTrackerClientError, TopicData, TopicId,
+};
+use reqwest::StatusCode;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[test]
 fn test_parsing_of_search_results() {
-    let results = parse_search_results(include_str!("fixtures/search_results.html"))
-        .expect("Expected successful parse results");
+    let results = parse_search_results(
+        include_str!("fixtures/search_results.html"),
+        &CategoryFilter::default(),
+        &KeywordWeights::default(),
+    )
+    .expect("Expected successful parse results");
 
     let expected_results = vec![
         TopicData {
@@ -13,6 +29,11 @@ fn test_parsing_of_search_results() {
             topic_id: TopicId(1183770),
             download_id: DownloadId(1183770),
             seeds_number: 18,
+            size_bytes: 447129784,
+            category: "Trance (lossless)".into(),
+            format: Some(AudioFormat::Flac),
+            bitrate: Some(Bitrate::Lossless),
+            year: Some(1996),
         },
         TopicData {
             #[rustfmt::skip]
@@ -20,6 +41,11 @@ fn test_parsing_of_search_results() {
             topic_id: TopicId(1184081),
             download_id: DownloadId(1184081),
             seeds_number: 11,
+            size_bytes: 545959122,
+            category: "Trance (lossless)".into(),
+            format: Some(AudioFormat::Flac),
+            bitrate: Some(Bitrate::Lossless),
+            year: Some(1996),
         },
         TopicData {
             #[rustfmt::skip]
@@ -27,6 +53,11 @@ fn test_parsing_of_search_results() {
             topic_id: TopicId(5318721),
             download_id: DownloadId(5318721),
             seeds_number: 8,
+            size_bytes: 530180022,
+            category: "Trance (lossless)".into(),
+            format: Some(AudioFormat::Flac),
+            bitrate: Some(Bitrate::Lossless),
+            year: Some(2016),
         },
         TopicData {
             #[rustfmt::skip]
@@ -34,6 +65,11 @@ fn test_parsing_of_search_results() {
             topic_id: TopicId(3418878),
             download_id: DownloadId(3418878),
             seeds_number: 4,
+            size_bytes: 664059511,
+            category: "Trance (lossless)".into(),
+            format: Some(AudioFormat::Flac),
+            bitrate: Some(Bitrate::Lossless),
+            year: Some(1996),
         },
         TopicData {
             #[rustfmt::skip]
@@ -41,6 +77,11 @@ fn test_parsing_of_search_results() {
             topic_id: TopicId(1201152),
             download_id: DownloadId(1201152),
             seeds_number: 3,
+            size_bytes: 428560959,
+            category: "Музыка lossless (ALAC)".into(),
+            format: Some(AudioFormat::Alac),
+            bitrate: Some(Bitrate::Lossless),
+            year: Some(1996),
         },
         TopicData {
             #[rustfmt::skip]
@@ -48,6 +89,11 @@ fn test_parsing_of_search_results() {
             topic_id: TopicId(5309922),
             download_id: DownloadId(5309922),
             seeds_number: 9,
+            size_bytes: 188233781,
+            category: "Trance (lossy)".into(),
+            format: Some(AudioFormat::Mp3),
+            bitrate: Some(Bitrate::Kbps320),
+            year: Some(2016),
         },
         TopicData {
             #[rustfmt::skip]
@@ -55,9 +101,338 @@ fn test_parsing_of_search_results() {
             topic_id: TopicId(4737164),
             download_id: DownloadId(4737164),
             seeds_number: 2,
+            size_bytes: 145378309,
+            category: "Музыка Lossy (AAC-iTunes)".into(),
+            format: Some(AudioFormat::Aac),
+            bitrate: Some(Bitrate::Kbps256),
+            year: Some(1996),
         },
     ];
 
     assert_eq!(7, results.len());
     assert_eq!(expected_results, results);
 }
+
+#[test]
+fn test_parsing_of_topic() {
+    let topic = parse_topic(include_str!("fixtures/topic.html"), 5309922)
+        .expect("Expected successful parse topic");
+
+    assert_eq!(
+        Some(TopicData {
+            title: "(Trance) Robert Miles - Dreamland (Remastered) - 2016, MP3, 320 kbps".into(),
+            topic_id: TopicId(5309922),
+            download_id: DownloadId(5309922),
+            seeds_number: 9,
+            size_bytes: 188233781,
+            category: "Trance (lossy)".into(),
+            format: Some(AudioFormat::Mp3),
+            bitrate: Some(Bitrate::Kbps320),
+            year: Some(2016),
+        }),
+        topic
+    );
+    assert_eq!(
+        None,
+        parse_topic(include_str!("fixtures/index_logged_in.html"), 5309922).unwrap()
+    );
+}
+
+#[test]
+fn test_ranking_of_search_results() {
+    let results = parse_search_results(
+        include_str!("fixtures/search_results.html"),
+        &CategoryFilter::default(),
+        &KeywordWeights::default(),
+    )
+    .expect("Expected successful parse results");
+
+    let priorities: Vec<_> = results
+        .iter()
+        .map(|result| get_search_result_priority(result, &KeywordWeights::default()))
+        .collect();
+
+    assert_eq!(vec![2, 2, 3, 3, 13, 18, 38], priorities);
+}
+
+#[test]
+fn test_breaking_ties_in_ranking_of_search_results() {
+    let result = |topic_id: u64, seeds_number: u64, size_bytes: u64| TopicData {
+        title: "Robert Miles - Dreamland - 1996, FLAC (tracks+.cue), lossless".into(),
+        topic_id: TopicId(topic_id),
+        download_id: DownloadId(topic_id),
+        seeds_number,
+        size_bytes,
+        category: "Trance (lossless)".into(),
+        format: Some(AudioFormat::Flac),
+        bitrate: Some(Bitrate::Lossless),
+        year: Some(1996),
+    };
+    // Same priority for all of them, as 10 to 19 seeds fall into the same bucket.
+    let mut results = vec![
+        result(5, 12, 400),
+        result(4, 12, 500),
+        result(3, 15, 100),
+        result(2, 12, 500),
+        result(1, 12, 400),
+    ];
+
+    sort_search_results(&mut results, &KeywordWeights::default());
+
+    let topic_ids: Vec<_> = results.iter().map(|r| *r.topic_id).collect();
+    assert_eq!(vec![3, 2, 4, 1, 5], topic_ids);
+}
+
+#[test]
+fn test_weighting_search_results_by_title_keywords() {
+    let result = |topic_id: u64, title: &str| TopicData {
+        title: title.into(),
+        topic_id: TopicId(topic_id),
+        download_id: DownloadId(topic_id),
+        seeds_number: 12,
+        size_bytes: 400,
+        category: "Trance (lossless)".into(),
+        format: Some(AudioFormat::Flac),
+        bitrate: Some(Bitrate::Lossless),
+        year: Some(1996),
+    };
+    let mut results = vec![
+        result(
+            1,
+            "Robert Miles - Dreamland (Promo) - 1996, FLAC (tracks+.cue), lossless",
+        ),
+        result(
+            2,
+            "Robert Miles - Dreamland - 1996, FLAC (tracks+.cue), lossless",
+        ),
+        result(
+            3,
+            "Robert Miles - Dreamland - 1996, FLAC (tracks+.cue), lossless [TrustedRip]",
+        ),
+    ];
+    let keyword_weights = KeywordWeights {
+        trusted: vec!["trustedrip".into()],
+        avoided: vec!["promo".into(), "sample".into()],
+    };
+
+    sort_search_results(&mut results, &keyword_weights);
+
+    let topic_ids: Vec<_> = results.iter().map(|r| *r.topic_id).collect();
+    assert_eq!(vec![3, 2, 1], topic_ids);
+}
+
+#[test]
+fn test_detecting_image_releases() {
+    let image_releases = [
+        "Robert Miles - Dreamland - 1996, FLAC (image+.cue), lossless",
+        "Robert Miles - Dreamland - 1996, FLAC (image + .cue), lossless",
+        "Robert Miles - Dreamland - 1996, FLAC (image (.cue)), lossless",
+        "Robert Miles - Dreamland - 1996, APE (Image+.CUE) lossless",
+        "Robert Miles - Dreamland - 1996, WavPack (.cue), lossless",
+    ];
+    let tracks_releases = [
+        "Robert Miles - Dreamland - 1996, FLAC (tracks+.cue), lossless",
+        "Robert Miles - Dreamland - 1996, FLAC (tracks + .cue), lossless",
+        "Robert Miles - Dreamland (Remastered) - 2016, FLAC (tracks), lossless",
+        "Robert Miles - Dreamland - 1996, ALAC, lossless",
+    ];
+
+    for title in image_releases {
+        assert!(is_image_release(title), "{}", title);
+    }
+
+    for title in tracks_releases {
+        assert!(!is_image_release(title), "{}", title);
+    }
+}
+
+#[test]
+fn test_classifying_transient_errors() {
+    assert!(RuTrackerClientError::BadStatus(StatusCode::BAD_GATEWAY).is_transient());
+    assert!(RuTrackerClientError::BadStatus(StatusCode::TOO_MANY_REQUESTS).is_transient());
+    assert!(RuTrackerClientError::AuthError(AuthError::UnknownAuthError).is_transient());
+
+    assert!(!RuTrackerClientError::BadStatus(StatusCode::NOT_FOUND).is_transient());
+    assert!(!RuTrackerClientError::AuthError(AuthError::IncorrectPasswordText).is_transient());
+    assert!(
+        !RuTrackerClientError::AuthError(AuthError::CaptchaVerificationIsRequired).is_transient()
+    );
+}
+
+#[actix_rt::test]
+async fn test_classifying_connectivity_errors() {
+    // Nothing listens on the port once the listener is dropped.
+    let host = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let client = RuTrackerClient::create_with_host(&host, RequestLimits::default());
+
+    let error = client.search_music("Robert Miles").await.unwrap_err();
+
+    assert!(error.is_connectivity_error());
+    assert!(!RuTrackerClientError::BadStatus(StatusCode::BAD_GATEWAY).is_connectivity_error());
+}
+
+#[actix_rt::test]
+async fn test_limiting_concurrent_requests() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let host = format!("http://{}", listener.local_addr().unwrap());
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let max_in_flight = Arc::new(AtomicUsize::new(0));
+
+    std::thread::spawn({
+        let in_flight = in_flight.clone();
+        let max_in_flight = max_in_flight.clone();
+
+        move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let in_flight = in_flight.clone();
+                let max_in_flight = max_in_flight.clone();
+
+                std::thread::spawn(move || {
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+
+                    let mut buf = [0u8; 4096];
+                    let _ = stream.read(&mut buf);
+                    std::thread::sleep(Duration::from_millis(100));
+
+                    let body = include_str!("fixtures/search_results.html");
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                });
+            }
+        }
+    });
+
+    let client = Arc::new(RuTrackerClient::create_with_host(
+        &host,
+        RequestLimits {
+            max_concurrent_requests: 2,
+            min_delay: Duration::ZERO,
+        },
+    ));
+
+    let tasks: Vec<_> = (0..6)
+        .map(|_| {
+            let client = client.clone();
+            actix_rt::spawn(async move { client.search_music("Robert Miles").await })
+        })
+        .collect();
+    for task in tasks {
+        assert!(task.await.unwrap().is_ok());
+    }
+
+    assert_eq!(2, max_in_flight.load(Ordering::SeqCst));
+}
+
+#[actix_rt::test]
+async fn test_sending_quoted_search_query() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let host = format!("http://{}", listener.local_addr().unwrap());
+    let (request_line_sender, request_line_receiver) = std::sync::mpsc::channel();
+
+    std::thread::spawn(move || {
+        let mut stream = listener.incoming().next().unwrap().unwrap();
+
+        let mut buf = [0u8; 4096];
+        let len = stream.read(&mut buf).unwrap();
+        let request = String::from_utf8_lossy(&buf[..len]).to_string();
+        let _ = request_line_sender.send(request.lines().next().unwrap_or_default().to_string());
+
+        let body = include_str!("fixtures/search_results.html");
+        let _ = write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+    });
+
+    let client = RuTrackerClient::create_with_host(&host, RequestLimits::default());
+
+    assert!(client.search_music("\"Air\" \"Moon Safari\"").await.is_ok());
+    assert_eq!(
+        "GET /forum/tracker.php?nm=%22Air%22+%22Moon+Safari%22 HTTP/1.1",
+        request_line_receiver.recv().unwrap()
+    );
+}
+
+#[test]
+fn test_filtering_search_results_by_category() {
+    let results = parse_search_results(
+        include_str!("fixtures/search_results.html"),
+        &CategoryFilter {
+            include: vec!["trance".into()],
+            exclude: vec!["lossy".into()],
+        },
+        &KeywordWeights::default(),
+    )
+    .expect("Expected successful parse results");
+
+    assert_eq!(4, results.len());
+    assert!(results.iter().all(|r| r.category == "Trance (lossless)"));
+}
+
+#[test]
+fn test_redacting_form_token() {
+    let redacted = redact_form_token(include_str!("fixtures/index_logged_in.html"));
+
+    assert!(!redacted.contains("6e8601b81e481637ac3807275bb305dd"));
+    assert!(redacted.contains("form_token: '',"));
+    assert_eq!(
+        "form_token: \"\", form_token:",
+        redact_form_token("form_token: \"secret\", form_token:")
+    );
+}
+
+#[test]
+fn test_parsing_of_search_results_with_changed_layout() {
+    // Size and seeds columns are merged, so the row has 9 columns instead of 10.
+    let raw_html = r#"
+        <table class="forumline">
+          <tr><th>Header</th></tr>
+          <tr>
+            <td></td><td></td>
+            <td><a class="gen f" href="tracker.php?f=1754">Trance (lossless)</a></td>
+            <td><a data-topic_id="1183770" href="viewtopic.php?t=1183770">Robert Miles - Dreamland - 1996, FLAC (tracks+.cue), lossless</a></td>
+            <td></td>
+            <td><a href="dl.php?t=1183771">983.8 MB</a> <b class="seedmed">18</b></td>
+            <td></td><td></td><td></td>
+          </tr>
+          <tr>
+            <td><a class="gen f" href="tracker.php?f=1755">Рэп, Хип-Хоп (MP3)</a></td>
+            <td><a data-topic_id="1183772" href="viewtopic.php?t=1183772">Robert Miles - Dreamland - 1996, MP3, 320 kbps</a></td>
+          </tr>
+        </table>
+    "#;
+
+    let results = parse_search_results(
+        raw_html,
+        &CategoryFilter::default(),
+        &KeywordWeights::default(),
+    )
+    .expect("Expected successful parse results");
+
+    assert_eq!(
+        vec![TopicData {
+            title: "Robert Miles - Dreamland - 1996, FLAC (tracks+.cue), lossless".into(),
+            topic_id: TopicId(1183770),
+            download_id: DownloadId(1183771),
+            seeds_number: 18,
+            size_bytes: 0,
+            category: "Trance (lossless)".into(),
+            format: Some(AudioFormat::Flac),
+            bitrate: Some(Bitrate::Lossless),
+            year: Some(1996),
+        }],
+        results
+    );
+}
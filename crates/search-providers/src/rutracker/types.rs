@@ -45,3 +45,52 @@ impl std::fmt::Display for DownloadId {
         write!(f, "{}", self.0)
     }
 }
+
+/// Audio format of the release, as stated in the topic title.
+/// Variants are ordered from the most to the least preferred one.
+#[derive(Eq, PartialEq, Clone, Copy, Hash, Debug)]
+pub enum AudioFormat {
+    Flac,
+    Mp3,
+    Alac,
+    Aac,
+}
+
+impl AudioFormat {
+    pub(crate) const ALL: [AudioFormat; 4] = [
+        AudioFormat::Flac,
+        AudioFormat::Mp3,
+        AudioFormat::Alac,
+        AudioFormat::Aac,
+    ];
+
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            AudioFormat::Flac => "FLAC",
+            AudioFormat::Mp3 => "MP3",
+            AudioFormat::Alac => "ALAC",
+            AudioFormat::Aac => "AAC",
+        }
+    }
+}
+
+/// Bitrate of the release, as stated in the topic title.
+/// Variants are ordered from the most to the least preferred one.
+#[derive(Eq, PartialEq, Clone, Copy, Hash, Debug)]
+pub enum Bitrate {
+    Lossless,
+    Kbps320,
+    Kbps256,
+}
+
+impl Bitrate {
+    pub(crate) const ALL: [Bitrate; 3] = [Bitrate::Lossless, Bitrate::Kbps320, Bitrate::Kbps256];
+
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            Bitrate::Lossless => "lossless",
+            Bitrate::Kbps320 => "320 kbps",
+            Bitrate::Kbps256 => "256 kbps",
+        }
+    }
+}
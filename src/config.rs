@@ -1,3 +1,4 @@
+use crate::services::{HttpClientOptions, DEFAULT_FILENAME_PATTERNS};
 use serde::Deserialize;
 
 fn default_bind_address() -> String {
@@ -8,6 +9,86 @@ fn default_shutdown_timeout() -> u64 {
     30u64
 }
 
+fn default_step_poll_interval() -> u64 {
+    1u64
+}
+
+fn default_download_poll_interval() -> u64 {
+    5u64
+}
+
+fn default_upload_timeout() -> u64 {
+    600u64
+}
+
+fn default_http_user_agent() -> String {
+    HttpClientOptions::default().user_agent
+}
+
+fn default_http_timeout() -> u64 {
+    HttpClientOptions::default().timeout.as_secs()
+}
+
+fn default_http_connect_timeout() -> u64 {
+    HttpClientOptions::default().connect_timeout.as_secs()
+}
+
+fn default_debug_dump_directory() -> String {
+    "debug".to_string()
+}
+
+fn default_rutracker_login_attempts() -> u32 {
+    5u32
+}
+
+fn default_rutracker_session_check_interval() -> u64 {
+    300u64
+}
+
+fn default_max_step_retries() -> u32 {
+    3u32
+}
+
+fn default_step_retry_backoff() -> u64 {
+    5u64
+}
+
+fn default_max_concurrent_uploads() -> usize {
+    2usize
+}
+
+fn default_max_dead_topics() -> usize {
+    100usize
+}
+
+fn default_max_torrent_file_retries() -> u32 {
+    2u32
+}
+
+fn default_torrent_file_retry_backoff() -> u64 {
+    1u64
+}
+
+fn default_rutracker_max_concurrent_requests() -> usize {
+    2usize
+}
+
+fn default_rutracker_min_request_delay_ms() -> u64 {
+    500u64
+}
+
+fn default_rutracker_include_categories() -> Vec<String> {
+    vec!["loss".to_string()]
+}
+
+fn default_matching_file_preference() -> String {
+    "first".to_string()
+}
+
+fn default_metadata_filename_patterns() -> Vec<String> {
+    DEFAULT_FILENAME_PATTERNS.map(String::from).to_vec()
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub(crate) struct RuTrackerCredentials {
     #[serde(rename = "rutracker_username")]
@@ -20,12 +101,16 @@ pub(crate) struct RuTrackerCredentials {
 pub(crate) struct TransmissionConfig {
     #[serde(rename = "transmission_rpc_endpoint")]
     pub(crate) transmission_rpc_endpoint: String,
+    /// Download directory in Transmission's filesystem.
     #[serde(rename = "transmission_download_directory")]
     pub(crate) download_directory: String,
     #[serde(default, rename = "transmission_username")]
     pub(crate) username: Option<String>,
     #[serde(default, rename = "transmission_password")]
     pub(crate) password: Option<String>,
+    /// Accepts self-signed and otherwise invalid TLS certificates of an HTTPS RPC endpoint.
+    #[serde(default, rename = "transmission_accept_invalid_certs")]
+    pub(crate) accept_invalid_certs: bool,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -38,21 +123,161 @@ pub(crate) struct RadioManagerConfig {
     pub(crate) password: String,
 }
 
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, with the event and span fields as keys, for log aggregators.
+    Json,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub(crate) struct Config {
     #[serde(default = "default_bind_address")]
     pub(crate) bind_address: String,
     #[serde(default = "default_shutdown_timeout")]
     pub(crate) shutdown_timeout: u64,
+    #[serde(default)]
+    pub(crate) log_format: LogFormat,
+    /// Seconds without HTTP requests and requests being processed after which the bot
+    /// shuts down, so it can be scaled to zero. Runs until stopped if not set.
+    #[serde(default)]
+    pub(crate) idle_shutdown_timeout: Option<u64>,
+    /// Transmission download directory as mounted into the bot's filesystem.
     pub(crate) download_directory: String,
+    /// Subdirectory of the download directory for each request, e.g. `{artist}/{album}`.
+    /// Supports `{artist}`, `{album}`, `{request_id}` and `{user_id}` placeholders.
+    #[serde(default)]
+    pub(crate) download_path_template: Option<String>,
+    /// Absolute path of the directory downloaded files are copied into and uploaded from,
+    /// e.g. to keep them on another disk than the downloads. Not copied if not set.
+    #[serde(default)]
+    pub(crate) library_directory: Option<String>,
+    #[serde(default)]
+    pub(crate) max_download_bytes: Option<u64>,
+    #[serde(default)]
+    pub(crate) max_download_kbps: Option<u32>,
+    #[serde(default)]
+    pub(crate) max_upload_kbps: Option<u32>,
+    #[serde(default)]
+    pub(crate) max_upload_bytes: Option<u64>,
+    #[serde(default = "default_upload_timeout")]
+    pub(crate) upload_timeout: u64,
+    #[serde(default = "default_http_user_agent")]
+    pub(crate) http_user_agent: String,
+    #[serde(default = "default_http_timeout")]
+    pub(crate) http_timeout: u64,
+    #[serde(default = "default_http_connect_timeout")]
+    pub(crate) http_connect_timeout: u64,
+    /// Proxy URL for outbound HTTP requests of RuTracker, RadioManager and OpenAI clients.
+    #[serde(default)]
+    pub(crate) http_proxy: Option<String>,
+    /// Proxy URL for outbound HTTPS requests of RuTracker, RadioManager and OpenAI clients.
+    #[serde(default)]
+    pub(crate) https_proxy: Option<String>,
+    #[serde(default = "default_step_poll_interval")]
+    pub(crate) step_poll_interval: u64,
+    #[serde(default = "default_download_poll_interval")]
+    pub(crate) download_poll_interval: u64,
+    #[serde(default = "default_max_step_retries")]
+    pub(crate) max_step_retries: u32,
+    /// Requests a user may create per minute, unlimited if not set.
+    #[serde(default)]
+    pub(crate) max_requests_per_minute: Option<u32>,
+    /// Comma-separated `user_id:channel_id` pairs of the RadioManager channels users may
+    /// add tracks to, e.g. `1:100,1:200,2:300`. Any channel is allowed if empty.
+    #[serde(default)]
+    pub(crate) channel_allowlist: Vec<String>,
+    /// Seconds a torrent may take to download before the next topic is tried, unlimited if not set.
+    #[serde(default)]
+    pub(crate) download_completion_timeout: Option<u64>,
+    /// Seconds torrents of finished requests keep seeding before they're removed, removed at once if not set.
+    #[serde(default)]
+    pub(crate) seed_duration: Option<u64>,
+    /// Seconds since its creation a request may take before it's given up on, unlimited if not set.
+    #[serde(default)]
+    pub(crate) request_timeout: Option<u64>,
+    /// Torrents downloading at the same time, unlimited if not set.
+    #[serde(default)]
+    pub(crate) max_active_downloads: Option<usize>,
+    /// Files uploading to the radio manager at the same time.
+    #[serde(default = "default_max_concurrent_uploads")]
+    pub(crate) max_concurrent_uploads: usize,
+    /// Writes the requested artist, title and album into the tags of a downloaded file
+    /// before it is uploaded. Only FLAC and MP3 files are supported. Requires the library
+    /// directory, as only the library copy is tagged.
+    #[serde(default)]
+    pub(crate) normalize_tags_before_upload: bool,
+    /// Uploads downloaded files to RadioManager as `{artist} - {title}.{ext}`
+    /// rather than under their names in the torrent.
+    #[serde(default)]
+    pub(crate) name_uploads_after_metadata: bool,
+    /// Checks that the track shows up on the channel after adding it, and adds it once
+    /// again if it doesn't, before the request is finished.
+    #[serde(default)]
+    pub(crate) verify_channel_add: bool,
+    /// Downloaded file uploaded when several match the title: `first`, `shortest_path`,
+    /// `exact_title` or `folder:<name>`.
+    #[serde(default = "default_matching_file_preference")]
+    pub(crate) matching_file_preference: String,
+    #[serde(default = "default_step_retry_backoff")]
+    pub(crate) step_retry_backoff: u64,
+    /// Failed topics remembered per request not to be downloaded again, the oldest are forgotten.
+    #[serde(default = "default_max_dead_topics")]
+    pub(crate) max_dead_topics: usize,
+    /// Retries of downloading a torrent file before its topic is skipped, on top of the step retries.
+    #[serde(default = "default_max_torrent_file_retries")]
+    pub(crate) max_torrent_file_retries: u32,
+    /// Seconds before the first retry of downloading a torrent file, doubled on each subsequent one.
+    #[serde(default = "default_torrent_file_retry_backoff")]
+    pub(crate) torrent_file_retry_backoff: u64,
     pub(crate) state_storage_directory: String,
     #[serde(flatten)]
     pub(crate) rutracker: RuTrackerCredentials,
+    #[serde(default = "default_rutracker_login_attempts")]
+    pub(crate) rutracker_login_attempts: u32,
+    /// RuTracker mirror, e.g. `https://rutracker.org`, searched with the same credentials
+    /// only while the main host is unreachable.
+    #[serde(default)]
+    pub(crate) rutracker_fallback_host: Option<String>,
+    /// Seconds between checks that the RuTracker session is still valid.
+    #[serde(default = "default_rutracker_session_check_interval")]
+    pub(crate) rutracker_session_check_interval: u64,
+    #[serde(default = "default_rutracker_max_concurrent_requests")]
+    pub(crate) rutracker_max_concurrent_requests: usize,
+    #[serde(default = "default_rutracker_min_request_delay_ms")]
+    pub(crate) rutracker_min_request_delay_ms: u64,
+    /// Comma-separated substrings of the forum categories to search in, any if empty.
+    #[serde(default = "default_rutracker_include_categories")]
+    pub(crate) rutracker_include_categories: Vec<String>,
+    /// Comma-separated substrings of the forum categories to skip.
+    #[serde(default)]
+    pub(crate) rutracker_exclude_categories: Vec<String>,
+    /// Comma-separated keywords, e.g. names of known uploaders, of the topic titles to prefer.
+    #[serde(default)]
+    pub(crate) rutracker_trusted_keywords: Vec<String>,
+    /// Comma-separated keywords of the topic titles to try last, e.g. "sample" or "promo".
+    #[serde(default)]
+    pub(crate) rutracker_avoided_keywords: Vec<String>,
     #[serde(flatten)]
     pub(crate) transmission: TransmissionConfig,
     #[serde(flatten)]
     pub(crate) radiomanager: RadioManagerConfig,
     pub(crate) openai_api_key: String,
+    #[serde(default = "default_metadata_filename_patterns")]
+    pub(crate) metadata_filename_patterns: Vec<String>,
+    /// Exposes the `/debug/*` endpoints meant for administrators only.
+    #[serde(default)]
+    pub(crate) enable_debug_endpoints: bool,
+    /// Bearer token of the `/admin/*` endpoints, which are disabled if not set.
+    #[serde(default)]
+    pub(crate) admin_token: Option<String>,
+    /// Writes RuTracker search pages without any results into `debug_dump_directory`.
+    #[serde(default)]
+    pub(crate) debug_dump_html: bool,
+    #[serde(default = "default_debug_dump_directory")]
+    pub(crate) debug_dump_directory: String,
 }
 
 impl Config {
@@ -0,0 +1,64 @@
+use crate::services::{Reloadable, RuTrackerCredentials, RuTrackerLogin};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use search_providers::{RuTrackerClient, RuTrackerClientError};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+/// Token expected as `Authorization: Bearer <token>` by the `/admin/*` endpoints.
+pub(crate) struct AdminToken(pub(crate) String);
+
+fn is_authorized(req: &HttpRequest, admin_token: &AdminToken) -> bool {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token == admin_token.0)
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RuTrackerCredentialsData {
+    username: String,
+    password: String,
+}
+
+/// Logs in to RuTracker with the given credentials and replaces the client in use.
+/// Searches already in progress finish with the previous client.
+pub(crate) async fn reauth_rutracker(
+    params: web::Json<RuTrackerCredentialsData>,
+    admin_token: web::Data<AdminToken>,
+    rutracker_login: web::Data<Arc<RuTrackerLogin>>,
+    rutracker_client: web::Data<Arc<Reloadable<RuTrackerClient>>>,
+    rutracker_credentials: web::Data<Arc<Reloadable<RuTrackerCredentials>>>,
+    req: HttpRequest,
+) -> impl Responder {
+    if !is_authorized(&req, &admin_token) {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let params = params.into_inner();
+
+    match rutracker_login
+        .login(&params.username, &params.password)
+        .await
+    {
+        Ok(client) => {
+            rutracker_client.replace(client);
+            rutracker_credentials.replace(RuTrackerCredentials {
+                username: params.username,
+                password: params.password,
+            });
+            info!("Logged in to RuTracker with new credentials");
+            HttpResponse::Ok().finish()
+        }
+        Err(error @ RuTrackerClientError::AuthError(_)) => {
+            warn!(?error, "RuTracker rejected new credentials");
+            HttpResponse::BadRequest().finish()
+        }
+        Err(error) => {
+            error!(?error, "Unable to log in to RuTracker with new credentials");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use crate::services::RadioManagerClient;
+use actix_web::{web, HttpResponse, Responder};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{error, info};
+
+#[derive(Deserialize)]
+pub(crate) struct CreateChannelData {
+    name: String,
+}
+
+/// Creates the channel unless the channel with the same name already exists.
+pub(crate) async fn create_channel(
+    radio_manager_client: web::Data<Arc<RadioManagerClient>>,
+    params: web::Json<CreateChannelData>,
+) -> impl Responder {
+    let name = params.into_inner().name;
+
+    let channels = match radio_manager_client.list_channels().await {
+        Ok(channels) => channels,
+        Err(error) => {
+            error!(?error, "Unable to list channels");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Some(channel) = channels.into_iter().find(|channel| channel.name == name) {
+        return HttpResponse::Ok().json(serde_json::json!({
+            "channelId": channel.id,
+        }));
+    }
+
+    let channel_id = match radio_manager_client.create_channel(&name).await {
+        Ok(channel_id) => channel_id,
+        Err(error) => {
+            error!(?error, "Unable to create channel");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    info!(%channel_id, "Created channel \"{}\"", name);
+
+    HttpResponse::Created().json(serde_json::json!({
+        "channelId": channel_id,
+    }))
+}
@@ -0,0 +1,48 @@
+use crate::services::Reloadable;
+use actix_web::{web, HttpResponse, Responder};
+use search_providers::{get_search_result_priority, RuTrackerClient};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::error;
+
+#[derive(Deserialize)]
+pub(crate) struct DebugSearchQuery {
+    q: String,
+}
+
+/// Returns the search results in the order they are tried by the track request processor,
+/// along with their priorities, to tell whether parsing or ranking is at fault.
+pub(crate) async fn debug_search(
+    query: web::Query<DebugSearchQuery>,
+    rutracker_client: web::Data<Arc<Reloadable<RuTrackerClient>>>,
+) -> impl Responder {
+    let rutracker_client = rutracker_client.get();
+    let results = match rutracker_client.search_music(&query.q).await {
+        Ok(results) => results,
+        Err(error) => {
+            error!(?error, "Unable to search for \"{}\"", query.q);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    HttpResponse::Ok().json(
+        results
+            .iter()
+            .map(|result| {
+                serde_json::json!({
+                    "title": result.title,
+                    "topicId": *result.topic_id,
+                    "downloadId": *result.download_id,
+                    "seedsNumber": result.seeds_number,
+                    "sizeBytes": result.size_bytes,
+                    "category": result.category,
+                    "year": result.year,
+                    "priority": get_search_result_priority(
+                        result,
+                        rutracker_client.keyword_weights(),
+                    ),
+                })
+            })
+            .collect::<Vec<_>>(),
+    )
+}
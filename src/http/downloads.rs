@@ -0,0 +1,33 @@
+use crate::services::TrackRequestProcessor;
+use crate::types::UserId;
+use actix_web::{web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub(crate) async fn pause_downloads(
+    track_request_processor: web::Data<Arc<TrackRequestProcessor>>,
+) -> impl Responder {
+    let user_id = UserId(1); // Not used yet
+
+    match track_request_processor.pause_downloads(&user_id).await {
+        Ok(count) => HttpResponse::Ok().json(serde_json::json!({ "pausedTorrents": count })),
+        Err(error) => {
+            error!(?error, "Unable to pause downloads");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub(crate) async fn resume_downloads(
+    track_request_processor: web::Data<Arc<TrackRequestProcessor>>,
+) -> impl Responder {
+    let user_id = UserId(1); // Not used yet
+
+    match track_request_processor.resume_downloads(&user_id).await {
+        Ok(count) => HttpResponse::Ok().json(serde_json::json!({ "resumedTorrents": count })),
+        Err(error) => {
+            error!(?error, "Unable to resume downloads");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -1,26 +1,70 @@
-use crate::services::{RadioManagerClient, TransmissionClient};
+use crate::services::{
+    RadioManagerClient, Reloadable, RuTrackerSessionMonitor, TransmissionClient,
+};
 use actix_web::web::Data;
 use actix_web::{HttpResponse, Responder};
+use futures_lite::future;
 use search_providers::RuTrackerClient;
 use std::sync::Arc;
 use tracing::error;
 
+pub(crate) async fn liveness_check() -> impl Responder {
+    HttpResponse::Ok().finish()
+}
+
 pub(crate) async fn readiness_check(
     transmission_client: Data<Arc<TransmissionClient>>,
     radio_manager_client: Data<Arc<RadioManagerClient>>,
-    rutracker_client: Data<Arc<RuTrackerClient>>,
+    rutracker_client: Data<Arc<Reloadable<RuTrackerClient>>>,
+    rutracker_session_monitor: Data<Arc<RuTrackerSessionMonitor>>,
 ) -> impl Responder {
-    if let Err(error) = transmission_client.check_connection().await {
-        error!(?error, "Readiness check failed");
+    let (transmission_result, (radio_manager_result, rutracker_result)) = future::zip(
+        transmission_client.session_check(),
+        future::zip(
+            radio_manager_client.check_connection(),
+            rutracker_client.get().check_connection(),
+        ),
+    )
+    .await;
+
+    if let Err(error) = &transmission_result {
+        error!(?error, "Transmission readiness check failed");
     }
 
-    if let Err(error) = radio_manager_client.check_connection().await {
-        error!(?error, "Readiness check failed");
+    if let Err(error) = &radio_manager_result {
+        error!(?error, "RadioManager readiness check failed");
     }
 
-    if let Err(error) = rutracker_client.check_connection().await {
-        error!(?error, "Readiness check failed");
+    if let Err(error) = &rutracker_result {
+        error!(?error, "RuTracker readiness check failed");
     }
 
-    HttpResponse::Ok().finish()
+    let transmission = transmission_result.is_ok();
+    let radio_manager = radio_manager_result.is_ok();
+    let rutracker = rutracker_result.is_ok();
+
+    let mut response = match transmission && radio_manager && rutracker {
+        true => HttpResponse::Ok(),
+        false => HttpResponse::ServiceUnavailable(),
+    };
+
+    response.json(serde_json::json!({
+        "transmission": transmission,
+        "radioManager": radio_manager,
+        "rutracker": rutracker,
+        "rutrackerSession": rutracker_session_monitor.last_check(),
+    }))
+}
+
+pub(crate) async fn metrics(
+    rutracker_session_monitor: Data<Arc<RuTrackerSessionMonitor>>,
+) -> impl Responder {
+    let metrics = rutracker_session_monitor
+        .last_check()
+        .map(|check| check.render_metrics())
+        .unwrap_or_default();
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics)
 }
@@ -1,7 +1,17 @@
+mod admin;
+mod channels;
+mod debug;
+mod downloads;
 mod health;
 mod track_request;
 
-pub(crate) use health::readiness_check;
+pub(crate) use admin::{reauth_rutracker, AdminToken};
+pub(crate) use channels::create_channel;
+pub(crate) use debug::debug_search;
+pub(crate) use downloads::{pause_downloads, resume_downloads};
+pub(crate) use health::{liveness_check, metrics, readiness_check};
 pub(crate) use track_request::{
-    get_track_request_statuses, make_track_request, make_tracks_suggestion,
+    cancel_all_track_requests, get_not_found_requests, get_request_provenance, get_request_stats,
+    get_track_request_statuses, list_track_requests, make_search_request, make_track_request,
+    make_tracks_suggestion, make_upload_request, retry_track_request,
 };
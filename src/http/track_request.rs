@@ -1,30 +1,130 @@
 use crate::services::track_request_processor::{
-    AudioMetadata, CreateRequestOptions, RadioManagerChannelId, TrackRequestController,
+    AudioFormat, AudioMetadata, CreateRequestOptions, RadioManagerChannelId, RequestFilter,
+    RequestId, RequestPriority, RequestStatusFilter, RequestsPage, RetryRequestError, TitleMatch,
+    TopicId, TrackRequestController, TrackRequestControllerError,
+};
+use crate::services::{
+    ChannelAllowlist, OpenAIService, RadioManagerClient, RateLimiter, TrackRequestProcessor,
 };
-use crate::services::{OpenAIService, RadioManagerClient, TrackRequestProcessor};
 use crate::types::UserId;
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use serde::Deserialize;
 use std::sync::Arc;
+use std::time::Duration;
 use tracing::{error, info};
+use uuid::Uuid;
+
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+const TOTAL_COUNT_HEADER: &str = "X-Total-Count";
+
+fn too_many_requests(retry_after: Duration) -> HttpResponse {
+    // Rounded up, so the client doesn't come back too early.
+    let retry_after_secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+    HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", retry_after_secs.to_string()))
+        .finish()
+}
+
+fn default_pre_download_match() -> TitleMatch {
+    TitleMatch::Loose
+}
+
+fn default_post_download_match() -> TitleMatch {
+    TitleMatch::Tags
+}
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct MakeTrackRequestData {
     #[serde(flatten)]
     metadata: AudioMetadata,
-    target_channel_id: RadioManagerChannelId,
+    /// Channel to add the track to. The track is only uploaded to the library if missing.
+    #[serde(default)]
+    target_channel_id: Option<RadioManagerChannelId>,
+    /// RuTracker topic to download the track from, instead of searching for it.
+    #[serde(default)]
+    topic_id: Option<TopicId>,
+    /// Searches for the artist and album as exact phrases, for names made of common words.
+    #[serde(default)]
+    exact_phrase_search: bool,
+    /// Match of the files in the torrent file list, deciding what to download.
+    #[serde(default = "default_pre_download_match")]
+    pre_download_match: TitleMatch,
+    /// Match of the downloaded files, deciding which one to upload.
+    #[serde(default = "default_post_download_match")]
+    post_download_match: TitleMatch,
+    /// Releases in these formats are tried first, e.g. `["flac", "alac"]`.
+    #[serde(default)]
+    preferred_formats: Vec<AudioFormat>,
+    /// Releases of this year are tried first.
+    #[serde(default)]
+    preferred_year: Option<u16>,
+    /// Earlier releases are tried before later ones, e.g. the original before remasters.
+    #[serde(default)]
+    prefer_original: bool,
+}
+
+impl MakeTrackRequestData {
+    /// Request without an artist or a title can't be found and would only churn until
+    /// it's marked as not found. Album may be unknown.
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.metadata.artist.trim().is_empty() {
+            return Err("Artist must not be empty");
+        }
+        if self.metadata.title.trim().is_empty() {
+            return Err("Title must not be empty");
+        }
+
+        Ok(())
+    }
 }
 
 pub(crate) async fn make_track_request(
     track_request_controller: web::Data<Arc<TrackRequestController>>,
+    rate_limiter: web::Data<Arc<RateLimiter>>,
+    channel_allowlist: web::Data<Arc<ChannelAllowlist>>,
     params: web::Json<MakeTrackRequestData>,
+    req: HttpRequest,
 ) -> impl Responder {
     let query = params.into_inner();
     let user_id = UserId(1); // Not used yet
 
+    if let Err(message) = query.validate() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": message,
+        }));
+    }
+    if let Some(channel_id) = &query.target_channel_id {
+        if !channel_allowlist.is_allowed(&user_id, channel_id) {
+            return HttpResponse::Forbidden().finish();
+        }
+    }
+    if let Err(retry_after) = rate_limiter.try_acquire(&user_id) {
+        return too_many_requests(retry_after);
+    }
+    let idempotency_key = req
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok());
+
     let request_id = match track_request_controller
-        .create_request(&user_id, &query.metadata, &query.target_channel_id)
+        .create_request(
+            &user_id,
+            &query.metadata,
+            &CreateRequestOptions {
+                pre_download_match: query.pre_download_match,
+                post_download_match: query.post_download_match,
+                preferred_formats: query.preferred_formats,
+                preferred_year: query.preferred_year,
+                prefer_original: query.prefer_original,
+                topic_id: query.topic_id,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: query.exact_phrase_search,
+            },
+            query.target_channel_id.as_ref(),
+            idempotency_key,
+        )
         .await
     {
         Err(error) => {
@@ -39,55 +139,275 @@ pub(crate) async fn make_track_request(
     }))
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MakeUploadRequestData {
+    path_to_file: String,
+    #[serde(default)]
+    target_channel_id: Option<RadioManagerChannelId>,
+}
+
+pub(crate) async fn make_upload_request(
+    track_request_controller: web::Data<Arc<TrackRequestController>>,
+    rate_limiter: web::Data<Arc<RateLimiter>>,
+    channel_allowlist: web::Data<Arc<ChannelAllowlist>>,
+    params: web::Json<MakeUploadRequestData>,
+) -> impl Responder {
+    let query = params.into_inner();
+    let user_id = UserId(1); // Not used yet
+
+    if let Some(channel_id) = &query.target_channel_id {
+        if !channel_allowlist.is_allowed(&user_id, channel_id) {
+            return HttpResponse::Forbidden().finish();
+        }
+    }
+    if let Err(retry_after) = rate_limiter.try_acquire(&user_id) {
+        return too_many_requests(retry_after);
+    }
+
+    let request_id = match track_request_controller
+        .create_upload_request(
+            &user_id,
+            &query.path_to_file,
+            query.target_channel_id.as_ref(),
+        )
+        .await
+    {
+        Err(error @ TrackRequestControllerError::UploadPathNotAllowed) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error.to_string(),
+            }));
+        }
+        Err(error) => {
+            error!(?error, "Unable to create upload request");
+            return HttpResponse::InternalServerError().finish();
+        }
+        Ok(request_id) => request_id,
+    };
+
+    HttpResponse::Accepted().json(serde_json::json!({
+        "requestId": request_id,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MakeSearchRequestData {
+    /// Free-text search, e.g. "robert miles children".
+    query: String,
+    #[serde(default)]
+    target_channel_id: Option<RadioManagerChannelId>,
+}
+
+pub(crate) async fn make_search_request(
+    track_request_controller: web::Data<Arc<TrackRequestController>>,
+    rate_limiter: web::Data<Arc<RateLimiter>>,
+    channel_allowlist: web::Data<Arc<ChannelAllowlist>>,
+    params: web::Json<MakeSearchRequestData>,
+) -> impl Responder {
+    let query = params.into_inner();
+    let user_id = UserId(1); // Not used yet
+
+    if query.query.trim().is_empty() {
+        return HttpResponse::BadRequest().finish();
+    }
+    if let Some(channel_id) = &query.target_channel_id {
+        if !channel_allowlist.is_allowed(&user_id, channel_id) {
+            return HttpResponse::Forbidden().finish();
+        }
+    }
+    if let Err(retry_after) = rate_limiter.try_acquire(&user_id) {
+        return too_many_requests(retry_after);
+    }
+
+    let request_id = match track_request_controller
+        .create_search_request(
+            &user_id,
+            query.query.trim(),
+            query.target_channel_id.as_ref(),
+        )
+        .await
+    {
+        Err(error) => {
+            error!(?error, "Unable to create search request");
+            return HttpResponse::InternalServerError().finish();
+        }
+        Ok(request_id) => request_id,
+    };
+
+    HttpResponse::Accepted().json(serde_json::json!({
+        "requestId": request_id,
+    }))
+}
+
+pub(crate) async fn retry_track_request(
+    track_request_controller: web::Data<Arc<TrackRequestController>>,
+    request_id: web::Path<Uuid>,
+) -> impl Responder {
+    let request_id = RequestId(request_id.into_inner());
+    let user_id = UserId(1); // Not used yet
+
+    match track_request_controller
+        .retry_request(&user_id, &request_id)
+        .await
+    {
+        Ok(()) => (),
+        Err(TrackRequestControllerError::RetryRequestError(RetryRequestError::RequestNotFound)) => {
+            return HttpResponse::NotFound().finish();
+        }
+        Err(TrackRequestControllerError::RetryRequestError(RetryRequestError::NotFailed)) => {
+            return HttpResponse::Conflict().finish();
+        }
+        Err(error) => {
+            error!(?error, "Unable to retry track request");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    HttpResponse::Accepted().json(serde_json::json!({
+        "requestId": request_id,
+    }))
+}
+
+pub(crate) async fn get_request_provenance(
+    track_request_processor: web::Data<Arc<TrackRequestProcessor>>,
+    request_id: web::Path<Uuid>,
+) -> impl Responder {
+    let request_id = RequestId(request_id.into_inner());
+    let user_id = UserId(1); // Not used yet
+
+    match track_request_processor
+        .get_provenance(&user_id, &request_id)
+        .await
+    {
+        Ok(Some(record)) => HttpResponse::Ok().json(record),
+        // Only finished requests have the provenance.
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(error) => {
+            error!(?error, "Unable to get track request provenance");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub(crate) async fn cancel_all_track_requests(
+    track_request_controller: web::Data<Arc<TrackRequestController>>,
+) -> impl Responder {
+    let user_id = UserId(1); // Not used yet
+
+    match track_request_controller.cancel_all_requests(&user_id).await {
+        Ok(count) => HttpResponse::Ok().json(serde_json::json!({ "cancelledRequests": count })),
+        Err(error) => {
+            error!(?error, "Unable to cancel track requests");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub(crate) struct MakeTracksSuggestionData {
     target_channel_id: RadioManagerChannelId,
+    /// Free-text mood or genre wishes, e.g. "more ambient, less vocal".
+    #[serde(default)]
+    hint: Option<String>,
 }
 
 pub(crate) async fn make_tracks_suggestion(
     track_request_controller: web::Data<Arc<TrackRequestController>>,
     openai_service: web::Data<Arc<OpenAIService>>,
     radio_manager_client: web::Data<Arc<RadioManagerClient>>,
+    rate_limiter: web::Data<Arc<RateLimiter>>,
+    channel_allowlist: web::Data<Arc<ChannelAllowlist>>,
     params: web::Json<MakeTracksSuggestionData>,
 ) -> impl Responder {
     let query = params.into_inner();
     let user_id = UserId(1); // Not used yet
 
-    let tracks = radio_manager_client
+    if !channel_allowlist.is_allowed(&user_id, &query.target_channel_id) {
+        return HttpResponse::Forbidden().finish();
+    }
+    if let Err(retry_after) = rate_limiter.try_acquire(&user_id) {
+        return too_many_requests(retry_after);
+    }
+
+    let tracks: Vec<_> = match radio_manager_client
         .get_channel_tracks(&query.target_channel_id)
         .await
-        .expect("Unable to get channel tracks")
-        .into_iter()
-        .map(|t| AudioMetadata {
-            title: t.title,
-            artist: t.artist,
-            album: t.album,
-        })
-        .collect();
-
-    let suggested_tracks = openai_service
-        .get_audio_tracks_suggestion(&tracks)
+    {
+        Ok(tracks) => tracks
+            .into_iter()
+            .map(|t| AudioMetadata {
+                title: t.title,
+                artist: t.artist,
+                album: t.album,
+            })
+            .collect(),
+        Err(error) => {
+            error!(?error, "Unable to get channel tracks");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut suggested_tracks = match openai_service
+        .get_audio_tracks_suggestion(&tracks, query.hint.as_deref())
         .await
-        .expect("Unable to get suggestions");
+    {
+        Ok(suggested_tracks) => suggested_tracks,
+        Err(error) => {
+            error!(?error, "Unable to get suggestions");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
 
     info!("Suggested tracks are: {:?}", suggested_tracks);
 
+    let suggested_count = suggested_tracks.len();
+    suggested_tracks.retain(|suggested| !tracks.iter().any(|t| t.is_same_track(suggested)));
+
+    let filtered_count = suggested_count - suggested_tracks.len();
+    if filtered_count > 0 {
+        info!(
+            filtered_count,
+            "Filtered out suggested tracks already present on the channel"
+        );
+    }
+
+    // A track that couldn't be requested shouldn't fail the rest of the batch,
+    // so it's reported back to the caller alongside the created requests.
     let mut request_ids = vec![];
+    let mut failed_tracks = vec![];
     for track in suggested_tracks {
-        let request_id = match track_request_controller
-            .create_request(&user_id, &track, &query.target_channel_id)
+        match track_request_controller
+            .create_request(
+                &user_id,
+                &track,
+                &CreateRequestOptions {
+                    pre_download_match: TitleMatch::Loose,
+                    post_download_match: TitleMatch::Tags,
+                    preferred_formats: vec![],
+                    preferred_year: None,
+                    prefer_original: false,
+                    topic_id: None,
+                    priority: RequestPriority::Suggestion,
+                    exact_phrase_search: false,
+                },
+                Some(&query.target_channel_id),
+                None,
+            )
             .await
         {
-            Ok(request_id) => request_id,
+            Ok(request_id) => request_ids.push(request_id),
             Err(error) => {
-                error!(?error, "Unable to create track request");
-                return HttpResponse::InternalServerError().finish();
+                error!(?error, ?track, "Unable to create track request");
+                failed_tracks.push(track);
             }
-        };
-        request_ids.push(request_id);
+        }
     }
 
-    HttpResponse::Accepted().json(serde_json::json!({ "requestIds": request_ids }))
+    HttpResponse::Accepted().json(serde_json::json!({
+        "requestIds": request_ids,
+        "failedTracks": failed_tracks,
+    }))
 }
 
 pub(crate) async fn get_track_request_statuses(
@@ -95,10 +415,7 @@ pub(crate) async fn get_track_request_statuses(
 ) -> impl Responder {
     let user_id = UserId(1); // Not used yet
 
-    let statuses = match track_request_processor
-        .get_processing_requests(&user_id)
-        .await
-    {
+    let statuses = match track_request_processor.get_status_reports(&user_id).await {
         Ok(statuses) => statuses,
         Err(error) => {
             error!(?error, "Unable to get track processing statuses");
@@ -108,3 +425,131 @@ pub(crate) async fn get_track_request_statuses(
 
     HttpResponse::Ok().json(statuses)
 }
+
+fn default_requests_limit() -> usize {
+    50
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ListTrackRequestsQuery {
+    status: Option<RequestStatusFilter>,
+    #[serde(default = "default_requests_limit")]
+    limit: usize,
+    #[serde(default)]
+    offset: usize,
+}
+
+pub(crate) async fn list_track_requests(
+    track_request_processor: web::Data<Arc<TrackRequestProcessor>>,
+    query: web::Query<ListTrackRequestsQuery>,
+) -> impl Responder {
+    let user_id = UserId(1); // Not used yet
+    let filter = RequestFilter {
+        status: query.status,
+    };
+    let page = RequestsPage {
+        limit: query.limit,
+        offset: query.offset,
+    };
+
+    let (requests, total) = match track_request_processor
+        .list_requests(&user_id, &filter, &page)
+        .await
+    {
+        Ok(requests) => requests,
+        Err(error) => {
+            error!(?error, "Unable to list track requests");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    HttpResponse::Ok()
+        .insert_header((TOTAL_COUNT_HEADER, total.to_string()))
+        .json(requests)
+}
+
+pub(crate) async fn get_request_stats(
+    track_request_processor: web::Data<Arc<TrackRequestProcessor>>,
+) -> impl Responder {
+    let user_id = UserId(1); // Not used yet
+
+    let stats = match track_request_processor.get_request_stats(&user_id).await {
+        Ok(stats) => stats,
+        Err(error) => {
+            error!(?error, "Unable to get track request stats");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    HttpResponse::Ok().json(stats)
+}
+
+pub(crate) async fn get_not_found_requests(
+    track_request_processor: web::Data<Arc<TrackRequestProcessor>>,
+) -> impl Responder {
+    let user_id = UserId(1); // Not used yet
+
+    let records = match track_request_processor
+        .get_not_found_requests(&user_id)
+        .await
+    {
+        Ok(records) => records,
+        Err(error) => {
+            error!(?error, "Unable to get not found track requests");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    HttpResponse::Ok().json(
+        records
+            .into_iter()
+            .map(|(request_id, record)| {
+                serde_json::json!({
+                    "requestId": request_id,
+                    "metadata": record.metadata,
+                    "targetChannelId": record.target_channel_id,
+                    "createdAt": record.created_at,
+                    "searchAttempts": record.search_attempts,
+                })
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_track_request_data(body: serde_json::Value) -> MakeTrackRequestData {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn test_rejecting_track_request_without_artist_or_title() {
+        let without_artist = make_track_request_data(serde_json::json!({
+            "title": "Children",
+            "artist": "  ",
+            "album": "Dreamland",
+        }));
+        let without_title = make_track_request_data(serde_json::json!({
+            "title": "",
+            "artist": "Robert Miles",
+            "album": "Dreamland",
+        }));
+
+        assert_eq!(Err("Artist must not be empty"), without_artist.validate());
+        assert_eq!(Err("Title must not be empty"), without_title.validate());
+    }
+
+    #[test]
+    fn test_accepting_track_request_without_album() {
+        let request = make_track_request_data(serde_json::json!({
+            "title": "Children",
+            "artist": "Robert Miles",
+            "album": "",
+            "targetChannelId": 1,
+        }));
+
+        assert_eq!(Ok(()), request.validate());
+    }
+}
@@ -1,16 +1,30 @@
 use crate::services::track_request_processor::{
-    DownloadId, RadioManagerChannelId, RadioManagerChannelTrack, RadioManagerClientError,
+    AudioFormat, AudioMetadata, DownloadId, DownloadedTorrentRecord, IdempotencyRecord,
+    MetadataServiceError, MetadataServiceTrait, NotFoundRecord, ProvenanceRecord,
+    RadioManagerChannelId, RadioManagerChannelTrack, RadioManagerClientError,
     RadioManagerClientTrait, RadioManagerLinkId, RadioManagerTrackId, RequestId,
-    SearchProviderError, SearchProviderTrait, StateStorageError, StateStorageTrait, TopicData,
-    TopicId, Torrent, TorrentClientError, TorrentClientTrait, TorrentId, TorrentStatus,
-    TrackRequestProcessingContext, TrackRequestProcessingState, TrackRequestProcessingStatus,
+    SearchProviderError, SearchProviderTrait, SeedingTorrentRecord, StateStorageError,
+    StateStorageTrait, TopicData, TopicId, Torrent, TorrentClientError, TorrentClientTrait,
+    TorrentId, TorrentStatus, TrackRequestProcessingContext, TrackRequestProcessingState,
+    TrackRequestProcessingStatus, UnreachableError, UnrecoverableError,
 };
-use crate::services::{radio_manager_client, RadioManagerClient, TransmissionClient};
+use crate::services::{
+    radio_manager_client, CombinedSearchProvider, FallbackSearchProvider, MetadataService,
+    RadioManagerClient, Reloadable, TransmissionClient, TransmissionClientError,
+    REQUEST_LABEL_PREFIX,
+};
+use crate::storage::keys;
 use crate::storage::on_disk::OnDiskStorage;
 use crate::types::UserId;
+use crate::utils::eq_ignore_case;
 use async_trait::async_trait;
-use search_providers::RuTrackerClient;
-use std::collections::HashMap;
+use futures_util::future::join_all;
+use futures_util::FutureExt;
+use reqwest::StatusCode;
+use search_providers::{RuTrackerClient, RuTrackerClientError};
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
+use tracing::{info, warn};
 use uuid::Uuid;
 
 #[async_trait]
@@ -21,10 +35,14 @@ impl StateStorageTrait for OnDiskStorage {
         request_id: &RequestId,
         state: TrackRequestProcessingState,
     ) -> Result<(), StateStorageError> {
-        let prefix = format!("{}-state", user_id);
+        let prefix = keys::state_prefix(user_id);
         let key = format!("{}", request_id);
         let state_str = serde_json::to_string(&state).expect("Unable to serialize state");
 
+        // Indexed before the state is saved, so the index never misses a request.
+        self.add_to_set(keys::TASKS_INDEX, &keys::tasks_index_entry(user_id, &key))
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?;
         self.save(&prefix, &key, &state_str)
             .await
             .map_err(|error| StateStorageError(Box::new(error)))?;
@@ -38,7 +56,7 @@ impl StateStorageTrait for OnDiskStorage {
         request_id: &RequestId,
         ctx: TrackRequestProcessingContext,
     ) -> Result<(), StateStorageError> {
-        let prefix = format!("{}-ctx", user_id);
+        let prefix = keys::context_prefix(user_id);
         let key = format!("{}", request_id);
         let state_str = serde_json::to_string(&ctx).expect("Unable to serialize context");
 
@@ -55,7 +73,7 @@ impl StateStorageTrait for OnDiskStorage {
         request_id: &RequestId,
         state: &TrackRequestProcessingState,
     ) -> Result<(), StateStorageError> {
-        let prefix = format!("{}-state", user_id);
+        let prefix = keys::state_prefix(user_id);
         let key = format!("{}", request_id);
         let state_str = serde_json::to_string(&state).expect("Unable to serialize state");
 
@@ -66,13 +84,30 @@ impl StateStorageTrait for OnDiskStorage {
         Ok(())
     }
 
+    async fn update_context(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        ctx: &TrackRequestProcessingContext,
+    ) -> Result<(), StateStorageError> {
+        let prefix = keys::context_prefix(user_id);
+        let key = format!("{}", request_id);
+        let ctx_str = serde_json::to_string(ctx).expect("Unable to serialize context");
+
+        self.save(&prefix, &key, &ctx_str)
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?;
+
+        Ok(())
+    }
+
     async fn update_status(
         &self,
         user_id: &UserId,
         request_id: &RequestId,
         state: &TrackRequestProcessingStatus,
     ) -> Result<(), StateStorageError> {
-        let prefix = format!("{}-status", user_id);
+        let prefix = keys::status_prefix(user_id);
         let key = format!("{}", request_id);
         let state_str = serde_json::to_string(&state).expect("Unable to serialize status");
 
@@ -88,7 +123,7 @@ impl StateStorageTrait for OnDiskStorage {
         user_id: &UserId,
         request_id: &RequestId,
     ) -> Result<TrackRequestProcessingState, StateStorageError> {
-        let prefix = format!("{}-state", user_id);
+        let prefix = keys::state_prefix(user_id);
         let key = format!("{}", request_id);
         let value = match self
             .get(&prefix, &key)
@@ -107,7 +142,7 @@ impl StateStorageTrait for OnDiskStorage {
         user_id: &UserId,
         request_id: &RequestId,
     ) -> Result<TrackRequestProcessingContext, StateStorageError> {
-        let prefix = format!("{}-ctx", user_id);
+        let prefix = keys::context_prefix(user_id);
         let key = format!("{}", request_id);
         let value = match self
             .get(&prefix, &key)
@@ -126,12 +161,15 @@ impl StateStorageTrait for OnDiskStorage {
         user_id: &UserId,
         request_id: &RequestId,
     ) -> Result<(), StateStorageError> {
-        let prefix = format!("{}-state", user_id);
+        let prefix = keys::state_prefix(user_id);
         let key = format!("{}", request_id);
 
         self.delete(&prefix, &key)
             .await
             .map_err(|error| StateStorageError(Box::new(error)))?;
+        self.remove_from_set(keys::TASKS_INDEX, &keys::tasks_index_entry(user_id, &key))
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?;
 
         Ok(())
     }
@@ -141,7 +179,7 @@ impl StateStorageTrait for OnDiskStorage {
         user_id: &UserId,
         request_id: &RequestId,
     ) -> Result<(), StateStorageError> {
-        let prefix = format!("{}-ctx", user_id);
+        let prefix = keys::context_prefix(user_id);
         let key = format!("{}", request_id);
 
         self.delete(&prefix, &key)
@@ -156,7 +194,7 @@ impl StateStorageTrait for OnDiskStorage {
         user_id: &UserId,
         request_id: &RequestId,
     ) -> Result<(), StateStorageError> {
-        let prefix = format!("{}-status", user_id);
+        let prefix = keys::status_prefix(user_id);
         let key = format!("{}", request_id);
 
         self.delete(&prefix, &key)
@@ -170,7 +208,7 @@ impl StateStorageTrait for OnDiskStorage {
         &self,
         user_id: &UserId,
     ) -> Result<HashMap<RequestId, TrackRequestProcessingStatus>, StateStorageError> {
-        let prefix = format!("{}-status", user_id);
+        let prefix = keys::status_prefix(user_id);
         let values = self
             .get_all(&prefix)
             .await
@@ -192,54 +230,357 @@ impl StateStorageTrait for OnDiskStorage {
         Ok(results)
     }
 
-    async fn get_all_tasks(&self) -> Result<Vec<(UserId, RequestId)>, StateStorageError> {
+    async fn get_all_contexts(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<RequestId, TrackRequestProcessingContext>, StateStorageError> {
+        let prefix = keys::context_prefix(user_id);
+        let values = self
+            .get_all(&prefix)
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?;
+
+        let mut results = HashMap::new();
+
+        for (key, value) in values {
+            let request_id = RequestId(
+                key.parse::<Uuid>()
+                    .map_err(|error| StateStorageError(Box::new(error)))?,
+            );
+            let ctx =
+                serde_json::from_str(&value).map_err(|error| StateStorageError(Box::new(error)))?;
+
+            results.insert(request_id, ctx);
+        }
+
+        Ok(results)
+    }
+
+    async fn get_status_owners(&self) -> Result<Vec<UserId>, StateStorageError> {
         let prefixes = self
             .get_prefixes()
             .await
-            .map_err(|error| StateStorageError(Box::new(error)))?
-            .into_iter()
-            .filter(|prefix| prefix.ends_with("-ctx"))
-            .collect::<Vec<_>>();
+            .map_err(|error| StateStorageError(Box::new(error)))?;
+
+        Ok(prefixes
+            .iter()
+            .filter_map(|prefix| keys::parse_status_prefix(prefix))
+            .collect())
+    }
+
+    async fn get_all_tasks(&self) -> Result<Vec<(UserId, RequestId)>, StateStorageError> {
+        let indexed_entries = self
+            .get_set(keys::TASKS_INDEX)
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?;
+        let entries = match indexed_entries {
+            Some(entries) => entries,
+            None => {
+                info!("Tasks index is missing, scanning request states...");
+                let entries = scan_tasks_index_entries(self)
+                    .await
+                    .map_err(|error| StateStorageError(Box::new(error)))?;
+                self.save_set(keys::TASKS_INDEX, &entries)
+                    .await
+                    .map_err(|error| StateStorageError(Box::new(error)))?;
+
+                entries
+            }
+        };
 
         let mut tasks = vec![];
 
-        for prefix in prefixes {
-            let contexts = self
-                .get_all(&prefix)
+        for (user_id, request_id) in entries.iter().filter_map(|entry| {
+            let (user_id, request_id) = keys::parse_tasks_index_entry(entry)?;
+
+            Some((user_id, request_id.parse::<Uuid>().ok()?))
+        }) {
+            // The index might still list requests dropped while it was being updated.
+            let has_context = self
+                .get(&keys::context_prefix(&user_id), &request_id.to_string())
                 .await
-                .map_err(|error| StateStorageError(Box::new(error)))?;
+                .map_err(|error| StateStorageError(Box::new(error)))?
+                .is_some();
+            if !has_context {
+                continue;
+            }
 
-            let user_id = match prefix.replace("-ctx", "").parse::<u64>() {
-                Ok(user_id) => user_id,
-                Err(_) => {
-                    continue;
-                }
-            };
-
-            for request_id in contexts
-                .keys()
-                .cloned()
-                .filter_map(|request_id| request_id.parse::<Uuid>().ok())
-            {
-                let status = self
-                    .get(&format!("{}-status", user_id), &request_id.to_string())
-                    .await
-                    .map_err(|error| StateStorageError(Box::new(error)))?
-                    .and_then(|status| {
-                        serde_json::from_str::<TrackRequestProcessingStatus>(&status).ok()
-                    });
-
-                match status {
-                    Some(TrackRequestProcessingStatus::Processing) | None => {
-                        tasks.push((UserId(user_id), RequestId(request_id)));
-                    }
-                    _ => (),
+            let status = self
+                .get(&keys::status_prefix(&user_id), &request_id.to_string())
+                .await
+                .map_err(|error| StateStorageError(Box::new(error)))?
+                .and_then(|status| {
+                    serde_json::from_str::<TrackRequestProcessingStatus>(&status).ok()
+                });
+
+            match status {
+                Some(TrackRequestProcessingStatus::Processing) | None => {
+                    tasks.push((user_id.clone(), RequestId(request_id)));
                 }
+                _ => (),
             }
         }
 
         Ok(tasks)
     }
+
+    async fn get_idempotency(
+        &self,
+        user_id: &UserId,
+        idempotency_key: &str,
+    ) -> Result<Option<IdempotencyRecord>, StateStorageError> {
+        let prefix = keys::idempotency_prefix(user_id);
+        let key = hash_idempotency_key(idempotency_key);
+        let value = self
+            .get(&prefix, &key)
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?
+            .map(|value| serde_json::from_str(&value).expect("Unable to deserialize record"));
+
+        Ok(value)
+    }
+
+    async fn set_idempotency(
+        &self,
+        user_id: &UserId,
+        idempotency_key: &str,
+        record: &IdempotencyRecord,
+    ) -> Result<(), StateStorageError> {
+        let prefix = keys::idempotency_prefix(user_id);
+        let key = hash_idempotency_key(idempotency_key);
+        let record_str = serde_json::to_string(record).expect("Unable to serialize record");
+
+        self.save(&prefix, &key, &record_str)
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?;
+
+        Ok(())
+    }
+
+    async fn add_not_found(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        record: &NotFoundRecord,
+    ) -> Result<(), StateStorageError> {
+        let prefix = keys::not_found_prefix(user_id);
+        let key = format!("{}", request_id);
+        let record_str = serde_json::to_string(record).expect("Unable to serialize record");
+
+        self.save(&prefix, &key, &record_str)
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?;
+
+        Ok(())
+    }
+
+    async fn delete_not_found(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<(), StateStorageError> {
+        let prefix = keys::not_found_prefix(user_id);
+        let key = format!("{}", request_id);
+
+        match self.delete(&prefix, &key).await {
+            Ok(()) => Ok(()),
+            // Only requests that have been not found have the record.
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(StateStorageError(Box::new(error))),
+        }
+    }
+
+    async fn get_downloaded_torrent(
+        &self,
+        info_hash: &str,
+    ) -> Result<Option<DownloadedTorrentRecord>, StateStorageError> {
+        let value = self
+            .get(keys::DOWNLOADED_TORRENTS_PREFIX, info_hash)
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?
+            .map(|value| serde_json::from_str(&value).expect("Unable to deserialize record"));
+
+        Ok(value)
+    }
+
+    async fn set_downloaded_torrent(
+        &self,
+        info_hash: &str,
+        record: &DownloadedTorrentRecord,
+    ) -> Result<(), StateStorageError> {
+        let record_str = serde_json::to_string(record).expect("Unable to serialize record");
+
+        self.save(keys::DOWNLOADED_TORRENTS_PREFIX, info_hash, &record_str)
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?;
+
+        Ok(())
+    }
+
+    async fn add_seeding_torrent(
+        &self,
+        torrent_id: &TorrentId,
+        record: &SeedingTorrentRecord,
+    ) -> Result<(), StateStorageError> {
+        let key = format!("{}", torrent_id);
+        let record_str = serde_json::to_string(record).expect("Unable to serialize record");
+
+        self.save(keys::SEEDING_TORRENTS_PREFIX, &key, &record_str)
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?;
+
+        Ok(())
+    }
+
+    async fn get_all_seeding_torrents(
+        &self,
+    ) -> Result<HashMap<TorrentId, SeedingTorrentRecord>, StateStorageError> {
+        let values = self
+            .get_all(keys::SEEDING_TORRENTS_PREFIX)
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?;
+
+        let mut results = HashMap::new();
+
+        for (key, value) in values {
+            let torrent_id = TorrentId(
+                key.parse::<i64>()
+                    .map_err(|error| StateStorageError(Box::new(error)))?,
+            );
+            let record =
+                serde_json::from_str(&value).map_err(|error| StateStorageError(Box::new(error)))?;
+
+            results.insert(torrent_id, record);
+        }
+
+        Ok(results)
+    }
+
+    async fn delete_seeding_torrent(
+        &self,
+        torrent_id: &TorrentId,
+    ) -> Result<(), StateStorageError> {
+        let key = format!("{}", torrent_id);
+
+        match self.delete(keys::SEEDING_TORRENTS_PREFIX, &key).await {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(StateStorageError(Box::new(error))),
+        }
+    }
+
+    async fn get_all_not_found(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<RequestId, NotFoundRecord>, StateStorageError> {
+        let prefix = keys::not_found_prefix(user_id);
+        let values = self
+            .get_all(&prefix)
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?;
+
+        let mut results = HashMap::new();
+
+        for (key, value) in values {
+            let request_id = RequestId(
+                key.parse::<Uuid>()
+                    .map_err(|error| StateStorageError(Box::new(error)))?,
+            );
+            let record =
+                serde_json::from_str(&value).map_err(|error| StateStorageError(Box::new(error)))?;
+
+            results.insert(request_id, record);
+        }
+
+        Ok(results)
+    }
+
+    async fn set_provenance(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        record: &ProvenanceRecord,
+    ) -> Result<(), StateStorageError> {
+        let prefix = keys::provenance_prefix(user_id);
+        let key = format!("{}", request_id);
+        let record_str = serde_json::to_string(record).expect("Unable to serialize record");
+
+        self.save(&prefix, &key, &record_str)
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?;
+
+        Ok(())
+    }
+
+    async fn get_provenance(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<Option<ProvenanceRecord>, StateStorageError> {
+        let prefix = keys::provenance_prefix(user_id);
+        let key = format!("{}", request_id);
+        let value = self
+            .get(&prefix, &key)
+            .await
+            .map_err(|error| StateStorageError(Box::new(error)))?
+            .map(|value| serde_json::from_str(&value).expect("Unable to deserialize record"));
+
+        Ok(value)
+    }
+}
+
+// Idempotency keys come from clients, so they are hashed to be safe to use as file names.
+fn hash_idempotency_key(idempotency_key: &str) -> String {
+    Sha1::digest(idempotency_key)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn into_torrent_status(torrent: &transmission_rpc::types::Torrent) -> TorrentStatus {
+    use transmission_rpc::types::{ErrorType, TorrentStatus as TransmissionStatus};
+
+    // Tracker warnings don't stop the download, unlike tracker and local errors.
+    if let Some(ErrorType::TrackerError | ErrorType::LocalError) = torrent.error {
+        return TorrentStatus::Error(torrent.error_string.clone().unwrap_or_default());
+    }
+
+    let is_done = torrent
+        .percent_done
+        .is_some_and(|percent_done| percent_done >= 1.0);
+
+    match torrent.status {
+        Some(TransmissionStatus::Seeding | TransmissionStatus::QueuedToSeed) => {
+            TorrentStatus::Complete
+        }
+        // Transmission stops torrents that have reached the seed ratio limit.
+        Some(TransmissionStatus::Stopped) if is_done => TorrentStatus::Complete,
+        Some(TransmissionStatus::Stopped) => TorrentStatus::Paused,
+        Some(
+            TransmissionStatus::QueuedToVerify
+            | TransmissionStatus::Verifying
+            | TransmissionStatus::QueuedToDownload
+            | TransmissionStatus::Downloading,
+        )
+        | None => TorrentStatus::Downloading,
+    }
+}
+
+/// Entries of the tasks index for all the request states in the storage.
+async fn scan_tasks_index_entries(
+    storage: &OnDiskStorage,
+) -> Result<HashSet<String>, std::io::Error> {
+    let mut entries = HashSet::new();
+
+    for prefix in storage.get_prefixes().await? {
+        if let Some(user_id) = keys::parse_state_prefix(&prefix) {
+            for key in storage.get_keys(&prefix).await? {
+                entries.insert(keys::tasks_index_entry(&user_id, &key));
+            }
+        }
+    }
+
+    Ok(entries)
 }
 
 #[async_trait]
@@ -248,36 +589,59 @@ impl TorrentClientTrait for TransmissionClient {
         &self,
         torrent_file_data: Vec<u8>,
         selected_files_indexes: Vec<i32>,
+        request_id: &RequestId,
+        download_dir: Option<&str>,
     ) -> Result<TorrentId, TorrentClientError> {
+        let label = format!("{}{}", REQUEST_LABEL_PREFIX, request_id);
         let torrent_id = self
-            .add(torrent_file_data)
+            .add_or_reuse(
+                torrent_file_data,
+                &selected_files_indexes,
+                &label,
+                download_dir,
+            )
             .await
             .map_err(|err| TorrentClientError(Box::from(err)))?;
-        self.select_files(&torrent_id, &selected_files_indexes)
+
+        let speed_limits = self.speed_limits();
+        if !speed_limits.is_unlimited() {
+            self.set_speed_limit(
+                &torrent_id,
+                speed_limits.download_kbps,
+                speed_limits.upload_kbps,
+            )
             .await
             .map_err(|err| TorrentClientError(Box::from(err)))?;
+        }
 
         Ok(TorrentId(torrent_id))
     }
 
-    async fn get_torrent(&self, torrent_id: &TorrentId) -> Result<Torrent, TorrentClientError> {
-        let torrent = self
-            .get(torrent_id)
-            .await
-            .map_err(|err| TorrentClientError(Box::from(err)))?;
+    async fn get_torrent(
+        &self,
+        torrent_id: &TorrentId,
+    ) -> Result<Option<Torrent>, TorrentClientError> {
+        let torrent = match self.get(torrent_id).await {
+            Ok(torrent) => torrent,
+            Err(TransmissionClientError::NotFound) => return Ok(None),
+            Err(err) => return Err(TorrentClientError(Box::from(err))),
+        };
 
-        Ok(Torrent {
-            status: match torrent.status {
-                Some(transmission_rpc::types::TorrentStatus::Seeding) => TorrentStatus::Complete,
-                _ => TorrentStatus::Downloading,
-            },
+        let status = into_torrent_status(&torrent);
+        // Reused torrents might have been downloaded into another directory.
+        let download_dir = torrent
+            .download_dir
+            .unwrap_or_else(|| self.download_dir().to_string());
+
+        Ok(Some(Torrent {
+            status,
             files: torrent
                 .files
                 .unwrap_or_default()
                 .into_iter()
-                .map(|f| f.name)
+                .map(|f| format!("{}/{}", download_dir.trim_end_matches('/'), f.name))
                 .collect(),
-        })
+        }))
     }
 
     async fn delete_torrent(&self, torrent_id: &TorrentId) -> Result<(), TorrentClientError> {
@@ -287,6 +651,59 @@ impl TorrentClientTrait for TransmissionClient {
 
         Ok(())
     }
+
+    async fn get_request_torrents(
+        &self,
+    ) -> Result<Vec<(TorrentId, Vec<RequestId>)>, TorrentClientError> {
+        let torrents = self
+            .get_by_label(&format!("{}*", REQUEST_LABEL_PREFIX))
+            .await
+            .map_err(|err| TorrentClientError(Box::from(err)))?;
+
+        Ok(torrents
+            .into_iter()
+            .filter_map(|torrent| {
+                let request_ids = torrent
+                    .labels
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|label| label.strip_prefix(REQUEST_LABEL_PREFIX))
+                    .filter_map(|request_id| request_id.parse::<Uuid>().ok())
+                    .map(RequestId)
+                    .collect();
+
+                Some((TorrentId(torrent.id?), request_ids))
+            })
+            .collect())
+    }
+
+    async fn pause_torrents(&self, torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError> {
+        let torrent_ids: Vec<_> = torrent_ids.iter().map(|id| **id).collect();
+
+        self.pause_all(&torrent_ids)
+            .await
+            .map_err(|err| TorrentClientError(Box::from(err)))?;
+
+        Ok(())
+    }
+
+    async fn resume_torrents(&self, torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError> {
+        let torrent_ids: Vec<_> = torrent_ids.iter().map(|id| **id).collect();
+
+        self.resume_all(&torrent_ids)
+            .await
+            .map_err(|err| TorrentClientError(Box::from(err)))?;
+
+        Ok(())
+    }
+
+    async fn check_session(&self) -> Result<(), TorrentClientError> {
+        self.session_check()
+            .await
+            .map_err(|err| TorrentClientError(Box::from(err)))?;
+
+        Ok(())
+    }
 }
 
 impl Into<TopicData> for search_providers::TopicData {
@@ -295,6 +712,19 @@ impl Into<TopicData> for search_providers::TopicData {
             title: self.title,
             download_id: DownloadId(*self.download_id),
             topic_id: TopicId(*self.topic_id),
+            format: self.format.map(Into::into),
+            year: self.year,
+        }
+    }
+}
+
+impl From<search_providers::AudioFormat> for AudioFormat {
+    fn from(format: search_providers::AudioFormat) -> Self {
+        match format {
+            search_providers::AudioFormat::Flac => AudioFormat::Flac,
+            search_providers::AudioFormat::Mp3 => AudioFormat::Mp3,
+            search_providers::AudioFormat::Alac => AudioFormat::Alac,
+            search_providers::AudioFormat::Aac => AudioFormat::Aac,
         }
     }
 }
@@ -305,7 +735,17 @@ impl SearchProviderTrait for RuTrackerClient {
         self.search_music(query)
             .await
             .map(|results| results.into_iter().map(Into::into).collect())
-            .map_err(|error| SearchProviderError(Box::new(error)))
+            .map_err(into_search_provider_error)
+    }
+
+    async fn resolve_topic(
+        &self,
+        topic_id: &TopicId,
+    ) -> Result<Option<TopicData>, SearchProviderError> {
+        self.get_topic(**topic_id)
+            .await
+            .map(|topic| topic.map(Into::into))
+            .map_err(into_search_provider_error)
     }
 
     async fn download_torrent(
@@ -314,13 +754,235 @@ impl SearchProviderTrait for RuTrackerClient {
     ) -> Result<Vec<u8>, SearchProviderError> {
         RuTrackerClient::download_torrent(&self, **download_id)
             .await
-            .map_err(|error| SearchProviderError(Box::new(error)))
+            .map_err(into_search_provider_error)
+    }
+}
+
+// The provider is taken once per call, so a replaced provider is used starting from the next call.
+#[async_trait]
+impl<T> SearchProviderTrait for Reloadable<T>
+where
+    T: SearchProviderTrait + Send + Sync,
+{
+    async fn find_all(&self, query: &str) -> Result<Vec<TopicData>, SearchProviderError> {
+        self.get().find_all(query).await
+    }
+
+    async fn resolve_topic(
+        &self,
+        topic_id: &TopicId,
+    ) -> Result<Option<TopicData>, SearchProviderError> {
+        self.get().resolve_topic(topic_id).await
+    }
+
+    async fn download_torrent(
+        &self,
+        download_id: &DownloadId,
+    ) -> Result<Vec<u8>, SearchProviderError> {
+        self.get().download_torrent(download_id).await
+    }
+}
+
+#[async_trait]
+impl SearchProviderTrait for CombinedSearchProvider {
+    async fn find_all(&self, query: &str) -> Result<Vec<TopicData>, SearchProviderError> {
+        // Errors aren't Send, so they are only kept as failures until every provider is done.
+        let results = join_all(self.providers().iter().enumerate().map(
+            |(provider_index, provider)| {
+                provider.find_all(query).map(move |result| {
+                    result.map_err(|error| {
+                        warn!(%error, provider_index, "Search provider failed, skipping it");
+                        SearchFailure::from(error)
+                    })
+                })
+            },
+        ))
+        .await;
+
+        let mut found_results = vec![];
+        let mut first_failure = None;
+
+        for result in results {
+            match result {
+                Ok(topics) => found_results.push(topics),
+                Err(failure) => {
+                    first_failure.get_or_insert(failure);
+                }
+            }
+        }
+
+        match first_failure {
+            Some(failure) if found_results.is_empty() => Err(failure.into()),
+            _ => Ok(merge_ranked_results(found_results)),
+        }
+    }
+
+    async fn resolve_topic(
+        &self,
+        topic_id: &TopicId,
+    ) -> Result<Option<TopicData>, SearchProviderError> {
+        let providers_count = self.providers().len();
+
+        for (provider_index, provider) in self.providers().iter().enumerate() {
+            match provider.resolve_topic(topic_id).await {
+                Ok(Some(topic)) => return Ok(Some(topic)),
+                Ok(None) => (),
+                Err(error) if provider_index + 1 < providers_count => {
+                    warn!(%error, provider_index, "Search provider failed, skipping it");
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn download_torrent(
+        &self,
+        download_id: &DownloadId,
+    ) -> Result<Vec<u8>, SearchProviderError> {
+        let providers_count = self.providers().len();
+
+        for (provider_index, provider) in self.providers().iter().enumerate() {
+            match provider.download_torrent(download_id).await {
+                Ok(torrent_file_data) => return Ok(torrent_file_data),
+                Err(error) if provider_index + 1 < providers_count => {
+                    warn!(%error, provider_index, "Search provider failed, skipping it");
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        Err(SearchProviderError(Box::new(std::io::Error::from(
+            std::io::ErrorKind::NotFound,
+        ))))
+    }
+}
+
+#[async_trait]
+impl SearchProviderTrait for FallbackSearchProvider {
+    async fn find_all(&self, query: &str) -> Result<Vec<TopicData>, SearchProviderError> {
+        // Errors aren't Send, so only the message is kept while the fallback one is used.
+        let error = match self.primary().find_all(query).await {
+            Err(error) if error.is_unreachable() => error.to_string(),
+            result => return result,
+        };
+
+        warn!(
+            error,
+            "Search provider is unreachable, searching the fallback one"
+        );
+        self.fallback().find_all(query).await
+    }
+
+    async fn resolve_topic(
+        &self,
+        topic_id: &TopicId,
+    ) -> Result<Option<TopicData>, SearchProviderError> {
+        // Errors aren't Send, so only the message is kept while the fallback one is used.
+        let error = match self.primary().resolve_topic(topic_id).await {
+            Err(error) if error.is_unreachable() => error.to_string(),
+            result => return result,
+        };
+
+        warn!(
+            error,
+            "Search provider is unreachable, using the fallback one"
+        );
+        self.fallback().resolve_topic(topic_id).await
+    }
+
+    async fn download_torrent(
+        &self,
+        download_id: &DownloadId,
+    ) -> Result<Vec<u8>, SearchProviderError> {
+        // Errors aren't Send, so only the message is kept while the fallback one is used.
+        let error = match self.primary().download_torrent(download_id).await {
+            Err(error) if error.is_unreachable() => error.to_string(),
+            result => return result,
+        };
+
+        warn!(
+            error,
+            "Search provider is unreachable, using the fallback one"
+        );
+        self.fallback().download_torrent(download_id).await
+    }
+}
+
+struct SearchFailure {
+    message: String,
+    is_unrecoverable: bool,
+}
+
+impl From<SearchProviderError> for SearchFailure {
+    fn from(error: SearchProviderError) -> Self {
+        Self {
+            message: error.to_string(),
+            is_unrecoverable: error.0.is::<UnrecoverableError>(),
+        }
+    }
+}
+
+impl From<SearchFailure> for SearchProviderError {
+    fn from(failure: SearchFailure) -> Self {
+        let error = Box::new(std::io::Error::other(failure.message));
+
+        match failure.is_unrecoverable {
+            true => SearchProviderError(Box::new(UnrecoverableError(error))),
+            false => SearchProviderError(error),
+        }
+    }
+}
+
+// Every provider ranks its own results, so the best results of each of them go first.
+fn merge_ranked_results(results: Vec<Vec<TopicData>>) -> Vec<TopicData> {
+    let mut iterators: Vec<_> = results.into_iter().map(Vec::into_iter).collect();
+    let mut merged = vec![];
+
+    loop {
+        let before = merged.len();
+        merged.extend(iterators.iter_mut().filter_map(Iterator::next));
+        if merged.len() == before {
+            return merged;
+        }
+    }
+}
+
+fn into_search_provider_error(error: RuTrackerClientError) -> SearchProviderError {
+    // Rejected credentials and a gone tracker won't be fixed by retrying the request.
+    let is_unrecoverable = match &error {
+        RuTrackerClientError::AuthError(_) => true,
+        RuTrackerClientError::BadStatus(status) => matches!(
+            *status,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::GONE
+        ),
+        _ => false,
+    };
+
+    if is_unrecoverable {
+        SearchProviderError(Box::new(UnrecoverableError(Box::new(error))))
+    } else if error.is_connectivity_error() {
+        SearchProviderError(Box::new(UnreachableError(Box::new(error))))
+    } else {
+        SearchProviderError(Box::new(error))
+    }
+}
+
+fn into_radio_manager_error(
+    error: radio_manager_client::RadioManagerClientError,
+) -> RadioManagerClientError {
+    if error.is_unrecoverable() {
+        RadioManagerClientError(Box::new(UnrecoverableError(Box::new(error))))
+    } else {
+        RadioManagerClientError(Box::new(error))
     }
 }
 
 impl Into<RadioManagerChannelTrack> for radio_manager_client::RadioManagerChannelTrack {
     fn into(self) -> RadioManagerChannelTrack {
         RadioManagerChannelTrack {
+            track_id: self.id.map(RadioManagerTrackId),
             title: self.title,
             album: self.album,
             artist: self.artist,
@@ -334,11 +996,12 @@ impl RadioManagerClientTrait for RadioManagerClient {
         &self,
         _user_id: &UserId,
         path_to_audio_file: &str,
+        file_name: Option<&str>,
     ) -> Result<RadioManagerTrackId, RadioManagerClientError> {
         let track_id = self
-            .upload_track(path_to_audio_file)
+            .upload_track(path_to_audio_file, file_name)
             .await
-            .map_err(|error| RadioManagerClientError(Box::new(error)))?;
+            .map_err(into_radio_manager_error)?;
 
         Ok(track_id)
     }
@@ -352,7 +1015,7 @@ impl RadioManagerClientTrait for RadioManagerClient {
         let link_id = self
             .add_track_to_channel(track_id, channel_id)
             .await
-            .map_err(|error| RadioManagerClientError(Box::new(error)))?;
+            .map_err(into_radio_manager_error)?;
 
         Ok(link_id)
     }
@@ -363,8 +1026,413 @@ impl RadioManagerClientTrait for RadioManagerClient {
     ) -> Result<Vec<RadioManagerChannelTrack>, RadioManagerClientError> {
         let tracks = RadioManagerClient::get_channel_tracks(self, channel_id)
             .await
-            .map_err(|error| RadioManagerClientError(Box::new(error)))?;
+            .map_err(into_radio_manager_error)?;
 
         Ok(tracks.into_iter().map(Into::into).collect())
     }
+
+    async fn find_track(
+        &self,
+        _user_id: &UserId,
+        metadata: &AudioMetadata,
+    ) -> Result<Option<RadioManagerTrackId>, RadioManagerClientError> {
+        let tracks = self.get_tracks().await.map_err(into_radio_manager_error)?;
+
+        Ok(tracks
+            .into_iter()
+            .find(|track| {
+                eq_ignore_case(&track.artist, &metadata.artist)
+                    && eq_ignore_case(&track.title, &metadata.title)
+                    && metadata.matches_album(&track.album)
+            })
+            .map(|track| RadioManagerTrackId(track.id)))
+    }
+}
+
+#[async_trait]
+impl MetadataServiceTrait for MetadataService {
+    async fn get_audio_metadata(
+        &self,
+        path_to_audio_file: &str,
+    ) -> Result<Option<AudioMetadata>, MetadataServiceError> {
+        self.read_metadata(path_to_audio_file)
+            .await
+            .map_err(|error| MetadataServiceError(Box::new(error)))
+    }
+
+    async fn set_audio_metadata(
+        &self,
+        path_to_audio_file: &str,
+        metadata: &AudioMetadata,
+    ) -> Result<bool, MetadataServiceError> {
+        self.write_metadata(path_to_audio_file, metadata)
+            .await
+            .map_err(|error| MetadataServiceError(Box::new(error)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::track_request_processor::{
+        CreateRequestOptions, ProcessRequestError, RequestPriority, TitleMatch,
+    };
+    use async_lock::Barrier;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Finds the given topics, or fails if there are none, once all the other providers
+    /// sharing the barrier have started searching.
+    struct SearchProviderMock {
+        topic_ids: Option<Vec<u64>>,
+        delay: Duration,
+        barrier: Option<Arc<Barrier>>,
+    }
+
+    #[async_trait]
+    impl SearchProviderTrait for SearchProviderMock {
+        async fn find_all(&self, _query: &str) -> Result<Vec<TopicData>, SearchProviderError> {
+            if let Some(barrier) = &self.barrier {
+                barrier.wait().await;
+            }
+            actix_rt::time::sleep(self.delay).await;
+
+            match &self.topic_ids {
+                Some(topic_ids) => Ok(topic_ids
+                    .iter()
+                    .map(|topic_id| TopicData {
+                        topic_id: TopicId(*topic_id),
+                        download_id: DownloadId(*topic_id),
+                        title: format!("Topic {}", topic_id),
+                        format: None,
+                        year: None,
+                    })
+                    .collect()),
+                None => Err(SearchProviderError(Box::new(UnrecoverableError(Box::new(
+                    std::io::Error::from(std::io::ErrorKind::PermissionDenied),
+                ))))),
+            }
+        }
+
+        async fn resolve_topic(
+            &self,
+            _topic_id: &TopicId,
+        ) -> Result<Option<TopicData>, SearchProviderError> {
+            Ok(None)
+        }
+
+        async fn download_torrent(
+            &self,
+            _download_id: &DownloadId,
+        ) -> Result<Vec<u8>, SearchProviderError> {
+            Err(SearchProviderError(Box::new(std::io::Error::from(
+                std::io::ErrorKind::NotFound,
+            ))))
+        }
+    }
+
+    fn search_provider(
+        topic_ids: Option<Vec<u64>>,
+        delay: Duration,
+        barrier: Option<Arc<Barrier>>,
+    ) -> Arc<dyn SearchProviderTrait + Send + Sync + 'static> {
+        Arc::new(SearchProviderMock {
+            topic_ids,
+            delay,
+            barrier,
+        })
+    }
+
+    fn topic_ids(topics: &[TopicData]) -> Vec<u64> {
+        topics.iter().map(|topic| *topic.topic_id).collect()
+    }
+
+    #[actix_rt::test]
+    async fn test_searching_all_providers_concurrently() {
+        // Neither provider returns before both have started searching.
+        let barrier = Arc::new(Barrier::new(2));
+        let provider = CombinedSearchProvider::new(vec![
+            search_provider(Some(vec![1, 2, 3]), Duration::ZERO, Some(barrier.clone())),
+            search_provider(Some(vec![4]), Duration::ZERO, Some(barrier)),
+        ]);
+
+        let topics = actix_rt::time::timeout(Duration::from_secs(5), provider.find_all("query"))
+            .await
+            .expect("Providers should be searched concurrently")
+            .unwrap();
+
+        assert_eq!(vec![1, 4, 2, 3], topic_ids(&topics));
+    }
+
+    #[actix_rt::test]
+    async fn test_skipping_failed_search_provider() {
+        let provider = CombinedSearchProvider::new(vec![
+            search_provider(None, Duration::from_millis(50), None),
+            search_provider(Some(vec![1, 2]), Duration::ZERO, None),
+        ]);
+
+        let topics = provider.find_all("query").await.unwrap();
+
+        assert_eq!(vec![1, 2], topic_ids(&topics));
+    }
+
+    /// Fails to connect, or finds the given topics.
+    struct ReachableSearchProviderMock(Option<Vec<u64>>);
+
+    #[async_trait]
+    impl SearchProviderTrait for ReachableSearchProviderMock {
+        async fn find_all(&self, query: &str) -> Result<Vec<TopicData>, SearchProviderError> {
+            match &self.0 {
+                Some(topic_ids) => {
+                    search_provider(Some(topic_ids.clone()), Duration::ZERO, None)
+                        .find_all(query)
+                        .await
+                }
+                None => Err(SearchProviderError(Box::new(UnreachableError(Box::new(
+                    std::io::Error::from(std::io::ErrorKind::ConnectionRefused),
+                ))))),
+            }
+        }
+
+        async fn resolve_topic(
+            &self,
+            _topic_id: &TopicId,
+        ) -> Result<Option<TopicData>, SearchProviderError> {
+            Ok(None)
+        }
+
+        async fn download_torrent(
+            &self,
+            _download_id: &DownloadId,
+        ) -> Result<Vec<u8>, SearchProviderError> {
+            Ok(vec![])
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_searching_fallback_provider_only_if_primary_is_unreachable() {
+        let fallback = Arc::new(ReachableSearchProviderMock(Some(vec![2])));
+
+        let unreachable = FallbackSearchProvider::new(
+            Arc::new(ReachableSearchProviderMock(None)),
+            fallback.clone(),
+        );
+        let topics = unreachable.find_all("query").await.unwrap();
+        assert_eq!(vec![2], topic_ids(&topics));
+
+        let empty = FallbackSearchProvider::new(
+            Arc::new(ReachableSearchProviderMock(Some(vec![]))),
+            fallback.clone(),
+        );
+        let topics = empty.find_all("query").await.unwrap();
+        assert!(topics.is_empty());
+
+        let failing =
+            FallbackSearchProvider::new(search_provider(None, Duration::ZERO, None), fallback);
+        let error = failing.find_all("query").await.unwrap_err();
+        assert!(error.0.is::<UnrecoverableError>());
+    }
+
+    #[actix_rt::test]
+    async fn test_failing_search_if_all_providers_failed() {
+        let provider = CombinedSearchProvider::new(vec![
+            search_provider(None, Duration::from_millis(50), None),
+            search_provider(None, Duration::ZERO, None),
+        ]);
+
+        let error = provider.find_all("query").await.unwrap_err();
+
+        assert!(error.0.is::<UnrecoverableError>());
+    }
+
+    fn create_transmission_torrent(
+        status: u8,
+        percent_done: f32,
+        error: u8,
+    ) -> transmission_rpc::types::Torrent {
+        serde_json::from_value(serde_json::json!({
+            "status": status,
+            "percentDone": percent_done,
+            "error": error,
+            "errorString": if error > 0 { "No space left on device" } else { "" },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_mapping_transmission_torrent_status() {
+        let status = |status, percent_done, error| {
+            into_torrent_status(&create_transmission_torrent(status, percent_done, error))
+        };
+
+        // Stopped, either by the user or on reaching the seed ratio limit.
+        assert_eq!(TorrentStatus::Paused, status(0, 0.5, 0));
+        assert_eq!(TorrentStatus::Complete, status(0, 1.0, 0));
+        // Queued to verify, verifying, queued to download and downloading.
+        for transmission_status in 1..=4 {
+            assert_eq!(
+                TorrentStatus::Downloading,
+                status(transmission_status, 0.5, 0)
+            );
+        }
+        // Queued to seed and seeding.
+        assert_eq!(TorrentStatus::Complete, status(5, 1.0, 0));
+        assert_eq!(TorrentStatus::Complete, status(6, 1.0, 0));
+        // Tracker warning doesn't stop the download, while tracker and local errors do.
+        assert_eq!(TorrentStatus::Downloading, status(4, 0.5, 1));
+        for error in 2..=3 {
+            assert_eq!(
+                TorrentStatus::Error("No space left on device".into()),
+                status(4, 0.5, error)
+            );
+        }
+    }
+
+    #[test]
+    fn test_failing_request_on_missing_channel() {
+        let error = |error| ProcessRequestError::from(into_radio_manager_error(error));
+
+        assert!(
+            error(radio_manager_client::RadioManagerClientError::ChannelNotFound)
+                .is_unrecoverable()
+        );
+        assert!(
+            error(radio_manager_client::RadioManagerClientError::SessionExpired).is_transient()
+        );
+    }
+
+    fn create_storage() -> OnDiskStorage {
+        let path = std::env::temp_dir().join(format!("channel-bot-{}", Uuid::new_v4()));
+
+        OnDiskStorage::create(path.to_string_lossy().to_string())
+    }
+
+    #[actix_rt::test]
+    async fn test_deleting_state_keeps_context() {
+        let storage = create_storage();
+        let user_id = UserId(1);
+        let request_id = RequestId(Uuid::new_v4());
+        let ctx = TrackRequestProcessingContext::new(
+            AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(RadioManagerChannelId(1)),
+        );
+
+        storage
+            .create_context(&user_id, &request_id, ctx)
+            .await
+            .unwrap();
+        storage
+            .create_state(
+                &user_id,
+                &request_id,
+                TrackRequestProcessingState::default(),
+            )
+            .await
+            .unwrap();
+
+        storage.delete_state(&user_id, &request_id).await.unwrap();
+
+        assert!(storage.load_state(&user_id, &request_id).await.is_err());
+        assert!(storage.load_context(&user_id, &request_id).await.is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn test_finding_tasks_by_index() {
+        let storage = create_storage();
+        let user_id = UserId(1);
+        let (first_id, second_id) = (RequestId(Uuid::new_v4()), RequestId(Uuid::new_v4()));
+        let create_request = |request_id: RequestId| {
+            let storage = &storage;
+            let user_id = &user_id;
+
+            async move {
+                let ctx = TrackRequestProcessingContext::new(
+                    AudioMetadata::default(),
+                    CreateRequestOptions {
+                        pre_download_match: TitleMatch::Loose,
+                        post_download_match: TitleMatch::Contains,
+                        preferred_formats: vec![],
+                        preferred_year: None,
+                        prefer_original: false,
+                        topic_id: None,
+                        priority: RequestPriority::Manual,
+                        exact_phrase_search: false,
+                    },
+                    Some(RadioManagerChannelId(1)),
+                );
+                storage
+                    .create_context(user_id, &request_id, ctx)
+                    .await
+                    .unwrap();
+                storage
+                    .create_state(user_id, &request_id, TrackRequestProcessingState::default())
+                    .await
+                    .unwrap();
+            }
+        };
+
+        // The index is built by scanning the states the first time the tasks are read.
+        create_request(first_id.clone()).await;
+        assert_eq!(
+            vec![(user_id.clone(), first_id.clone())],
+            storage.get_all_tasks().await.unwrap()
+        );
+        assert!(storage.get_set(keys::TASKS_INDEX).await.unwrap().is_some());
+
+        create_request(second_id.clone()).await;
+        storage.delete_state(&user_id, &first_id).await.unwrap();
+        storage
+            .update_status(&user_id, &first_id, &TrackRequestProcessingStatus::Finished)
+            .await
+            .unwrap();
+        assert_eq!(
+            Some(HashSet::from([keys::tasks_index_entry(
+                &user_id,
+                &second_id.to_string()
+            )])),
+            storage.get_set(keys::TASKS_INDEX).await.unwrap()
+        );
+        assert_eq!(
+            vec![(user_id.clone(), second_id.clone())],
+            storage.get_all_tasks().await.unwrap()
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_storing_downloaded_torrents() {
+        let storage = create_storage();
+        let info_hash = "2ec5340de73d63d6faa162a9dd435acb462d8737";
+        let record = DownloadedTorrentRecord {
+            files: vec!["/downloads/Ted Irens/01 - Sunday Breakfast.flac".into()],
+        };
+
+        assert_eq!(
+            None,
+            storage.get_downloaded_torrent(info_hash).await.unwrap()
+        );
+
+        storage
+            .set_downloaded_torrent(info_hash, &record)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Some(record),
+            storage.get_downloaded_torrent(info_hash).await.unwrap()
+        );
+    }
 }
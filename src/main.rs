@@ -1,15 +1,28 @@
-use crate::config::Config;
-use crate::services::track_request_processor::TrackRequestController;
+use crate::config::{Config, LogFormat};
+use crate::services::track_request_processor::{
+    PollIntervals, SearchProviderTrait, StepRetries, TrackRequestController,
+};
 use crate::services::{
-    OpenAIService, RadioManagerClient, TrackRequestProcessor, TransmissionClient,
+    ActivityTracker, CertificateVerification, ChannelAllowlist, CombinedSearchProvider,
+    DownloadDirectories, FallbackSearchProvider, HttpClientOptions, MetadataService, OpenAIService,
+    RadioManagerClient, RateLimiter, Reloadable, RuTrackerCredentials, RuTrackerLogin,
+    RuTrackerSessionMonitor, SpeedLimits, TrackRequestProcessor, TransmissionClient, UploadLimits,
 };
 use crate::storage::on_disk::OnDiskStorage;
 use actix_rt::signal::unix;
+use actix_web::dev::Service;
 use actix_web::web::Data;
 use actix_web::{web, App, HttpServer};
 use futures_lite::FutureExt;
+use reqwest::Proxy;
+use search_providers::{
+    CategoryFilter, KeywordWeights, RequestLimits, RuTrackerClient, RuTrackerClientError,
+};
+use std::path::PathBuf;
 use std::sync::Arc;
-use tracing::{debug, error, info};
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
 
 mod config;
 mod http;
@@ -21,16 +34,64 @@ mod utils;
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+fn is_health_check_path(path: &str) -> bool {
+    matches!(path, "/livez" | "/readyz" | "/metrics") || path.starts_with("/health/")
+}
+
+// Log level is still taken from `RUST_LOG`, as it was with `env_logger`.
+fn init_logging(log_format: LogFormat) {
+    let subscriber = tracing_subscriber::fmt().with_env_filter(EnvFilter::from_default_env());
+
+    match log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().flatten_event(true).init(),
+    }
+}
+
+const MAX_LOGIN_RETRY_DELAY: Duration = Duration::from_secs(60);
+const SEEDED_TORRENTS_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+// RuTracker might be briefly unreachable on startup, so the login is retried
+// with exponential backoff unless the credentials have been rejected.
+async fn create_rutracker_client(
+    config: &Config,
+    rutracker_login: &RuTrackerLogin,
+) -> Result<RuTrackerClient, RuTrackerClientError> {
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 1;
+
+    loop {
+        match rutracker_login
+            .login(&config.rutracker.username, &config.rutracker.password)
+            .await
+        {
+            Ok(client) => return Ok(client),
+            Err(error) if error.is_transient() && attempt < config.rutracker_login_attempts => {
+                warn!(
+                    ?error,
+                    attempt, "Unable to log in to RuTracker, retrying..."
+                );
+            }
+            Err(error) => return Err(error),
+        }
+
+        actix_rt::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_LOGIN_RETRY_DELAY);
+        attempt += 1;
+    }
+}
+
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
     let mut terminate = unix::signal(unix::SignalKind::terminate())?;
     let mut interrupt = unix::signal(unix::SignalKind::interrupt())?;
 
     dotenv::dotenv().ok();
-    env_logger::init();
 
     let config = Arc::from(Config::from_env());
 
+    init_logging(config.log_format);
+
     info!("Starting application...");
 
     debug!("Init state storage...");
@@ -38,15 +99,86 @@ async fn main() -> std::io::Result<()> {
         config.state_storage_directory.clone(),
     ));
 
+    let http_client_options = HttpClientOptions {
+        user_agent: config.http_user_agent.clone(),
+        timeout: Duration::from_secs(config.http_timeout),
+        connect_timeout: Duration::from_secs(config.http_connect_timeout),
+        http_proxy: config
+            .http_proxy
+            .as_ref()
+            .map(|url| Proxy::http(url).expect("Invalid HTTP proxy URL")),
+        https_proxy: config
+            .https_proxy
+            .as_ref()
+            .map(|url| Proxy::https(url).expect("Invalid HTTPS proxy URL")),
+    };
+
     debug!("Init rutracker client...");
-    let rutracker_client = Arc::from(
-        search_providers::RuTrackerClient::create(
-            &config.rutracker.username,
-            &config.rutracker.password,
-        )
-        .await
-        .expect("Unable to initialize RuTracker client"),
-    );
+    let rutracker_login = Arc::new(RuTrackerLogin {
+        http_client_options: http_client_options.clone(),
+        request_limits: RequestLimits {
+            max_concurrent_requests: config.rutracker_max_concurrent_requests,
+            min_delay: Duration::from_millis(config.rutracker_min_request_delay_ms),
+        },
+        category_filter: CategoryFilter {
+            include: config.rutracker_include_categories.clone(),
+            exclude: config.rutracker_exclude_categories.clone(),
+        },
+        keyword_weights: KeywordWeights {
+            trusted: config.rutracker_trusted_keywords.clone(),
+            avoided: config.rutracker_avoided_keywords.clone(),
+        },
+        html_dump_directory: config
+            .debug_dump_html
+            .then(|| PathBuf::from(&config.debug_dump_directory)),
+    });
+    let rutracker_client = Arc::new(Reloadable::new(
+        create_rutracker_client(&config, &rutracker_login)
+            .await
+            .expect("Unable to initialize RuTracker client"),
+    ));
+
+    let rutracker_credentials = Arc::new(Reloadable::new(RuTrackerCredentials {
+        username: config.rutracker.username.clone(),
+        password: config.rutracker.password.clone(),
+    }));
+
+    let rutracker_session_monitor = Arc::new(RuTrackerSessionMonitor::new(
+        rutracker_client.clone(),
+        rutracker_login.clone(),
+        rutracker_credentials.clone(),
+    ));
+    actix_rt::spawn({
+        let rutracker_session_monitor = rutracker_session_monitor.clone();
+        let check_interval = Duration::from_secs(config.rutracker_session_check_interval);
+
+        async move { rutracker_session_monitor.run(check_interval).await }
+    });
+
+    // Mirror unreachable on startup is skipped rather than keeping the bot from starting.
+    let rutracker_search_provider: Arc<dyn SearchProviderTrait + Send + Sync> =
+        match &config.rutracker_fallback_host {
+            Some(host) => match rutracker_login
+                .login_on_host(host, &config.rutracker.username, &config.rutracker.password)
+                .await
+            {
+                Ok(mirror_client) => Arc::new(FallbackSearchProvider::new(
+                    rutracker_client.clone(),
+                    Arc::new(mirror_client),
+                )),
+                Err(error) => {
+                    warn!(
+                        ?error,
+                        host, "Unable to log in to RuTracker mirror, skipping it"
+                    );
+                    rutracker_client.clone()
+                }
+            },
+            None => rutracker_client.clone(),
+        };
+
+    // RuTracker is the only search provider so far.
+    let search_provider = Arc::new(CombinedSearchProvider::new(vec![rutracker_search_provider]));
 
     debug!("Init transmission client...");
     let transmission_client = Arc::new(TransmissionClient::create(
@@ -54,6 +186,14 @@ async fn main() -> std::io::Result<()> {
         config.transmission.username.clone(),
         config.transmission.password.clone(),
         config.transmission.download_directory.clone(),
+        SpeedLimits {
+            download_kbps: config.max_download_kbps,
+            upload_kbps: config.max_upload_kbps,
+        },
+        match config.transmission.accept_invalid_certs {
+            true => CertificateVerification::AcceptInvalid,
+            false => CertificateVerification::Verify,
+        },
     ));
 
     debug!("Init radio manager client...");
@@ -62,22 +202,93 @@ async fn main() -> std::io::Result<()> {
             &config.radiomanager.endpoint,
             &config.radiomanager.username,
             &config.radiomanager.password,
+            &http_client_options,
         )
         .await
-        .expect("Unable to initialize RadioManager client"),
+        .expect("Unable to initialize RadioManager client")
+        .with_upload_limits(UploadLimits {
+            timeout: Duration::from_secs(config.upload_timeout),
+            max_file_size: config.max_upload_bytes,
+        }),
     );
 
+    debug!("Init metadata service...");
+    let metadata_service = Arc::new(MetadataService::create(&config.metadata_filename_patterns));
+
     debug!("Init track request processor...");
+    if config.normalize_tags_before_upload && config.library_directory.is_none() {
+        warn!("Tag normalization requires a library directory, leaving tags as is");
+    }
     let track_request_processor = {
-        Arc::new(TrackRequestProcessor::new(
-            state_storage.clone(),
-            rutracker_client.clone(),
-            transmission_client.clone(),
-            radio_manager_client.clone(),
-            config.download_directory.clone(),
-        ))
+        Arc::new(
+            TrackRequestProcessor::new(
+                state_storage.clone(),
+                search_provider.clone(),
+                transmission_client.clone(),
+                radio_manager_client.clone(),
+                metadata_service.clone(),
+                DownloadDirectories::new(
+                    &config.transmission.download_directory,
+                    &config.download_directory,
+                )
+                .with_path_template(config.download_path_template.clone())
+                .with_library_dir(config.library_directory.clone()),
+                config.max_download_bytes,
+            )
+            .with_poll_intervals(PollIntervals {
+                step: Duration::from_secs(config.step_poll_interval),
+                download: Duration::from_secs(config.download_poll_interval),
+            })
+            .with_step_retries(StepRetries {
+                max_retries: config.max_step_retries,
+                backoff: Duration::from_secs(config.step_retry_backoff),
+            })
+            .with_torrent_file_retries(StepRetries {
+                max_retries: config.max_torrent_file_retries,
+                backoff: Duration::from_secs(config.torrent_file_retry_backoff),
+            })
+            .with_max_dead_topics(config.max_dead_topics)
+            .with_seed_duration(config.seed_duration.map(Duration::from_secs))
+            .with_download_timeout(config.download_completion_timeout.map(Duration::from_secs))
+            .with_request_timeout(config.request_timeout.map(Duration::from_secs))
+            .with_tag_normalization(config.normalize_tags_before_upload)
+            .with_upload_naming(config.name_uploads_after_metadata)
+            .with_channel_add_verification(config.verify_channel_add)
+            .with_matching_file_preference(
+                config
+                    .matching_file_preference
+                    .parse()
+                    .expect("Invalid matching file preference"),
+            )
+            .with_max_active_downloads(config.max_active_downloads)
+            .with_max_concurrent_uploads(config.max_concurrent_uploads),
+        )
     };
 
+    // Also sweeps torrents left seeding under a previous configuration
+    // and the ones of requests deleted while the bot was down.
+    actix_rt::spawn({
+        let track_request_processor = track_request_processor.clone();
+
+        async move {
+            loop {
+                actix_rt::time::sleep(SEEDED_TORRENTS_SWEEP_INTERVAL).await;
+
+                match track_request_processor.remove_seeded_torrents().await {
+                    Ok(0) => (),
+                    Ok(removed) => info!(removed, "Removed torrents done seeding"),
+                    Err(error) => error!(?error, "Unable to remove torrents done seeding"),
+                }
+
+                match track_request_processor.remove_orphaned_torrents().await {
+                    Ok(0) => (),
+                    Ok(removed) => info!(removed, "Removed torrents of deleted requests"),
+                    Err(error) => error!(?error, "Unable to remove torrents of deleted requests"),
+                }
+            }
+        }
+    });
+
     debug!("Init track request controller...");
     let track_request_controller = Arc::new(
         TrackRequestController::create(state_storage.clone(), track_request_processor.clone())
@@ -86,27 +297,113 @@ async fn main() -> std::io::Result<()> {
     );
 
     debug!("Init OpenAI client...");
-    let openai_service = Arc::new(OpenAIService::create(config.openai_api_key.clone()));
+    let openai_service = Arc::new(OpenAIService::create(
+        config.openai_api_key.clone(),
+        &http_client_options,
+    ));
+
+    let rate_limiter = Arc::new(RateLimiter::new(config.max_requests_per_minute));
+
+    let channel_allowlist = Arc::new(
+        ChannelAllowlist::from_entries(&config.channel_allowlist)
+            .expect("Invalid channel allowlist"),
+    );
+
+    let activity_tracker = Arc::new(ActivityTracker::new());
 
     let shutdown_timeout = config.shutdown_timeout.clone();
     let bind_address = config.bind_address.clone();
+    let enable_debug_endpoints = config.enable_debug_endpoints;
+    let admin_token = config.admin_token.clone();
 
     debug!("Init http server...");
     let server = HttpServer::new({
+        let track_request_controller = track_request_controller.clone();
+        let activity_tracker = activity_tracker.clone();
+
         move || {
             App::new()
+                .wrap_fn({
+                    let activity_tracker = Arc::clone(&activity_tracker);
+
+                    move |req, srv| {
+                        // Probes of an orchestrator must not keep the bot from being idle.
+                        if !is_health_check_path(req.path()) {
+                            activity_tracker.touch();
+                        }
+                        srv.call(req)
+                    }
+                })
                 .app_data(Data::new(Arc::clone(&track_request_processor)))
                 .app_data(Data::new(Arc::clone(&track_request_controller)))
                 .app_data(Data::new(Arc::clone(&openai_service)))
                 .app_data(Data::new(Arc::clone(&radio_manager_client)))
                 .app_data(Data::new(Arc::clone(&transmission_client)))
                 .app_data(Data::new(Arc::clone(&rutracker_client)))
+                .app_data(Data::new(Arc::clone(&rate_limiter)))
+                .app_data(Data::new(Arc::clone(&channel_allowlist)))
+                .app_data(Data::new(Arc::clone(&rutracker_login)))
+                .app_data(Data::new(Arc::clone(&rutracker_credentials)))
+                .app_data(Data::new(Arc::clone(&rutracker_session_monitor)))
                 .service(web::resource("/").route(web::get().to(http::get_track_request_statuses)))
+                .service(web::resource("/requests").route(web::get().to(http::list_track_requests)))
+                .service(web::resource("/stats").route(web::get().to(http::get_request_stats)))
                 .service(web::resource("/create").route(web::post().to(http::make_track_request)))
+                .service(
+                    web::resource("/create/search")
+                        .route(web::post().to(http::make_search_request)),
+                )
+                .service(web::resource("/upload").route(web::post().to(http::make_upload_request)))
+                .service(
+                    web::resource("/retry/{requestId}")
+                        .route(web::post().to(http::retry_track_request)),
+                )
+                .service(
+                    web::resource("/provenance/{requestId}")
+                        .route(web::get().to(http::get_request_provenance)),
+                )
+                .service(
+                    web::resource("/cancel-all")
+                        .route(web::post().to(http::cancel_all_track_requests)),
+                )
+                .service(
+                    web::resource("/notfound").route(web::get().to(http::get_not_found_requests)),
+                )
+                .service(
+                    web::resource("/notfound/{requestId}/retry")
+                        .route(web::post().to(http::retry_track_request)),
+                )
                 .service(
                     web::resource("/suggest").route(web::post().to(http::make_tracks_suggestion)),
                 )
-                .route("/health/alive", web::get().to(http::readiness_check))
+                .service(web::resource("/channels").route(web::post().to(http::create_channel)))
+                .service(
+                    web::resource("/downloads/pause").route(web::post().to(http::pause_downloads)),
+                )
+                .service(
+                    web::resource("/downloads/resume")
+                        .route(web::post().to(http::resume_downloads)),
+                )
+                .configure(|cfg| {
+                    if enable_debug_endpoints {
+                        cfg.service(
+                            web::resource("/debug/search").route(web::get().to(http::debug_search)),
+                        );
+                    }
+                })
+                .configure(|cfg| {
+                    if let Some(admin_token) = &admin_token {
+                        cfg.app_data(Data::new(http::AdminToken(admin_token.clone())))
+                            .service(
+                                web::resource("/admin/rutracker/reauth")
+                                    .route(web::post().to(http::reauth_rutracker)),
+                            );
+                    }
+                })
+                .route("/livez", web::get().to(http::liveness_check))
+                .route("/readyz", web::get().to(http::readiness_check))
+                .route("/metrics", web::get().to(http::metrics))
+                .route("/health/alive", web::get().to(http::liveness_check))
                 .route("/health/ready", web::get().to(http::readiness_check))
         }
     })
@@ -126,9 +423,27 @@ async fn main() -> std::io::Result<()> {
 
     info!("Application started");
 
-    interrupt.recv().or(terminate.recv()).await;
+    let shutdown_signal = async {
+        interrupt.recv().or(terminate.recv()).await;
+        info!("Received shutdown signal");
+    };
+    let idle_shutdown = async {
+        match config.idle_shutdown_timeout.map(Duration::from_secs) {
+            Some(idle_timeout) => {
+                activity_tracker
+                    .wait_until_idle(idle_timeout, || {
+                        track_request_controller.running_tasks_count()
+                    })
+                    .await;
+                info!(?idle_timeout, "Application has been idle for too long");
+            }
+            None => std::future::pending().await,
+        }
+    };
+
+    shutdown_signal.or(idle_shutdown).await;
 
-    info!("Received shutdown signal. Shutting down gracefully...");
+    info!("Shutting down gracefully...");
 
     server_handle.stop(true).await;
 
@@ -0,0 +1,114 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often the idle state is checked while waiting for the application to become idle.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Remembers when the application has been used last, so it can shut down once idle
+/// and let an orchestrator scale it to zero.
+pub(crate) struct ActivityTracker {
+    last_activity: Mutex<Instant>,
+}
+
+impl ActivityTracker {
+    pub(crate) fn new() -> Self {
+        Self {
+            last_activity: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub(crate) fn touch(&self) {
+        self.touch_at(Instant::now())
+    }
+
+    fn touch_at(&self, now: Instant) {
+        let mut last_activity = self.last_activity.lock().unwrap();
+        *last_activity = (*last_activity).max(now);
+    }
+
+    /// Returns the time left until the application has been idle for `idle_timeout`,
+    /// or `None` if it already has.
+    fn idle_time_left_at(&self, idle_timeout: Duration, now: Instant) -> Option<Duration> {
+        let idle_time = now.saturating_duration_since(*self.last_activity.lock().unwrap());
+
+        idle_timeout
+            .checked_sub(idle_time)
+            .filter(|left| !left.is_zero())
+    }
+
+    /// Resolves once there has been no activity for `idle_timeout`. The application is
+    /// never idle while `in_flight` counts requests being processed.
+    pub(crate) async fn wait_until_idle(
+        &self,
+        idle_timeout: Duration,
+        in_flight: impl Fn() -> usize,
+    ) {
+        loop {
+            if in_flight() > 0 {
+                self.touch();
+            }
+
+            match self.idle_time_left_at(idle_timeout, Instant::now()) {
+                Some(left) => actix_rt::time::sleep(left.min(IDLE_CHECK_INTERVAL)).await,
+                None => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counting_idle_time_from_last_activity() {
+        let activity_tracker = ActivityTracker::new();
+        let idle_timeout = Duration::from_secs(60);
+        let now = Instant::now();
+
+        activity_tracker.touch_at(now);
+        assert_eq!(
+            Some(Duration::from_secs(20)),
+            activity_tracker.idle_time_left_at(idle_timeout, now + Duration::from_secs(40))
+        );
+        assert_eq!(
+            None,
+            activity_tracker.idle_time_left_at(idle_timeout, now + Duration::from_secs(60))
+        );
+
+        activity_tracker.touch_at(now + Duration::from_secs(50));
+        assert_eq!(
+            Some(Duration::from_secs(50)),
+            activity_tracker.idle_time_left_at(idle_timeout, now + Duration::from_secs(60))
+        );
+        // Activity reported late doesn't move the last activity back.
+        activity_tracker.touch_at(now);
+        assert_eq!(
+            Some(Duration::from_secs(50)),
+            activity_tracker.idle_time_left_at(idle_timeout, now + Duration::from_secs(60))
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_not_being_idle_with_requests_in_flight() {
+        let activity_tracker = ActivityTracker::new();
+        let in_flight = Mutex::new(1);
+
+        assert!(actix_rt::time::timeout(
+            Duration::from_millis(200),
+            activity_tracker
+                .wait_until_idle(Duration::from_millis(50), || *in_flight.lock().unwrap()),
+        )
+        .await
+        .is_err());
+
+        *in_flight.lock().unwrap() = 0;
+        assert!(actix_rt::time::timeout(
+            Duration::from_millis(200),
+            activity_tracker
+                .wait_until_idle(Duration::from_millis(50), || *in_flight.lock().unwrap()),
+        )
+        .await
+        .is_ok());
+    }
+}
@@ -0,0 +1,83 @@
+use crate::services::track_request_processor::RadioManagerChannelId;
+use crate::types::UserId;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid channel allowlist entry {0:?}, expected `user_id:channel_id`")]
+pub(crate) struct InvalidAllowlistEntry(String);
+
+/// RadioManager channels each user may add tracks to, so users of a shared instance
+/// can't add tracks to channels of each other. Any channel is allowed if empty.
+pub(crate) struct ChannelAllowlist {
+    channels: HashMap<UserId, HashSet<RadioManagerChannelId>>,
+}
+
+impl ChannelAllowlist {
+    /// Parses entries like `1:100`, meaning the user 1 may target the channel 100.
+    pub(crate) fn from_entries(entries: &[String]) -> Result<Self, InvalidAllowlistEntry> {
+        let mut channels: HashMap<_, HashSet<_>> = HashMap::new();
+
+        for entry in entries.iter().map(|entry| entry.trim()) {
+            if entry.is_empty() {
+                continue;
+            }
+
+            let (user_id, channel_id) = entry
+                .split_once(':')
+                .and_then(|(user_id, channel_id)| {
+                    Some((
+                        user_id.trim().parse().ok()?,
+                        channel_id.trim().parse().ok()?,
+                    ))
+                })
+                .ok_or_else(|| InvalidAllowlistEntry(entry.to_string()))?;
+
+            channels
+                .entry(UserId(user_id))
+                .or_default()
+                .insert(RadioManagerChannelId(channel_id));
+        }
+
+        Ok(Self { channels })
+    }
+
+    /// Users without entries may target no channel once any entry is configured.
+    pub(crate) fn is_allowed(&self, user_id: &UserId, channel_id: &RadioManagerChannelId) -> bool {
+        self.channels.is_empty()
+            || self
+                .channels
+                .get(user_id)
+                .is_some_and(|channels| channels.contains(channel_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowing_only_listed_channels_of_user() {
+        let allowlist =
+            ChannelAllowlist::from_entries(&["1:100".into(), " 1:200".into(), "2:300".into()])
+                .unwrap();
+
+        assert!(allowlist.is_allowed(&UserId(1), &RadioManagerChannelId(100)));
+        assert!(allowlist.is_allowed(&UserId(1), &RadioManagerChannelId(200)));
+        assert!(!allowlist.is_allowed(&UserId(1), &RadioManagerChannelId(300)));
+        assert!(allowlist.is_allowed(&UserId(2), &RadioManagerChannelId(300)));
+        assert!(!allowlist.is_allowed(&UserId(3), &RadioManagerChannelId(100)));
+    }
+
+    #[test]
+    fn test_allowing_any_channel_without_entries() {
+        let allowlist = ChannelAllowlist::from_entries(&[]).unwrap();
+
+        assert!(allowlist.is_allowed(&UserId(1), &RadioManagerChannelId(100)));
+    }
+
+    #[test]
+    fn test_rejecting_invalid_entries() {
+        assert!(ChannelAllowlist::from_entries(&["100".into()]).is_err());
+        assert!(ChannelAllowlist::from_entries(&["1:channel".into()]).is_err());
+    }
+}
@@ -0,0 +1,21 @@
+use crate::services::track_request_processor::SearchProviderTrait;
+use std::sync::Arc;
+
+/// Searches all the registered providers at once and merges their results, skipping the
+/// providers that fail as long as at least one of them succeeds.
+/// Topic and download ids are expected to be unique across the providers.
+pub(crate) struct CombinedSearchProvider {
+    providers: Vec<Arc<dyn SearchProviderTrait + Send + Sync + 'static>>,
+}
+
+impl CombinedSearchProvider {
+    pub(crate) fn new(
+        providers: Vec<Arc<dyn SearchProviderTrait + Send + Sync + 'static>>,
+    ) -> Self {
+        Self { providers }
+    }
+
+    pub(crate) fn providers(&self) -> &[Arc<dyn SearchProviderTrait + Send + Sync + 'static>] {
+        &self.providers
+    }
+}
@@ -0,0 +1,313 @@
+use crate::services::track_request_processor::{AudioMetadata, RequestId};
+use crate::types::UserId;
+use std::path::{Component, Path, PathBuf};
+
+/// The directory torrents are downloaded into, as seen by Transmission and by the bot.
+///
+/// Both paths point to the same directory, but they differ when Transmission and the bot
+/// run in separate containers with the download volume mounted at different locations.
+/// Paths reported by Transmission must be translated before the bot opens the files.
+#[derive(Clone, Debug)]
+pub(crate) struct DownloadDirectories {
+    /// Download directory in Transmission's filesystem.
+    pub(crate) transmission_download_dir: String,
+    /// The same directory in the bot's filesystem.
+    pub(crate) local_download_dir: String,
+    /// Subdirectory of the download directory each request is downloaded into, like
+    /// `{artist}/{album}`. Everything is downloaded right into the download directory if not set.
+    pub(crate) path_template: Option<String>,
+    /// Directory in the bot's filesystem downloaded files are copied into and uploaded from,
+    /// so they are kept after the torrent is removed. Files are uploaded right from the
+    /// download directory if not set.
+    pub(crate) library_dir: Option<String>,
+}
+
+impl DownloadDirectories {
+    pub(crate) fn new(transmission_download_dir: &str, local_download_dir: &str) -> Self {
+        Self {
+            transmission_download_dir: transmission_download_dir.to_string(),
+            local_download_dir: local_download_dir.to_string(),
+            path_template: None,
+            library_dir: None,
+        }
+    }
+
+    pub(crate) fn with_library_dir(self, library_dir: Option<String>) -> Self {
+        Self {
+            library_dir,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_path_template(self, path_template: Option<String>) -> Self {
+        Self {
+            path_template,
+            ..self
+        }
+    }
+
+    /// Directory in Transmission's filesystem the request should be downloaded into,
+    /// or `None` to use the download directory itself.
+    ///
+    /// Supported placeholders are `{artist}`, `{album}`, `{request_id}` and `{user_id}`.
+    /// Substituted values can't add or escape directories.
+    pub(crate) fn get_request_download_dir(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        metadata: &AudioMetadata,
+    ) -> Option<String> {
+        let path_template = self.path_template.as_deref()?;
+
+        let subdirectory = path_template
+            .split('/')
+            .map(|component| {
+                component
+                    .replace("{artist}", &sanitize_path_component(&metadata.artist))
+                    .replace("{album}", &sanitize_path_component(&metadata.album))
+                    .replace("{request_id}", &request_id.to_string())
+                    .replace("{user_id}", &user_id.to_string())
+            })
+            .filter(|component| !component.is_empty() && component != "." && component != "..")
+            .collect::<Vec<_>>();
+
+        if subdirectory.is_empty() {
+            return None;
+        }
+
+        Some(
+            Path::new(&self.transmission_download_dir)
+                .join(subdirectory.join("/"))
+                .to_string_lossy()
+                .to_string(),
+        )
+    }
+
+    /// Translates the path of a downloaded file reported by Transmission into the path the
+    /// bot can open. Relative paths are resolved against the local download directory.
+    /// Absolute paths outside of the Transmission download directory are kept as is.
+    pub(crate) fn to_local_path(&self, transmission_path: &str) -> String {
+        let path = Path::new(transmission_path);
+
+        let relative_path = match path.strip_prefix(&self.transmission_download_dir) {
+            Ok(relative_path) => relative_path,
+            Err(_) if path.is_relative() => path,
+            Err(_) => return transmission_path.to_string(),
+        };
+
+        Path::new(&self.local_download_dir)
+            .join(relative_path)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Whether the file lies inside the local download directory or the library directory,
+    /// so clients can't have the bot upload any other file it can read. Symlinks and `..`
+    /// are resolved first, and files that don't exist are not uploadable either.
+    pub(crate) async fn is_uploadable(&self, path: &str) -> bool {
+        let path = match tokio::fs::canonicalize(self.to_local_path(path)).await {
+            Ok(path) => path,
+            Err(_) => return false,
+        };
+
+        for dir in std::iter::once(&self.local_download_dir).chain(self.library_dir.as_ref()) {
+            if let Ok(dir) = tokio::fs::canonicalize(dir).await {
+                if path.starts_with(dir) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Path in the library directory the downloaded file is copied to, keeping its
+    /// subdirectories of the download directory, or `None` if there's no library directory.
+    pub(crate) fn to_library_path(&self, transmission_path: &str) -> Option<String> {
+        let library_dir = self.library_dir.as_deref()?;
+        let path = Path::new(transmission_path);
+
+        let relative_path = match path.strip_prefix(&self.transmission_download_dir) {
+            Ok(relative_path) => relative_path,
+            Err(_) if path.is_relative() => path,
+            Err(_) => Path::new(path.file_name()?),
+        };
+        // Paths come from torrents, so they must not escape the library directory.
+        let mut library_path = PathBuf::from(library_dir);
+        for component in relative_path.components() {
+            if let Component::Normal(component) = component {
+                library_path.push(component);
+            }
+        }
+
+        Some(library_path.to_string_lossy().to_string())
+    }
+}
+
+// Metadata comes from users, so it must stay a single directory name.
+fn sanitize_path_component(value: &str) -> String {
+    let sanitized = value
+        .chars()
+        .filter(|c| !matches!(c, '/' | '\\' | '\0'))
+        .collect::<String>()
+        .trim()
+        .to_string();
+
+    match sanitized.as_str() {
+        "" | "." | ".." => "_".to_string(),
+        _ => sanitized,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translating_transmission_paths() {
+        let dirs = DownloadDirectories::new(
+            "/downloads/radioterio/",
+            "./docker/transmission/downloads/radioterio",
+        );
+
+        assert_eq!(
+            "./docker/transmission/downloads/radioterio/Ted Irens/01. Sunday Breakfast.flac",
+            dirs.to_local_path("/downloads/radioterio/Ted Irens/01. Sunday Breakfast.flac")
+        );
+        assert_eq!(
+            "./docker/transmission/downloads/radioterio/Ted Irens/01. Sunday Breakfast.flac",
+            dirs.to_local_path("Ted Irens/01. Sunday Breakfast.flac")
+        );
+        assert_eq!(
+            "/elsewhere/Ted Irens/01. Sunday Breakfast.flac",
+            dirs.to_local_path("/elsewhere/Ted Irens/01. Sunday Breakfast.flac")
+        );
+    }
+
+    #[test]
+    fn test_translating_paths_with_same_directories() {
+        let dirs = DownloadDirectories::new("/downloads", "/downloads");
+
+        assert_eq!(
+            "/downloads/Ted Irens/01. Sunday Breakfast.flac",
+            dirs.to_local_path("/downloads/Ted Irens/01. Sunday Breakfast.flac")
+        );
+    }
+
+    #[test]
+    fn test_translating_paths_into_library() {
+        let dirs = DownloadDirectories::new("/downloads", "/mnt/scratch");
+
+        assert_eq!(
+            None,
+            dirs.to_library_path("/downloads/Ted Irens/01. Sunday Breakfast.flac")
+        );
+
+        let dirs = dirs.with_library_dir(Some("/library".into()));
+
+        assert_eq!(
+            Some("/library/Ted Irens/01. Sunday Breakfast.flac".to_string()),
+            dirs.to_library_path("/downloads/Ted Irens/01. Sunday Breakfast.flac")
+        );
+        assert_eq!(
+            Some("/library/Ted Irens/01. Sunday Breakfast.flac".to_string()),
+            dirs.to_library_path("Ted Irens/01. Sunday Breakfast.flac")
+        );
+        assert_eq!(
+            Some("/library/01. Sunday Breakfast.flac".to_string()),
+            dirs.to_library_path("/elsewhere/Ted Irens/01. Sunday Breakfast.flac")
+        );
+        assert_eq!(
+            Some("/library/etc/passwd".to_string()),
+            dirs.to_library_path("../../etc/passwd")
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_uploading_only_files_inside_download_or_library_dir() {
+        let root = std::env::temp_dir().join(format!("channel-bot-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("downloads/Ted Irens")).unwrap();
+        std::fs::create_dir_all(root.join("library")).unwrap();
+        std::fs::write(
+            root.join("downloads/Ted Irens/01. Sunday Breakfast.flac"),
+            "",
+        )
+        .unwrap();
+        std::fs::write(root.join("library/02. Life @ Mirror.flac"), "").unwrap();
+        std::fs::write(root.join("secret"), "").unwrap();
+        let root_path = root.to_string_lossy().to_string();
+        let dirs = DownloadDirectories::new("/downloads", &format!("{}/downloads", root_path))
+            .with_library_dir(Some(format!("{}/library", root_path)));
+
+        assert!(
+            dirs.is_uploadable("/downloads/Ted Irens/01. Sunday Breakfast.flac")
+                .await
+        );
+        assert!(
+            dirs.is_uploadable("Ted Irens/01. Sunday Breakfast.flac")
+                .await
+        );
+        assert!(
+            dirs.is_uploadable(&format!("{}/library/02. Life @ Mirror.flac", root_path))
+                .await
+        );
+        // Absolute paths elsewhere and paths escaping the directories.
+        assert!(!dirs.is_uploadable(&format!("{}/secret", root_path)).await);
+        assert!(!dirs.is_uploadable("../secret").await);
+        assert!(
+            !dirs
+                .is_uploadable("/downloads/Ted Irens/../../secret")
+                .await
+        );
+        assert!(!dirs.is_uploadable("Ted Irens/missing.flac").await);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    fn metadata(artist: &str, album: &str) -> AudioMetadata {
+        AudioMetadata {
+            title: "Sunday Breakfast".into(),
+            artist: artist.into(),
+            album: album.into(),
+        }
+    }
+
+    #[test]
+    fn test_rendering_request_download_dir() {
+        let request_id = RequestId(uuid::Uuid::nil());
+        let dirs = DownloadDirectories::new("/downloads", "/downloads");
+
+        assert_eq!(
+            None,
+            dirs.get_request_download_dir(&UserId(1), &request_id, &metadata("Ted Irens", "Foo"))
+        );
+
+        let dirs =
+            dirs.with_path_template(Some("{user_id}/{artist}/{album} [{request_id}]".into()));
+
+        assert_eq!(
+            Some("/downloads/1/Ted Irens/Foo [00000000-0000-0000-0000-000000000000]".to_string()),
+            dirs.get_request_download_dir(&UserId(1), &request_id, &metadata("Ted Irens", "Foo"))
+        );
+    }
+
+    #[test]
+    fn test_sanitizing_request_download_dir() {
+        let request_id = RequestId(uuid::Uuid::nil());
+        let dirs = DownloadDirectories::new("/downloads", "/downloads")
+            .with_path_template(Some("/{artist}//{album}/".into()));
+
+        assert_eq!(
+            Some("/downloads/etcpasswd/_".to_string()),
+            dirs.get_request_download_dir(&UserId(1), &request_id, &metadata("/etc/passwd", ".."))
+        );
+        assert_eq!(
+            Some("/downloads/AC_DC/_".to_string()),
+            dirs.get_request_download_dir(&UserId(1), &request_id, &metadata("AC\\_DC\0", " / "))
+        );
+        assert_eq!(
+            Some("/downloads/....../Foo".to_string()),
+            dirs.get_request_download_dir(&UserId(1), &request_id, &metadata("../../..", "Foo"))
+        );
+    }
+}
@@ -0,0 +1,27 @@
+use crate::services::track_request_processor::SearchProviderTrait;
+use std::sync::Arc;
+
+/// Uses the fallback provider only while the primary one can't be reached at all,
+/// e.g. a mirror of the same tracker. Any other error or an empty result of the primary
+/// provider is returned as is, as the fallback one would most likely respond the same.
+pub(crate) struct FallbackSearchProvider {
+    primary: Arc<dyn SearchProviderTrait + Send + Sync + 'static>,
+    fallback: Arc<dyn SearchProviderTrait + Send + Sync + 'static>,
+}
+
+impl FallbackSearchProvider {
+    pub(crate) fn new(
+        primary: Arc<dyn SearchProviderTrait + Send + Sync + 'static>,
+        fallback: Arc<dyn SearchProviderTrait + Send + Sync + 'static>,
+    ) -> Self {
+        Self { primary, fallback }
+    }
+
+    pub(crate) fn primary(&self) -> &(dyn SearchProviderTrait + Send + Sync + 'static) {
+        self.primary.as_ref()
+    }
+
+    pub(crate) fn fallback(&self) -> &(dyn SearchProviderTrait + Send + Sync + 'static) {
+        self.fallback.as_ref()
+    }
+}
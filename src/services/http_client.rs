@@ -0,0 +1,65 @@
+use reqwest::{ClientBuilder, Proxy};
+use std::time::Duration;
+
+/// Settings shared by every outbound HTTP client.
+#[derive(Clone, Debug)]
+pub(crate) struct HttpClientOptions {
+    pub(crate) user_agent: String,
+    /// Time the whole request may take, unless overridden for a particular request.
+    pub(crate) timeout: Duration,
+    pub(crate) connect_timeout: Duration,
+    /// Proxy for plain HTTP requests.
+    pub(crate) http_proxy: Option<Proxy>,
+    /// Proxy for HTTPS requests.
+    pub(crate) https_proxy: Option<Proxy>,
+}
+
+impl Default for HttpClientOptions {
+    fn default() -> Self {
+        Self {
+            user_agent: format!("channel-bot/{}", env!("CARGO_PKG_VERSION")),
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            http_proxy: None,
+            https_proxy: None,
+        }
+    }
+}
+
+impl HttpClientOptions {
+    pub(crate) fn client_builder(&self) -> ClientBuilder {
+        let mut builder = ClientBuilder::new()
+            .user_agent(&self.user_agent)
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout);
+
+        for proxy in [&self.http_proxy, &self.https_proxy].into_iter().flatten() {
+            builder = builder.proxy(proxy.clone());
+        }
+
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_applying_proxies() {
+        let builder = HttpClientOptions::default().client_builder();
+
+        assert!(!format!("{:?}", builder).contains("proxies"));
+
+        let builder = HttpClientOptions {
+            http_proxy: Some(Proxy::http("http://127.0.0.1:3128").unwrap()),
+            https_proxy: Some(Proxy::https("http://127.0.0.1:3129").unwrap()),
+            ..HttpClientOptions::default()
+        }
+        .client_builder();
+        let builder_config = format!("{:?}", builder);
+
+        assert!(builder_config.contains("127.0.0.1:3128"));
+        assert!(builder_config.contains("127.0.0.1:3129"));
+    }
+}
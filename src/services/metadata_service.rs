@@ -0,0 +1,591 @@
+use crate::services::track_request_processor::AudioMetadata;
+use id3::TagLike;
+use std::path::Path;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia::core::probe::Hint;
+
+pub(crate) const DEFAULT_FILENAME_PATTERNS: [&str; 3] = [
+    "{track}. {artist} - {title}",
+    "{track} - {artist} - {title}",
+    "{artist} - {title}",
+];
+
+pub(crate) struct MetadataService {
+    filename_patterns: Vec<FilenamePattern>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum MetadataServiceError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    SymphoniaError(#[from] SymphoniaError),
+    #[error(transparent)]
+    Id3Error(#[from] id3::Error),
+    #[error("Malformed FLAC metadata")]
+    MalformedFlac,
+    #[error("Metadata reading task has been interrupted")]
+    Interrupted,
+}
+
+#[derive(Default)]
+struct Tags {
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+}
+
+impl Tags {
+    fn merge_revision(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                _ => continue,
+            };
+            let value = tag.value.to_string().trim().to_string();
+
+            if field.is_none() && !value.is_empty() {
+                field.replace(value);
+            }
+        }
+    }
+
+    fn into_audio_metadata(self) -> Option<AudioMetadata> {
+        Some(AudioMetadata {
+            title: self.title?,
+            artist: self.artist?,
+            album: self.album.unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum PatternToken {
+    Literal(String),
+    Track,
+    Artist,
+    Title,
+    Album,
+}
+
+/// Filename template like `{track}. {artist} - {title}` used to extract metadata
+/// from files that have no tags. Supported placeholders are `{track}`, `{artist}`,
+/// `{title}` and `{album}`; everything else is matched literally.
+#[derive(Debug)]
+pub(crate) struct FilenamePattern {
+    tokens: Vec<PatternToken>,
+}
+
+impl FilenamePattern {
+    pub(crate) fn new(pattern: &str) -> Self {
+        let mut tokens = vec![];
+        let mut rest = pattern;
+
+        while !rest.is_empty() {
+            let placeholder = [
+                ("{track}", PatternToken::Track),
+                ("{artist}", PatternToken::Artist),
+                ("{title}", PatternToken::Title),
+                ("{album}", PatternToken::Album),
+            ]
+            .into_iter()
+            .find(|(name, _)| rest.starts_with(name));
+
+            match placeholder {
+                Some((name, token)) => {
+                    tokens.push(token);
+                    rest = &rest[name.len()..];
+                }
+                None => {
+                    let len = rest.chars().next().map(char::len_utf8).unwrap_or(1);
+                    match tokens.last_mut() {
+                        Some(PatternToken::Literal(literal)) => literal.push_str(&rest[..len]),
+                        _ => tokens.push(PatternToken::Literal(rest[..len].to_string())),
+                    }
+                    rest = &rest[len..];
+                }
+            }
+        }
+
+        Self { tokens }
+    }
+
+    pub(crate) fn parse(&self, filename: &str) -> Option<AudioMetadata> {
+        let mut metadata = AudioMetadata::default();
+        let mut rest = filename;
+        let mut tokens = self.tokens.iter().peekable();
+
+        while let Some(token) = tokens.next() {
+            if let PatternToken::Literal(literal) = token {
+                rest = rest.strip_prefix(literal.as_str())?;
+                continue;
+            }
+
+            // Placeholder captures everything up to the next literal or till the end.
+            let value = match tokens.peek() {
+                Some(PatternToken::Literal(literal)) => {
+                    let end = rest.find(literal.as_str())?;
+                    let value = &rest[..end];
+                    rest = &rest[end..];
+                    value
+                }
+                _ => std::mem::take(&mut rest),
+            }
+            .trim();
+
+            if value.is_empty() {
+                return None;
+            }
+
+            match token {
+                PatternToken::Track if !value.chars().all(|c| c.is_ascii_digit()) => return None,
+                PatternToken::Artist => metadata.artist = value.to_string(),
+                PatternToken::Title => metadata.title = value.to_string(),
+                PatternToken::Album => metadata.album = value.to_string(),
+                _ => (),
+            }
+        }
+
+        if !rest.is_empty() || metadata.artist.is_empty() || metadata.title.is_empty() {
+            return None;
+        }
+
+        Some(metadata)
+    }
+}
+
+impl MetadataService {
+    pub(crate) fn create(filename_patterns: &[String]) -> Self {
+        Self {
+            filename_patterns: filename_patterns
+                .iter()
+                .map(|pattern| FilenamePattern::new(pattern))
+                .collect(),
+        }
+    }
+
+    pub(crate) async fn read_metadata(
+        &self,
+        path_to_audio_file: &str,
+    ) -> Result<Option<AudioMetadata>, MetadataServiceError> {
+        let path = path_to_audio_file.to_string();
+
+        let tags = actix_rt::task::spawn_blocking(move || read_tags(&path))
+            .await
+            .map_err(|_| MetadataServiceError::Interrupted)??;
+
+        Ok(match tags {
+            Some(tags) => tags
+                .into_audio_metadata()
+                .or_else(|| self.parse_filename(path_to_audio_file)),
+            None => None,
+        })
+    }
+
+    /// Writes the metadata into the tags of the file, leaving the audio stream untouched.
+    /// Returns `false` if tags of this audio format are not supported.
+    pub(crate) async fn write_metadata(
+        &self,
+        path_to_audio_file: &str,
+        metadata: &AudioMetadata,
+    ) -> Result<bool, MetadataServiceError> {
+        let path = path_to_audio_file.to_string();
+        let metadata = metadata.clone();
+
+        actix_rt::task::spawn_blocking(move || write_tags(&path, &metadata))
+            .await
+            .map_err(|_| MetadataServiceError::Interrupted)?
+    }
+
+    fn parse_filename(&self, path_to_audio_file: &str) -> Option<AudioMetadata> {
+        let filename = Path::new(path_to_audio_file).file_stem()?.to_str()?;
+
+        self.filename_patterns
+            .iter()
+            .find_map(|pattern| pattern.parse(filename))
+    }
+}
+
+// Returns `None` if the file is not an audio file we are able to read.
+fn read_tags(path_to_audio_file: &str) -> Result<Option<Tags>, MetadataServiceError> {
+    let path = Path::new(path_to_audio_file);
+    let file = std::fs::File::open(path)?;
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let mut probed = match symphonia::default::get_probe().format(
+        &hint,
+        source,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    ) {
+        Ok(probed) => probed,
+        // Not an audio file or an audio format we are not able to read.
+        Err(SymphoniaError::Unsupported(_)) => return Ok(None),
+        Err(SymphoniaError::IoError(error))
+            if matches!(error.kind(), std::io::ErrorKind::UnexpectedEof) =>
+        {
+            return Ok(None)
+        }
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut tags = Tags::default();
+
+    // Tags embedded into the container (e.g. FLAC Vorbis comments, MP4 atoms)...
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.merge_revision(revision);
+    }
+
+    // ...and tags found in front of the container (e.g. ID3v2).
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        tags.merge_revision(revision);
+    }
+
+    Ok(Some(tags))
+}
+
+fn write_tags(
+    path_to_audio_file: &str,
+    metadata: &AudioMetadata,
+) -> Result<bool, MetadataServiceError> {
+    let path = Path::new(path_to_audio_file);
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+
+    match extension.as_deref() {
+        Some("flac") => {
+            let data = std::fs::read(path)?;
+            let data = write_flac_tags(&data, metadata)?;
+            // Written next to the original first, so it is never left half-written.
+            let tmp_path = path.with_extension("flac.tmp");
+            std::fs::write(&tmp_path, data)?;
+            std::fs::rename(&tmp_path, path)?;
+        }
+        Some("mp3") => {
+            let mut tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => tag,
+                Err(error) if matches!(error.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+                Err(error) => return Err(error.into()),
+            };
+            tag.set_title(&metadata.title);
+            tag.set_artist(&metadata.artist);
+            if !metadata.album.is_empty() {
+                tag.set_album(&metadata.album);
+            }
+            tag.write_to_path(path, id3::Version::Id3v24)?;
+        }
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+const FLAC_MARKER: &[u8] = b"fLaC";
+const FLAC_STREAMINFO_BLOCK: u8 = 0;
+const FLAC_VORBIS_COMMENT_BLOCK: u8 = 4;
+const FLAC_LAST_BLOCK_FLAG: u8 = 0x80;
+
+// Replaces the title, artist and album in the Vorbis comment block, keeping the other
+// comments, metadata blocks and the audio frames following them as they are.
+fn write_flac_tags(data: &[u8], metadata: &AudioMetadata) -> Result<Vec<u8>, MetadataServiceError> {
+    if !data.starts_with(FLAC_MARKER) {
+        return Err(MetadataServiceError::MalformedFlac);
+    }
+
+    let mut blocks = vec![];
+    let mut offset = FLAC_MARKER.len();
+
+    loop {
+        let header = data
+            .get(offset..offset + 4)
+            .ok_or(MetadataServiceError::MalformedFlac)?;
+        let block_type = header[0] & !FLAC_LAST_BLOCK_FLAG;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let body = data
+            .get(offset + 4..offset + 4 + length)
+            .ok_or(MetadataServiceError::MalformedFlac)?;
+        blocks.push((block_type, body.to_vec()));
+        offset += 4 + length;
+
+        if header[0] & FLAC_LAST_BLOCK_FLAG != 0 {
+            break;
+        }
+    }
+
+    if blocks.first().map(|(block_type, _)| *block_type) != Some(FLAC_STREAMINFO_BLOCK) {
+        return Err(MetadataServiceError::MalformedFlac);
+    }
+
+    let (vendor, mut comments) = match blocks
+        .iter()
+        .find(|(block_type, _)| *block_type == FLAC_VORBIS_COMMENT_BLOCK)
+    {
+        Some((_, body)) => parse_vorbis_comment(body).ok_or(MetadataServiceError::MalformedFlac)?,
+        None => (env!("CARGO_PKG_NAME").as_bytes().to_vec(), vec![]),
+    };
+
+    let mut fields = vec![("TITLE", &metadata.title), ("ARTIST", &metadata.artist)];
+    if !metadata.album.is_empty() {
+        fields.push(("ALBUM", &metadata.album));
+    }
+    for (key, value) in fields {
+        comments.retain(|comment| !is_vorbis_comment_of(comment, key));
+        comments.push(format!("{}={}", key, value).into_bytes());
+    }
+
+    let vorbis_comment = build_vorbis_comment(&vendor, &comments);
+    match blocks
+        .iter_mut()
+        .find(|(block_type, _)| *block_type == FLAC_VORBIS_COMMENT_BLOCK)
+    {
+        Some((_, body)) => *body = vorbis_comment,
+        None => blocks.insert(1, (FLAC_VORBIS_COMMENT_BLOCK, vorbis_comment)),
+    }
+
+    let mut output = FLAC_MARKER.to_vec();
+    let blocks_count = blocks.len();
+    for (index, (block_type, body)) in blocks.into_iter().enumerate() {
+        let length = u32::try_from(body.len())
+            .ok()
+            .filter(|length| *length < 1 << 24)
+            .ok_or(MetadataServiceError::MalformedFlac)?;
+        let flag = if index + 1 == blocks_count {
+            FLAC_LAST_BLOCK_FLAG
+        } else {
+            0
+        };
+        output.push(block_type | flag);
+        output.extend_from_slice(&length.to_be_bytes()[1..]);
+        output.extend_from_slice(&body);
+    }
+    output.extend_from_slice(&data[offset..]);
+
+    Ok(output)
+}
+
+fn is_vorbis_comment_of(comment: &[u8], key: &str) -> bool {
+    comment.len() > key.len()
+        && comment[key.len()] == b'='
+        && comment[..key.len()].eq_ignore_ascii_case(key.as_bytes())
+}
+
+fn parse_vorbis_comment(body: &[u8]) -> Option<(Vec<u8>, Vec<Vec<u8>>)> {
+    let mut offset = 0;
+
+    let vendor = read_vorbis_field(body, &mut offset)?.to_vec();
+    let count = u32::from_le_bytes(body.get(offset..offset + 4)?.try_into().ok()?);
+    offset += 4;
+    let comments = (0..count)
+        .map(|_| read_vorbis_field(body, &mut offset).map(<[u8]>::to_vec))
+        .collect::<Option<Vec<_>>>()?;
+
+    Some((vendor, comments))
+}
+
+fn read_vorbis_field<'a>(body: &'a [u8], offset: &mut usize) -> Option<&'a [u8]> {
+    let length = u32::from_le_bytes(body.get(*offset..*offset + 4)?.try_into().ok()?) as usize;
+    let field = body.get(*offset + 4..*offset + 4 + length)?;
+    *offset += 4 + length;
+    Some(field)
+}
+
+fn build_vorbis_comment(vendor: &[u8], comments: &[Vec<u8>]) -> Vec<u8> {
+    let mut body = vec![];
+    body.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    body.extend_from_slice(vendor);
+    body.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        body.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        body.extend_from_slice(comment);
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::torrent_parser::get_files;
+
+    fn default_patterns() -> Vec<String> {
+        DEFAULT_FILENAME_PATTERNS.map(String::from).to_vec()
+    }
+
+    #[actix_rt::test]
+    async fn test_reading_flac_tags() {
+        let metadata = MetadataService::create(&default_patterns())
+            .read_metadata("tests/fixtures/tagged.flac")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Some(AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Life @ Mirror".into(),
+            }),
+            metadata
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_reading_mp3_tags() {
+        let metadata = MetadataService::create(&default_patterns())
+            .read_metadata("tests/fixtures/tagged.mp3")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Some(AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Life @ Mirror".into(),
+            }),
+            metadata
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_reading_untagged_file() {
+        let metadata = MetadataService::create(&default_patterns())
+            .read_metadata("tests/fixtures/untagged.mp3")
+            .await
+            .unwrap();
+
+        assert_eq!(None, metadata);
+    }
+
+    #[actix_rt::test]
+    async fn test_reading_non_audio_file() {
+        let metadata = MetadataService::create(&default_patterns())
+            .read_metadata("tests/fixtures/example.torrent")
+            .await
+            .unwrap();
+
+        assert_eq!(None, metadata);
+    }
+
+    fn copy_fixture(name: &str) -> String {
+        let path = std::env::temp_dir()
+            .join(format!("channel-bot-{}-{}", uuid::Uuid::new_v4(), name))
+            .to_string_lossy()
+            .to_string();
+        std::fs::copy(format!("tests/fixtures/{}", name), &path).unwrap();
+        path
+    }
+
+    fn requested_metadata() -> AudioMetadata {
+        AudioMetadata {
+            title: "Winter's Sunset".into(),
+            artist: "Ted Irens".into(),
+            album: "Faces Of Love".into(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_writing_flac_tags() {
+        // The audio frames of the fixture follow the STREAMINFO and VORBIS_COMMENT blocks.
+        let original = std::fs::read("tests/fixtures/tagged.flac").unwrap();
+        let audio_stream = &original[4 + 4 + 34 + 4 + 88..];
+        let path = copy_fixture("tagged.flac");
+        let service = MetadataService::create(&default_patterns());
+
+        let written = service
+            .write_metadata(&path, &requested_metadata())
+            .await
+            .unwrap();
+        let metadata = service.read_metadata(&path).await.unwrap();
+        let modified = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(written);
+        assert_eq!(Some(requested_metadata()), metadata);
+        assert!(modified.ends_with(audio_stream));
+        assert_ne!(original, modified);
+    }
+
+    #[actix_rt::test]
+    async fn test_writing_mp3_tags() {
+        let original = std::fs::read("tests/fixtures/untagged.mp3").unwrap();
+        let path = copy_fixture("untagged.mp3");
+        let service = MetadataService::create(&default_patterns());
+
+        let written = service
+            .write_metadata(&path, &requested_metadata())
+            .await
+            .unwrap();
+        let metadata = service.read_metadata(&path).await.unwrap();
+        let modified = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(written);
+        assert_eq!(Some(requested_metadata()), metadata);
+        assert!(modified.ends_with(&original));
+    }
+
+    #[actix_rt::test]
+    async fn test_writing_tags_of_unsupported_file() {
+        let written = MetadataService::create(&default_patterns())
+            .write_metadata("tests/fixtures/example.torrent", &requested_metadata())
+            .await
+            .unwrap();
+
+        assert!(!written);
+    }
+
+    #[test]
+    fn test_parsing_filenames_from_torrent() {
+        let service = MetadataService::create(&default_patterns());
+        let contents = include_bytes!("../../tests/fixtures/example.torrent");
+        let parsed: Vec<_> = get_files(contents)
+            .unwrap()
+            .into_iter()
+            .filter(|file| file.ends_with(".flac"))
+            .filter_map(|file| service.parse_filename(&file))
+            .collect();
+
+        assert_eq!(14, parsed.len());
+        assert!(parsed.iter().all(|m| m.artist == "Ted Irens"));
+        assert_eq!("Sunday Breakfast", parsed[0].title);
+        assert_eq!("Winter's Sunset", parsed[6].title);
+        assert_eq!("Dancing On The Moon", parsed[12].title);
+    }
+
+    #[test]
+    fn test_parsing_filenames_with_patterns() {
+        let service = MetadataService::create(&default_patterns());
+
+        assert_eq!(
+            Some(AudioMetadata {
+                title: "Rider".into(),
+                artist: "Ted Irens".into(),
+                album: "".into(),
+            }),
+            service.parse_filename("path/to/Ted Irens - Rider.mp3")
+        );
+        assert_eq!(None, service.parse_filename("path/to/track02.mp3"));
+        assert_eq!(None, service.parse_filename("audiochecker.log"));
+
+        let pattern = FilenamePattern::new("{artist} - {album} - {track} {title}");
+        assert_eq!(
+            Some(AudioMetadata {
+                title: "Rider".into(),
+                artist: "Ted Irens".into(),
+                album: "Life @ Mirror".into(),
+            }),
+            pattern.parse("Ted Irens - Life @ Mirror - 12 Rider")
+        );
+        assert_eq!(None, pattern.parse("Ted Irens - Life @ Mirror - XII Rider"));
+    }
+}
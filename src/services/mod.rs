@@ -1,3 +1,6 @@
+pub(crate) mod http_client;
+pub(crate) use http_client::*;
+
 pub(crate) mod transmission_client;
 pub(crate) use transmission_client::*;
 
@@ -11,3 +14,33 @@ pub(crate) mod track_request_processor;
 pub(crate) use track_request_processor::TrackRequestProcessor;
 
 pub(crate) mod torrent_parser;
+
+pub(crate) mod download_directories;
+pub(crate) use download_directories::*;
+
+pub(crate) mod metadata_service;
+pub(crate) use metadata_service::*;
+
+pub(crate) mod rate_limiter;
+pub(crate) use rate_limiter::*;
+
+pub(crate) mod reloadable;
+pub(crate) use reloadable::*;
+
+pub(crate) mod rutracker_login;
+pub(crate) use rutracker_login::*;
+
+pub(crate) mod rutracker_session_monitor;
+pub(crate) use rutracker_session_monitor::*;
+
+pub(crate) mod combined_search_provider;
+pub(crate) use combined_search_provider::*;
+
+pub(crate) mod fallback_search_provider;
+pub(crate) use fallback_search_provider::*;
+
+pub(crate) mod activity_tracker;
+pub(crate) use activity_tracker::*;
+
+pub(crate) mod channel_allowlist;
+pub(crate) use channel_allowlist::*;
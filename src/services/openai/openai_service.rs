@@ -1,7 +1,10 @@
 use crate::services::track_request_processor::AudioMetadata;
+use crate::services::HttpClientOptions;
 use reqwest::Client;
+use tracing::warn;
 
 const OPENAI_ENDPOINT: &str = "https://api.openai.com";
+const SYSTEM_PROMPT: &str = "Here are the rules you should follow:\n\n1. The user will provide you with a list of audio tracks, where each track is separated by a new line. Album name, if known, is given in parentheses after the track.\n\n2. Create a valid JSON array containing two audio tracks that will ideally fit existing ones in the list in terms of vibe and mood. Objects should have the following fields: title, artist and album.\n\n3. Without any additional comments and descriptions. Just array.";
 
 pub(crate) struct OpenAIService {
     openai_api_key: String,
@@ -15,8 +18,9 @@ pub(crate) enum OpenAIServiceError {
 }
 
 impl OpenAIService {
-    pub(crate) fn create(openai_api_key: String) -> Self {
-        let client = Client::builder()
+    pub(crate) fn create(openai_api_key: String, http_client_options: &HttpClientOptions) -> Self {
+        let client = http_client_options
+            .client_builder()
             .build()
             .expect("Failed to create HTTP Client");
 
@@ -29,13 +33,8 @@ impl OpenAIService {
     pub(crate) async fn get_audio_tracks_suggestion(
         &self,
         tracks_list: &Vec<AudioMetadata>,
+        hint: Option<&str>,
     ) -> Result<Vec<AudioMetadata>, OpenAIServiceError> {
-        let tracks_list_str = tracks_list
-            .iter()
-            .map(|m| format!("{} - {}", m.artist, m.title))
-            .collect::<Vec<_>>()
-            .join("\n");
-
         let response = self
             .client
             .post(format!("{}/v1/chat/completions", OPENAI_ENDPOINT))
@@ -43,8 +42,8 @@ impl OpenAIService {
             .json(&serde_json::json!({
                 "model": "gpt-3.5-turbo",
                 "messages": [
-                    {"role": "system", "content": "Here are the rules you should follow:\n\n1. The user will provide you with a list of audio tracks, where each track is separated by a new line.\n\n2. Create a valid JSON array containing two audio tracks that will ideally fit existing ones in the list in terms of vibe and mood. Objects should have the following fields: title, artist and album.\n\n3. Without any additional comments and descriptions. Just array."},
-                    {"role": "user", "content": tracks_list_str}
+                    {"role": "system", "content": make_system_prompt(hint)},
+                    {"role": "user", "content": make_tracks_list(tracks_list)}
                 ]
             }))
             .send()
@@ -59,9 +58,130 @@ impl OpenAIService {
             .and_then(|choice| choice.get("message"))
             .and_then(|message| message.get("content"))
             .and_then(|content| content.as_str())
-            .and_then(|str| serde_json::from_str::<Vec<AudioMetadata>>(str).ok())
+            .map(parse_suggested_tracks)
             .unwrap_or_default();
 
         Ok(response_content)
     }
 }
+
+// The model doesn't always follow the rules, so entries without artist or title are dropped
+// instead of failing the whole suggestion. Missing album is fine, the search doesn't need it.
+fn parse_suggested_tracks(content: &str) -> Vec<AudioMetadata> {
+    let entries = match serde_json::from_str::<Vec<serde_json::Value>>(content) {
+        Ok(entries) => entries,
+        Err(error) => {
+            warn!(?error, "Unable to parse suggested tracks");
+            return vec![];
+        }
+    };
+    let entries_count = entries.len();
+
+    let get_field = |entry: &serde_json::Value, name: &str| {
+        entry
+            .get(name)
+            .and_then(|value| value.as_str())
+            .map(str::trim)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let tracks = entries
+        .iter()
+        .map(|entry| AudioMetadata {
+            title: get_field(entry, "title"),
+            artist: get_field(entry, "artist"),
+            album: get_field(entry, "album"),
+        })
+        .filter(|track| !track.artist.is_empty() && !track.title.is_empty())
+        .collect::<Vec<_>>();
+
+    let discarded_count = entries_count - tracks.len();
+    if discarded_count > 0 {
+        warn!(discarded_count, "Discarded invalid suggested tracks");
+    }
+
+    tracks
+}
+
+fn make_system_prompt(hint: Option<&str>) -> String {
+    match hint.map(str::trim).filter(|hint| !hint.is_empty()) {
+        Some(hint) => format!(
+            "{}\n\n4. Take into account the following wishes of the curator: {}",
+            SYSTEM_PROMPT, hint
+        ),
+        None => SYSTEM_PROMPT.to_string(),
+    }
+}
+
+fn make_tracks_list(tracks_list: &[AudioMetadata]) -> String {
+    tracks_list
+        .iter()
+        .map(|m| match m.album.is_empty() {
+            true => format!("{} - {}", m.artist, m.title),
+            false => format!("{} - {} ({})", m.artist, m.title, m.album),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_making_tracks_list_with_albums() {
+        let tracks_list = vec![
+            AudioMetadata {
+                title: "Life @ Mirror".into(),
+                artist: "Ted Irens".into(),
+                album: "Life".into(),
+            },
+            AudioMetadata {
+                title: "Untitled".into(),
+                artist: "Unknown".into(),
+                album: "".into(),
+            },
+        ];
+
+        assert_eq!(
+            "Ted Irens - Life @ Mirror (Life)\nUnknown - Untitled",
+            make_tracks_list(&tracks_list)
+        );
+    }
+
+    #[test]
+    fn test_parsing_suggested_tracks() {
+        let content = r#"[
+            {"title": "Life @ Mirror", "artist": "Ted Irens", "album": "Life"},
+            {"title": " Untitled ", "artist": "Unknown"},
+            {"title": "", "artist": "Ted Irens", "album": "Life"},
+            {"title": "Life @ Mirror", "artist": null},
+            42
+        ]"#;
+
+        assert_eq!(
+            vec![
+                AudioMetadata {
+                    title: "Life @ Mirror".into(),
+                    artist: "Ted Irens".into(),
+                    album: "Life".into(),
+                },
+                AudioMetadata {
+                    title: "Untitled".into(),
+                    artist: "Unknown".into(),
+                    album: "".into(),
+                },
+            ],
+            parse_suggested_tracks(content)
+        );
+        assert!(parse_suggested_tracks("Sorry, I can't help with that.").is_empty());
+    }
+
+    #[test]
+    fn test_making_system_prompt_with_hint() {
+        assert_eq!(SYSTEM_PROMPT, make_system_prompt(None));
+        assert_eq!(SYSTEM_PROMPT, make_system_prompt(Some("  ")));
+        assert!(make_system_prompt(Some("more ambient, less vocal"))
+            .ends_with("wishes of the curator: more ambient, less vocal"));
+    }
+}
@@ -1,27 +1,106 @@
 use crate::services::track_request_processor::{
     RadioManagerChannelId, RadioManagerLinkId, RadioManagerTrackId,
 };
+use crate::services::HttpClientOptions;
+use crate::utils::contains_ignore_case;
 use reqwest::redirect::Policy;
-use reqwest::{multipart, Body, Client, Error};
+use reqwest::{multipart, Body, Client, Error, StatusCode};
 use serde::Deserialize;
+use std::future::Future;
 use std::path::Path;
+use std::time::Duration;
 use tokio_util::codec::{BytesCodec, FramedRead};
+use tracing::warn;
 
 pub(crate) struct RadioManagerClient {
     endpoint: String,
     client: Client,
+    upload_limits: UploadLimits,
+    // Kept to log in again once the session expires.
+    username: String,
+    password: String,
+}
+
+/// Guards against audio files that would tie up the upload for too long.
+#[derive(Clone, Debug)]
+pub(crate) struct UploadLimits {
+    /// Time the whole upload, including the server response, may take.
+    pub(crate) timeout: Duration,
+    /// Files larger than this are rejected before the upload starts.
+    pub(crate) max_file_size: Option<u64>,
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(600),
+            max_file_size: None,
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum RadioManagerClientError {
     #[error(transparent)]
-    ReqwestError(#[from] Error),
+    ReqwestError(Error),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
-    #[error("Unexpected error: {0}")]
-    Unexpected(String),
     #[error("Audio track already exists in user library")]
     TrackExists,
+    #[error("Audio file size {size} exceeds the upload limit of {max_size} bytes")]
+    FileTooLarge { size: u64, max_size: u64 },
+    #[error("Audio file upload has timed out")]
+    UploadTimeout,
+    #[error("RadioManager session has expired")]
+    SessionExpired,
+    #[error("RadioManager channel does not exist")]
+    ChannelNotFound,
+    #[error("Audio track does not exist in user library")]
+    TrackNotFound,
+    #[error("RadioManager storage quota has been exceeded")]
+    QuotaExceeded,
+    #[error("RadioManager responded with code {0}: {1}")]
+    Other(i64, String),
+}
+
+impl RadioManagerClientError {
+    /// Maps a failed RadioManager response to the error. Failures are told apart
+    /// by their messages, the code is only kept for the ones not known here.
+    fn from_response(code: i64, message: String) -> Self {
+        let mentions = |needles: &[&str]| {
+            needles
+                .iter()
+                .any(|needle| contains_ignore_case(&message, needle))
+        };
+
+        if mentions(&["file already exists"]) {
+            Self::TrackExists
+        } else if mentions(&["unauthorized", "not authorized", "login required"]) {
+            Self::SessionExpired
+        } else if mentions(&["stream not found", "channel not found"]) {
+            Self::ChannelNotFound
+        } else if mentions(&["track not found"]) {
+            Self::TrackNotFound
+        } else if mentions(&["quota", "not enough space"]) {
+            Self::QuotaExceeded
+        } else {
+            Self::Other(code, message)
+        }
+    }
+
+    /// Whether retrying the same call can't succeed, e.g. the channel has been deleted.
+    pub(crate) fn is_unrecoverable(&self) -> bool {
+        matches!(self, Self::ChannelNotFound)
+    }
+}
+
+impl From<Error> for RadioManagerClientError {
+    fn from(error: Error) -> Self {
+        match error.status() {
+            Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => Self::SessionExpired,
+            _ => Self::ReqwestError(error),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,12 +112,9 @@ pub(crate) struct RadioManagerResponse<Data> {
 
 impl<Data> RadioManagerResponse<Data> {
     fn error_for_code(self) -> Result<Data, RadioManagerClientError> {
-        match (self.code, self.data, self.message) {
-            (1, Some(data), _) => Ok(data),
-            (_, _, message) if message.contains("file already exists") => {
-                Err(RadioManagerClientError::TrackExists)
-            }
-            (_, _, message) => Err(RadioManagerClientError::Unexpected(message)),
+        match (self.code, self.data) {
+            (1, Some(data)) => Ok(data),
+            (code, _) => Err(RadioManagerClientError::from_response(code, self.message)),
         }
     }
 }
@@ -51,9 +127,9 @@ pub(crate) struct RadioManagerVoidResponse {
 
 impl RadioManagerVoidResponse {
     fn error_for_code(self) -> Result<(), RadioManagerClientError> {
-        match (self.code, self.message) {
-            (1, _) => Ok(()),
-            (_, message) => Err(RadioManagerClientError::Unexpected(message)),
+        match self.code {
+            1 => Ok(()),
+            code => Err(RadioManagerClientError::from_response(code, self.message)),
         }
     }
 }
@@ -70,13 +146,24 @@ struct RadioManagerUploadedTrack {
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct RadioManagerChannelTrack {
+    #[serde(default, alias = "tid")]
+    pub(crate) id: Option<u64>,
     pub(crate) album: String,
     pub(crate) artist: String,
     pub(crate) title: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub(crate) struct RadioManagerChannel {
+    #[serde(alias = "sid")]
+    pub(crate) id: u64,
+    pub(crate) name: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub(crate) struct RadioManagerTrack {
+    #[serde(alias = "tid")]
+    pub(crate) id: u64,
     pub(crate) album: String,
     pub(crate) artist: String,
     pub(crate) title: String,
@@ -87,18 +174,33 @@ impl RadioManagerClient {
         endpoint: &str,
         username: &str,
         password: &str,
+        http_client_options: &HttpClientOptions,
     ) -> Result<Self, RadioManagerClientError> {
-        let client = Client::builder()
+        let client = http_client_options
+            .client_builder()
             .redirect(Policy::limited(10))
             .cookie_store(true)
             .build()
             .expect("Failed to create HTTP Client");
 
-        client
-            .post(format!("{}api/v2/user/login", endpoint))
+        let client = Self {
+            endpoint: endpoint.into(),
+            client,
+            upload_limits: UploadLimits::default(),
+            username: username.into(),
+            password: password.into(),
+        };
+        client.login().await?;
+
+        Ok(client)
+    }
+
+    async fn login(&self) -> Result<(), RadioManagerClientError> {
+        self.client
+            .post(format!("{}api/v2/user/login", self.endpoint))
             .form(&serde_json::json!({
-                "login": username.to_string(),
-                "password": password.to_string(),
+                "login": self.username,
+                "password": self.password,
                 "save": false,
             }))
             .send()
@@ -108,43 +210,83 @@ impl RadioManagerClient {
             .await?
             .error_for_code()?;
 
-        Ok(Self {
-            endpoint: endpoint.into(),
-            client,
-        })
+        Ok(())
     }
 
+    /// Runs the operation, and if the session has expired meanwhile, logs in again
+    /// and retries the operation once.
+    async fn with_relogin<T, F, Fut>(&self, operation: F) -> Result<T, RadioManagerClientError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, RadioManagerClientError>>,
+    {
+        match operation().await {
+            Err(RadioManagerClientError::SessionExpired) => {
+                warn!("RadioManager session has expired, logging in again...");
+                self.login().await?;
+                operation().await
+            }
+            result => result,
+        }
+    }
+
+    pub(crate) fn with_upload_limits(self, upload_limits: UploadLimits) -> Self {
+        Self {
+            upload_limits,
+            ..self
+        }
+    }
+
+    /// Uploads the file under `file_name`, or under its own name if not set.
     pub(crate) async fn upload_track(
         &self,
         path_to_track_file: &str,
+        file_name: Option<&str>,
     ) -> Result<RadioManagerTrackId, RadioManagerClientError> {
-        let path = Path::new(path_to_track_file);
-        let file = tokio::fs::File::open(path).await?;
-        let stream = FramedRead::new(file, BytesCodec::new());
-        let file_body = Body::wrap_stream(stream);
-        let file_part = multipart::Part::stream(file_body).file_name(
-            path.file_name()
-                .unwrap_or_default()
-                .to_str()
-                .unwrap_or_default()
-                .to_string(),
-        );
+        self.with_relogin(|| async {
+            let path = Path::new(path_to_track_file);
+            let file = tokio::fs::File::open(path).await?;
 
-        let form = multipart::Form::new().part("file", file_part);
-        let data = self
-            .client
-            .post(format!("{}api/v2/track/upload", self.endpoint))
-            .multipart(form)
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<RadioManagerResponse<RadioManagerUploadedTracksData>>()
-            .await?
-            .error_for_code()?;
+            if let Some(max_size) = self.upload_limits.max_file_size {
+                let size = file.metadata().await?.len();
+
+                if size > max_size {
+                    return Err(RadioManagerClientError::FileTooLarge { size, max_size });
+                }
+            }
+
+            let stream = FramedRead::new(file, BytesCodec::new());
+            let file_body = Body::wrap_stream(stream);
+            let file_name = match file_name {
+                Some(file_name) => file_name,
+                None => path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_str()
+                    .unwrap_or_default(),
+            };
+            let file_part = multipart::Part::stream(file_body).file_name(file_name.to_string());
+
+            let form = multipart::Form::new().part("file", file_part);
+            let data = self
+                .client
+                .post(format!("{}api/v2/track/upload", self.endpoint))
+                .multipart(form)
+                .timeout(self.upload_limits.timeout)
+                .send()
+                .await
+                .map_err(into_upload_error)?
+                .error_for_status()?
+                .json::<RadioManagerResponse<RadioManagerUploadedTracksData>>()
+                .await
+                .map_err(into_upload_error)?
+                .error_for_code()?;
 
-        Ok(RadioManagerTrackId(
-            data.tracks.first().map(|t| t.tid).unwrap_or_default(),
-        ))
+            Ok(RadioManagerTrackId(
+                data.tracks.first().map(|t| t.tid).unwrap_or_default(),
+            ))
+        })
+        .await
     }
 
     pub(crate) async fn add_track_to_channel(
@@ -152,100 +294,383 @@ impl RadioManagerClient {
         track_id: &RadioManagerTrackId,
         channel_id: &RadioManagerChannelId,
     ) -> Result<RadioManagerLinkId, RadioManagerClientError> {
-        self.client
-            .post(format!("{}api/v2/stream/addTracks", self.endpoint))
-            .form(&serde_json::json!({
-                "stream_id": **channel_id,
-                "tracks": format!("{}", track_id),
-            }))
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<RadioManagerVoidResponse>()
-            .await?
-            .error_for_code()?;
+        let mut link_ids = self
+            .add_tracks_to_channel(std::slice::from_ref(track_id), channel_id)
+            .await?;
 
-        Ok(RadioManagerLinkId("123".into()))
+        Ok(link_ids.remove(0))
     }
 
-    pub(crate) async fn get_channel_tracks(
+    /// Adds all tracks to the channel in a single request.
+    /// Returns the link ids in the same order as the tracks.
+    pub(crate) async fn add_tracks_to_channel(
         &self,
+        track_ids: &[RadioManagerTrackId],
         channel_id: &RadioManagerChannelId,
-    ) -> Result<Vec<RadioManagerChannelTrack>, RadioManagerClientError> {
-        let mut tracks = vec![];
+    ) -> Result<Vec<RadioManagerLinkId>, RadioManagerClientError> {
+        if track_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        self.with_relogin(|| async {
+            self.client
+                .post(format!("{}api/v2/stream/addTracks", self.endpoint))
+                .form(&serde_json::json!({
+                    "stream_id": **channel_id,
+                    "tracks": join_track_ids(track_ids),
+                }))
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<RadioManagerVoidResponse>()
+                .await?
+                .error_for_code()?;
 
-        let mut offset = 0;
-        loop {
-            let mut data = self
+            Ok(track_ids
+                .iter()
+                .map(|_| RadioManagerLinkId("123".into()))
+                .collect())
+        })
+        .await
+    }
+
+    pub(crate) async fn create_channel(
+        &self,
+        name: &str,
+    ) -> Result<RadioManagerChannelId, RadioManagerClientError> {
+        self.with_relogin(|| async {
+            let channel_id = self
                 .client
-                .get(format!(
-                    "{}radio-manager/api/v0/streams/{}/tracks/",
-                    self.endpoint, channel_id
-                ))
-                .query(&serde_json::json!({
-                    "offset": offset,
+                .post(format!("{}api/v2/stream/create", self.endpoint))
+                .form(&serde_json::json!({
+                    "name": name,
                 }))
                 .send()
                 .await?
                 .error_for_status()?
-                .json::<RadioManagerResponse<Vec<RadioManagerChannelTrack>>>()
+                .json::<RadioManagerResponse<u64>>()
                 .await?
                 .error_for_code()?;
 
-            if data.is_empty() {
-                break;
-            }
+            Ok(RadioManagerChannelId(channel_id))
+        })
+        .await
+    }
 
-            offset += data.len();
+    pub(crate) async fn list_channels(
+        &self,
+    ) -> Result<Vec<RadioManagerChannel>, RadioManagerClientError> {
+        self.with_relogin(|| async {
+            let channels = self
+                .client
+                .get(format!("{}radio-manager/api/v0/streams/", self.endpoint))
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<RadioManagerResponse<Vec<RadioManagerChannel>>>()
+                .await?
+                .error_for_code()?;
 
-            tracks.append(&mut data);
-        }
+            Ok(channels)
+        })
+        .await
+    }
+
+    pub(crate) async fn get_channel_tracks(
+        &self,
+        channel_id: &RadioManagerChannelId,
+    ) -> Result<Vec<RadioManagerChannelTrack>, RadioManagerClientError> {
+        self.with_relogin(|| async {
+            let mut tracks = vec![];
+
+            let mut offset = 0;
+            loop {
+                let mut data = self
+                    .client
+                    .get(format!(
+                        "{}radio-manager/api/v0/streams/{}/tracks/",
+                        self.endpoint, channel_id
+                    ))
+                    .query(&serde_json::json!({
+                        "offset": offset,
+                    }))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<RadioManagerResponse<Vec<RadioManagerChannelTrack>>>()
+                    .await?
+                    .error_for_code()?;
+
+                if data.is_empty() {
+                    break;
+                }
+
+                offset += data.len();
+
+                tracks.append(&mut data);
+            }
 
-        Ok(tracks)
+            Ok(tracks)
+        })
+        .await
     }
 
     pub(crate) async fn get_tracks(
         &self,
     ) -> Result<Vec<RadioManagerTrack>, RadioManagerClientError> {
-        let mut tracks = vec![];
+        self.with_relogin(|| async {
+            let mut tracks = vec![];
+
+            let mut offset = 0;
+            loop {
+                let mut data = self
+                    .client
+                    .get(format!("{}radio-manager/api/v0/tracks/", self.endpoint))
+                    .query(&serde_json::json!({
+                        "offset": offset,
+                    }))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<RadioManagerResponse<Vec<RadioManagerTrack>>>()
+                    .await?
+                    .error_for_code()?;
+
+                if data.is_empty() {
+                    break;
+                }
+
+                offset += data.len();
+
+                tracks.append(&mut data);
+            }
 
-        let mut offset = 0;
-        loop {
-            let mut data = self
-                .client
-                .get(format!("{}radio-manager/api/v0/tracks/", self.endpoint))
-                .query(&serde_json::json!({
-                    "offset": offset,
-                }))
+            Ok(tracks)
+        })
+        .await
+    }
+
+    pub(crate) async fn check_connection(&self) -> Result<(), RadioManagerClientError> {
+        self.with_relogin(|| async {
+            self.client
+                .get(format!("{}api/v2/self", self.endpoint))
                 .send()
                 .await?
                 .error_for_status()?
-                .json::<RadioManagerResponse<Vec<RadioManagerTrack>>>()
+                .json::<RadioManagerVoidResponse>()
                 .await?
                 .error_for_code()?;
 
-            if data.is_empty() {
-                break;
-            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn join_track_ids(track_ids: &[RadioManagerTrackId]) -> String {
+    track_ids
+        .iter()
+        .map(|track_id| track_id.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
 
-            offset += data.len();
+fn into_upload_error(error: Error) -> RadioManagerClientError {
+    match error.is_timeout() {
+        true => RadioManagerClientError::UploadTimeout,
+        false => error.into(),
+    }
+}
 
-            tracks.append(&mut data);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::web::{self, Data};
+    use actix_web::{App, HttpResponse, HttpServer};
+    use std::sync::{Arc, Mutex};
+
+    fn create_client(endpoint: &str, upload_limits: UploadLimits) -> RadioManagerClient {
+        RadioManagerClient {
+            endpoint: endpoint.into(),
+            client: Client::new(),
+            upload_limits,
+            username: "username".into(),
+            password: "password".into(),
         }
+    }
 
-        Ok(tracks)
+    #[test]
+    fn test_joining_track_ids() {
+        assert_eq!(
+            "1,22,333",
+            join_track_ids(&[
+                RadioManagerTrackId(1),
+                RadioManagerTrackId(22),
+                RadioManagerTrackId(333)
+            ])
+        );
     }
 
-    pub(crate) async fn check_connection(&self) -> Result<(), RadioManagerClientError> {
-        self.client
-            .get(format!("{}api/v2/self", self.endpoint))
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<RadioManagerVoidResponse>()
-            .await?
-            .error_for_code()?;
+    #[test]
+    fn test_mapping_failed_responses_to_errors() {
+        let error_for = |response: serde_json::Value| {
+            serde_json::from_value::<RadioManagerVoidResponse>(response)
+                .unwrap()
+                .error_for_code()
+                .unwrap_err()
+        };
+
+        assert!(matches!(
+            error_for(serde_json::json!({ "code": 0, "message": "Login required" })),
+            RadioManagerClientError::SessionExpired
+        ));
+        assert!(matches!(
+            error_for(serde_json::json!({ "code": 0, "message": "Stream not found" })),
+            RadioManagerClientError::ChannelNotFound
+        ));
+        assert!(matches!(
+            error_for(serde_json::json!({ "code": 0, "message": "Upload quota exceeded" })),
+            RadioManagerClientError::QuotaExceeded
+        ));
+        assert!(matches!(
+            error_for(serde_json::json!({ "code": 5, "message": "Something broke" })),
+            RadioManagerClientError::Other(5, message) if message == "Something broke"
+        ));
+    }
 
-        Ok(())
+    #[actix_rt::test]
+    async fn test_adding_no_tracks_to_channel() {
+        let client = create_client("http://127.0.0.1:9/", UploadLimits::default());
+
+        let link_ids = client
+            .add_tracks_to_channel(&[], &RadioManagerChannelId(1))
+            .await
+            .unwrap();
+
+        assert!(link_ids.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_rejecting_too_large_file() {
+        let client = create_client(
+            "http://127.0.0.1:9/",
+            UploadLimits {
+                max_file_size: Some(16),
+                ..UploadLimits::default()
+            },
+        );
+
+        let result = client
+            .upload_track("tests/fixtures/tagged.flac", None)
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(RadioManagerClientError::FileTooLarge { max_size: 16, .. })
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn test_timing_out_upload() {
+        // Accepts connections, but never responds.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = create_client(
+            &format!("http://{}/", listener.local_addr().unwrap()),
+            UploadLimits {
+                timeout: Duration::from_millis(200),
+                ..UploadLimits::default()
+            },
+        );
+
+        let result = client
+            .upload_track("tests/fixtures/tagged.flac", None)
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(RadioManagerClientError::UploadTimeout)
+        ));
+    }
+
+    /// Session of the fake RadioManager, which is expired until the client logs in.
+    #[derive(Default)]
+    struct FakeSession {
+        logins: usize,
+        expired: bool,
+        // Login doesn't help, e.g. the account has been blocked.
+        always_expired: bool,
+    }
+
+    async fn fake_login(session: Data<Arc<Mutex<FakeSession>>>) -> HttpResponse {
+        let mut session = session.lock().unwrap();
+        session.logins += 1;
+        session.expired = session.always_expired;
+
+        HttpResponse::Ok().json(serde_json::json!({ "code": 1, "message": "OK", "data": {} }))
+    }
+
+    async fn fake_add_tracks(session: Data<Arc<Mutex<FakeSession>>>) -> HttpResponse {
+        match session.lock().unwrap().expired {
+            true => HttpResponse::Unauthorized().finish(),
+            false => HttpResponse::Ok().json(serde_json::json!({ "code": 1, "message": "OK" })),
+        }
+    }
+
+    fn start_fake_radio_manager(session: Arc<Mutex<FakeSession>>) -> String {
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(Data::new(Arc::clone(&session)))
+                .route("/api/v2/user/login", web::post().to(fake_login))
+                .route("/api/v2/stream/addTracks", web::post().to(fake_add_tracks))
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let address = server.addrs()[0];
+        actix_rt::spawn(server.run());
+
+        format!("http://{}/", address)
+    }
+
+    #[actix_rt::test]
+    async fn test_logging_in_again_once_session_expired() {
+        let session = Arc::new(Mutex::new(FakeSession {
+            expired: true,
+            ..FakeSession::default()
+        }));
+        let client = create_client(
+            &start_fake_radio_manager(session.clone()),
+            UploadLimits::default(),
+        );
+
+        let result = client
+            .add_track_to_channel(&RadioManagerTrackId(1), &RadioManagerChannelId(1))
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(1, session.lock().unwrap().logins);
+    }
+
+    #[actix_rt::test]
+    async fn test_giving_up_if_session_expired_after_login() {
+        let session = Arc::new(Mutex::new(FakeSession {
+            expired: true,
+            always_expired: true,
+            ..FakeSession::default()
+        }));
+        let client = create_client(
+            &start_fake_radio_manager(session.clone()),
+            UploadLimits::default(),
+        );
+
+        let result = client
+            .add_track_to_channel(&RadioManagerTrackId(1), &RadioManagerChannelId(1))
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(RadioManagerClientError::SessionExpired)
+        ));
+        assert_eq!(1, session.lock().unwrap().logins);
     }
 }
@@ -0,0 +1,96 @@
+use crate::types::UserId;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Token bucket per user, refilled evenly over a minute.
+/// A user may create up to `max_requests_per_minute` requests at once, then has to wait.
+pub(crate) struct RateLimiter {
+    max_requests_per_minute: Option<u32>,
+    buckets: Mutex<HashMap<UserId, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// Nothing is limited if `max_requests_per_minute` is not set.
+    pub(crate) fn new(max_requests_per_minute: Option<u32>) -> Self {
+        Self {
+            max_requests_per_minute,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token of the user. Returns the time until the next token
+    /// becomes available if there are none left.
+    pub(crate) fn try_acquire(&self, user_id: &UserId) -> Result<(), Duration> {
+        self.try_acquire_at(user_id, Instant::now())
+    }
+
+    fn try_acquire_at(&self, user_id: &UserId, now: Instant) -> Result<(), Duration> {
+        let capacity = match self.max_requests_per_minute {
+            Some(max_requests_per_minute) => f64::from(max_requests_per_minute),
+            None => return Ok(()),
+        };
+        let tokens_per_sec = capacity / 60.0;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(user_id.clone()).or_insert(Bucket {
+            tokens: capacity,
+            refilled_at: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * tokens_per_sec).min(capacity);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        match tokens_per_sec > 0.0 {
+            true => Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / tokens_per_sec,
+            )),
+            // Zero requests per minute means nothing can be created at all.
+            false => Err(Duration::from_secs(60)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limiting_requests_per_user() {
+        let rate_limiter = RateLimiter::new(Some(2));
+        let now = Instant::now();
+
+        assert_eq!(Ok(()), rate_limiter.try_acquire_at(&UserId(1), now));
+        assert_eq!(Ok(()), rate_limiter.try_acquire_at(&UserId(1), now));
+        assert_eq!(
+            Err(Duration::from_secs(30)),
+            rate_limiter.try_acquire_at(&UserId(1), now)
+        );
+        // Buckets of other users are not affected.
+        assert_eq!(Ok(()), rate_limiter.try_acquire_at(&UserId(2), now));
+
+        let later = now + Duration::from_secs(30);
+        assert_eq!(Ok(()), rate_limiter.try_acquire_at(&UserId(1), later));
+        assert!(rate_limiter.try_acquire_at(&UserId(1), later).is_err());
+    }
+
+    #[test]
+    fn test_not_limiting_requests_without_limit() {
+        let rate_limiter = RateLimiter::new(None);
+
+        for _ in 0..100 {
+            assert_eq!(Ok(()), rate_limiter.try_acquire(&UserId(1)));
+        }
+    }
+}
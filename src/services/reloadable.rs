@@ -0,0 +1,39 @@
+use std::sync::{Arc, RwLock};
+
+/// Shared value that can be replaced at runtime, e.g. a client logged in with new credentials.
+/// Callers holding the previous value keep using it until they are done with it.
+pub(crate) struct Reloadable<T> {
+    current: RwLock<Arc<T>>,
+}
+
+impl<T> Reloadable<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(value)),
+        }
+    }
+
+    pub(crate) fn get(&self) -> Arc<T> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    pub(crate) fn replace(&self, value: T) {
+        *self.current.write().unwrap() = Arc::new(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replacing_value() {
+        let reloadable = Reloadable::new("old");
+        let in_use = reloadable.get();
+
+        reloadable.replace("new");
+
+        assert_eq!("old", *in_use);
+        assert_eq!("new", *reloadable.get());
+    }
+}
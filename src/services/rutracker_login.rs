@@ -0,0 +1,66 @@
+use crate::services::HttpClientOptions;
+use search_providers::{
+    CategoryFilter, KeywordWeights, RequestLimits, RuTrackerClient, RuTrackerClientError,
+};
+use std::path::PathBuf;
+
+/// Credentials the bot is logged in to RuTracker with, replaced once an admin
+/// logs it in with new ones, so later logins don't fall back to the startup ones.
+pub(crate) struct RuTrackerCredentials {
+    pub(crate) username: String,
+    pub(crate) password: String,
+}
+
+/// Settings of the RuTracker client kept between logins,
+/// so the client logged in with new credentials behaves the same as the previous one.
+#[derive(Clone, Debug)]
+pub(crate) struct RuTrackerLogin {
+    pub(crate) http_client_options: HttpClientOptions,
+    pub(crate) request_limits: RequestLimits,
+    pub(crate) category_filter: CategoryFilter,
+    pub(crate) keyword_weights: KeywordWeights,
+    pub(crate) html_dump_directory: Option<PathBuf>,
+}
+
+impl RuTrackerLogin {
+    pub(crate) async fn login(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<RuTrackerClient, RuTrackerClientError> {
+        let client = RuTrackerClient::create(
+            self.http_client_options.client_builder(),
+            username,
+            password,
+        )
+        .await?;
+
+        Ok(self.configure(client))
+    }
+
+    /// Logs in to a mirror of RuTracker with the same credentials.
+    pub(crate) async fn login_on_host(
+        &self,
+        host: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<RuTrackerClient, RuTrackerClientError> {
+        let client = RuTrackerClient::create_on_host(
+            self.http_client_options.client_builder(),
+            host,
+            username,
+            password,
+        )
+        .await?;
+
+        Ok(self.configure(client))
+    }
+
+    fn configure(&self, client: RuTrackerClient) -> RuTrackerClient {
+        client
+            .with_request_limits(self.request_limits.clone())
+            .with_category_filter(self.category_filter.clone())
+            .with_keyword_weights(self.keyword_weights.clone())
+            .with_html_dump_directory(self.html_dump_directory.clone())
+    }
+}
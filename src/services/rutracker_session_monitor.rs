@@ -0,0 +1,140 @@
+use crate::services::{Reloadable, RuTrackerCredentials, RuTrackerLogin};
+use search_providers::{RuTrackerClient, RuTrackerClientError};
+use serde::Serialize;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn};
+
+/// Result of the last check of the RuTracker session.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SessionCheck {
+    pub(crate) valid: bool,
+    /// Unix timestamp in seconds.
+    pub(crate) checked_at: u64,
+}
+
+impl SessionCheck {
+    /// Gauges of the check in the Prometheus text format.
+    pub(crate) fn render_metrics(&self) -> String {
+        let mut metrics = String::new();
+        let _ = writeln!(metrics, "# TYPE rutracker_session_valid gauge");
+        let _ = writeln!(metrics, "rutracker_session_valid {}", u8::from(self.valid));
+        let _ = writeln!(metrics, "# TYPE rutracker_session_checked_at_seconds gauge");
+        let _ = writeln!(
+            metrics,
+            "rutracker_session_checked_at_seconds {}",
+            self.checked_at
+        );
+
+        metrics
+    }
+}
+
+/// Periodically checks that RuTracker still considers the bot logged in, so an expired
+/// session shows up before requests start failing. The client is logged in again with
+/// the current credentials once the session is found expired.
+pub(crate) struct RuTrackerSessionMonitor {
+    rutracker_client: Arc<Reloadable<RuTrackerClient>>,
+    rutracker_login: Arc<RuTrackerLogin>,
+    rutracker_credentials: Arc<Reloadable<RuTrackerCredentials>>,
+    last_check: Mutex<Option<SessionCheck>>,
+}
+
+impl RuTrackerSessionMonitor {
+    pub(crate) fn new(
+        rutracker_client: Arc<Reloadable<RuTrackerClient>>,
+        rutracker_login: Arc<RuTrackerLogin>,
+        rutracker_credentials: Arc<Reloadable<RuTrackerCredentials>>,
+    ) -> Self {
+        Self {
+            rutracker_client,
+            rutracker_login,
+            rutracker_credentials,
+            last_check: Mutex::new(None),
+        }
+    }
+
+    /// `None` until the first check has finished.
+    pub(crate) fn last_check(&self) -> Option<SessionCheck> {
+        *self.last_check.lock().unwrap()
+    }
+
+    pub(crate) async fn run(&self, check_interval: Duration) {
+        loop {
+            actix_rt::time::sleep(check_interval).await;
+            self.check().await;
+        }
+    }
+
+    async fn check(&self) {
+        let valid = match self.rutracker_client.get().check_connection().await {
+            Ok(()) => {
+                debug!("RuTracker session is valid");
+                true
+            }
+            Err(error @ RuTrackerClientError::AuthError(_)) => {
+                warn!(?error, "RuTracker session has expired, logging in again...");
+                self.login_again().await
+            }
+            Err(error) => {
+                error!(?error, "Unable to check RuTracker session");
+                false
+            }
+        };
+
+        self.last_check.lock().unwrap().replace(SessionCheck {
+            valid,
+            checked_at: unix_timestamp(),
+        });
+    }
+
+    async fn login_again(&self) -> bool {
+        let credentials = self.rutracker_credentials.get();
+
+        match self
+            .rutracker_login
+            .login(&credentials.username, &credentials.password)
+            .await
+        {
+            Ok(client) => {
+                self.rutracker_client.replace(client);
+                info!("Logged in to RuTracker again");
+                true
+            }
+            Err(error) => {
+                error!(?error, "Unable to log in to RuTracker again");
+                false
+            }
+        }
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rendering_session_check_as_gauges() {
+        let check = SessionCheck {
+            valid: false,
+            checked_at: 1700000000,
+        };
+
+        assert_eq!(
+            "# TYPE rutracker_session_valid gauge\n\
+            rutracker_session_valid 0\n\
+            # TYPE rutracker_session_checked_at_seconds gauge\n\
+            rutracker_session_checked_at_seconds 1700000000\n",
+            check.render_metrics()
+        );
+    }
+}
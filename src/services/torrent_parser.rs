@@ -1,5 +1,12 @@
+use crate::utils::eq_ignore_case;
 use serde::Deserialize;
 use serde_bytes::ByteBuf;
+use sha1::{Digest, Sha1};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Audio formats album images are usually ripped to.
+const IMAGE_AUDIO_EXTENSIONS: [&str; 4] = ["flac", "ape", "wv", "wav"];
 
 #[derive(Debug, Deserialize)]
 struct Node(String, i64);
@@ -60,27 +67,218 @@ struct Torrent {
     created_by: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct RawTorrent {
+    info: serde_bencode::value::Value,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum TorrentParserError {
     #[error(transparent)]
     SerdeError(#[from] serde_bencode::Error),
 }
 
-pub(crate) fn get_files_count(torrent_file_content: &[u8]) -> Result<usize, TorrentParserError> {
-    let torrent = serde_bencode::from_bytes::<Torrent>(torrent_file_content)?;
+impl Info {
+    /// Single-file torrents have no `files` list, the torrent name is the file name instead.
+    fn into_files(self) -> Vec<(String, u64)> {
+        match self.files {
+            Some(files) => files
+                .into_iter()
+                .map(|f| {
+                    (
+                        f.path.join(std::path::MAIN_SEPARATOR_STR),
+                        f.length.max(0) as u64,
+                    )
+                })
+                .collect(),
+            None => vec![(self.name, self.length.unwrap_or_default().max(0) as u64)],
+        }
+    }
+}
 
-    Ok(torrent.info.files.unwrap_or_default().len())
+pub(crate) fn get_files_count(torrent_file_content: &[u8]) -> Result<usize, TorrentParserError> {
+    Ok(get_files_with_sizes(torrent_file_content)?.len())
 }
 
 pub(crate) fn get_files(torrent_file_content: &[u8]) -> Result<Vec<String>, TorrentParserError> {
+    Ok(get_files_with_sizes(torrent_file_content)?
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect())
+}
+
+pub(crate) fn get_files_with_sizes(
+    torrent_file_content: &[u8],
+) -> Result<Vec<(String, u64)>, TorrentParserError> {
     let torrent = serde_bencode::from_bytes::<Torrent>(torrent_file_content)?;
 
-    Ok(torrent
-        .info
-        .files
-        .unwrap_or_default()
-        .into_iter()
-        .map(|f| f.path.join(std::path::MAIN_SEPARATOR_STR))
+    Ok(torrent.info.into_files())
+}
+
+fn has_extension(path: &str, extensions: &[&str]) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extensions
+                .iter()
+                .any(|expected| extension.eq_ignore_ascii_case(expected))
+        })
+}
+
+/// How the album is laid out in the torrent, told by its file list.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ReleaseLayout {
+    /// Separate file for each track.
+    Tracks,
+    /// Separate file for each track along with a `.cue` sheet, e.g. `FLAC (tracks+.cue)`.
+    TracksWithCue,
+    /// Whole album in a single audio file along with a `.cue` sheet,
+    /// so the tracks can only be had by splitting the image.
+    CueImage,
+}
+
+pub(crate) fn get_release_layout(files: &[String]) -> ReleaseLayout {
+    let audio_files_count = files
+        .iter()
+        .filter(|path| has_extension(path, &IMAGE_AUDIO_EXTENSIONS))
+        .count();
+
+    match files.iter().any(|path| has_extension(path, &["cue"])) {
+        true if audio_files_count == 1 => ReleaseLayout::CueImage,
+        true => ReleaseLayout::TracksWithCue,
+        false => ReleaseLayout::Tracks,
+    }
+}
+
+/// Whether the file may hold a single track. `.cue` sheets and the album images
+/// they describe, named after the sheet, are never selected even if their names
+/// contain the title, e.g. for the title track of the album.
+pub(crate) fn is_track_file(path: &str, files: &[String]) -> bool {
+    if has_extension(path, &["cue"]) {
+        return false;
+    }
+    if !has_extension(path, &IMAGE_AUDIO_EXTENSIONS) {
+        return true;
+    }
+
+    let path = Path::new(path);
+
+    !files
+        .iter()
+        .map(Path::new)
+        .filter(|file| has_extension(&file.to_string_lossy(), &["cue"]))
+        .any(|cue| cue.with_extension("") == path.with_extension(""))
+}
+
+/// Disc of the file in a multi-disc release, told by a folder like `CD1`, `CD 2` or `Disc 3`.
+pub(crate) fn get_disc_number(path: &str) -> Option<u32> {
+    let mut folders = Path::new(path).parent()?.components().rev();
+
+    folders.find_map(|folder| {
+        let folder = folder.as_os_str().to_str()?.to_lowercase();
+        let rest = ["cd", "disc", "disk"]
+            .iter()
+            .find_map(|prefix| folder.strip_prefix(prefix))?
+            .trim_start_matches([' ', '_', '.']);
+        let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+
+        digits.parse().ok()
+    })
+}
+
+/// Whether the file is named exactly after the title, apart from the track number
+/// and the artist, e.g. `01. Ted Irens - Sunday Breakfast.flac` for `Sunday Breakfast`.
+pub(crate) fn is_exact_title_match(path: &str, title: &str) -> bool {
+    let name = match Path::new(path).file_stem().and_then(|stem| stem.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+    let without_track_number = name.trim_start_matches(|c: char| {
+        c.is_ascii_digit() || matches!(c, '.' | '-' | '_' | ')' | ' ')
+    });
+    let title = title.trim();
+
+    eq_ignore_case(name, title)
+        || eq_ignore_case(without_track_number, title)
+        || name
+            .to_lowercase()
+            .ends_with(&format!(" - {}", title.to_lowercase()))
+}
+
+/// Narrows the files matching the title down to the exact matches if the matches are
+/// spread over several discs, e.g. `CD1/01. Intro.flac` and `CD2/01. Intro (Reprise).flac`
+/// for `Intro`. Keeps all the matches if none of them is exact.
+pub(crate) fn prefer_exact_matches_across_discs(
+    matching_indexes: Vec<usize>,
+    files: &[String],
+    title: &str,
+) -> Vec<usize> {
+    let discs: HashSet<_> = matching_indexes
+        .iter()
+        .map(|index| get_disc_number(&files[*index]))
+        .collect();
+    if discs.len() < 2 {
+        return matching_indexes;
+    }
+
+    let exact_indexes: Vec<_> = matching_indexes
+        .iter()
+        .copied()
+        .filter(|index| is_exact_title_match(&files[*index], title))
+        .collect();
+
+    match exact_indexes.is_empty() {
+        true => matching_indexes,
+        false => exact_indexes,
+    }
+}
+
+/// Picks the file whose name has the most words of the free-text query, e.g.
+/// `01. Children (Dream Version).flac` for `robert miles children`. Earlier files win ties.
+/// Returns `None` if none of the file names has any word of the query.
+pub(crate) fn find_best_matching_file(
+    indexes: Vec<usize>,
+    files: &[String],
+    query: &str,
+) -> Option<usize> {
+    let query_words = get_words(query);
+
+    let mut best_match = None;
+    let mut best_score = 0;
+    for index in indexes {
+        let name = Path::new(&files[index])
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default();
+        let name_words = get_words(name);
+        let score = query_words.intersection(&name_words).count();
+
+        if score > best_score {
+            best_match = Some(index);
+            best_score = score;
+        }
+    }
+
+    best_match
+}
+
+fn get_words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Returns hex-encoded SHA-1 hash of the bencoded `info` dictionary,
+/// the same value torrent clients use to identify the torrent.
+pub(crate) fn get_info_hash(torrent_file_content: &[u8]) -> Result<String, TorrentParserError> {
+    let torrent = serde_bencode::from_bytes::<RawTorrent>(torrent_file_content)?;
+    let info = serde_bencode::to_bytes(&torrent.info)?;
+
+    Ok(Sha1::digest(info)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
         .collect())
 }
 
@@ -125,4 +323,167 @@ mod tests {
             files
         );
     }
+
+    #[test]
+    fn test_getting_files_with_sizes() {
+        let contents = include_bytes!("../../tests/fixtures/example.torrent");
+        let files = get_files_with_sizes(contents).unwrap();
+
+        assert_eq!(get_files(contents).unwrap().len(), files.len());
+        assert_eq!("01. Ted Irens - Sunday Breakfast.flac", files[2].0);
+        assert!(files.iter().all(|(_, size)| *size > 0));
+    }
+
+    #[test]
+    fn test_getting_single_file() {
+        let contents = b"d4:infod6:lengthi1024e4:name25:01 - Sunday Breakfast.mp3\
+            12:piece lengthi16384e6:pieces20:01234567890123456789ee";
+
+        assert_eq!(1, get_files_count(contents).unwrap());
+        assert_eq!(
+            vec![("01 - Sunday Breakfast.mp3".to_string(), 1024)],
+            get_files_with_sizes(contents).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_detecting_release_layout() {
+        let layout = |contents: &[u8]| get_release_layout(&get_files(contents).unwrap());
+
+        assert_eq!(
+            ReleaseLayout::Tracks,
+            layout(include_bytes!("../../tests/fixtures/example.torrent"))
+        );
+        assert_eq!(
+            ReleaseLayout::TracksWithCue,
+            layout(include_bytes!(
+                "../../tests/fixtures/tracks_with_cue.torrent"
+            ))
+        );
+        assert_eq!(
+            ReleaseLayout::CueImage,
+            layout(include_bytes!("../../tests/fixtures/image.torrent"))
+        );
+        assert_eq!(
+            ReleaseLayout::CueImage,
+            get_release_layout(&[
+                "Ted Irens - Life @ Mirror.cue".to_string(),
+                "Ted Irens - Life @ Mirror.FLAC".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_selecting_track_files() {
+        let track_files = |contents: &[u8]| {
+            let files = get_files(contents).unwrap();
+            files
+                .iter()
+                .filter(|path| is_track_file(path, &files))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![
+                "01. Ted Irens - Sunday Breakfast.flac",
+                "02. Ted Irens - Rain In The Forest.flac",
+                "03. Ted Irens - Another Moon Night.flac",
+                "Ted Irens - Life @ Mirror.log",
+            ],
+            track_files(include_bytes!(
+                "../../tests/fixtures/tracks_with_cue.torrent"
+            ))
+        );
+        assert_eq!(
+            vec!["Ted Irens - Life @ Mirror.log", "Scans/Front.jpg"],
+            track_files(include_bytes!("../../tests/fixtures/image.torrent"))
+        );
+    }
+
+    #[test]
+    fn test_getting_disc_number() {
+        assert_eq!(Some(1), get_disc_number("CD1/01. Sunday Breakfast.flac"));
+        assert_eq!(
+            Some(2),
+            get_disc_number("Album/CD 2/01. Sunday Breakfast.flac")
+        );
+        assert_eq!(Some(3), get_disc_number("Disc 3/01. Sunday Breakfast.flac"));
+        assert_eq!(
+            Some(4),
+            get_disc_number("disk_4 - Bonus/01. Sunday Breakfast.flac")
+        );
+        assert_eq!(None, get_disc_number("01. Sunday Breakfast.flac"));
+        assert_eq!(
+            None,
+            get_disc_number("Discography/01. Sunday Breakfast.flac")
+        );
+    }
+
+    #[test]
+    fn test_matching_exact_title() {
+        assert!(is_exact_title_match(
+            "CD1/01. Ted Irens - Sunday Breakfast.flac",
+            "Sunday Breakfast"
+        ));
+        assert!(is_exact_title_match(
+            "CD1/1-01 sunday breakfast.mp3",
+            "Sunday Breakfast"
+        ));
+        assert!(!is_exact_title_match(
+            "CD2/01. Ted Irens - Sunday Breakfast (Live).flac",
+            "Sunday Breakfast"
+        ));
+    }
+
+    #[test]
+    fn test_selecting_exact_matches_in_multi_disc_release() {
+        let files = get_files(include_bytes!("../../tests/fixtures/multi_disc.torrent")).unwrap();
+        let select = |title: &str| {
+            let matching_indexes = files
+                .iter()
+                .enumerate()
+                .filter(|(_, path)| crate::utils::contains_in_filename_ignore_case(path, title))
+                .map(|(index, _)| index)
+                .collect();
+
+            prefer_exact_matches_across_discs(matching_indexes, &files, title)
+        };
+
+        assert_eq!(vec![0], select("Sunday Breakfast"));
+        assert_eq!(vec![1], select("Rain In The Forest"));
+        // None of the matches is exact, so all of them are kept.
+        assert_eq!(vec![0, 2, 4], select("Sunday"));
+        // Matches on a single disc are kept as they are.
+        assert_eq!(vec![3], select("Remix"));
+    }
+
+    #[test]
+    fn test_finding_best_matching_file() {
+        let files = get_files(include_bytes!("../../tests/fixtures/example.torrent")).unwrap();
+        let indexes = (0..files.len()).collect::<Vec<_>>();
+
+        assert_eq!(
+            Some("01. Ted Irens - Sunday Breakfast.flac"),
+            find_best_matching_file(indexes.clone(), &files, "ted irens sunday breakfast")
+                .map(|index| files[index].as_str())
+        );
+        assert_eq!(
+            Some("13. Ted Irens - Dancing On The Moon.flac"),
+            find_best_matching_file(indexes.clone(), &files, "dancing moon")
+                .map(|index| files[index].as_str())
+        );
+        assert_eq!(
+            None,
+            find_best_matching_file(indexes, &files, "robert miles children")
+        );
+    }
+
+    #[test]
+    fn test_getting_info_hash() {
+        let contents = include_bytes!("../../tests/fixtures/example.torrent");
+        let info_hash = get_info_hash(contents).unwrap();
+
+        assert_eq!("2ec5340de73d63d6faa162a9dd435acb462d8737", info_hash);
+    }
 }
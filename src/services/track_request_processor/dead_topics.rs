@@ -0,0 +1,74 @@
+use super::track_request_processor::TopicId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+
+/// Topics whose torrents failed or didn't complete in time, not to be downloaded again.
+/// Only the most recent ones are kept, as the set is written with the state on every step.
+/// Stored as a list of ids in the order the topics died, same as it used to be.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Vec<TopicId>", into = "Vec<TopicId>")]
+pub(crate) struct DeadTopics {
+    order: VecDeque<TopicId>,
+    ids: HashSet<TopicId>,
+}
+
+impl DeadTopics {
+    pub(crate) fn contains(&self, topic_id: &TopicId) -> bool {
+        self.ids.contains(topic_id)
+    }
+
+    /// Adds the topic, evicting the ones that died first to keep at most `max_len` topics.
+    pub(crate) fn insert(&mut self, topic_id: TopicId, max_len: usize) {
+        if self.ids.insert(topic_id.clone()) {
+            self.order.push_back(topic_id);
+        }
+
+        while self.order.len() > max_len {
+            if let Some(evicted) = self.order.pop_front() {
+                self.ids.remove(&evicted);
+            }
+        }
+    }
+}
+
+impl From<Vec<TopicId>> for DeadTopics {
+    fn from(topic_ids: Vec<TopicId>) -> Self {
+        let mut dead_topics = Self::default();
+        for topic_id in topic_ids {
+            dead_topics.insert(topic_id, usize::MAX);
+        }
+        dead_topics
+    }
+}
+
+impl From<DeadTopics> for Vec<TopicId> {
+    fn from(dead_topics: DeadTopics) -> Self {
+        dead_topics.order.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicting_topics_that_died_first() {
+        let mut dead_topics = DeadTopics::from(vec![TopicId(1), TopicId(2)]);
+
+        dead_topics.insert(TopicId(3), 2);
+        dead_topics.insert(TopicId(3), 2);
+
+        assert!(!dead_topics.contains(&TopicId(1)));
+        assert!(dead_topics.contains(&TopicId(2)));
+        assert!(dead_topics.contains(&TopicId(3)));
+        assert_eq!(vec![TopicId(2), TopicId(3)], Vec::from(dead_topics));
+    }
+
+    #[test]
+    fn test_reading_topics_stored_as_list() {
+        let dead_topics: DeadTopics = serde_json::from_str("[1,2]").unwrap();
+
+        assert!(dead_topics.contains(&TopicId(1)));
+        assert_eq!("[1,2]", serde_json::to_string(&dead_topics).unwrap());
+    }
+}
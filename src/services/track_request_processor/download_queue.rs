@@ -0,0 +1,156 @@
+use super::track_request_processor::{RequestId, RequestPriority};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+
+/// Limited download slots given out to the waiting requests by priority, then in the
+/// order they started waiting, so manual requests jump ahead of a backlog of suggestions.
+pub(crate) struct DownloadQueue {
+    max_active_downloads: usize,
+    slots: Mutex<Slots>,
+}
+
+#[derive(Default)]
+struct Slots {
+    active: HashSet<RequestId>,
+    waiting: BTreeMap<(Reverse<RequestPriority>, u64), RequestId>,
+    waiting_keys: HashMap<RequestId, (Reverse<RequestPriority>, u64)>,
+    next_seq: u64,
+}
+
+impl DownloadQueue {
+    pub(crate) fn new(max_active_downloads: usize) -> Self {
+        Self {
+            max_active_downloads,
+            slots: Mutex::new(Slots::default()),
+        }
+    }
+
+    /// Takes a download slot for the request, unless it already has one. Returns `false`
+    /// and keeps the request in the queue if all the slots are taken, or if a free one
+    /// is due to a request ahead in the queue.
+    pub(crate) fn try_acquire(&self, request_id: &RequestId, priority: RequestPriority) -> bool {
+        let mut slots = self.slots.lock().unwrap();
+        if slots.active.contains(request_id) {
+            return true;
+        }
+
+        let key = match slots.waiting_keys.get(request_id) {
+            Some(key) => *key,
+            None => {
+                let key = (Reverse(priority), slots.next_seq);
+                slots.next_seq += 1;
+                slots.waiting.insert(key, request_id.clone());
+                slots.waiting_keys.insert(request_id.clone(), key);
+                key
+            }
+        };
+
+        let is_next = slots.waiting.keys().next() == Some(&key);
+        if !is_next || slots.active.len() >= self.max_active_downloads {
+            return false;
+        }
+
+        slots.waiting.remove(&key);
+        slots.waiting_keys.remove(request_id);
+        slots.active.insert(request_id.clone());
+
+        true
+    }
+
+    /// Takes a free download slot for the request bypassing the queue, e.g. for a download
+    /// which is already running. Returns `false` if all the slots are taken.
+    pub(crate) fn try_acquire_free(&self, request_id: &RequestId) -> bool {
+        let mut slots = self.slots.lock().unwrap();
+        if slots.active.contains(request_id) {
+            return true;
+        }
+        if slots.active.len() >= self.max_active_downloads {
+            return false;
+        }
+
+        slots.active.insert(request_id.clone());
+
+        true
+    }
+
+    /// Frees the slot of the request or drops it from the queue.
+    pub(crate) fn release(&self, request_id: &RequestId) {
+        let mut slots = self.slots.lock().unwrap();
+        slots.active.remove(request_id);
+        if let Some(key) = slots.waiting_keys.remove(request_id) {
+            slots.waiting.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn request_id() -> RequestId {
+        RequestId(Uuid::new_v4())
+    }
+
+    #[test]
+    fn test_giving_free_slots_to_manual_requests_first() {
+        let queue = DownloadQueue::new(1);
+        let (first, suggestion, manual) = (request_id(), request_id(), request_id());
+
+        assert!(queue.try_acquire(&first, RequestPriority::Suggestion));
+        assert!(!queue.try_acquire(&suggestion, RequestPriority::Suggestion));
+        assert!(!queue.try_acquire(&manual, RequestPriority::Manual));
+
+        queue.release(&first);
+
+        // The suggestion has been waiting longer, but the manual request goes first.
+        assert!(!queue.try_acquire(&suggestion, RequestPriority::Suggestion));
+        assert!(queue.try_acquire(&manual, RequestPriority::Manual));
+        assert!(queue.try_acquire(&manual, RequestPriority::Manual));
+
+        queue.release(&manual);
+        assert!(queue.try_acquire(&suggestion, RequestPriority::Suggestion));
+    }
+
+    #[test]
+    fn test_giving_free_slots_in_waiting_order_within_priority() {
+        let queue = DownloadQueue::new(1);
+        let (first, second, third) = (request_id(), request_id(), request_id());
+
+        assert!(queue.try_acquire(&first, RequestPriority::Manual));
+        assert!(!queue.try_acquire(&second, RequestPriority::Manual));
+        assert!(!queue.try_acquire(&third, RequestPriority::Manual));
+
+        queue.release(&first);
+        assert!(!queue.try_acquire(&third, RequestPriority::Manual));
+        assert!(queue.try_acquire(&second, RequestPriority::Manual));
+    }
+
+    #[test]
+    fn test_dropping_released_requests_from_queue() {
+        let queue = DownloadQueue::new(1);
+        let (first, gone, waiting) = (request_id(), request_id(), request_id());
+
+        assert!(queue.try_acquire(&first, RequestPriority::Manual));
+        assert!(!queue.try_acquire(&gone, RequestPriority::Manual));
+        assert!(!queue.try_acquire(&waiting, RequestPriority::Suggestion));
+
+        queue.release(&gone);
+        queue.release(&first);
+        assert!(queue.try_acquire(&waiting, RequestPriority::Suggestion));
+    }
+
+    #[test]
+    fn test_taking_free_slot_bypassing_queue() {
+        let queue = DownloadQueue::new(1);
+        let (resumed, waiting) = (request_id(), request_id());
+
+        assert!(queue.try_acquire_free(&resumed));
+        assert!(!queue.try_acquire(&waiting, RequestPriority::Manual));
+        assert!(!queue.try_acquire_free(&waiting));
+
+        queue.release(&resumed);
+        assert!(queue.try_acquire(&waiting, RequestPriority::Manual));
+    }
+}
@@ -4,8 +4,15 @@ pub(crate) use track_request_processor::*;
 pub(crate) mod track_request_controller;
 pub(crate) use track_request_controller::*;
 
+pub(crate) mod dead_topics;
+
+pub(crate) mod download_queue;
+
 #[cfg(test)]
 mod processor_tests;
 
+#[cfg(test)]
+mod pipeline_tests;
+
 #[cfg(test)]
 mod step_tests;
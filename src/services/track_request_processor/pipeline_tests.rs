@@ -0,0 +1,671 @@
+//! End-to-end tests driving track requests through every processing step using
+//! scriptable mocks of the processor dependencies.
+
+use super::processor_tests::StateStorageMock;
+use super::track_request_processor::{
+    AudioMetadata, CreateRequestOptions, DownloadId, DownloadedTorrentRecord, IdempotencyRecord,
+    MetadataServiceError, MetadataServiceTrait, NotFoundRecord, PollIntervals, ProcessRequestError,
+    ProvenanceRecord, RadioManagerChannelId, RadioManagerChannelTrack, RadioManagerClientError,
+    RadioManagerClientTrait, RadioManagerLinkId, RadioManagerTrackId, RequestId, RequestPriority,
+    SearchProviderError, SearchProviderTrait, SeedingTorrentRecord, StateStorageError,
+    StateStorageTrait, TitleMatch, TopicData, TopicId, Torrent, TorrentClientError,
+    TorrentClientTrait, TorrentId, TorrentStatus, TrackRequestProcessingContext,
+    TrackRequestProcessingState, TrackRequestProcessingStatus, TrackRequestProcessingStep,
+    TrackRequestProcessor,
+};
+use crate::services::DownloadDirectories;
+use crate::types::UserId;
+use async_trait::async_trait;
+use std::collections::{HashMap, VecDeque};
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const EXAMPLE_TORRENT: &[u8] = include_bytes!("../../../tests/fixtures/example.torrent");
+
+/// Wraps the in-memory state storage and records the next step after every state update.
+struct RecordingStateStorage {
+    inner: StateStorageMock,
+    steps: Mutex<Vec<TrackRequestProcessingStep>>,
+}
+
+impl RecordingStateStorage {
+    fn new() -> Self {
+        Self {
+            inner: StateStorageMock::new(),
+            steps: Mutex::new(vec![]),
+        }
+    }
+
+    fn take_steps(&self) -> Vec<TrackRequestProcessingStep> {
+        std::mem::take(&mut self.steps.lock().unwrap())
+    }
+}
+
+#[async_trait]
+impl StateStorageTrait for RecordingStateStorage {
+    async fn create_state(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        state: TrackRequestProcessingState,
+    ) -> Result<(), StateStorageError> {
+        self.inner.create_state(user_id, request_id, state).await
+    }
+
+    async fn create_context(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        ctx: TrackRequestProcessingContext,
+    ) -> Result<(), StateStorageError> {
+        self.inner.create_context(user_id, request_id, ctx).await
+    }
+
+    async fn update_context(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        ctx: &TrackRequestProcessingContext,
+    ) -> Result<(), StateStorageError> {
+        self.inner.update_context(user_id, request_id, ctx).await
+    }
+
+    async fn update_state(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        state: &TrackRequestProcessingState,
+    ) -> Result<(), StateStorageError> {
+        self.steps.lock().unwrap().push(state.get_step());
+        self.inner.update_state(user_id, request_id, state).await
+    }
+
+    async fn update_status(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        status: &TrackRequestProcessingStatus,
+    ) -> Result<(), StateStorageError> {
+        self.inner.update_status(user_id, request_id, status).await
+    }
+
+    async fn load_state(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<TrackRequestProcessingState, StateStorageError> {
+        self.inner.load_state(user_id, request_id).await
+    }
+
+    async fn load_context(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<TrackRequestProcessingContext, StateStorageError> {
+        self.inner.load_context(user_id, request_id).await
+    }
+
+    async fn delete_state(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<(), StateStorageError> {
+        self.inner.delete_state(user_id, request_id).await
+    }
+
+    async fn delete_context(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<(), StateStorageError> {
+        self.inner.delete_context(user_id, request_id).await
+    }
+
+    async fn delete_status(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<(), StateStorageError> {
+        self.inner.delete_status(user_id, request_id).await
+    }
+
+    async fn get_all_statuses(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<RequestId, TrackRequestProcessingStatus>, StateStorageError> {
+        self.inner.get_all_statuses(user_id).await
+    }
+
+    async fn get_all_contexts(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<RequestId, TrackRequestProcessingContext>, StateStorageError> {
+        self.inner.get_all_contexts(user_id).await
+    }
+
+    async fn get_status_owners(&self) -> Result<Vec<UserId>, StateStorageError> {
+        self.inner.get_status_owners().await
+    }
+
+    async fn get_all_tasks(&self) -> Result<Vec<(UserId, RequestId)>, StateStorageError> {
+        self.inner.get_all_tasks().await
+    }
+
+    async fn get_idempotency(
+        &self,
+        user_id: &UserId,
+        idempotency_key: &str,
+    ) -> Result<Option<IdempotencyRecord>, StateStorageError> {
+        self.inner.get_idempotency(user_id, idempotency_key).await
+    }
+
+    async fn set_idempotency(
+        &self,
+        user_id: &UserId,
+        idempotency_key: &str,
+        record: &IdempotencyRecord,
+    ) -> Result<(), StateStorageError> {
+        self.inner
+            .set_idempotency(user_id, idempotency_key, record)
+            .await
+    }
+
+    async fn add_not_found(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        record: &NotFoundRecord,
+    ) -> Result<(), StateStorageError> {
+        self.inner.add_not_found(user_id, request_id, record).await
+    }
+
+    async fn delete_not_found(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<(), StateStorageError> {
+        self.inner.delete_not_found(user_id, request_id).await
+    }
+
+    async fn get_all_not_found(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<RequestId, NotFoundRecord>, StateStorageError> {
+        self.inner.get_all_not_found(user_id).await
+    }
+
+    async fn set_provenance(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        record: &ProvenanceRecord,
+    ) -> Result<(), StateStorageError> {
+        self.inner.set_provenance(user_id, request_id, record).await
+    }
+
+    async fn get_provenance(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<Option<ProvenanceRecord>, StateStorageError> {
+        self.inner.get_provenance(user_id, request_id).await
+    }
+
+    async fn get_downloaded_torrent(
+        &self,
+        info_hash: &str,
+    ) -> Result<Option<DownloadedTorrentRecord>, StateStorageError> {
+        self.inner.get_downloaded_torrent(info_hash).await
+    }
+
+    async fn set_downloaded_torrent(
+        &self,
+        info_hash: &str,
+        record: &DownloadedTorrentRecord,
+    ) -> Result<(), StateStorageError> {
+        self.inner.set_downloaded_torrent(info_hash, record).await
+    }
+
+    async fn add_seeding_torrent(
+        &self,
+        torrent_id: &TorrentId,
+        record: &SeedingTorrentRecord,
+    ) -> Result<(), StateStorageError> {
+        self.inner.add_seeding_torrent(torrent_id, record).await
+    }
+
+    async fn get_all_seeding_torrents(
+        &self,
+    ) -> Result<HashMap<TorrentId, SeedingTorrentRecord>, StateStorageError> {
+        self.inner.get_all_seeding_torrents().await
+    }
+
+    async fn delete_seeding_torrent(
+        &self,
+        torrent_id: &TorrentId,
+    ) -> Result<(), StateStorageError> {
+        self.inner.delete_seeding_torrent(torrent_id).await
+    }
+}
+
+/// Returns the given topics for the album query and the example torrent for every topic.
+struct ScriptedSearchProvider {
+    topics: Vec<TopicData>,
+}
+
+impl ScriptedSearchProvider {
+    fn with_topics(count: u64) -> Self {
+        Self {
+            topics: (1..=count)
+                .map(|id| TopicData {
+                    title: format!("Ted Irens - Life @ Mirror #{}", id),
+                    topic_id: TopicId(id),
+                    download_id: DownloadId(id),
+                    format: None,
+                    year: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl SearchProviderTrait for ScriptedSearchProvider {
+    async fn find_all(&self, query: &str) -> Result<Vec<TopicData>, SearchProviderError> {
+        match query {
+            "Ted Irens - Life @ Mirror" => Ok(self.topics.clone()),
+            _ => Ok(vec![]),
+        }
+    }
+
+    async fn resolve_topic(
+        &self,
+        topic_id: &TopicId,
+    ) -> Result<Option<TopicData>, SearchProviderError> {
+        Ok(self
+            .topics
+            .iter()
+            .find(|topic| topic.topic_id == *topic_id)
+            .cloned())
+    }
+
+    async fn download_torrent(
+        &self,
+        download_id: &DownloadId,
+    ) -> Result<Vec<u8>, SearchProviderError> {
+        match self
+            .topics
+            .iter()
+            .any(|topic| topic.download_id == *download_id)
+        {
+            true => Ok(EXAMPLE_TORRENT.to_vec()),
+            false => Err(SearchProviderError(Box::new(Error::from(
+                ErrorKind::NotFound,
+            )))),
+        }
+    }
+}
+
+/// The n-th added torrent reports the n-th script of statuses, one per status check.
+/// The last status of a script is repeated once the script is exhausted.
+struct ScriptedTorrentClient {
+    scripts: Mutex<VecDeque<VecDeque<Torrent>>>,
+    torrents: Mutex<HashMap<TorrentId, VecDeque<Torrent>>>,
+}
+
+impl ScriptedTorrentClient {
+    fn new(scripts: Vec<Vec<Torrent>>) -> Self {
+        Self {
+            scripts: Mutex::new(scripts.into_iter().map(VecDeque::from).collect()),
+            torrents: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn added_torrents(&self) -> usize {
+        self.torrents.lock().unwrap().len()
+    }
+}
+
+#[async_trait]
+impl TorrentClientTrait for ScriptedTorrentClient {
+    async fn add_torrent(
+        &self,
+        _torrent_file_data: Vec<u8>,
+        _selected_files_indexes: Vec<i32>,
+        _request_id: &RequestId,
+        _download_dir: Option<&str>,
+    ) -> Result<TorrentId, TorrentClientError> {
+        let script = self
+            .scripts
+            .lock()
+            .unwrap()
+            .pop_front()
+            .expect("Unexpected torrent has been added");
+        let mut torrents = self.torrents.lock().unwrap();
+        let torrent_id = TorrentId(torrents.len() as i64 + 1);
+
+        torrents.insert(torrent_id.clone(), script);
+
+        Ok(torrent_id)
+    }
+
+    async fn get_torrent(
+        &self,
+        torrent_id: &TorrentId,
+    ) -> Result<Option<Torrent>, TorrentClientError> {
+        let mut torrents = self.torrents.lock().unwrap();
+        let script = match torrents.get_mut(torrent_id) {
+            Some(script) => script,
+            None => return Ok(None),
+        };
+
+        Ok(Some(match script.len() {
+            1 => script[0].clone(),
+            _ => script.pop_front().expect("Script should not be empty"),
+        }))
+    }
+
+    async fn delete_torrent(&self, torrent_id: &TorrentId) -> Result<(), TorrentClientError> {
+        self.torrents.lock().unwrap().remove(torrent_id);
+
+        Ok(())
+    }
+
+    async fn get_request_torrents(
+        &self,
+    ) -> Result<Vec<(TorrentId, Vec<RequestId>)>, TorrentClientError> {
+        Ok(vec![])
+    }
+
+    async fn pause_torrents(&self, _torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError> {
+        Ok(())
+    }
+
+    async fn resume_torrents(&self, _torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError> {
+        Ok(())
+    }
+
+    async fn check_session(&self) -> Result<(), TorrentClientError> {
+        Ok(())
+    }
+}
+
+/// Accepts every upload and records the uploaded paths.
+#[derive(Default)]
+struct RecordingRadioManager {
+    uploaded_files: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl RadioManagerClientTrait for RecordingRadioManager {
+    async fn upload_audio_track(
+        &self,
+        _user_id: &UserId,
+        path_to_audio_file: &str,
+        _file_name: Option<&str>,
+    ) -> Result<RadioManagerTrackId, RadioManagerClientError> {
+        let mut uploaded_files = self.uploaded_files.lock().unwrap();
+        uploaded_files.push(path_to_audio_file.to_string());
+
+        Ok(RadioManagerTrackId(uploaded_files.len() as u64))
+    }
+
+    async fn add_track_to_channel_playlist(
+        &self,
+        _user_id: &UserId,
+        track_id: &RadioManagerTrackId,
+        channel_id: &RadioManagerChannelId,
+    ) -> Result<RadioManagerLinkId, RadioManagerClientError> {
+        Ok(RadioManagerLinkId(format!("{}-{}", channel_id, track_id)))
+    }
+
+    async fn get_channel_tracks(
+        &self,
+        _channel_id: &RadioManagerChannelId,
+    ) -> Result<Vec<RadioManagerChannelTrack>, RadioManagerClientError> {
+        Ok(vec![])
+    }
+
+    async fn find_track(
+        &self,
+        _user_id: &UserId,
+        _metadata: &AudioMetadata,
+    ) -> Result<Option<RadioManagerTrackId>, RadioManagerClientError> {
+        Ok(None)
+    }
+}
+
+/// Returns metadata for the known files and nothing for the rest.
+struct ScriptedMetadataService {
+    metadata: HashMap<String, AudioMetadata>,
+}
+
+#[async_trait]
+impl MetadataServiceTrait for ScriptedMetadataService {
+    async fn get_audio_metadata(
+        &self,
+        path_to_audio_file: &str,
+    ) -> Result<Option<AudioMetadata>, MetadataServiceError> {
+        Ok(self.metadata.get(path_to_audio_file).cloned())
+    }
+
+    async fn set_audio_metadata(
+        &self,
+        _path_to_audio_file: &str,
+        _metadata: &AudioMetadata,
+    ) -> Result<bool, MetadataServiceError> {
+        Ok(false)
+    }
+}
+
+struct Harness {
+    state_storage: Arc<RecordingStateStorage>,
+    torrent_client: Arc<ScriptedTorrentClient>,
+    radio_manager: Arc<RecordingRadioManager>,
+    processor: TrackRequestProcessor,
+}
+
+impl Harness {
+    fn new(
+        search_provider: ScriptedSearchProvider,
+        torrent_client: ScriptedTorrentClient,
+        metadata_service: ScriptedMetadataService,
+    ) -> Self {
+        let state_storage = Arc::new(RecordingStateStorage::new());
+        let torrent_client = Arc::new(torrent_client);
+        let radio_manager = Arc::new(RecordingRadioManager::default());
+        let processor = TrackRequestProcessor::new(
+            state_storage.clone(),
+            Arc::new(search_provider),
+            torrent_client.clone(),
+            radio_manager.clone(),
+            Arc::new(metadata_service),
+            DownloadDirectories::new("/downloads", "downloads"),
+            None,
+        )
+        .with_poll_intervals(PollIntervals {
+            step: Duration::ZERO,
+            download: Duration::ZERO,
+        });
+
+        Self {
+            state_storage,
+            torrent_client,
+            radio_manager,
+            processor,
+        }
+    }
+
+    async fn run(
+        &self,
+        user_id: &UserId,
+        metadata: &AudioMetadata,
+        post_download_match: TitleMatch,
+    ) -> (RequestId, Result<(), ProcessRequestError>) {
+        let request_id = self
+            .processor
+            .create_request(
+                user_id,
+                metadata,
+                &CreateRequestOptions {
+                    pre_download_match: TitleMatch::Loose,
+                    post_download_match,
+                    preferred_formats: vec![],
+                    preferred_year: None,
+                    prefer_original: false,
+                    topic_id: None,
+                    priority: RequestPriority::Manual,
+                    exact_phrase_search: false,
+                },
+                Some(&RadioManagerChannelId(1)),
+            )
+            .await
+            .unwrap();
+        let result = self.processor.process_request(user_id, &request_id).await;
+
+        (request_id, result)
+    }
+
+    async fn status(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> TrackRequestProcessingStatus {
+        self.state_storage
+            .get_all_statuses(user_id)
+            .await
+            .unwrap()
+            .remove(request_id)
+            .expect("Status should be stored")
+    }
+}
+
+fn requested_metadata() -> AudioMetadata {
+    AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Life @ Mirror".into(),
+    }
+}
+
+fn torrent(status: TorrentStatus, files: &[&str]) -> Torrent {
+    Torrent {
+        status,
+        files: files.iter().map(ToString::to_string).collect(),
+    }
+}
+
+#[actix_rt::test]
+async fn test_processing_request_through_all_steps() {
+    let harness = Harness::new(
+        ScriptedSearchProvider::with_topics(2),
+        ScriptedTorrentClient::new(vec![
+            // Torrent claims to have the track, but the downloaded file belongs to someone else.
+            vec![
+                torrent(TorrentStatus::Downloading, &[]),
+                torrent(
+                    TorrentStatus::Complete,
+                    &["first/01. Sunday Breakfast.flac"],
+                ),
+            ],
+            vec![torrent(
+                TorrentStatus::Complete,
+                &["/downloads/second/01. Sunday Breakfast.flac"],
+            )],
+        ]),
+        ScriptedMetadataService {
+            metadata: HashMap::from([
+                (
+                    "downloads/first/01. Sunday Breakfast.flac".to_string(),
+                    AudioMetadata {
+                        artist: "Someone Else".into(),
+                        ..requested_metadata()
+                    },
+                ),
+                (
+                    "downloads/second/01. Sunday Breakfast.flac".to_string(),
+                    requested_metadata(),
+                ),
+            ]),
+        },
+    );
+    let user_id = UserId(1);
+
+    let (request_id, result) = harness
+        .run(&user_id, &requested_metadata(), TitleMatch::Tags)
+        .await;
+
+    result.unwrap();
+    assert_eq!(
+        vec![
+            TrackRequestProcessingStep::GetTopicsIntoQueue,
+            TrackRequestProcessingStep::DownloadNextTorrentFile,
+            TrackRequestProcessingStep::Download,
+            TrackRequestProcessingStep::CheckDownloadStatus,
+            // Still downloading
+            TrackRequestProcessingStep::CheckDownloadStatus,
+            // Downloaded file does not match, try the next topic
+            TrackRequestProcessingStep::DownloadNextTorrentFile,
+            TrackRequestProcessingStep::Download,
+            TrackRequestProcessingStep::CheckDownloadStatus,
+            // Upload is marked as in progress before the file is sent
+            TrackRequestProcessingStep::UploadToRadioManager,
+            TrackRequestProcessingStep::UploadToRadioManager,
+            TrackRequestProcessingStep::AddToRadioManagerChannel,
+            TrackRequestProcessingStep::Finish,
+        ],
+        harness.state_storage.take_steps()
+    );
+    // Torrents of both the finished request and the abandoned topic are removed.
+    assert_eq!(0, harness.torrent_client.added_torrents());
+    assert_eq!(
+        vec!["downloads/second/01. Sunday Breakfast.flac".to_string()],
+        *harness.radio_manager.uploaded_files.lock().unwrap()
+    );
+    assert!(matches!(
+        harness.status(&user_id, &request_id).await,
+        TrackRequestProcessingStatus::Finished
+    ));
+}
+
+#[actix_rt::test]
+async fn test_processing_request_until_topics_are_exhausted() {
+    let harness = Harness::new(
+        ScriptedSearchProvider::with_topics(2),
+        ScriptedTorrentClient::new(vec![]),
+        ScriptedMetadataService {
+            metadata: HashMap::new(),
+        },
+    );
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Missing Track".into(),
+        ..requested_metadata()
+    };
+
+    let (request_id, result) = harness.run(&user_id, &metadata, TitleMatch::Contains).await;
+
+    assert!(matches!(result, Err(ProcessRequestError::TrackNotFound)));
+    assert_eq!(
+        vec![
+            TrackRequestProcessingStep::GetTopicsIntoQueue,
+            TrackRequestProcessingStep::DownloadNextTorrentFile,
+            // Neither of the torrent files has the requested track
+            TrackRequestProcessingStep::DownloadNextTorrentFile,
+            TrackRequestProcessingStep::DownloadNextTorrentFile,
+        ],
+        harness.state_storage.take_steps()
+    );
+    assert_eq!(0, harness.torrent_client.added_torrents());
+    assert!(harness
+        .radio_manager
+        .uploaded_files
+        .lock()
+        .unwrap()
+        .is_empty());
+    assert!(matches!(
+        harness.status(&user_id, &request_id).await,
+        TrackRequestProcessingStatus::NotFound
+    ));
+}
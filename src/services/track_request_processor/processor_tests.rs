@@ -1,33 +1,86 @@
+use super::dead_topics::DeadTopics;
 use super::track_request_processor::{
-    AudioMetadata, DownloadId, RadioManagerChannelId, RadioManagerClientError,
-    RadioManagerClientTrait, RadioManagerLinkId, RadioManagerTrackId, RequestId,
-    SearchProviderError, SearchProviderTrait, StateStorageError, StateStorageTrait, TopicData,
-    TopicId, Torrent, TorrentClientError, TorrentClientTrait, TorrentId, TorrentStatus,
-    TrackRequestProcessingContext, TrackRequestProcessingState, TrackRequestProcessingStep,
-    TrackRequestProcessor,
+    AudioFormat, AudioMetadata, ClockTrait, DownloadId, MetadataServiceError, MetadataServiceTrait,
+    ProcessRequestError, RadioManagerChannelId, RadioManagerClientError, RadioManagerClientTrait,
+    RadioManagerLinkId, RadioManagerTrackId, RequestId, SearchProviderError, SearchProviderTrait,
+    StateStorageError, StateStorageTrait, TopicData, TopicId, Torrent, TorrentClientError,
+    TorrentClientTrait, TorrentId, TorrentStatus, TrackRequestProcessingContext,
+    TrackRequestProcessingState, TrackRequestProcessingStep, TrackRequestProcessor,
 };
 use crate::services::track_request_processor::{
-    CreateRequestOptions, RadioManagerChannelTrack, TrackRequestProcessingStatus,
+    CreateRequestOptions, DownloadedTorrentRecord, IdempotencyRecord, NotFoundRecord,
+    PollIntervals, ProvenanceRecord, RadioManagerChannelTrack, RequestFilter, RequestPriority,
+    RequestStats, RequestStatusFilter, RequestsPage, RetryRequestError, SearchAttempt,
+    SeedingTorrentRecord, StepRetries, TitleMatch, TrackRequestController,
+    TrackRequestProcessingStatus, UnrecoverableError,
 };
+use crate::services::{ChannelAllowlist, DownloadDirectories, RateLimiter, Reloadable};
 use crate::types::UserId;
 use async_trait::async_trait;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Clock whose sleeps advance the time instantly.
+struct ClockMock {
+    now: AtomicU64,
+}
+
+impl ClockMock {
+    fn new() -> Self {
+        Self {
+            now: AtomicU64::new(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl ClockTrait for ClockMock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
 
-struct StateStorageMock {
+    async fn sleep(&self, duration: Duration) {
+        self.now.fetch_add(duration.as_secs(), Ordering::SeqCst);
+    }
+}
+
+pub(super) struct StateStorageMock {
+    idempotency_storage: Mutex<HashMap<(UserId, String), IdempotencyRecord>>,
     context_storage: Mutex<HashMap<UserId, HashMap<RequestId, TrackRequestProcessingContext>>>,
     state_storage: Mutex<HashMap<UserId, HashMap<RequestId, TrackRequestProcessingState>>>,
     status_storage: Mutex<HashMap<UserId, HashMap<RequestId, TrackRequestProcessingStatus>>>,
+    not_found_storage: Mutex<HashMap<UserId, HashMap<RequestId, NotFoundRecord>>>,
+    provenance_storage: Mutex<HashMap<UserId, HashMap<RequestId, ProvenanceRecord>>>,
+    downloaded_torrent_storage: Mutex<HashMap<String, DownloadedTorrentRecord>>,
+    seeding_torrent_storage: Mutex<HashMap<TorrentId, SeedingTorrentRecord>>,
 }
 
 impl StateStorageMock {
-    fn new() -> Self {
+    pub(super) fn new() -> Self {
         Self {
+            idempotency_storage: Mutex::new(HashMap::new()),
             context_storage: Mutex::new(HashMap::new()),
             state_storage: Mutex::new(HashMap::new()),
             status_storage: Mutex::new(HashMap::new()),
+            not_found_storage: Mutex::new(HashMap::new()),
+            provenance_storage: Mutex::new(HashMap::new()),
+            downloaded_torrent_storage: Mutex::new(HashMap::new()),
+            seeding_torrent_storage: Mutex::new(HashMap::new()),
         }
     }
 }
@@ -42,15 +95,12 @@ impl StateStorageTrait for StateStorageMock {
     ) -> Result<(), StateStorageError> {
         let mut lock = self.state_storage.lock().unwrap();
 
-        let user_map = lock.entry(user_id.clone()).or_default();
+        // Retried requests get their state created anew.
+        lock.entry(user_id.clone())
+            .or_default()
+            .insert(request_id.clone(), state);
 
-        match user_map.entry(request_id.clone()) {
-            Entry::Occupied(_) => todo!(),
-            Entry::Vacant(entry) => {
-                entry.insert(state);
-                Ok(())
-            }
-        }
+        Ok(())
     }
 
     async fn create_context(
@@ -72,6 +122,26 @@ impl StateStorageTrait for StateStorageMock {
         }
     }
 
+    async fn update_context(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        ctx: &TrackRequestProcessingContext,
+    ) -> Result<(), StateStorageError> {
+        let mut lock = self.context_storage.lock().unwrap();
+
+        match lock
+            .get_mut(user_id)
+            .and_then(|map| map.get_mut(request_id))
+        {
+            Some(stored_ctx) => {
+                *stored_ctx = ctx.clone();
+                Ok(())
+            }
+            None => Err(StateStorageError::not_found()),
+        }
+    }
+
     async fn update_state(
         &self,
         user_id: &UserId,
@@ -182,13 +252,191 @@ impl StateStorageTrait for StateStorageMock {
 
     async fn get_all_statuses(
         &self,
-        _user_id: &UserId,
+        user_id: &UserId,
     ) -> Result<HashMap<RequestId, TrackRequestProcessingStatus>, StateStorageError> {
-        Ok(HashMap::new())
+        let lock = self.status_storage.lock().unwrap();
+
+        Ok(lock.get(user_id).cloned().unwrap_or_default())
+    }
+
+    async fn get_all_contexts(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<RequestId, TrackRequestProcessingContext>, StateStorageError> {
+        let lock = self.context_storage.lock().unwrap();
+
+        Ok(lock.get(user_id).cloned().unwrap_or_default())
+    }
+
+    async fn get_status_owners(&self) -> Result<Vec<UserId>, StateStorageError> {
+        let lock = self.status_storage.lock().unwrap();
+
+        Ok(lock.keys().cloned().collect())
     }
 
     async fn get_all_tasks(&self) -> Result<Vec<(UserId, RequestId)>, StateStorageError> {
-        todo!()
+        let contexts = self.context_storage.lock().unwrap();
+        let statuses = self.status_storage.lock().unwrap();
+
+        // Same as the on-disk storage: requests with context that haven't stopped processing.
+        Ok(contexts
+            .iter()
+            .flat_map(|(user_id, user_contexts)| {
+                user_contexts
+                    .keys()
+                    .filter(|request_id| {
+                        matches!(
+                            statuses.get(user_id).and_then(|map| map.get(*request_id)),
+                            Some(TrackRequestProcessingStatus::Processing) | None
+                        )
+                    })
+                    .map(|request_id| (user_id.clone(), request_id.clone()))
+            })
+            .collect())
+    }
+
+    async fn get_idempotency(
+        &self,
+        user_id: &UserId,
+        idempotency_key: &str,
+    ) -> Result<Option<IdempotencyRecord>, StateStorageError> {
+        let lock = self.idempotency_storage.lock().unwrap();
+
+        Ok(lock
+            .get(&(user_id.clone(), idempotency_key.to_string()))
+            .cloned())
+    }
+
+    async fn set_idempotency(
+        &self,
+        user_id: &UserId,
+        idempotency_key: &str,
+        record: &IdempotencyRecord,
+    ) -> Result<(), StateStorageError> {
+        let mut lock = self.idempotency_storage.lock().unwrap();
+
+        lock.insert(
+            (user_id.clone(), idempotency_key.to_string()),
+            record.clone(),
+        );
+
+        Ok(())
+    }
+
+    async fn add_not_found(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        record: &NotFoundRecord,
+    ) -> Result<(), StateStorageError> {
+        let mut lock = self.not_found_storage.lock().unwrap();
+
+        lock.entry(user_id.clone())
+            .or_default()
+            .insert(request_id.clone(), record.clone());
+
+        Ok(())
+    }
+
+    async fn delete_not_found(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<(), StateStorageError> {
+        let mut lock = self.not_found_storage.lock().unwrap();
+
+        let _ = lock.get_mut(user_id).and_then(|map| map.remove(request_id));
+
+        Ok(())
+    }
+
+    async fn get_all_not_found(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<RequestId, NotFoundRecord>, StateStorageError> {
+        let lock = self.not_found_storage.lock().unwrap();
+
+        Ok(lock.get(user_id).cloned().unwrap_or_default())
+    }
+
+    async fn set_provenance(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        record: &ProvenanceRecord,
+    ) -> Result<(), StateStorageError> {
+        let mut lock = self.provenance_storage.lock().unwrap();
+
+        lock.entry(user_id.clone())
+            .or_default()
+            .insert(request_id.clone(), record.clone());
+
+        Ok(())
+    }
+
+    async fn get_provenance(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<Option<ProvenanceRecord>, StateStorageError> {
+        let lock = self.provenance_storage.lock().unwrap();
+
+        Ok(lock
+            .get(user_id)
+            .and_then(|records| records.get(request_id))
+            .cloned())
+    }
+
+    async fn get_downloaded_torrent(
+        &self,
+        info_hash: &str,
+    ) -> Result<Option<DownloadedTorrentRecord>, StateStorageError> {
+        let lock = self.downloaded_torrent_storage.lock().unwrap();
+
+        Ok(lock.get(info_hash).cloned())
+    }
+
+    async fn set_downloaded_torrent(
+        &self,
+        info_hash: &str,
+        record: &DownloadedTorrentRecord,
+    ) -> Result<(), StateStorageError> {
+        let mut lock = self.downloaded_torrent_storage.lock().unwrap();
+
+        lock.insert(info_hash.to_string(), record.clone());
+
+        Ok(())
+    }
+
+    async fn add_seeding_torrent(
+        &self,
+        torrent_id: &TorrentId,
+        record: &SeedingTorrentRecord,
+    ) -> Result<(), StateStorageError> {
+        let mut lock = self.seeding_torrent_storage.lock().unwrap();
+
+        lock.insert(torrent_id.clone(), record.clone());
+
+        Ok(())
+    }
+
+    async fn get_all_seeding_torrents(
+        &self,
+    ) -> Result<HashMap<TorrentId, SeedingTorrentRecord>, StateStorageError> {
+        let lock = self.seeding_torrent_storage.lock().unwrap();
+
+        Ok(lock.clone())
+    }
+
+    async fn delete_seeding_torrent(
+        &self,
+        torrent_id: &TorrentId,
+    ) -> Result<(), StateStorageError> {
+        let mut lock = self.seeding_torrent_storage.lock().unwrap();
+
+        lock.remove(torrent_id);
+
+        Ok(())
     }
 }
 
@@ -203,23 +451,53 @@ impl SearchProviderTrait for SearchProviderMock {
                     title: "Ted Irens - Foo [MP3]".into(),
                     topic_id: TopicId(1),
                     download_id: DownloadId(1),
+                    format: Some(AudioFormat::Mp3),
+                    year: None,
                 },
                 TopicData {
                     title: "Ted Irens - Foo [FLAC]".into(),
                     topic_id: TopicId(2),
                     download_id: DownloadId(2),
+                    format: Some(AudioFormat::Flac),
+                    year: None,
                 },
             ]),
+            "ted irens sunday breakfast" | "Ted Irens дискография" => {
+                self.find_all("Ted Irens - Foo").await
+            }
+            "Ted Irens - Mirrors" => Ok(vec![TopicData {
+                title: "Ted Irens - Mirrors [FLAC image+.cue]".into(),
+                topic_id: TopicId(4),
+                download_id: DownloadId(4),
+                format: Some(AudioFormat::Flac),
+                year: None,
+            }]),
             _ => Ok(vec![]),
         }
     }
 
+    async fn resolve_topic(
+        &self,
+        topic_id: &TopicId,
+    ) -> Result<Option<TopicData>, SearchProviderError> {
+        let topics = self.find_all("Ted Irens - Foo").await?;
+
+        Ok(topics.into_iter().find(|topic| topic.topic_id == *topic_id))
+    }
+
     async fn download_torrent(
         &self,
         download_id: &DownloadId,
     ) -> Result<Vec<u8>, SearchProviderError> {
         match **download_id {
             1 => Ok(include_bytes!("../../../tests/fixtures/example.torrent").to_vec()),
+            4 => Ok(b"d4:infod5:filesld6:lengthi1024e4:pathl23:Ted Irens - Mirrors.cueee\
+                d6:lengthi300000000e4:pathl24:Ted Irens - Mirrors.flaceee\
+                4:name19:Ted Irens - Mirrors12:piece lengthi16384e6:pieces20:01234567890123456789ee"
+                .to_vec()),
+            3 => Err(SearchProviderError(Box::new(UnrecoverableError(Box::new(
+                Error::from(ErrorKind::PermissionDenied),
+            ))))),
             _ => Err(SearchProviderError(Box::new(Error::from(
                 ErrorKind::NotFound,
             )))),
@@ -227,96 +505,702 @@ impl SearchProviderTrait for SearchProviderMock {
     }
 }
 
+/// Search provider which fails to serve the first `failures` torrent files.
+struct FlakySearchProviderMock {
+    failures: Mutex<usize>,
+}
+
+#[async_trait]
+impl SearchProviderTrait for FlakySearchProviderMock {
+    async fn find_all(&self, query: &str) -> Result<Vec<TopicData>, SearchProviderError> {
+        SearchProviderMock.find_all(query).await
+    }
+
+    async fn resolve_topic(
+        &self,
+        topic_id: &TopicId,
+    ) -> Result<Option<TopicData>, SearchProviderError> {
+        SearchProviderMock.resolve_topic(topic_id).await
+    }
+
+    async fn download_torrent(
+        &self,
+        download_id: &DownloadId,
+    ) -> Result<Vec<u8>, SearchProviderError> {
+        {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(SearchProviderError(Box::new(Error::from(
+                    ErrorKind::ConnectionReset,
+                ))));
+            }
+        }
+
+        SearchProviderMock.download_torrent(download_id).await
+    }
+}
+
+/// Search provider logged in with credentials which are no longer valid, or with the current ones.
+enum SearchSessionMock {
+    Expired,
+    Active,
+}
+
+#[async_trait]
+impl SearchProviderTrait for SearchSessionMock {
+    async fn find_all(&self, query: &str) -> Result<Vec<TopicData>, SearchProviderError> {
+        match self {
+            SearchSessionMock::Expired => Err(SearchProviderError(Box::new(UnrecoverableError(
+                Box::new(Error::from(ErrorKind::PermissionDenied)),
+            )))),
+            SearchSessionMock::Active => SearchProviderMock.find_all(query).await,
+        }
+    }
+
+    async fn resolve_topic(
+        &self,
+        topic_id: &TopicId,
+    ) -> Result<Option<TopicData>, SearchProviderError> {
+        match self {
+            SearchSessionMock::Expired => Err(SearchProviderError(Box::new(UnrecoverableError(
+                Box::new(Error::from(ErrorKind::PermissionDenied)),
+            )))),
+            SearchSessionMock::Active => SearchProviderMock.resolve_topic(topic_id).await,
+        }
+    }
+
+    async fn download_torrent(
+        &self,
+        download_id: &DownloadId,
+    ) -> Result<Vec<u8>, SearchProviderError> {
+        match self {
+            SearchSessionMock::Expired => Err(SearchProviderError(Box::new(UnrecoverableError(
+                Box::new(Error::from(ErrorKind::PermissionDenied)),
+            )))),
+            SearchSessionMock::Active => SearchProviderMock.download_torrent(download_id).await,
+        }
+    }
+}
+
 struct TorrentClientMock;
 
 #[async_trait]
 impl TorrentClientTrait for TorrentClientMock {
     async fn add_torrent(
         &self,
-        url: Vec<u8>,
-        selected_files_indexes: Vec<i32>,
+        _torrent_file_data: Vec<u8>,
+        _selected_files_indexes: Vec<i32>,
+        _request_id: &RequestId,
+        _download_dir: Option<&str>,
     ) -> Result<TorrentId, TorrentClientError> {
         Ok(TorrentId(1))
     }
 
-    async fn get_torrent(&self, torrent_id: &TorrentId) -> Result<Torrent, TorrentClientError> {
+    async fn get_torrent(
+        &self,
+        torrent_id: &TorrentId,
+    ) -> Result<Option<Torrent>, TorrentClientError> {
         match **torrent_id {
-            1 => Ok(Torrent {
+            1 => Ok(Some(Torrent {
                 status: TorrentStatus::Complete,
                 files: vec![
                     "path/to/01 - Sunday Breakfast.mp3".into(),
                     "path/to/track02.mp3".into(),
                 ],
-            }),
-            _ => todo!(),
+            })),
+            // Removed from the torrent client.
+            2 => Ok(None),
+            // Stuck on a dead swarm.
+            5 => Ok(Some(Torrent {
+                status: TorrentStatus::Downloading,
+                files: vec!["path/to/01 - Sunday Breakfast.mp3".into()],
+            })),
+            4 => Ok(Some(Torrent {
+                status: TorrentStatus::Error("Tracker rejected the torrent".into()),
+                files: vec!["path/to/01 - Sunday Breakfast.mp3".into()],
+            })),
+            _ => Err(TorrentClientError(Box::new(Error::from(
+                ErrorKind::ConnectionRefused,
+            )))),
         }
     }
 
-    async fn delete_torrent(&self, torrent_id: &TorrentId) -> Result<(), TorrentClientError> {
-        todo!()
+    async fn delete_torrent(&self, _torrent_id: &TorrentId) -> Result<(), TorrentClientError> {
+        Ok(())
+    }
+
+    async fn get_request_torrents(
+        &self,
+    ) -> Result<Vec<(TorrentId, Vec<RequestId>)>, TorrentClientError> {
+        Ok(vec![])
+    }
+
+    async fn pause_torrents(&self, _torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError> {
+        Ok(())
+    }
+
+    async fn resume_torrents(&self, _torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError> {
+        Ok(())
+    }
+
+    async fn check_session(&self) -> Result<(), TorrentClientError> {
+        Ok(())
     }
 }
 
-struct RadioManagerMock;
+/// Torrent client which records the removed torrents and lists the given torrents of requests.
+struct RemovalRecordingTorrentClientMock {
+    removed_torrents: Mutex<Vec<TorrentId>>,
+    request_torrents: Mutex<Vec<(TorrentId, Vec<RequestId>)>>,
+}
+
+impl RemovalRecordingTorrentClientMock {
+    fn new() -> Self {
+        Self {
+            removed_torrents: Mutex::new(vec![]),
+            request_torrents: Mutex::new(vec![]),
+        }
+    }
+}
 
 #[async_trait]
-impl RadioManagerClientTrait for RadioManagerMock {
-    async fn upload_audio_track(
+impl TorrentClientTrait for RemovalRecordingTorrentClientMock {
+    async fn add_torrent(
         &self,
-        _user_id: &UserId,
-        path_to_audio_file: &str,
-    ) -> Result<RadioManagerTrackId, RadioManagerClientError> {
-        match path_to_audio_file {
-            "downloads/path/to/01 - Sunday Breakfast.mp3" => Ok(RadioManagerTrackId(1)),
-            _ => Err(RadioManagerClientError(Box::new(Error::from(
-                ErrorKind::NotFound,
-            )))),
-        }
+        torrent_file_data: Vec<u8>,
+        selected_files_indexes: Vec<i32>,
+        request_id: &RequestId,
+        download_dir: Option<&str>,
+    ) -> Result<TorrentId, TorrentClientError> {
+        TorrentClientMock
+            .add_torrent(
+                torrent_file_data,
+                selected_files_indexes,
+                request_id,
+                download_dir,
+            )
+            .await
     }
 
-    async fn add_track_to_channel_playlist(
+    async fn get_torrent(
         &self,
-        _user_id: &UserId,
-        _track_id: &RadioManagerTrackId,
-        _channel_id: &RadioManagerChannelId,
-    ) -> Result<RadioManagerLinkId, RadioManagerClientError> {
-        Ok(RadioManagerLinkId("link".into()))
+        torrent_id: &TorrentId,
+    ) -> Result<Option<Torrent>, TorrentClientError> {
+        TorrentClientMock.get_torrent(torrent_id).await
     }
 
-    async fn get_channel_tracks(
+    async fn delete_torrent(&self, torrent_id: &TorrentId) -> Result<(), TorrentClientError> {
+        self.removed_torrents
+            .lock()
+            .unwrap()
+            .push(torrent_id.clone());
+
+        Ok(())
+    }
+
+    async fn get_request_torrents(
         &self,
-        _channel_id: &RadioManagerChannelId,
-    ) -> Result<Vec<RadioManagerChannelTrack>, RadioManagerClientError> {
-        Ok(vec![])
+    ) -> Result<Vec<(TorrentId, Vec<RequestId>)>, TorrentClientError> {
+        Ok(self.request_torrents.lock().unwrap().clone())
+    }
+
+    async fn pause_torrents(&self, _torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError> {
+        Ok(())
+    }
+
+    async fn resume_torrents(&self, _torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError> {
+        Ok(())
+    }
+
+    async fn check_session(&self) -> Result<(), TorrentClientError> {
+        Ok(())
     }
 }
 
-#[actix_rt::test]
-async fn test_create_track_request() {
-    let state_storage = Arc::new(StateStorageMock::new());
+/// Torrent client which is down, for requests expected not to download anything.
+struct UnavailableTorrentClientMock;
 
-    let processor = TrackRequestProcessor::new(
-        state_storage.clone(),
-        Arc::new(SearchProviderMock),
-        Arc::new(TorrentClientMock),
-        Arc::new(RadioManagerMock),
-        "downloads".to_string(),
-    );
-    let user_id = 1.into();
-    let metadata = AudioMetadata {
-        title: "Sunday Breakfast".into(),
-        artist: "Ted Irens".into(),
-        album: "Foo".into(),
-    };
-    let channel_id = RadioManagerChannelId(1);
-    let request_id = processor
+#[async_trait]
+impl TorrentClientTrait for UnavailableTorrentClientMock {
+    async fn add_torrent(
+        &self,
+        _torrent_file_data: Vec<u8>,
+        _selected_files_indexes: Vec<i32>,
+        _request_id: &RequestId,
+        _download_dir: Option<&str>,
+    ) -> Result<TorrentId, TorrentClientError> {
+        Err(TorrentClientError(Box::new(Error::from(
+            ErrorKind::ConnectionRefused,
+        ))))
+    }
+
+    async fn get_torrent(
+        &self,
+        _torrent_id: &TorrentId,
+    ) -> Result<Option<Torrent>, TorrentClientError> {
+        Err(TorrentClientError(Box::new(Error::from(
+            ErrorKind::ConnectionRefused,
+        ))))
+    }
+
+    async fn delete_torrent(&self, _torrent_id: &TorrentId) -> Result<(), TorrentClientError> {
+        Err(TorrentClientError(Box::new(Error::from(
+            ErrorKind::ConnectionRefused,
+        ))))
+    }
+
+    async fn get_request_torrents(
+        &self,
+    ) -> Result<Vec<(TorrentId, Vec<RequestId>)>, TorrentClientError> {
+        Err(TorrentClientError(Box::new(Error::from(
+            ErrorKind::ConnectionRefused,
+        ))))
+    }
+
+    async fn pause_torrents(&self, _torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError> {
+        Err(TorrentClientError(Box::new(Error::from(
+            ErrorKind::ConnectionRefused,
+        ))))
+    }
+
+    async fn resume_torrents(&self, _torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError> {
+        Err(TorrentClientError(Box::new(Error::from(
+            ErrorKind::ConnectionRefused,
+        ))))
+    }
+
+    async fn check_session(&self) -> Result<(), TorrentClientError> {
+        Err(TorrentClientError(Box::new(Error::from(
+            ErrorKind::ConnectionRefused,
+        ))))
+    }
+}
+
+/// Torrent client which is reachable but fails the session check, e.g. rejects the credentials.
+struct DeadSessionTorrentClientMock;
+
+#[async_trait]
+impl TorrentClientTrait for DeadSessionTorrentClientMock {
+    async fn add_torrent(
+        &self,
+        _torrent_file_data: Vec<u8>,
+        _selected_files_indexes: Vec<i32>,
+        _request_id: &RequestId,
+        _download_dir: Option<&str>,
+    ) -> Result<TorrentId, TorrentClientError> {
+        panic!("Torrent must not be added when the session check fails");
+    }
+
+    async fn get_torrent(
+        &self,
+        torrent_id: &TorrentId,
+    ) -> Result<Option<Torrent>, TorrentClientError> {
+        TorrentClientMock.get_torrent(torrent_id).await
+    }
+
+    async fn delete_torrent(&self, torrent_id: &TorrentId) -> Result<(), TorrentClientError> {
+        TorrentClientMock.delete_torrent(torrent_id).await
+    }
+
+    async fn get_request_torrents(
+        &self,
+    ) -> Result<Vec<(TorrentId, Vec<RequestId>)>, TorrentClientError> {
+        TorrentClientMock.get_request_torrents().await
+    }
+
+    async fn pause_torrents(&self, torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError> {
+        TorrentClientMock.pause_torrents(torrent_ids).await
+    }
+
+    async fn resume_torrents(&self, torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError> {
+        TorrentClientMock.resume_torrents(torrent_ids).await
+    }
+
+    async fn check_session(&self) -> Result<(), TorrentClientError> {
+        Err(TorrentClientError(Box::new(Error::from(
+            ErrorKind::PermissionDenied,
+        ))))
+    }
+}
+
+struct RadioManagerMock;
+
+#[async_trait]
+impl RadioManagerClientTrait for RadioManagerMock {
+    async fn upload_audio_track(
+        &self,
+        _user_id: &UserId,
+        path_to_audio_file: &str,
+        _file_name: Option<&str>,
+    ) -> Result<RadioManagerTrackId, RadioManagerClientError> {
+        match path_to_audio_file {
+            path if path.ends_with("/path/to/01 - Sunday Breakfast.mp3") => {
+                Ok(RadioManagerTrackId(1))
+            }
+            _ => Err(RadioManagerClientError(Box::new(Error::from(
+                ErrorKind::NotFound,
+            )))),
+        }
+    }
+
+    async fn add_track_to_channel_playlist(
+        &self,
+        _user_id: &UserId,
+        _track_id: &RadioManagerTrackId,
+        _channel_id: &RadioManagerChannelId,
+    ) -> Result<RadioManagerLinkId, RadioManagerClientError> {
+        Ok(RadioManagerLinkId("link".into()))
+    }
+
+    async fn get_channel_tracks(
+        &self,
+        _channel_id: &RadioManagerChannelId,
+    ) -> Result<Vec<RadioManagerChannelTrack>, RadioManagerClientError> {
+        Ok(vec![])
+    }
+
+    async fn find_track(
+        &self,
+        _user_id: &UserId,
+        metadata: &AudioMetadata,
+    ) -> Result<Option<RadioManagerTrackId>, RadioManagerClientError> {
+        match metadata.title.as_str() {
+            "Winter's Sunset" => Ok(Some(RadioManagerTrackId(7))),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Radio manager which stores the first uploaded track, but loses the response to it.
+#[derive(Default)]
+struct LostResponseRadioManagerMock {
+    uploaded_paths: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl RadioManagerClientTrait for LostResponseRadioManagerMock {
+    async fn upload_audio_track(
+        &self,
+        _user_id: &UserId,
+        path_to_audio_file: &str,
+        _file_name: Option<&str>,
+    ) -> Result<RadioManagerTrackId, RadioManagerClientError> {
+        let mut uploaded_paths = self.uploaded_paths.lock().unwrap();
+        uploaded_paths.push(path_to_audio_file.to_string());
+
+        match uploaded_paths.len() {
+            1 => Err(RadioManagerClientError(Box::new(Error::from(
+                ErrorKind::ConnectionReset,
+            )))),
+            count => Ok(RadioManagerTrackId(count as u64)),
+        }
+    }
+
+    async fn add_track_to_channel_playlist(
+        &self,
+        user_id: &UserId,
+        track_id: &RadioManagerTrackId,
+        channel_id: &RadioManagerChannelId,
+    ) -> Result<RadioManagerLinkId, RadioManagerClientError> {
+        RadioManagerMock
+            .add_track_to_channel_playlist(user_id, track_id, channel_id)
+            .await
+    }
+
+    async fn get_channel_tracks(
+        &self,
+        channel_id: &RadioManagerChannelId,
+    ) -> Result<Vec<RadioManagerChannelTrack>, RadioManagerClientError> {
+        RadioManagerMock.get_channel_tracks(channel_id).await
+    }
+
+    async fn find_track(
+        &self,
+        _user_id: &UserId,
+        metadata: &AudioMetadata,
+    ) -> Result<Option<RadioManagerTrackId>, RadioManagerClientError> {
+        let is_uploaded = !self.uploaded_paths.lock().unwrap().is_empty();
+
+        match metadata.title.as_str() {
+            "Sunday Breakfast" if is_uploaded => Ok(Some(RadioManagerTrackId(1))),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Radio manager whose uploads take a while, recording how many of them overlapped.
+#[derive(Default)]
+struct SlowUploadRadioManagerMock {
+    uploading: AtomicU64,
+    max_uploading: AtomicU64,
+}
+
+#[async_trait]
+impl RadioManagerClientTrait for SlowUploadRadioManagerMock {
+    async fn upload_audio_track(
+        &self,
+        user_id: &UserId,
+        path_to_audio_file: &str,
+        file_name: Option<&str>,
+    ) -> Result<RadioManagerTrackId, RadioManagerClientError> {
+        let uploading = self.uploading.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_uploading.fetch_max(uploading, Ordering::SeqCst);
+        actix_rt::time::sleep(Duration::from_millis(50)).await;
+        self.uploading.fetch_sub(1, Ordering::SeqCst);
+
+        RadioManagerMock
+            .upload_audio_track(user_id, path_to_audio_file, file_name)
+            .await
+    }
+
+    async fn add_track_to_channel_playlist(
+        &self,
+        user_id: &UserId,
+        track_id: &RadioManagerTrackId,
+        channel_id: &RadioManagerChannelId,
+    ) -> Result<RadioManagerLinkId, RadioManagerClientError> {
+        RadioManagerMock
+            .add_track_to_channel_playlist(user_id, track_id, channel_id)
+            .await
+    }
+
+    async fn get_channel_tracks(
+        &self,
+        channel_id: &RadioManagerChannelId,
+    ) -> Result<Vec<RadioManagerChannelTrack>, RadioManagerClientError> {
+        RadioManagerMock.get_channel_tracks(channel_id).await
+    }
+
+    async fn find_track(
+        &self,
+        user_id: &UserId,
+        metadata: &AudioMetadata,
+    ) -> Result<Option<RadioManagerTrackId>, RadioManagerClientError> {
+        RadioManagerMock.find_track(user_id, metadata).await
+    }
+}
+
+/// Radio manager which silently drops the first `dropped_adds` tracks added to a channel.
+struct DroppingRadioManagerMock {
+    dropped_adds: usize,
+    added_tracks: Mutex<Vec<RadioManagerTrackId>>,
+}
+
+impl DroppingRadioManagerMock {
+    fn new(dropped_adds: usize) -> Self {
+        Self {
+            dropped_adds,
+            added_tracks: Mutex::new(vec![]),
+        }
+    }
+}
+
+#[async_trait]
+impl RadioManagerClientTrait for DroppingRadioManagerMock {
+    async fn upload_audio_track(
+        &self,
+        user_id: &UserId,
+        path_to_audio_file: &str,
+        file_name: Option<&str>,
+    ) -> Result<RadioManagerTrackId, RadioManagerClientError> {
+        RadioManagerMock
+            .upload_audio_track(user_id, path_to_audio_file, file_name)
+            .await
+    }
+
+    async fn add_track_to_channel_playlist(
+        &self,
+        _user_id: &UserId,
+        track_id: &RadioManagerTrackId,
+        _channel_id: &RadioManagerChannelId,
+    ) -> Result<RadioManagerLinkId, RadioManagerClientError> {
+        self.added_tracks.lock().unwrap().push(track_id.clone());
+
+        Ok(RadioManagerLinkId("link".into()))
+    }
+
+    async fn get_channel_tracks(
+        &self,
+        _channel_id: &RadioManagerChannelId,
+    ) -> Result<Vec<RadioManagerChannelTrack>, RadioManagerClientError> {
+        let added_tracks = self.added_tracks.lock().unwrap();
+
+        Ok(added_tracks
+            .iter()
+            .skip(self.dropped_adds)
+            .map(|track_id| RadioManagerChannelTrack {
+                track_id: Some(track_id.clone()),
+                album: "Foo".into(),
+                artist: "Ted Irens".into(),
+                title: "Sunday Breakfast".into(),
+            })
+            .collect())
+    }
+
+    async fn find_track(
+        &self,
+        user_id: &UserId,
+        metadata: &AudioMetadata,
+    ) -> Result<Option<RadioManagerTrackId>, RadioManagerClientError> {
+        RadioManagerMock.find_track(user_id, metadata).await
+    }
+}
+
+/// Radio manager which fails to search the library the first `failures` times.
+struct FlakyLibraryRadioManagerMock {
+    failures: Mutex<usize>,
+}
+
+#[async_trait]
+impl RadioManagerClientTrait for FlakyLibraryRadioManagerMock {
+    async fn upload_audio_track(
+        &self,
+        user_id: &UserId,
+        path_to_audio_file: &str,
+        file_name: Option<&str>,
+    ) -> Result<RadioManagerTrackId, RadioManagerClientError> {
+        RadioManagerMock
+            .upload_audio_track(user_id, path_to_audio_file, file_name)
+            .await
+    }
+
+    async fn add_track_to_channel_playlist(
+        &self,
+        user_id: &UserId,
+        track_id: &RadioManagerTrackId,
+        channel_id: &RadioManagerChannelId,
+    ) -> Result<RadioManagerLinkId, RadioManagerClientError> {
+        RadioManagerMock
+            .add_track_to_channel_playlist(user_id, track_id, channel_id)
+            .await
+    }
+
+    async fn get_channel_tracks(
+        &self,
+        channel_id: &RadioManagerChannelId,
+    ) -> Result<Vec<RadioManagerChannelTrack>, RadioManagerClientError> {
+        RadioManagerMock.get_channel_tracks(channel_id).await
+    }
+
+    async fn find_track(
+        &self,
+        user_id: &UserId,
+        metadata: &AudioMetadata,
+    ) -> Result<Option<RadioManagerTrackId>, RadioManagerClientError> {
+        {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(RadioManagerClientError(Box::new(Error::from(
+                    ErrorKind::ConnectionReset,
+                ))));
+            }
+        }
+
+        RadioManagerMock.find_track(user_id, metadata).await
+    }
+}
+
+struct MetadataServiceMock;
+
+#[async_trait]
+impl MetadataServiceTrait for MetadataServiceMock {
+    async fn get_audio_metadata(
+        &self,
+        path_to_audio_file: &str,
+    ) -> Result<Option<AudioMetadata>, MetadataServiceError> {
+        match path_to_audio_file {
+            "downloads/path/to/01 - Sunday Breakfast.mp3" => Ok(Some(AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    async fn set_audio_metadata(
+        &self,
+        _path_to_audio_file: &str,
+        _metadata: &AudioMetadata,
+    ) -> Result<bool, MetadataServiceError> {
+        Ok(true)
+    }
+}
+
+/// Records the metadata written into the audio files.
+#[derive(Default)]
+struct TagWritingMetadataServiceMock {
+    written: Mutex<Vec<(String, AudioMetadata)>>,
+}
+
+#[async_trait]
+impl MetadataServiceTrait for TagWritingMetadataServiceMock {
+    async fn get_audio_metadata(
+        &self,
+        path_to_audio_file: &str,
+    ) -> Result<Option<AudioMetadata>, MetadataServiceError> {
+        MetadataServiceMock
+            .get_audio_metadata(path_to_audio_file)
+            .await
+    }
+
+    async fn set_audio_metadata(
+        &self,
+        path_to_audio_file: &str,
+        metadata: &AudioMetadata,
+    ) -> Result<bool, MetadataServiceError> {
+        self.written
+            .lock()
+            .unwrap()
+            .push((path_to_audio_file.to_string(), metadata.clone()));
+        Ok(true)
+    }
+}
+
+/// Processor backed by the default mocks, downloading into `/downloads`.
+fn test_processor(
+    state_storage: Arc<dyn StateStorageTrait + Send + Sync + 'static>,
+) -> TrackRequestProcessor {
+    TrackRequestProcessor::new(
+        state_storage,
+        Arc::from(SearchProviderMock),
+        Arc::from(TorrentClientMock),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    )
+}
+
+#[actix_rt::test]
+async fn test_create_track_request() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone());
+    let user_id = 1.into();
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Foo".into(),
+    };
+    let channel_id = RadioManagerChannelId(1);
+    let request_id = processor
         .create_request(
             &user_id,
             &metadata,
             &CreateRequestOptions {
-                validate_metadata: true,
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Tags,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
             },
-            &channel_id,
+            Some(&channel_id),
         )
         .await
         .unwrap();
@@ -328,7 +1212,7 @@ async fn test_create_track_request() {
     assert_eq!(stored_context.metadata.title, "Sunday Breakfast");
     assert_eq!(stored_context.metadata.artist, "Ted Irens");
     assert_eq!(stored_context.metadata.album, "Foo");
-    assert_eq!(stored_context.target_channel_id, channel_id);
+    assert_eq!(stored_context.target_channel_id, Some(channel_id));
 
     let stored_state = state_storage
         .load_state(&user_id, &request_id)
@@ -336,19 +1220,13 @@ async fn test_create_track_request() {
         .unwrap();
     assert_eq!(
         stored_state.get_step(),
-        TrackRequestProcessingStep::GetTopicsIntoQueue
+        TrackRequestProcessingStep::FindInLibrary
     );
 }
 
 #[actix_rt::test]
 async fn test_processing_track_request() {
-    let processor = TrackRequestProcessor::new(
-        Arc::from(StateStorageMock::new()),
-        Arc::from(SearchProviderMock),
-        Arc::from(TorrentClientMock),
-        Arc::from(RadioManagerMock),
-        "downloads".into(),
-    );
+    let processor = test_processor(Arc::new(StateStorageMock::new()));
     let user_id = UserId(1);
     let metadata = AudioMetadata {
         title: "Sunday Breakfast".into(),
@@ -361,9 +1239,16 @@ async fn test_processing_track_request() {
             &user_id,
             &metadata,
             &CreateRequestOptions {
-                validate_metadata: false,
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
             },
-            &channel_id,
+            Some(&channel_id),
         )
         .await
         .unwrap();
@@ -372,4 +1257,3235 @@ async fn test_processing_track_request() {
         .process_request(&user_id, &request_id)
         .await
         .unwrap();
+
+    let provenance = processor
+        .get_provenance(&user_id, &request_id)
+        .await
+        .unwrap()
+        .expect("Provenance of the finished request is recorded");
+    assert_eq!(metadata, provenance.metadata);
+    assert_eq!(Some(TopicId(1)), provenance.topic_id);
+    assert!(provenance.info_hash.is_some());
+    assert_eq!(
+        Some("path/to/01 - Sunday Breakfast.mp3"),
+        provenance.file_path.as_deref()
+    );
+    assert!(provenance.radio_manager_track_id.is_some());
+}
+
+/// Processes a track request leaving its torrent seeding for the given time.
+async fn finish_request_seeding_for(
+    seed_duration: Option<Duration>,
+) -> (
+    TrackRequestProcessor,
+    Arc<ClockMock>,
+    Arc<RemovalRecordingTorrentClientMock>,
+) {
+    let clock = Arc::new(ClockMock::new());
+    let torrent_client = Arc::new(RemovalRecordingTorrentClientMock::new());
+    let processor = TrackRequestProcessor::new(
+        Arc::from(StateStorageMock::new()),
+        Arc::from(SearchProviderMock),
+        torrent_client.clone(),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    )
+    .with_clock(clock.clone())
+    .with_seed_duration(seed_duration);
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    processor
+        .process_request(&user_id, &request_id)
+        .await
+        .unwrap();
+
+    (processor, clock, torrent_client)
+}
+
+#[actix_rt::test]
+async fn test_removing_torrent_of_finished_request() {
+    let (processor, _, torrent_client) = finish_request_seeding_for(None).await;
+
+    assert_eq!(
+        vec![TorrentId(1)],
+        *torrent_client.removed_torrents.lock().unwrap()
+    );
+    assert_eq!(0, processor.remove_seeded_torrents().await.unwrap());
+}
+
+#[actix_rt::test]
+async fn test_removing_torrent_once_seeding_is_over() {
+    let seed_duration = Duration::from_secs(3600);
+    let (processor, clock, torrent_client) = finish_request_seeding_for(Some(seed_duration)).await;
+
+    clock.sleep(seed_duration - Duration::from_secs(1)).await;
+    assert_eq!(0, processor.remove_seeded_torrents().await.unwrap());
+    assert!(torrent_client.removed_torrents.lock().unwrap().is_empty());
+
+    clock.sleep(Duration::from_secs(1)).await;
+    assert_eq!(1, processor.remove_seeded_torrents().await.unwrap());
+    assert_eq!(
+        vec![TorrentId(1)],
+        *torrent_client.removed_torrents.lock().unwrap()
+    );
+    assert_eq!(0, processor.remove_seeded_torrents().await.unwrap());
+}
+
+#[actix_rt::test]
+async fn test_removing_torrents_of_deleted_requests() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let torrent_client = Arc::new(RemovalRecordingTorrentClientMock::new());
+
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        torrent_client.clone(),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    );
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+    let deleted_request_id = RequestId(uuid::Uuid::new_v4());
+
+    // The second torrent has been reused by the existing request.
+    *torrent_client.request_torrents.lock().unwrap() = vec![
+        (TorrentId(1), vec![deleted_request_id.clone()]),
+        (TorrentId(2), vec![deleted_request_id, request_id]),
+    ];
+
+    assert_eq!(1, processor.remove_orphaned_torrents().await.unwrap());
+    assert_eq!(
+        vec![TorrentId(1)],
+        *torrent_client.removed_torrents.lock().unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn test_reusing_file_of_already_downloaded_torrent() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let local_download_dir =
+        std::env::temp_dir().join(format!("channel-bot-{}", uuid::Uuid::new_v4()));
+    let local_download_dir_str = local_download_dir.to_string_lossy().to_string();
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Foo".into(),
+    };
+    let options = CreateRequestOptions {
+        pre_download_match: TitleMatch::Loose,
+        post_download_match: TitleMatch::Contains,
+        preferred_formats: vec![],
+        preferred_year: None,
+        prefer_original: false,
+        topic_id: None,
+        priority: RequestPriority::Manual,
+        exact_phrase_search: false,
+    };
+
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        Arc::from(TorrentClientMock),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", &local_download_dir_str),
+        None,
+    )
+    .with_poll_intervals(PollIntervals {
+        step: Duration::ZERO,
+        download: Duration::from_millis(10),
+    });
+    let request_id = processor
+        .create_request(
+            &UserId(1),
+            &metadata,
+            &options,
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+    processor
+        .process_request(&UserId(1), &request_id)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        Some(DownloadedTorrentRecord {
+            files: vec!["path/to/01 - Sunday Breakfast.mp3".into()],
+        }),
+        state_storage
+            .get_downloaded_torrent("2ec5340de73d63d6faa162a9dd435acb462d8737")
+            .await
+            .unwrap()
+    );
+
+    let local_path_to_file = local_download_dir.join("path/to/01 - Sunday Breakfast.mp3");
+    std::fs::create_dir_all(local_path_to_file.parent().unwrap()).unwrap();
+    std::fs::write(&local_path_to_file, b"").unwrap();
+
+    // Same torrent requested by another user is not downloaded again.
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        Arc::from(UnavailableTorrentClientMock),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", &local_download_dir_str),
+        None,
+    )
+    .with_poll_intervals(PollIntervals {
+        step: Duration::ZERO,
+        download: Duration::from_millis(10),
+    })
+    .with_step_retries(StepRetries {
+        max_retries: 0,
+        backoff: Duration::ZERO,
+    });
+    let request_id = processor
+        .create_request(
+            &UserId(2),
+            &metadata,
+            &options,
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+    let result = processor.process_request(&UserId(2), &request_id).await;
+
+    std::fs::remove_dir_all(&local_download_dir).unwrap();
+    result.unwrap();
+}
+
+#[actix_rt::test]
+async fn test_copying_downloaded_file_into_library() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let temp_dir = std::env::temp_dir().join(format!("channel-bot-{}", uuid::Uuid::new_v4()));
+    let local_download_dir = temp_dir.join("downloads");
+    let library_dir = temp_dir.join("library");
+
+    let local_path_to_file = local_download_dir.join("path/to/01 - Sunday Breakfast.mp3");
+    std::fs::create_dir_all(local_path_to_file.parent().unwrap()).unwrap();
+    std::fs::write(&local_path_to_file, b"audio").unwrap();
+
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        Arc::from(TorrentClientMock),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", &local_download_dir.to_string_lossy())
+            .with_library_dir(Some(library_dir.to_string_lossy().to_string())),
+        None,
+    )
+    .with_poll_intervals(PollIntervals {
+        step: Duration::ZERO,
+        download: Duration::ZERO,
+    });
+    let request_id = processor
+        .create_request(
+            &UserId(1),
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+    processor
+        .process_request(&UserId(1), &request_id)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        b"audio".to_vec(),
+        std::fs::read(library_dir.join("path/to/01 - Sunday Breakfast.mp3")).unwrap()
+    );
+    // The torrent keeps its own file to seed.
+    assert!(local_path_to_file.exists());
+
+    std::fs::remove_dir_all(temp_dir).unwrap();
+}
+
+#[actix_rt::test]
+async fn test_normalizing_tags_before_upload() {
+    let temp_dir = std::env::temp_dir().join(format!("channel-bot-{}", uuid::Uuid::new_v4()));
+    let local_download_dir = temp_dir.join("downloads");
+    let library_dir = temp_dir.join("library");
+
+    let local_path_to_file = local_download_dir.join("path/to/01 - Sunday Breakfast.mp3");
+    std::fs::create_dir_all(local_path_to_file.parent().unwrap()).unwrap();
+    std::fs::write(&local_path_to_file, b"audio").unwrap();
+
+    // The torrent's own file is seeded and reused, so only its library copy is tagged.
+    for library_dir in [None, Some(library_dir.to_string_lossy().to_string())] {
+        let metadata_service = Arc::new(TagWritingMetadataServiceMock::default());
+        let processor = TrackRequestProcessor::new(
+            Arc::from(StateStorageMock::new()),
+            Arc::from(SearchProviderMock),
+            Arc::from(TorrentClientMock),
+            Arc::from(RadioManagerMock),
+            metadata_service.clone(),
+            DownloadDirectories::new("/downloads", &local_download_dir.to_string_lossy())
+                .with_library_dir(library_dir.clone()),
+            None,
+        )
+        .with_tag_normalization(true);
+        let user_id = UserId(1);
+        let metadata = AudioMetadata {
+            title: "Sunday Breakfast".into(),
+            artist: "Ted Irens".into(),
+            album: "Foo".into(),
+        };
+        let request_id = processor
+            .create_request(
+                &user_id,
+                &metadata,
+                &CreateRequestOptions {
+                    pre_download_match: TitleMatch::Loose,
+                    post_download_match: TitleMatch::Contains,
+                    preferred_formats: vec![],
+                    preferred_year: None,
+                    prefer_original: false,
+                    topic_id: None,
+                    priority: RequestPriority::Manual,
+                    exact_phrase_search: false,
+                },
+                Some(&RadioManagerChannelId(1)),
+            )
+            .await
+            .unwrap();
+
+        processor
+            .process_request(&user_id, &request_id)
+            .await
+            .unwrap();
+
+        let expected_written: Vec<_> = library_dir
+            .map(|library_dir| {
+                (
+                    format!("{}/path/to/01 - Sunday Breakfast.mp3", library_dir),
+                    metadata,
+                )
+            })
+            .into_iter()
+            .collect();
+        assert_eq!(expected_written, *metadata_service.written.lock().unwrap());
+    }
+
+    std::fs::remove_dir_all(temp_dir).unwrap();
+}
+
+#[actix_rt::test]
+async fn test_processing_track_request_after_replacing_search_session() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let search_session = Arc::new(Reloadable::new(SearchSessionMock::Expired));
+
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        search_session.clone(),
+        Arc::from(TorrentClientMock),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    );
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Foo".into(),
+    };
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    search_session.replace(SearchSessionMock::Active);
+
+    processor
+        .process_request(&user_id, &request_id)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .get(&request_id),
+        Some(TrackRequestProcessingStatus::Finished)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_matching_title_in_two_phases() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone())
+        .with_poll_intervals(PollIntervals {
+            step: Duration::ZERO,
+            download: Duration::from_millis(10),
+        })
+        .with_step_retries(StepRetries {
+            max_retries: 0,
+            backoff: Duration::ZERO,
+        });
+    let user_id = UserId(1);
+    // Spelled differently from "01. Ted Irens - Sunday Breakfast.flac" in the torrent
+    // and "01 - Sunday Breakfast.mp3" in the download.
+    let metadata = AudioMetadata {
+        title: "Sunday-Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Foo".into(),
+    };
+
+    for (post_download_match, is_finished) in
+        [(TitleMatch::Loose, true), (TitleMatch::Contains, false)]
+    {
+        let request_id = processor
+            .create_request(
+                &user_id,
+                &metadata,
+                &CreateRequestOptions {
+                    pre_download_match: TitleMatch::Loose,
+                    post_download_match,
+                    preferred_formats: vec![],
+                    preferred_year: None,
+                    prefer_original: false,
+                    topic_id: None,
+                    priority: RequestPriority::Manual,
+                    exact_phrase_search: false,
+                },
+                Some(&RadioManagerChannelId(1)),
+            )
+            .await
+            .unwrap();
+
+        let result = processor.process_request(&user_id, &request_id).await;
+
+        assert_eq!(is_finished, result.is_ok());
+        assert_eq!(
+            is_finished,
+            matches!(
+                state_storage
+                    .get_all_statuses(&user_id)
+                    .await
+                    .unwrap()
+                    .get(&request_id),
+                Some(TrackRequestProcessingStatus::Finished)
+            )
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn test_processing_track_request_with_picked_topic() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone());
+    let user_id = UserId(1);
+    // Searching for the album wouldn't find anything.
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Sunday Breakfast (Single)".into(),
+    };
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: Some(TopicId(1)),
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    processor
+        .process_request(&user_id, &request_id)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .get(&request_id),
+        Some(TrackRequestProcessingStatus::Finished)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_processing_track_request_found_in_library() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone());
+    let user_id = UserId(1);
+    // Search provider has nothing for this album, so the track must come from the library.
+    let metadata = AudioMetadata {
+        title: "Winter's Sunset".into(),
+        artist: "Ted Irens".into(),
+        album: "Bar".into(),
+    };
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    processor
+        .process_request(&user_id, &request_id)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .get(&request_id),
+        Some(TrackRequestProcessingStatus::Finished)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_retrying_search_in_library() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        Arc::from(TorrentClientMock),
+        Arc::new(FlakyLibraryRadioManagerMock {
+            failures: Mutex::new(1),
+        }),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    )
+    .with_step_retries(StepRetries {
+        max_retries: 1,
+        backoff: Duration::ZERO,
+    });
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Winter's Sunset".into(),
+                artist: "Ted Irens".into(),
+                album: "Bar".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    processor
+        .process_request(&user_id, &request_id)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .get(&request_id),
+        Some(TrackRequestProcessingStatus::Finished)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_processing_track_request_without_album() {
+    let processor = test_processor(Arc::new(StateStorageMock::new()));
+    let user_id = UserId(1);
+    // Only the discography of the artist is searched for, as there is no album to search for.
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "".into(),
+    };
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    processor
+        .process_request(&user_id, &request_id)
+        .await
+        .unwrap();
+
+    let provenance = processor
+        .get_provenance(&user_id, &request_id)
+        .await
+        .unwrap()
+        .expect("Provenance of the finished request is recorded");
+    assert_eq!(Some(TopicId(1)), provenance.topic_id);
+    assert_eq!(
+        Some("path/to/01 - Sunday Breakfast.mp3"),
+        provenance.file_path.as_deref()
+    );
+}
+
+#[actix_rt::test]
+async fn test_processing_upload_request() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone());
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_upload_request(
+            &user_id,
+            "path/to/01 - Sunday Breakfast.mp3",
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    let state = state_storage
+        .load_state(&user_id, &request_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        state.get_step(),
+        TrackRequestProcessingStep::UploadToRadioManager
+    );
+
+    processor
+        .process_request(&user_id, &request_id)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .get(&request_id),
+        Some(TrackRequestProcessingStatus::Finished)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_not_uploading_track_again_after_lost_response() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let radio_manager = Arc::new(LostResponseRadioManagerMock::default());
+
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        Arc::from(TorrentClientMock),
+        radio_manager.clone(),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    )
+    .with_step_retries(StepRetries {
+        max_retries: 1,
+        backoff: Duration::ZERO,
+    });
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_upload_request(
+            &user_id,
+            "path/to/01 - Sunday Breakfast.mp3",
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    processor
+        .process_request(&user_id, &request_id)
+        .await
+        .unwrap();
+
+    assert_eq!(1, radio_manager.uploaded_paths.lock().unwrap().len());
+    assert!(matches!(
+        state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .get(&request_id),
+        Some(TrackRequestProcessingStatus::Finished)
+    ));
+}
+
+async fn add_track_to_dropping_channel(
+    dropped_adds: usize,
+) -> (usize, Option<TrackRequestProcessingStatus>) {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let radio_manager = Arc::new(DroppingRadioManagerMock::new(dropped_adds));
+
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        Arc::from(TorrentClientMock),
+        radio_manager.clone(),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    )
+    .with_channel_add_verification(true);
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_upload_request(
+            &user_id,
+            "path/to/01 - Sunday Breakfast.mp3",
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    let _ = processor.process_request(&user_id, &request_id).await;
+
+    let added_count = radio_manager.added_tracks.lock().unwrap().len();
+    let status = state_storage
+        .get_all_statuses(&user_id)
+        .await
+        .unwrap()
+        .remove(&request_id);
+
+    (added_count, status)
+}
+
+#[actix_rt::test]
+async fn test_adding_track_to_channel_again_if_dropped() {
+    let (added_count, status) = add_track_to_dropping_channel(1).await;
+
+    assert_eq!(2, added_count);
+    assert!(matches!(
+        status,
+        Some(TrackRequestProcessingStatus::Finished)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_failing_request_if_track_is_dropped_from_channel_again() {
+    let (added_count, status) = add_track_to_dropping_channel(2).await;
+
+    assert_eq!(2, added_count);
+    assert!(matches!(status, Some(TrackRequestProcessingStatus::Failed)));
+}
+
+#[actix_rt::test]
+async fn test_finishing_request_without_channel_after_upload() {
+    for (channel_id, expected_added_count) in [(Some(RadioManagerChannelId(1)), 1), (None, 0)] {
+        let state_storage = Arc::new(StateStorageMock::new());
+        let radio_manager = Arc::new(DroppingRadioManagerMock::new(0));
+
+        let processor = TrackRequestProcessor::new(
+            state_storage.clone(),
+            Arc::from(SearchProviderMock),
+            Arc::from(TorrentClientMock),
+            radio_manager.clone(),
+            Arc::from(MetadataServiceMock),
+            DownloadDirectories::new("/downloads", "downloads"),
+            None,
+        );
+        let user_id = UserId(1);
+        let request_id = processor
+            .create_upload_request(
+                &user_id,
+                "path/to/01 - Sunday Breakfast.mp3",
+                channel_id.as_ref(),
+            )
+            .await
+            .unwrap();
+
+        processor
+            .process_request(&user_id, &request_id)
+            .await
+            .unwrap();
+
+        let status = state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .remove(&request_id);
+        assert!(matches!(
+            status,
+            Some(TrackRequestProcessingStatus::Finished)
+        ));
+        assert_eq!(
+            expected_added_count,
+            radio_manager.added_tracks.lock().unwrap().len()
+        );
+        let provenance = processor
+            .get_provenance(&user_id, &request_id)
+            .await
+            .unwrap()
+            .expect("Provenance of the finished request is recorded");
+        assert_eq!(channel_id, provenance.target_channel_id);
+        assert!(provenance.radio_manager_track_id.is_some());
+    }
+}
+
+#[actix_rt::test]
+async fn test_processing_search_request() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone());
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_search_request(
+            &user_id,
+            "ted irens sunday breakfast",
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    processor
+        .process_request(&user_id, &request_id)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .get(&request_id),
+        Some(TrackRequestProcessingStatus::Finished)
+    ));
+    // Metadata of the request is taken from the tags of the downloaded file.
+    let ctx = state_storage
+        .load_context(&user_id, &request_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        AudioMetadata {
+            title: "Sunday Breakfast".into(),
+            artist: "Ted Irens".into(),
+            album: "Foo".into(),
+        },
+        ctx.metadata
+    );
+}
+
+#[actix_rt::test]
+async fn test_waiting_for_free_download_slot() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone())
+        .with_poll_intervals(PollIntervals {
+            step: Duration::ZERO,
+            download: Duration::from_millis(10),
+        })
+        .with_max_active_downloads(Some(1));
+    let user_id = UserId(1);
+    let options = CreateRequestOptions {
+        pre_download_match: TitleMatch::Loose,
+        post_download_match: TitleMatch::Contains,
+        preferred_formats: vec![],
+        preferred_year: None,
+        prefer_original: false,
+        topic_id: None,
+        priority: RequestPriority::Manual,
+        exact_phrase_search: false,
+    };
+
+    let mut request_ids = vec![];
+    for channel_id in [RadioManagerChannelId(1), RadioManagerChannelId(2)] {
+        let metadata = AudioMetadata {
+            title: "Sunday Breakfast".into(),
+            artist: "Ted Irens".into(),
+            album: "Foo".into(),
+        };
+        request_ids.push(
+            processor
+                .create_request(&user_id, &metadata, &options, Some(&channel_id))
+                .await
+                .unwrap(),
+        );
+    }
+
+    // The second request gets the download slot once the first one has downloaded the torrent.
+    let (first, second) = futures_lite::future::zip(
+        processor.process_request(&user_id, &request_ids[0]),
+        processor.process_request(&user_id, &request_ids[1]),
+    )
+    .await;
+    first.unwrap();
+    second.unwrap();
+}
+
+#[actix_rt::test]
+async fn test_limiting_concurrent_uploads() {
+    let radio_manager = Arc::new(SlowUploadRadioManagerMock::default());
+
+    let processor = TrackRequestProcessor::new(
+        Arc::from(StateStorageMock::new()),
+        Arc::from(SearchProviderMock),
+        Arc::from(TorrentClientMock),
+        radio_manager.clone(),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    )
+    .with_clock(Arc::new(ClockMock::new()))
+    .with_max_concurrent_uploads(1);
+
+    let mut requests = vec![];
+    for user_id in [UserId(1), UserId(2)] {
+        let request_id = processor
+            .create_upload_request(
+                &user_id,
+                "path/to/01 - Sunday Breakfast.mp3",
+                Some(&RadioManagerChannelId(1)),
+            )
+            .await
+            .unwrap();
+        requests.push((user_id, request_id));
+    }
+
+    let (first, second) = futures_lite::future::zip(
+        processor.process_request(&requests[0].0, &requests[0].1),
+        processor.process_request(&requests[1].0, &requests[1].1),
+    )
+    .await;
+    first.unwrap();
+    second.unwrap();
+
+    assert_eq!(1, radio_manager.max_uploading.load(Ordering::SeqCst));
+}
+
+#[actix_rt::test]
+async fn test_not_adding_torrent_without_free_download_slot() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone())
+        .with_poll_intervals(PollIntervals {
+            step: Duration::ZERO,
+            download: Duration::from_millis(10),
+        })
+        .with_max_active_downloads(Some(0));
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    assert!(actix_rt::time::timeout(
+        Duration::from_millis(200),
+        processor.process_request(&user_id, &request_id),
+    )
+    .await
+    .is_err());
+
+    let state = state_storage
+        .load_state(&user_id, &request_id)
+        .await
+        .unwrap();
+    assert_eq!(state.get_step(), TrackRequestProcessingStep::Download);
+}
+
+#[actix_rt::test]
+async fn test_failing_fast_on_dead_torrent_client_session() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        Arc::from(DeadSessionTorrentClientMock),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    )
+    .with_poll_intervals(PollIntervals {
+        step: Duration::ZERO,
+        download: Duration::from_millis(10),
+    })
+    .with_step_retries(StepRetries {
+        max_retries: 0,
+        backoff: Duration::ZERO,
+    });
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    let result = processor.process_request(&user_id, &request_id).await;
+    assert!(matches!(
+        result,
+        Err(ProcessRequestError::DownloaderError(_))
+    ));
+
+    let state = state_storage
+        .load_state(&user_id, &request_id)
+        .await
+        .unwrap();
+    assert_eq!(state.get_step(), TrackRequestProcessingStep::Download);
+}
+
+#[actix_rt::test]
+async fn test_falling_back_to_releases_in_other_formats() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone());
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![AudioFormat::Alac, AudioFormat::Aac],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    // None of the releases is in the preferred formats.
+    processor
+        .process_request(&user_id, &request_id)
+        .await
+        .unwrap();
+}
+
+#[actix_rt::test]
+async fn test_keeping_not_found_requests_for_retry() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone());
+    let user_id = UserId(1);
+    // Search provider has nothing for this album.
+    let metadata = AudioMetadata {
+        title: "Life @ Mirror".into(),
+        artist: "Ted Irens".into(),
+        album: "Life".into(),
+    };
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        processor.process_request(&user_id, &request_id).await,
+        Err(ProcessRequestError::TrackNotFound)
+    ));
+
+    let not_found_requests = processor.get_not_found_requests(&user_id).await.unwrap();
+    assert_eq!(1, not_found_requests.len());
+    assert_eq!(request_id, not_found_requests[0].0);
+    assert_eq!(metadata, not_found_requests[0].1.metadata);
+    assert_eq!(
+        SearchAttempt {
+            query: "Ted Irens - Life".into(),
+            results_count: 0,
+        },
+        not_found_requests[0].1.search_attempts[0]
+    );
+    assert_eq!(4, not_found_requests[0].1.search_attempts.len());
+    let reports = processor.get_status_reports(&user_id).await.unwrap();
+    assert_eq!(
+        not_found_requests[0].1.search_attempts,
+        reports[&request_id].search_attempts
+    );
+
+    processor
+        .reset_request(&user_id, &request_id)
+        .await
+        .unwrap();
+
+    assert!(processor
+        .get_not_found_requests(&user_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(matches!(
+        state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .get(&request_id),
+        Some(TrackRequestProcessingStatus::Processing)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_reporting_track_found_only_in_image_release() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone());
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Broken Glass".into(),
+        artist: "Ted Irens".into(),
+        album: "Mirrors".into(),
+    };
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        processor.process_request(&user_id, &request_id).await,
+        Err(ProcessRequestError::RequiresSplitting)
+    ));
+    assert!(matches!(
+        state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .get(&request_id),
+        Some(TrackRequestProcessingStatus::RequiresSplitting)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_replaying_unfinished_requests_on_startup() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let user_id = UserId(1);
+    let unfinished_request_id = RequestId(uuid::Uuid::new_v4());
+    let failed_request_id = RequestId(uuid::Uuid::new_v4());
+
+    // Requests left by the previous run.
+    for (request_id, status) in [
+        (
+            &unfinished_request_id,
+            TrackRequestProcessingStatus::Processing,
+        ),
+        (&failed_request_id, TrackRequestProcessingStatus::Failed),
+    ] {
+        state_storage
+            .create_context(
+                &user_id,
+                request_id,
+                TrackRequestProcessingContext::new(
+                    AudioMetadata {
+                        title: "Sunday Breakfast".into(),
+                        artist: "Ted Irens".into(),
+                        album: "Foo".into(),
+                    },
+                    CreateRequestOptions {
+                        pre_download_match: TitleMatch::Loose,
+                        post_download_match: TitleMatch::Contains,
+                        preferred_formats: vec![],
+                        preferred_year: None,
+                        prefer_original: false,
+                        topic_id: None,
+                        priority: RequestPriority::Manual,
+                        exact_phrase_search: false,
+                    },
+                    Some(RadioManagerChannelId(1)),
+                ),
+            )
+            .await
+            .unwrap();
+        state_storage
+            .create_state(
+                &user_id,
+                request_id,
+                TrackRequestProcessingState {
+                    radio_manager_track_id: Some(RadioManagerTrackId(1)),
+                    ..TrackRequestProcessingState::default()
+                },
+            )
+            .await
+            .unwrap();
+        state_storage
+            .update_status(&user_id, request_id, &status)
+            .await
+            .unwrap();
+    }
+
+    let processor = Arc::new(test_processor(state_storage.clone()).with_poll_intervals(
+        PollIntervals {
+            step: Duration::ZERO,
+            download: Duration::ZERO,
+        },
+    ));
+    let _controller = TrackRequestController::create(state_storage.clone(), processor)
+        .await
+        .unwrap();
+
+    actix_rt::time::sleep(Duration::from_millis(100)).await;
+
+    let statuses = state_storage.get_all_statuses(&user_id).await.unwrap();
+    assert!(matches!(
+        statuses.get(&unfinished_request_id),
+        Some(TrackRequestProcessingStatus::Finished)
+    ));
+    assert!(matches!(
+        statuses.get(&failed_request_id),
+        Some(TrackRequestProcessingStatus::Failed)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_downloading_again_torrent_removed_from_torrent_client() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone()).with_poll_intervals(PollIntervals {
+        step: Duration::ZERO,
+        download: Duration::ZERO,
+    });
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    // Request was downloading the torrent when the torrent client has been wiped.
+    state_storage
+        .update_state(
+            &user_id,
+            &request_id,
+            &TrackRequestProcessingState {
+                topics_queue: Some(vec![]),
+                current_topic: Some(TopicData {
+                    title: "Ted Irens - Foo [MP3]".into(),
+                    topic_id: TopicId(1),
+                    download_id: DownloadId(1),
+                    format: Some(AudioFormat::Mp3),
+                    year: None,
+                }),
+                current_torrent_id: Some(TorrentId(2)),
+                ..TrackRequestProcessingState::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    processor
+        .process_request(&user_id, &request_id)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .get(&request_id),
+        Some(TrackRequestProcessingStatus::Finished)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_failing_request_with_inconsistent_state() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone()).with_step_retries(StepRetries {
+        max_retries: 0,
+        ..StepRetries::default()
+    });
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    // Torrent data is missing, while the torrent id points to an unknown torrent.
+    state_storage
+        .update_state(
+            &user_id,
+            &request_id,
+            &TrackRequestProcessingState {
+                topics_queue: Some(vec![TopicData {
+                    title: "Ted Irens - Foo [MP3]".into(),
+                    topic_id: TopicId(1),
+                    download_id: DownloadId(1),
+                    format: Some(AudioFormat::Mp3),
+                    year: None,
+                }]),
+                current_torrent_id: Some(TorrentId(3)),
+                ..TrackRequestProcessingState::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    assert!(processor
+        .process_request(&user_id, &request_id)
+        .await
+        .is_err());
+    assert!(matches!(
+        state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .get(&request_id),
+        Some(TrackRequestProcessingStatus::Failed)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_trying_next_topic_if_torrent_failed() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let torrent_client = Arc::new(RemovalRecordingTorrentClientMock::new());
+
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        torrent_client.clone(),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    );
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    // Torrent client gave up on the torrent of the last topic in the queue.
+    state_storage
+        .update_state(
+            &user_id,
+            &request_id,
+            &TrackRequestProcessingState {
+                topics_queue: Some(vec![]),
+                current_torrent_id: Some(TorrentId(4)),
+                ..TrackRequestProcessingState::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        processor.process_request(&user_id, &request_id).await,
+        Err(ProcessRequestError::TrackNotFound)
+    ));
+    // Failed torrent is not left in the torrent client.
+    assert_eq!(
+        vec![TorrentId(4)],
+        *torrent_client.removed_torrents.lock().unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn test_trying_next_topic_if_download_timed_out() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone())
+        .with_download_timeout(Some(Duration::from_secs(3600)));
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    // Torrent of the last topic in the queue has been downloading for two hours.
+    state_storage
+        .update_state(
+            &user_id,
+            &request_id,
+            &TrackRequestProcessingState {
+                topics_queue: Some(vec![]),
+                current_topic: Some(TopicData {
+                    title: "Ted Irens - Foo [MP3]".into(),
+                    topic_id: TopicId(1),
+                    download_id: DownloadId(1),
+                    format: Some(AudioFormat::Mp3),
+                    year: None,
+                }),
+                current_torrent_id: Some(TorrentId(5)),
+                download_started_at: Some(
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs()
+                        - 7200,
+                ),
+                ..TrackRequestProcessingState::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        processor.process_request(&user_id, &request_id).await,
+        Err(ProcessRequestError::TrackNotFound)
+    ));
+
+    let state = state_storage
+        .load_state(&user_id, &request_id)
+        .await
+        .unwrap();
+    assert_eq!(vec![TopicId(1)], Vec::from(state.dead_topic_ids));
+}
+
+#[actix_rt::test]
+async fn test_abandoning_downloaded_torrent_without_requested_track() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let torrent_client = Arc::new(RemovalRecordingTorrentClientMock::new());
+
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        torrent_client.clone(),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    );
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Rain In The Forest".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    // Torrent of the last topic in the queue has downloaded, but has other tracks only.
+    state_storage
+        .update_state(
+            &user_id,
+            &request_id,
+            &TrackRequestProcessingState {
+                library_searched: true,
+                topics_queue: Some(vec![]),
+                current_topic: Some(TopicData {
+                    title: "Ted Irens - Foo [MP3]".into(),
+                    topic_id: TopicId(1),
+                    download_id: DownloadId(1),
+                    format: Some(AudioFormat::Mp3),
+                    year: None,
+                }),
+                current_torrent_id: Some(TorrentId(1)),
+                ..TrackRequestProcessingState::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        processor.process_request(&user_id, &request_id).await,
+        Err(ProcessRequestError::TrackNotFound)
+    ));
+
+    let state = state_storage
+        .load_state(&user_id, &request_id)
+        .await
+        .unwrap();
+    assert_eq!(vec![TopicId(1)], Vec::from(state.dead_topic_ids));
+    assert_eq!(
+        vec![TorrentId(1)],
+        *torrent_client.removed_torrents.lock().unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn test_keeping_timed_out_torrent_shared_with_another_request() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let torrent_client = Arc::new(RemovalRecordingTorrentClientMock::new());
+
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        torrent_client.clone(),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    )
+    .with_download_timeout(Some(Duration::from_secs(3600)));
+    let user_id = UserId(1);
+    let options = CreateRequestOptions {
+        pre_download_match: TitleMatch::Loose,
+        post_download_match: TitleMatch::Contains,
+        preferred_formats: vec![],
+        preferred_year: None,
+        prefer_original: false,
+        topic_id: None,
+        priority: RequestPriority::Manual,
+        exact_phrase_search: false,
+    };
+
+    let mut request_ids = vec![];
+    for title in ["Sunday Breakfast", "Rain In The Forest"] {
+        let request_id = processor
+            .create_request(
+                &user_id,
+                &AudioMetadata {
+                    title: title.into(),
+                    artist: "Ted Irens".into(),
+                    album: "Foo".into(),
+                },
+                &options,
+                Some(&RadioManagerChannelId(1)),
+            )
+            .await
+            .unwrap();
+
+        // Both requests download the same album, which has been downloading for two hours.
+        state_storage
+            .update_state(
+                &user_id,
+                &request_id,
+                &TrackRequestProcessingState {
+                    topics_queue: Some(vec![]),
+                    current_topic: Some(TopicData {
+                        title: "Ted Irens - Foo [MP3]".into(),
+                        topic_id: TopicId(1),
+                        download_id: DownloadId(1),
+                        format: Some(AudioFormat::Mp3),
+                        year: None,
+                    }),
+                    current_torrent_id: Some(TorrentId(5)),
+                    download_started_at: Some(
+                        SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap()
+                            .as_secs()
+                            - 7200,
+                    ),
+                    ..TrackRequestProcessingState::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        request_ids.push(request_id);
+    }
+
+    assert!(matches!(
+        processor.process_request(&user_id, &request_ids[0]).await,
+        Err(ProcessRequestError::TrackNotFound)
+    ));
+
+    // The other request is still downloading the torrent.
+    assert!(torrent_client.removed_torrents.lock().unwrap().is_empty());
+}
+
+#[actix_rt::test]
+async fn test_waiting_for_download_until_timeout() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let clock = Arc::new(ClockMock::new());
+
+    let processor = test_processor(state_storage.clone())
+        .with_clock(clock.clone())
+        .with_download_timeout(Some(Duration::from_secs(3600)));
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    // Torrent of the last topic in the queue has just started downloading and never completes.
+    let download_started_at = clock.now();
+    state_storage
+        .update_state(
+            &user_id,
+            &request_id,
+            &TrackRequestProcessingState {
+                topics_queue: Some(vec![]),
+                current_topic: Some(TopicData {
+                    title: "Ted Irens - Foo [MP3]".into(),
+                    topic_id: TopicId(1),
+                    download_id: DownloadId(1),
+                    format: Some(AudioFormat::Mp3),
+                    year: None,
+                }),
+                current_torrent_id: Some(TorrentId(5)),
+                download_started_at: Some(download_started_at),
+                ..TrackRequestProcessingState::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        processor.process_request(&user_id, &request_id).await,
+        Err(ProcessRequestError::TrackNotFound)
+    ));
+
+    assert!(clock.now() >= download_started_at + 3600);
+    let state = state_storage
+        .load_state(&user_id, &request_id)
+        .await
+        .unwrap();
+    assert_eq!(vec![TopicId(1)], Vec::from(state.dead_topic_ids));
+}
+
+#[actix_rt::test]
+async fn test_skipping_dead_topics_kept_after_eviction() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone())
+        .with_max_dead_topics(2)
+        .with_torrent_file_retries(StepRetries {
+            max_retries: 0,
+            ..StepRetries::default()
+        });
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+    state_storage
+        .update_state(
+            &user_id,
+            &request_id,
+            &TrackRequestProcessingState {
+                dead_topic_ids: DeadTopics::from(vec![TopicId(5), TopicId(1)]),
+                ..TrackRequestProcessingState::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    // The only downloadable topic is dead, and stays so when the torrent file of the other
+    // one fails to download, evicting the topic which died first.
+    assert!(matches!(
+        processor.process_request(&user_id, &request_id).await,
+        Err(ProcessRequestError::TrackNotFound)
+    ));
+
+    let state = state_storage
+        .load_state(&user_id, &request_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![TopicId(1), TopicId(2)],
+        Vec::from(state.dead_topic_ids)
+    );
+}
+
+#[actix_rt::test]
+async fn test_giving_up_request_after_deadline() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor =
+        test_processor(state_storage.clone()).with_request_timeout(Some(Duration::from_secs(600)));
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+    let page = RequestsPage {
+        limit: 50,
+        offset: 0,
+    };
+
+    let mut ctx = state_storage
+        .load_context(&user_id, &request_id)
+        .await
+        .unwrap();
+    assert_eq!(Some(ctx.created_at + 600), ctx.deadline);
+    let (requests, _) = processor
+        .list_requests(&user_id, &RequestFilter::default(), &page)
+        .await
+        .unwrap();
+    assert_eq!(ctx.deadline, requests[0].deadline);
+    let reports = processor.get_status_reports(&user_id).await.unwrap();
+    assert_eq!(ctx.deadline, reports[&request_id].deadline);
+
+    // Created well before the timeout.
+    ctx.created_at -= 3600;
+    ctx.deadline = Some(ctx.created_at + 600);
+    state_storage
+        .update_context(&user_id, &request_id, &ctx)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        processor.process_request(&user_id, &request_id).await,
+        Err(ProcessRequestError::DeadlineExceeded)
+    ));
+
+    let (requests, _) = processor
+        .list_requests(&user_id, &RequestFilter::default(), &page)
+        .await
+        .unwrap();
+    assert!(matches!(
+        requests[0].status,
+        TrackRequestProcessingStatus::Failed
+    ));
+    assert_eq!(None, requests[0].deadline);
+    let reports = processor.get_status_reports(&user_id).await.unwrap();
+    assert!(matches!(
+        reports[&request_id].status,
+        TrackRequestProcessingStatus::Failed
+    ));
+    assert_eq!(None, reports[&request_id].deadline);
+}
+
+#[actix_rt::test]
+async fn test_listing_requests_history() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone());
+    let user_id = UserId(1);
+    let options = CreateRequestOptions {
+        pre_download_match: TitleMatch::Loose,
+        post_download_match: TitleMatch::Contains,
+        preferred_formats: vec![],
+        preferred_year: None,
+        prefer_original: false,
+        topic_id: None,
+        priority: RequestPriority::Manual,
+        exact_phrase_search: false,
+    };
+    // Found in the library, so it finishes without downloading anything.
+    let finished_request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Winter's Sunset".into(),
+                artist: "Ted Irens".into(),
+                album: "Bar".into(),
+            },
+            &options,
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+    let processing_request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &options,
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    processor
+        .process_request(&user_id, &finished_request_id)
+        .await
+        .unwrap();
+
+    // Both requests are likely created within the same second.
+    for (request_id, created_at) in [(&finished_request_id, 1), (&processing_request_id, 2)] {
+        let mut ctx = state_storage
+            .load_context(&user_id, request_id)
+            .await
+            .unwrap();
+        ctx.created_at = created_at;
+        state_storage
+            .update_context(&user_id, request_id, &ctx)
+            .await
+            .unwrap();
+    }
+
+    let (requests, total) = processor
+        .list_requests(
+            &user_id,
+            &RequestFilter::default(),
+            &RequestsPage {
+                limit: 1,
+                offset: 0,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(2, total);
+    assert_eq!(1, requests.len());
+    assert_eq!(processing_request_id, requests[0].request_id);
+
+    let (requests, total) = processor
+        .list_requests(
+            &user_id,
+            &RequestFilter {
+                status: Some(RequestStatusFilter::Finished),
+            },
+            &RequestsPage {
+                limit: 50,
+                offset: 0,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(1, total);
+    assert_eq!(finished_request_id, requests[0].request_id);
+    assert_eq!("Winter's Sunset", requests[0].metadata.title);
+}
+
+#[actix_rt::test]
+async fn test_getting_request_stats() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let processor = test_processor(state_storage.clone());
+    let user_id = UserId(1);
+    let options = CreateRequestOptions {
+        pre_download_match: TitleMatch::Loose,
+        post_download_match: TitleMatch::Contains,
+        preferred_formats: vec![],
+        preferred_year: None,
+        prefer_original: false,
+        topic_id: None,
+        priority: RequestPriority::Manual,
+        exact_phrase_search: false,
+    };
+    let mut finished_request_ids = vec![];
+    // Found in the library, so they finish without downloading anything.
+    for channel_id in [1, 2] {
+        let request_id = processor
+            .create_request(
+                &user_id,
+                &AudioMetadata {
+                    title: "Winter's Sunset".into(),
+                    artist: "Ted Irens".into(),
+                    album: "Bar".into(),
+                },
+                &options,
+                Some(&RadioManagerChannelId(channel_id)),
+            )
+            .await
+            .unwrap();
+        processor
+            .process_request(&user_id, &request_id)
+            .await
+            .unwrap();
+        finished_request_ids.push(request_id);
+    }
+    processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &options,
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+
+    for (request_id, completion_secs) in finished_request_ids.iter().zip([60, 120]) {
+        let mut ctx = state_storage
+            .load_context(&user_id, request_id)
+            .await
+            .unwrap();
+        ctx.created_at = 1000;
+        ctx.updated_at = 1000 + completion_secs;
+        state_storage
+            .update_context(&user_id, request_id, &ctx)
+            .await
+            .unwrap();
+    }
+
+    let stats = processor.get_request_stats(&user_id).await.unwrap();
+
+    assert_eq!(
+        RequestStats {
+            processing: 1,
+            finished: 2,
+            tracks_added: 2,
+            average_completion_secs: Some(90),
+            ..RequestStats::default()
+        },
+        stats
+    );
+}
+
+#[actix_rt::test]
+async fn test_reading_statuses_from_memory() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let user_id = UserId(1);
+    let restored_request_id = RequestId(uuid::Uuid::new_v4());
+
+    // Status left by the previous run.
+    state_storage
+        .update_status(
+            &user_id,
+            &restored_request_id,
+            &TrackRequestProcessingStatus::Failed,
+        )
+        .await
+        .unwrap();
+
+    let processor = test_processor(state_storage.clone());
+
+    let statuses = processor.get_processing_requests(&user_id).await.unwrap();
+    assert_eq!(1, statuses.len());
+
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+    processor
+        .process_request(&user_id, &request_id)
+        .await
+        .unwrap();
+
+    // Statuses are not read from the state storage again.
+    state_storage
+        .delete_status(&user_id, &restored_request_id)
+        .await
+        .unwrap();
+
+    let statuses = processor.get_processing_requests(&user_id).await.unwrap();
+    assert_eq!(2, statuses.len());
+    assert!(matches!(
+        statuses.get(&request_id),
+        Some(TrackRequestProcessingStatus::Finished)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_loading_statuses_into_memory_at_startup() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let user_id = UserId(1);
+    let restored_request_id = RequestId(uuid::Uuid::new_v4());
+
+    // Status left by the previous run.
+    state_storage
+        .update_status(
+            &user_id,
+            &restored_request_id,
+            &TrackRequestProcessingStatus::Failed,
+        )
+        .await
+        .unwrap();
+
+    let processor = Arc::new(test_processor(state_storage.clone()));
+    let _controller = TrackRequestController::create(state_storage.clone(), processor.clone())
+        .await
+        .unwrap();
+
+    // Statuses are not read from the state storage after startup.
+    state_storage
+        .delete_status(&user_id, &restored_request_id)
+        .await
+        .unwrap();
+
+    let statuses = processor.get_processing_requests(&user_id).await.unwrap();
+    assert!(matches!(
+        statuses.get(&restored_request_id),
+        Some(TrackRequestProcessingStatus::Failed)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_resetting_failed_track_request() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone()).with_step_retries(StepRetries {
+        max_retries: 0,
+        ..StepRetries::default()
+    });
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Foo".into(),
+    };
+    let channel_id = RadioManagerChannelId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&channel_id),
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        processor.reset_request(&user_id, &request_id).await,
+        Err(RetryRequestError::RequestNotFound)
+    ));
+
+    // Torrent file of the only topic in the queue can't be downloaded.
+    state_storage
+        .update_state(
+            &user_id,
+            &request_id,
+            &TrackRequestProcessingState {
+                topics_queue: Some(vec![TopicData {
+                    title: "Ted Irens - Foo [FLAC]".into(),
+                    topic_id: TopicId(2),
+                    download_id: DownloadId(2),
+                    format: None,
+                    year: None,
+                }]),
+                ..TrackRequestProcessingState::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert!(processor
+        .process_request(&user_id, &request_id)
+        .await
+        .is_err());
+
+    processor
+        .reset_request(&user_id, &request_id)
+        .await
+        .unwrap();
+
+    let stored_state = state_storage
+        .load_state(&user_id, &request_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        stored_state.get_step(),
+        TrackRequestProcessingStep::FindInLibrary
+    );
+
+    let statuses = state_storage.get_all_statuses(&user_id).await.unwrap();
+    assert!(matches!(
+        statuses.get(&request_id),
+        Some(TrackRequestProcessingStatus::Processing)
+    ));
+
+    assert!(matches!(
+        processor.reset_request(&user_id, &request_id).await,
+        Err(RetryRequestError::NotFailed)
+    ));
+}
+
+#[actix_rt::test]
+async fn test_pausing_downloading_torrents() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Foo".into(),
+    };
+    let options = CreateRequestOptions {
+        pre_download_match: TitleMatch::Loose,
+        post_download_match: TitleMatch::Contains,
+        preferred_formats: vec![],
+        preferred_year: None,
+        prefer_original: false,
+        topic_id: None,
+        priority: RequestPriority::Manual,
+        exact_phrase_search: false,
+    };
+
+    // Requests left in progress by the previous run.
+    let requests = [
+        (
+            RequestId(uuid::Uuid::new_v4()),
+            TrackRequestProcessingState {
+                topics_queue: Some(vec![]),
+                current_torrent_id: Some(TorrentId(1)),
+                ..TrackRequestProcessingState::default()
+            },
+        ),
+        (
+            RequestId(uuid::Uuid::new_v4()),
+            TrackRequestProcessingState::default(),
+        ),
+    ];
+
+    for (request_id, state) in requests {
+        state_storage
+            .create_context(
+                &user_id,
+                &request_id,
+                TrackRequestProcessingContext::new(
+                    metadata.clone(),
+                    options.clone(),
+                    Some(RadioManagerChannelId(1)),
+                ),
+            )
+            .await
+            .unwrap();
+        state_storage
+            .create_state(&user_id, &request_id, state)
+            .await
+            .unwrap();
+        state_storage
+            .update_status(
+                &user_id,
+                &request_id,
+                &TrackRequestProcessingStatus::Processing,
+            )
+            .await
+            .unwrap();
+    }
+
+    let processor = test_processor(state_storage.clone());
+
+    assert_eq!(1, processor.pause_downloads(&user_id).await.unwrap());
+    assert_eq!(1, processor.resume_downloads(&user_id).await.unwrap());
+}
+
+#[actix_rt::test]
+async fn test_skipping_torrent_exceeding_download_size_limit() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        Arc::from(TorrentClientMock),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        Some(1024),
+    )
+    .with_torrent_file_retries(StepRetries {
+        max_retries: 0,
+        ..StepRetries::default()
+    });
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Foo".into(),
+    };
+    let channel_id = RadioManagerChannelId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&channel_id),
+        )
+        .await
+        .unwrap();
+
+    // The only downloadable torrent is too large, so the processor moves on to the
+    // next topic, whose torrent file is not available in the search provider mock.
+    assert!(matches!(
+        processor.process_request(&user_id, &request_id).await,
+        Err(ProcessRequestError::TrackNotFound)
+    ));
+
+    // Rejected topic is not downloaded again on retry, nor is it recorded as the source.
+    let state = state_storage
+        .load_state(&user_id, &request_id)
+        .await
+        .unwrap();
+    assert!(state.dead_topic_ids.contains(&TopicId(1)));
+    assert!(state.current_topic.is_none());
+    assert!(state.current_info_hash.is_none());
+}
+
+#[actix_rt::test]
+async fn test_retrying_torrent_file_download() {
+    // Two failures fit into the retry budget, while the third one makes the processor
+    // skip the topic, leaving only the one whose torrent file is not available.
+    for (failures, finishes) in [(2, true), (3, false)] {
+        let processor = TrackRequestProcessor::new(
+            Arc::from(StateStorageMock::new()),
+            Arc::new(FlakySearchProviderMock {
+                failures: Mutex::new(failures),
+            }),
+            Arc::from(TorrentClientMock),
+            Arc::from(RadioManagerMock),
+            Arc::from(MetadataServiceMock),
+            DownloadDirectories::new("/downloads", "downloads"),
+            None,
+        )
+        .with_torrent_file_retries(StepRetries {
+            max_retries: 2,
+            backoff: Duration::from_secs(1),
+        })
+        .with_clock(Arc::new(ClockMock::new()));
+        let user_id = UserId(1);
+        let request_id = processor
+            .create_request(
+                &user_id,
+                &AudioMetadata {
+                    title: "Sunday Breakfast".into(),
+                    artist: "Ted Irens".into(),
+                    album: "Foo".into(),
+                },
+                &CreateRequestOptions {
+                    pre_download_match: TitleMatch::Loose,
+                    post_download_match: TitleMatch::Contains,
+                    preferred_formats: vec![],
+                    preferred_year: None,
+                    prefer_original: false,
+                    topic_id: None,
+                    priority: RequestPriority::Manual,
+                    exact_phrase_search: false,
+                },
+                Some(&RadioManagerChannelId(1)),
+            )
+            .await
+            .unwrap();
+
+        let result = processor.process_request(&user_id, &request_id).await;
+
+        if finishes {
+            assert!(result.is_ok());
+        } else {
+            assert!(matches!(result, Err(ProcessRequestError::TrackNotFound)));
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn test_coalescing_identical_track_requests() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = test_processor(state_storage.clone());
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Foo".into(),
+    };
+    let channel_id = RadioManagerChannelId(1);
+    let options = CreateRequestOptions {
+        pre_download_match: TitleMatch::Loose,
+        post_download_match: TitleMatch::Contains,
+        preferred_formats: vec![],
+        preferred_year: None,
+        prefer_original: false,
+        topic_id: None,
+        priority: RequestPriority::Manual,
+        exact_phrase_search: false,
+    };
+
+    let first_request_id = processor
+        .create_request(&user_id, &metadata, &options, Some(&channel_id))
+        .await
+        .unwrap();
+    let second_request_id = processor
+        .create_request(&user_id, &metadata, &options, Some(&channel_id))
+        .await
+        .unwrap();
+
+    assert_eq!(first_request_id, second_request_id);
+    assert_eq!(
+        1,
+        state_storage
+            .get_all_contexts(&user_id)
+            .await
+            .unwrap()
+            .len()
+    );
+
+    let other_channel_request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &options,
+            Some(&RadioManagerChannelId(2)),
+        )
+        .await
+        .unwrap();
+
+    assert_ne!(first_request_id, other_channel_request_id);
+
+    let picked_topic_request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                topic_id: Some(TopicId(1)),
+                ..options.clone()
+            },
+            Some(&channel_id),
+        )
+        .await
+        .unwrap();
+
+    assert_ne!(first_request_id, picked_topic_request_id);
+    assert!(matches!(
+        state_storage
+            .load_context(&user_id, &picked_topic_request_id)
+            .await
+            .unwrap()
+            .options
+            .topic_id,
+        Some(TopicId(1))
+    ));
+}
+
+struct SpanRequestId(String);
+
+struct RequestIdVisitor(Option<String>);
+
+impl Visit for RequestIdVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "request_id" {
+            self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
+
+/// Records the `request_id` inherited by every event from its enclosing spans.
+#[derive(Clone, Default)]
+struct RequestIdCollector {
+    events: Arc<Mutex<Vec<Option<String>>>>,
+}
+
+impl<S> Layer<S> for RequestIdCollector
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = RequestIdVisitor(None);
+        attrs.record(&mut visitor);
+
+        if let (Some(request_id), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().insert(SpanRequestId(request_id));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let request_id = ctx.event_scope(event).and_then(|scope| {
+            scope.from_root().find_map(|span| {
+                span.extensions()
+                    .get::<SpanRequestId>()
+                    .map(|id| id.0.clone())
+            })
+        });
+
+        self.events.lock().unwrap().push(request_id);
+    }
+}
+
+#[actix_rt::test]
+async fn test_processing_logs_carry_request_id() {
+    let processor = test_processor(Arc::new(StateStorageMock::new()));
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Foo".into(),
+    };
+    let options = CreateRequestOptions {
+        pre_download_match: TitleMatch::Loose,
+        post_download_match: TitleMatch::Contains,
+        preferred_formats: vec![],
+        preferred_year: None,
+        prefer_original: false,
+        topic_id: None,
+        priority: RequestPriority::Manual,
+        exact_phrase_search: false,
+    };
+
+    let first_request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &options,
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+    let second_request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &options,
+            Some(&RadioManagerChannelId(2)),
+        )
+        .await
+        .unwrap();
+
+    let collector = RequestIdCollector::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(collector.clone()));
+
+    let (first, second) = futures_lite::future::zip(
+        processor.process_request(&user_id, &first_request_id),
+        processor.process_request(&user_id, &second_request_id),
+    )
+    .await;
+    first.unwrap();
+    second.unwrap();
+
+    let events = collector.events.lock().unwrap();
+    let count_events = |request_id: &RequestId| {
+        events
+            .iter()
+            .filter(|id| id.as_deref() == Some(request_id.to_string().as_str()))
+            .count()
+    };
+
+    assert!(events.iter().all(Option::is_some));
+    assert!(count_events(&first_request_id) > 0);
+    assert!(count_events(&second_request_id) > 0);
+    assert_eq!(
+        events.len(),
+        count_events(&first_request_id) + count_events(&second_request_id)
+    );
+}
+
+async fn fail_request_on_download(download_id: DownloadId) -> (Arc<StateStorageMock>, RequestId) {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        Arc::from(UnavailableTorrentClientMock),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    )
+    .with_step_retries(StepRetries {
+        max_retries: 2,
+        backoff: Duration::ZERO,
+    });
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                pre_download_match: TitleMatch::Loose,
+                post_download_match: TitleMatch::Contains,
+                preferred_formats: vec![],
+                preferred_year: None,
+                prefer_original: false,
+                topic_id: None,
+                priority: RequestPriority::Manual,
+                exact_phrase_search: false,
+            },
+            Some(&RadioManagerChannelId(1)),
+        )
+        .await
+        .unwrap();
+    state_storage
+        .update_state(
+            &user_id,
+            &request_id,
+            &TrackRequestProcessingState {
+                topics_queue: Some(vec![TopicData {
+                    title: "Ted Irens - Foo [FLAC]".into(),
+                    topic_id: TopicId(*download_id),
+                    download_id,
+                    format: None,
+                    year: None,
+                }]),
+                ..TrackRequestProcessingState::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    assert!(processor
+        .process_request(&user_id, &request_id)
+        .await
+        .is_err());
+
+    (state_storage, request_id)
+}
+
+#[actix_rt::test]
+async fn test_keeping_request_failed_with_transient_error() {
+    let user_id = UserId(1);
+    let (state_storage, request_id) = fail_request_on_download(DownloadId(1)).await;
+
+    assert!(matches!(
+        state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .get(&request_id),
+        Some(TrackRequestProcessingStatus::Failed)
+    ));
+    assert!(state_storage
+        .get_all_contexts(&user_id)
+        .await
+        .unwrap()
+        .contains_key(&request_id));
+
+    // The failing step has been retried from the same state.
+    let state = state_storage
+        .load_state(&user_id, &request_id)
+        .await
+        .unwrap();
+    assert_eq!(2, state.step_retries);
+    assert_eq!(TrackRequestProcessingStep::Download, state.get_step());
+}
+
+#[actix_rt::test]
+async fn test_dropping_request_failed_with_unrecoverable_error() {
+    let user_id = UserId(1);
+    let (state_storage, request_id) = fail_request_on_download(DownloadId(3)).await;
+
+    assert!(matches!(
+        state_storage
+            .get_all_statuses(&user_id)
+            .await
+            .unwrap()
+            .get(&request_id),
+        Some(TrackRequestProcessingStatus::Failed)
+    ));
+    assert!(state_storage
+        .get_all_contexts(&user_id)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[actix_rt::test]
+async fn test_finding_request_by_idempotency_key() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let processor = test_processor(state_storage.clone());
+    let user_id = UserId(1);
+    let request_id = RequestId(uuid::Uuid::new_v4());
+
+    assert!(processor
+        .find_idempotent_request(&user_id, "key")
+        .await
+        .unwrap()
+        .is_none());
+
+    processor
+        .save_idempotency_key(&user_id, "key", &request_id)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        Some(request_id.clone()),
+        processor
+            .find_idempotent_request(&user_id, "key")
+            .await
+            .unwrap()
+    );
+    assert!(processor
+        .find_idempotent_request(&UserId(2), "key")
+        .await
+        .unwrap()
+        .is_none());
+
+    // Expired keys are ignored.
+    state_storage
+        .set_idempotency(
+            &user_id,
+            "key",
+            &IdempotencyRecord {
+                request_id,
+                created_at: 0,
+            },
+        )
+        .await
+        .unwrap();
+
+    assert!(processor
+        .find_idempotent_request(&user_id, "key")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[actix_rt::test]
+async fn test_rejecting_request_to_channel_not_allowed_for_user() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = Arc::new(test_processor(state_storage.clone()));
+    let controller = Arc::new(
+        TrackRequestController::create(state_storage.clone(), processor)
+            .await
+            .unwrap(),
+    );
+    let channel_allowlist = Arc::new(ChannelAllowlist::from_entries(&["1:100".into()]).unwrap());
+    let app = actix_web::test::init_service(
+        actix_web::App::new()
+            .app_data(actix_web::web::Data::new(controller))
+            .app_data(actix_web::web::Data::new(Arc::new(RateLimiter::new(None))))
+            .app_data(actix_web::web::Data::new(channel_allowlist))
+            .route(
+                "/create",
+                actix_web::web::post().to(crate::http::make_track_request),
+            ),
+    )
+    .await;
+
+    let response = actix_web::test::call_service(
+        &app,
+        actix_web::test::TestRequest::post()
+            .uri("/create")
+            .set_json(serde_json::json!({
+                "title": "Sunday Breakfast",
+                "artist": "Ted Irens",
+                "album": "Foo",
+                "targetChannelId": 200,
+            }))
+            .to_request(),
+    )
+    .await;
+
+    assert_eq!(actix_web::http::StatusCode::FORBIDDEN, response.status());
+    assert!(state_storage
+        .get_all_contexts(&UserId(1))
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(state_storage
+        .get_all_statuses(&UserId(1))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[actix_rt::test]
+async fn test_passing_request_options_from_http_request() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = Arc::new(test_processor(state_storage.clone()));
+    let controller = Arc::new(
+        TrackRequestController::create(state_storage.clone(), processor)
+            .await
+            .unwrap(),
+    );
+    let app = actix_web::test::init_service(
+        actix_web::App::new()
+            .app_data(actix_web::web::Data::new(controller))
+            .app_data(actix_web::web::Data::new(Arc::new(RateLimiter::new(None))))
+            .app_data(actix_web::web::Data::new(Arc::new(
+                ChannelAllowlist::from_entries(&[]).unwrap(),
+            )))
+            .route(
+                "/create",
+                actix_web::web::post().to(crate::http::make_track_request),
+            ),
+    )
+    .await;
+
+    let response = actix_web::test::call_service(
+        &app,
+        actix_web::test::TestRequest::post()
+            .uri("/create")
+            .set_json(serde_json::json!({
+                "title": "Sunday Breakfast",
+                "artist": "Ted Irens",
+                "album": "Foo",
+                "preDownloadMatch": "contains",
+                "postDownloadMatch": "loose",
+                "preferredFormats": ["flac", "alac"],
+                "preferredYear": 1998,
+                "preferOriginal": true,
+            }))
+            .to_request(),
+    )
+    .await;
+
+    assert_eq!(actix_web::http::StatusCode::ACCEPTED, response.status());
+    let contexts = state_storage.get_all_contexts(&UserId(1)).await.unwrap();
+    let options = &contexts.values().next().unwrap().options;
+    assert_eq!(TitleMatch::Contains, options.pre_download_match);
+    assert_eq!(TitleMatch::Loose, options.post_download_match);
+    assert_eq!(
+        vec![AudioFormat::Flac, AudioFormat::Alac],
+        options.preferred_formats
+    );
+    assert_eq!(Some(1998), options.preferred_year);
+    assert!(options.prefer_original);
+}
+
+#[actix_rt::test]
+async fn test_rejecting_upload_to_channel_not_allowed_for_user() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = Arc::new(TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        Arc::from(TorrentClientMock),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "tests/fixtures"),
+        None,
+    ));
+    let controller = Arc::new(
+        TrackRequestController::create(state_storage.clone(), processor)
+            .await
+            .unwrap(),
+    );
+    let channel_allowlist = Arc::new(ChannelAllowlist::from_entries(&["1:100".into()]).unwrap());
+    let app = actix_web::test::init_service(
+        actix_web::App::new()
+            .app_data(actix_web::web::Data::new(controller))
+            .app_data(actix_web::web::Data::new(Arc::new(RateLimiter::new(None))))
+            .app_data(actix_web::web::Data::new(channel_allowlist))
+            .route(
+                "/upload",
+                actix_web::web::post().to(crate::http::make_upload_request),
+            ),
+    )
+    .await;
+
+    let response = actix_web::test::call_service(
+        &app,
+        actix_web::test::TestRequest::post()
+            .uri("/upload")
+            .set_json(serde_json::json!({
+                "pathToFile": "tagged.mp3",
+                "targetChannelId": 200,
+            }))
+            .to_request(),
+    )
+    .await;
+
+    assert_eq!(actix_web::http::StatusCode::FORBIDDEN, response.status());
+    assert!(state_storage
+        .get_all_contexts(&UserId(1))
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(state_storage
+        .get_all_statuses(&UserId(1))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[actix_rt::test]
+async fn test_rejecting_upload_of_file_outside_download_dirs() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = Arc::new(TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        Arc::from(TorrentClientMock),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "tests/fixtures"),
+        None,
+    ));
+    let controller = Arc::new(
+        TrackRequestController::create(state_storage.clone(), processor)
+            .await
+            .unwrap(),
+    );
+    let app = actix_web::test::init_service(
+        actix_web::App::new()
+            .app_data(actix_web::web::Data::new(controller))
+            .app_data(actix_web::web::Data::new(Arc::new(RateLimiter::new(None))))
+            .app_data(actix_web::web::Data::new(Arc::new(
+                ChannelAllowlist::from_entries(&[]).unwrap(),
+            )))
+            .route(
+                "/upload",
+                actix_web::web::post().to(crate::http::make_upload_request),
+            ),
+    )
+    .await;
+
+    for path_to_file in ["/etc/passwd", "../../../../../../etc/passwd"] {
+        let response = actix_web::test::call_service(
+            &app,
+            actix_web::test::TestRequest::post()
+                .uri("/upload")
+                .set_json(serde_json::json!({
+                    "pathToFile": path_to_file,
+                    "targetChannelId": 1,
+                }))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(actix_web::http::StatusCode::BAD_REQUEST, response.status());
+    }
+    assert!(state_storage
+        .get_all_contexts(&UserId(1))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[actix_rt::test]
+async fn test_keeping_torrent_of_deleted_request_shared_with_another_request() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let torrent_client = Arc::new(RemovalRecordingTorrentClientMock::new());
+
+    let processor = TrackRequestProcessor::new(
+        state_storage.clone(),
+        Arc::from(SearchProviderMock),
+        torrent_client.clone(),
+        Arc::from(RadioManagerMock),
+        Arc::from(MetadataServiceMock),
+        DownloadDirectories::new("/downloads", "downloads"),
+        None,
+    );
+    let user_id = UserId(1);
+    let options = CreateRequestOptions {
+        pre_download_match: TitleMatch::Loose,
+        post_download_match: TitleMatch::Contains,
+        preferred_formats: vec![],
+        preferred_year: None,
+        prefer_original: false,
+        topic_id: None,
+        priority: RequestPriority::Manual,
+        exact_phrase_search: false,
+    };
+
+    let mut request_ids = vec![];
+    for title in ["Sunday Breakfast", "Rain In The Forest"] {
+        let request_id = processor
+            .create_request(
+                &user_id,
+                &AudioMetadata {
+                    title: title.into(),
+                    artist: "Ted Irens".into(),
+                    album: "Foo".into(),
+                },
+                &options,
+                Some(&RadioManagerChannelId(1)),
+            )
+            .await
+            .unwrap();
+
+        // Both requests download the same album.
+        state_storage
+            .update_state(
+                &user_id,
+                &request_id,
+                &TrackRequestProcessingState {
+                    current_torrent_id: Some(TorrentId(5)),
+                    ..TrackRequestProcessingState::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        request_ids.push(request_id);
+    }
+
+    processor
+        .delete_request(&user_id, &request_ids[0])
+        .await
+        .unwrap();
+    assert!(torrent_client.removed_torrents.lock().unwrap().is_empty());
+
+    processor
+        .delete_request(&user_id, &request_ids[1])
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![TorrentId(5)],
+        *torrent_client.removed_torrents.lock().unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn test_cancelling_all_requests_of_user() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    // No download slots, so the requests keep waiting for one until cancelled.
+    let processor = Arc::new(
+        test_processor(state_storage.clone())
+            .with_max_active_downloads(Some(0))
+            .with_poll_intervals(PollIntervals {
+                step: Duration::ZERO,
+                download: Duration::from_millis(10),
+            }),
+    );
+    let controller = TrackRequestController::create(state_storage.clone(), processor)
+        .await
+        .unwrap();
+    let user_id = UserId(1);
+    for title in ["Sunday Breakfast", "Rain In The Forest"] {
+        controller
+            .create_request(
+                &user_id,
+                &AudioMetadata {
+                    title: title.into(),
+                    artist: "Ted Irens".into(),
+                    album: "Foo".into(),
+                },
+                &CreateRequestOptions {
+                    pre_download_match: TitleMatch::Loose,
+                    post_download_match: TitleMatch::Tags,
+                    preferred_formats: vec![],
+                    preferred_year: None,
+                    prefer_original: false,
+                    topic_id: None,
+                    priority: RequestPriority::Manual,
+                    exact_phrase_search: false,
+                },
+                Some(&RadioManagerChannelId(1)),
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    actix_rt::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(2, controller.running_tasks_count());
+
+    assert_eq!(2, controller.cancel_all_requests(&user_id).await.unwrap());
+    assert_eq!(0, controller.running_tasks_count());
+
+    // Stopped tasks don't write anything back.
+    actix_rt::time::sleep(Duration::from_millis(100)).await;
+    assert!(state_storage
+        .get_all_contexts(&user_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(state_storage
+        .get_all_statuses(&user_id)
+        .await
+        .unwrap()
+        .is_empty());
 }
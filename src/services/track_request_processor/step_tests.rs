@@ -1,13 +1,26 @@
 use super::track_request_processor::{
-    DownloadId, RadioManagerLinkId, RadioManagerTrackId, TorrentId, TrackRequestProcessingState,
-    TrackRequestProcessingStep,
+    AudioMetadata, DownloadId, RadioManagerLinkId, RadioManagerTrackId, TorrentId,
+    TrackRequestProcessingState, TrackRequestProcessingStep,
+};
+use crate::services::track_request_processor::{
+    AudioFormat, CreateRequestOptions, MatchingFilePreference, RequestPriority, TitleMatch,
+    TopicData, TopicId, TrackRequestProcessingStatus,
 };
-use crate::services::track_request_processor::{TopicData, TopicId};
 
 #[test]
-fn should_return_search_audio_album_by_default() {
+fn should_return_find_in_library_by_default() {
     let state = TrackRequestProcessingState::default();
 
+    assert_eq!(state.get_step(), TrackRequestProcessingStep::FindInLibrary)
+}
+
+#[test]
+fn should_return_search_audio_album_if_library_has_been_searched() {
+    let state = TrackRequestProcessingState {
+        library_searched: true,
+        ..TrackRequestProcessingState::default()
+    };
+
     assert_eq!(
         state.get_step(),
         TrackRequestProcessingStep::GetTopicsIntoQueue
@@ -20,6 +33,8 @@ fn should_return_get_album_url_if_current_topic_id_is_set() {
         topics_queue: Some(vec![TopicData {
             topic_id: TopicId(1),
             download_id: DownloadId(1),
+            format: None,
+            year: None,
             title: "Title".into(),
         }]),
         ..TrackRequestProcessingState::default()
@@ -37,6 +52,8 @@ fn should_return_download_album_if_current_url_is_set() {
         topics_queue: Some(vec![TopicData {
             topic_id: TopicId(1),
             download_id: DownloadId(1),
+            format: None,
+            year: None,
             title: "Title".into(),
         }]),
         current_torrent_data: Some(vec![]),
@@ -52,6 +69,8 @@ fn should_return_check_download_status_if_current_download_id_is_set() {
         topics_queue: Some(vec![TopicData {
             topic_id: TopicId(1),
             download_id: DownloadId(1),
+            format: None,
+            year: None,
             title: "Title".into(),
         }]),
         current_torrent_data: Some(vec![]),
@@ -65,12 +84,34 @@ fn should_return_check_download_status_if_current_download_id_is_set() {
     )
 }
 
+#[test]
+fn should_return_check_download_status_if_torrent_data_was_released() {
+    let state = TrackRequestProcessingState {
+        topics_queue: Some(vec![TopicData {
+            topic_id: TopicId(1),
+            download_id: DownloadId(1),
+            format: None,
+            year: None,
+            title: "Title".into(),
+        }]),
+        current_torrent_id: Some(TorrentId(1)),
+        ..TrackRequestProcessingState::default()
+    };
+
+    assert_eq!(
+        state.get_step(),
+        TrackRequestProcessingStep::CheckDownloadStatus
+    )
+}
+
 #[test]
 fn should_return_upload_to_radioterio_if_path_to_downloaded_file_is_set() {
     let state = TrackRequestProcessingState {
         topics_queue: Some(vec![TopicData {
             topic_id: TopicId(1),
             download_id: DownloadId(1),
+            format: None,
+            year: None,
             title: "Title".into(),
         }]),
         current_torrent_data: Some(vec![]),
@@ -91,6 +132,8 @@ fn should_return_add_track_to_radioterio_channel_if_radioterio_track_id_is_set()
         topics_queue: Some(vec![TopicData {
             topic_id: TopicId(1),
             download_id: DownloadId(1),
+            format: None,
+            year: None,
             title: "Title".into(),
         }]),
         current_torrent_data: Some(vec![]),
@@ -112,6 +155,8 @@ fn should_return_finish_if_radioterio_link_id_is_set() {
         topics_queue: Some(vec![TopicData {
             topic_id: TopicId(1),
             download_id: DownloadId(1),
+            format: None,
+            year: None,
             title: "Title".into(),
         }]),
         current_torrent_data: Some(vec![]),
@@ -124,3 +169,334 @@ fn should_return_finish_if_radioterio_link_id_is_set() {
 
     assert_eq!(state.get_step(), TrackRequestProcessingStep::Finish)
 }
+
+#[test]
+fn should_return_finish_after_upload_if_channel_add_is_skipped() {
+    let state = TrackRequestProcessingState {
+        path_to_downloaded_file: Some("path/to/file".into()),
+        radio_manager_track_id: Some(RadioManagerTrackId(1)),
+        skip_channel_add: true,
+        ..TrackRequestProcessingState::default()
+    };
+
+    assert_eq!(state.get_step(), TrackRequestProcessingStep::Finish)
+}
+
+#[test]
+fn should_return_add_track_to_radioterio_channel_if_track_was_found_in_library() {
+    let state = TrackRequestProcessingState {
+        radio_manager_track_id: Some(RadioManagerTrackId(1)),
+        ..TrackRequestProcessingState::default()
+    };
+
+    assert_eq!(
+        state.get_step(),
+        TrackRequestProcessingStep::AddToRadioManagerChannel
+    )
+}
+
+#[test]
+fn should_match_same_track_ignoring_case_and_whitespace() {
+    let track = AudioMetadata {
+        title: "Life @ Mirror".into(),
+        artist: "Ted Irens".into(),
+        album: "Life".into(),
+    };
+
+    assert!(track.is_same_track(&AudioMetadata {
+        title: " life @ mirror".into(),
+        artist: "TED IRENS".into(),
+        album: "Life ".into(),
+    }));
+    assert!(!track.is_same_track(&AudioMetadata {
+        album: "Life (Remastered)".into(),
+        ..track.clone()
+    }));
+}
+
+#[test]
+fn should_match_any_album_if_none_is_requested() {
+    let track = AudioMetadata {
+        title: "Life @ Mirror".into(),
+        artist: "Ted Irens".into(),
+        album: "Life".into(),
+    };
+
+    assert!(track.matches_album(" life"));
+    assert!(!track.matches_album("Life (Remastered)"));
+    assert!(AudioMetadata {
+        album: " ".into(),
+        ..track
+    }
+    .matches_album("Life (Remastered)"));
+}
+
+#[test]
+fn should_name_upload_file_after_artist_and_title() {
+    let track = AudioMetadata {
+        title: "Life @ Mirror".into(),
+        artist: "Ted Irens".into(),
+        album: "Life".into(),
+    };
+
+    assert_eq!(
+        Some("Ted Irens - Life @ Mirror.flac".to_string()),
+        track.get_upload_file_name("/downloads/Ted Irens/track02.flac")
+    );
+    assert_eq!(
+        Some("AC_DC - Who Made Who_ (Live_Remix).mp3".to_string()),
+        AudioMetadata {
+            title: "Who Made Who?  (Live/Remix)".into(),
+            artist: "AC/DC".into(),
+            album: "".into(),
+        }
+        .get_upload_file_name("/downloads/track02.mp3")
+    );
+    assert_eq!(
+        Some("Сплин - Выхода нет".to_string()),
+        AudioMetadata {
+            title: "Выхода нет...".into(),
+            artist: " Сплин\t".into(),
+            album: "".into(),
+        }
+        .get_upload_file_name("/downloads/track02")
+    );
+    assert_eq!(
+        Some("Life @ Mirror.flac".to_string()),
+        AudioMetadata {
+            artist: "".into(),
+            ..track.clone()
+        }
+        .get_upload_file_name("track02.flac")
+    );
+    assert_eq!(
+        None,
+        AudioMetadata {
+            title: " ... ".into(),
+            ..track.clone()
+        }
+        .get_upload_file_name("track02.flac")
+    );
+}
+
+#[test]
+fn should_serialize_statuses_as_plain_strings() {
+    let status = TrackRequestProcessingStatus::Processing;
+
+    assert_eq!(r#""Processing""#, serde_json::to_string(&status).unwrap());
+    assert!(matches!(
+        serde_json::from_str(r#""Failed""#).unwrap(),
+        TrackRequestProcessingStatus::Failed
+    ));
+}
+
+#[test]
+fn should_sort_topics_by_preferred_year() {
+    let topic = |topic_id: u64, year: Option<u16>| TopicData {
+        topic_id: TopicId(topic_id),
+        download_id: DownloadId(topic_id),
+        format: None,
+        year,
+        title: "Title".into(),
+    };
+    let topics = vec![
+        topic(1, Some(2016)),
+        topic(2, None),
+        topic(3, Some(1996)),
+        topic(4, Some(2003)),
+        topic(5, Some(1996)),
+    ];
+    let sorted_ids = |options: CreateRequestOptions| {
+        let mut topics = topics.clone();
+        options.sort_topics_by_preference(&mut topics);
+        topics
+            .iter()
+            .map(|topic| *topic.topic_id)
+            .collect::<Vec<_>>()
+    };
+    let options = CreateRequestOptions {
+        pre_download_match: TitleMatch::Loose,
+        post_download_match: TitleMatch::Contains,
+        preferred_formats: vec![],
+        preferred_year: None,
+        prefer_original: false,
+        topic_id: None,
+        priority: RequestPriority::Manual,
+        exact_phrase_search: false,
+    };
+
+    assert_eq!(vec![1, 2, 3, 4, 5], sorted_ids(options.clone()));
+    assert_eq!(
+        vec![4, 1, 2, 3, 5],
+        sorted_ids(CreateRequestOptions {
+            preferred_year: Some(2003),
+            ..options.clone()
+        })
+    );
+    assert_eq!(
+        vec![3, 5, 4, 1, 2],
+        sorted_ids(CreateRequestOptions {
+            prefer_original: true,
+            ..options.clone()
+        })
+    );
+    assert_eq!(
+        vec![4, 3, 5, 1, 2],
+        sorted_ids(CreateRequestOptions {
+            preferred_year: Some(2003),
+            prefer_original: true,
+            ..options
+        })
+    );
+}
+
+#[test]
+fn should_sort_topics_by_preferred_formats() {
+    let topic = |topic_id: u64, format: Option<AudioFormat>, year: Option<u16>| TopicData {
+        topic_id: TopicId(topic_id),
+        download_id: DownloadId(topic_id),
+        format,
+        year,
+        title: "Title".into(),
+    };
+    let mut topics = vec![
+        topic(1, Some(AudioFormat::Mp3), Some(2003)),
+        topic(2, None, None),
+        topic(3, Some(AudioFormat::Flac), Some(2016)),
+        topic(4, Some(AudioFormat::Alac), Some(2003)),
+    ];
+    let options = CreateRequestOptions {
+        pre_download_match: TitleMatch::Loose,
+        post_download_match: TitleMatch::Contains,
+        preferred_formats: vec![AudioFormat::Flac, AudioFormat::Alac],
+        preferred_year: Some(2003),
+        prefer_original: false,
+        topic_id: None,
+        priority: RequestPriority::Manual,
+        exact_phrase_search: false,
+    };
+
+    options.sort_topics_by_preference(&mut topics);
+
+    // Releases in other formats are kept, to be tried last.
+    assert_eq!(
+        vec![4, 3, 1, 2],
+        topics
+            .iter()
+            .map(|topic| *topic.topic_id)
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn should_match_titles_by_strictness() {
+    let filepath = "Ted Irens/07. Ted Irens - Winter's Sunset.flac";
+
+    assert!(TitleMatch::Loose.matches_filename(filepath, "Winters Sunset"));
+    assert!(!TitleMatch::Contains.matches_filename(filepath, "Winters Sunset"));
+    assert!(TitleMatch::Contains.matches_filename(filepath, "winter's sunset"));
+    assert!(!TitleMatch::Tags.matches_filename(filepath, "Winters Sunset"));
+}
+
+#[test]
+fn should_skip_album_queries_for_singles() {
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Life @ Mirror".into(),
+    };
+    assert_eq!(
+        vec![
+            "Ted Irens - Life @ Mirror",
+            "Ted Irens дискография",
+            "Ted Irens discography",
+            "Ted Irens дискографія",
+        ],
+        metadata.get_search_queries(false)
+    );
+
+    for album in ["", "  ", "sunday breakfast"] {
+        let single = AudioMetadata {
+            album: album.into(),
+            ..metadata.clone()
+        };
+        assert_eq!(
+            vec![
+                "Ted Irens дискография",
+                "Ted Irens discography",
+                "Ted Irens дискографія",
+            ],
+            single.get_search_queries(false)
+        );
+    }
+}
+
+#[test]
+fn should_quote_artist_and_album_in_exact_phrase_queries() {
+    let metadata = AudioMetadata {
+        title: "La Femme d'Argent".into(),
+        artist: "Air".into(),
+        album: "Moon \"Safari\"".into(),
+    };
+
+    assert_eq!(
+        vec![
+            "\"Air\" \"Moon Safari\"",
+            "\"Air\" дискография",
+            "\"Air\" discography",
+            "\"Air\" дискографія",
+        ],
+        metadata.get_search_queries(true)
+    );
+}
+
+#[test]
+fn should_pick_preferred_file_of_several_matching_ones() {
+    let files: Vec<String> = vec![
+        "Ted Irens/Live/03 - Sunday Breakfast (Live).flac".into(),
+        "Ted Irens/Bonus/11 - Sunday Breakfast (Remix).flac".into(),
+        "Ted Irens/Studio/05 - Sunday Breakfast.flac".into(),
+    ];
+    let pick = |preference: MatchingFilePreference| {
+        preference
+            .pick(&files, "Sunday Breakfast")
+            .map(String::as_str)
+    };
+
+    assert_eq!(Some(files[0].as_str()), pick(MatchingFilePreference::First));
+    assert_eq!(
+        Some(files[2].as_str()),
+        pick(MatchingFilePreference::ShortestPath)
+    );
+    assert_eq!(
+        Some(files[2].as_str()),
+        pick(MatchingFilePreference::ExactTitle)
+    );
+    assert_eq!(
+        Some(files[2].as_str()),
+        pick(MatchingFilePreference::Folder("studio".into()))
+    );
+    // The first file is picked if none is preferred.
+    assert_eq!(
+        Some(files[0].as_str()),
+        pick(MatchingFilePreference::Folder("CD2".into()))
+    );
+    assert_eq!(
+        None,
+        MatchingFilePreference::First.pick(&[], "Sunday Breakfast")
+    );
+}
+
+#[test]
+fn should_parse_matching_file_preference() {
+    assert_eq!(
+        MatchingFilePreference::ShortestPath,
+        "shortest_path".parse().unwrap()
+    );
+    assert_eq!(
+        MatchingFilePreference::Folder("Studio".into()),
+        "folder: Studio".parse().unwrap()
+    );
+    assert!("folder:".parse::<MatchingFilePreference>().is_err());
+    assert!("longest_path".parse::<MatchingFilePreference>().is_err());
+}
@@ -1,11 +1,13 @@
 use crate::services::track_request_processor::{
-    AudioMetadata, CreateRequestError, CreateRequestOptions, RadioManagerChannelId, RequestId,
-    StateStorageError, StateStorageTrait, TrackRequestProcessingContext,
-    TrackRequestProcessingState,
+    AudioMetadata, CreateRequestError, CreateRequestOptions, ProcessRequestError,
+    RadioManagerChannelId, RequestId, RetryRequestError, StateStorageError, StateStorageTrait,
+    TrackRequestProcessingContext, TrackRequestProcessingState, TrackRequestProcessingStatus,
 };
 use crate::services::TrackRequestProcessor;
 use crate::types::UserId;
-use std::sync::Arc;
+use actix_rt::task::JoinHandle;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use tracing::{debug, error, info};
 
 #[derive(Debug, thiserror::Error)]
@@ -14,10 +16,18 @@ pub(crate) enum TrackRequestControllerError {
     StateStorageError(#[from] StateStorageError),
     #[error(transparent)]
     TrackRequestError(#[from] CreateRequestError),
+    #[error(transparent)]
+    RetryRequestError(#[from] RetryRequestError),
+    #[error(transparent)]
+    ProcessRequestError(#[from] ProcessRequestError),
+    #[error("File is outside of the download and library directories")]
+    UploadPathNotAllowed,
 }
 
 pub(crate) struct TrackRequestController {
     track_request_processor: Arc<TrackRequestProcessor>,
+    // Handles of the tasks processing the requests, to stop them on cancellation.
+    running_tasks: Arc<Mutex<HashMap<RequestId, JoinHandle<()>>>>,
 }
 
 impl TrackRequestController {
@@ -27,8 +37,15 @@ impl TrackRequestController {
     ) -> Result<Self, TrackRequestControllerError> {
         let controller = Self {
             track_request_processor,
+            running_tasks: Arc::new(Mutex::new(HashMap::new())),
         };
 
+        debug!("Loading request statuses...");
+        controller
+            .track_request_processor
+            .load_status_index()
+            .await?;
+
         debug!("Loading tasks...");
         let tasks = state_storage.get_all_tasks().await?;
 
@@ -44,30 +61,146 @@ impl TrackRequestController {
         &self,
         user_id: &UserId,
         track_metadata: &AudioMetadata,
-        target_channel_id: &RadioManagerChannelId,
+        options: &CreateRequestOptions,
+        target_channel_id: Option<&RadioManagerChannelId>,
+        idempotency_key: Option<&str>,
     ) -> Result<RequestId, TrackRequestControllerError> {
+        if let Some(idempotency_key) = idempotency_key {
+            if let Some(request_id) = self
+                .track_request_processor
+                .find_idempotent_request(user_id, idempotency_key)
+                .await?
+            {
+                debug!("Replaying track request {} for idempotency key", request_id);
+                return Ok(request_id);
+            }
+        }
+
         let request_id = self
             .track_request_processor
-            .create_request(
-                user_id,
-                track_metadata,
-                &CreateRequestOptions {
-                    validate_metadata: false,
-                },
-                target_channel_id,
-            )
+            .create_request(user_id, track_metadata, options, target_channel_id)
             .await?;
 
+        if let Some(idempotency_key) = idempotency_key {
+            self.track_request_processor
+                .save_idempotency_key(user_id, idempotency_key, &request_id)
+                .await?;
+        }
+
         self.spawn_task(&user_id, &request_id);
 
         Ok(request_id)
     }
 
+    pub(crate) async fn create_upload_request(
+        &self,
+        user_id: &UserId,
+        path_to_file: &str,
+        target_channel_id: Option<&RadioManagerChannelId>,
+    ) -> Result<RequestId, TrackRequestControllerError> {
+        if !self
+            .track_request_processor
+            .download_directories()
+            .is_uploadable(path_to_file)
+            .await
+        {
+            return Err(TrackRequestControllerError::UploadPathNotAllowed);
+        }
+
+        let request_id = self
+            .track_request_processor
+            .create_upload_request(user_id, path_to_file, target_channel_id)
+            .await?;
+
+        self.spawn_task(user_id, &request_id);
+
+        Ok(request_id)
+    }
+
+    pub(crate) async fn create_search_request(
+        &self,
+        user_id: &UserId,
+        query: &str,
+        target_channel_id: Option<&RadioManagerChannelId>,
+    ) -> Result<RequestId, TrackRequestControllerError> {
+        let request_id = self
+            .track_request_processor
+            .create_search_request(user_id, query, target_channel_id)
+            .await?;
+
+        self.spawn_task(user_id, &request_id);
+
+        Ok(request_id)
+    }
+
+    pub(crate) async fn retry_request(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<(), TrackRequestControllerError> {
+        self.track_request_processor
+            .reset_request(user_id, request_id)
+            .await?;
+
+        self.spawn_task(user_id, request_id);
+
+        Ok(())
+    }
+
+    /// Stops and drops every request of the user still being processed.
+    /// Returns the number of cancelled requests.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn cancel_all_requests(
+        &self,
+        user_id: &UserId,
+    ) -> Result<usize, TrackRequestControllerError> {
+        let statuses = self
+            .track_request_processor
+            .get_processing_requests(user_id)
+            .await?;
+        let request_ids: Vec<_> = statuses
+            .into_iter()
+            .filter(|(_, status)| matches!(status, TrackRequestProcessingStatus::Processing))
+            .map(|(request_id, _)| request_id)
+            .collect();
+
+        for request_id in &request_ids {
+            // Aborted task doesn't run again, so it can't write the state back.
+            if let Some(task) = self.running_tasks.lock().unwrap().remove(request_id) {
+                task.abort();
+            }
+
+            self.track_request_processor
+                .delete_request(user_id, request_id)
+                .await?;
+        }
+
+        info!("Cancelled {} track request(s)", request_ids.len());
+
+        Ok(request_ids.len())
+    }
+
+    /// Number of requests being processed right now.
+    pub(crate) fn running_tasks_count(&self) -> usize {
+        self.running_tasks.lock().unwrap().len()
+    }
+
     fn spawn_task(&self, user_id: &UserId, request_id: &RequestId) {
-        actix_rt::spawn({
+        // Running tasks stay locked until the handle is stored, so a task finishing
+        // right away doesn't leave its handle behind.
+        let mut running_tasks = self.running_tasks.lock().unwrap();
+
+        // Coalesced requests share the same id, so they must be processed only once.
+        if running_tasks.contains_key(request_id) {
+            debug!("Track request {} is already being processed", request_id);
+            return;
+        }
+
+        let task = actix_rt::spawn({
             let user_id = user_id.clone();
             let request_id = request_id.clone();
             let track_request_processor = self.track_request_processor.clone();
+            let running_tasks = self.running_tasks.clone();
 
             async move {
                 if let Err(error) = track_request_processor
@@ -76,7 +209,11 @@ impl TrackRequestController {
                 {
                     error!(?error, "Track request processing failed");
                 }
+
+                running_tasks.lock().unwrap().remove(&request_id);
             }
         });
+
+        running_tasks.insert(request_id.clone(), task);
     }
 }
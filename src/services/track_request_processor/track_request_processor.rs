@@ -1,14 +1,27 @@
-use crate::services::torrent_parser::{get_files, TorrentParserError};
+use crate::services::download_directories::DownloadDirectories;
+use crate::services::torrent_parser::{
+    find_best_matching_file, get_files, get_files_with_sizes, get_info_hash, get_release_layout,
+    is_exact_title_match, is_track_file, prefer_exact_matches_across_discs, ReleaseLayout,
+    TorrentParserError,
+};
+use crate::services::track_request_processor::dead_topics::DeadTopics;
+use crate::services::track_request_processor::download_queue::DownloadQueue;
 use crate::types::UserId;
-use crate::utils::{contains_ignore_case, contains_in_filename_ignore_case};
+use crate::utils::{
+    contains_ignore_case, contains_in_filename_ignore_case, contains_words_in_filename_ignore_case,
+    eq_ignore_case, get_artist_name_variants, to_safe_filename,
+};
+use async_lock::Mutex;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::io::ErrorKind;
 use std::ops::Deref;
+use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Semaphore;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
@@ -37,6 +50,87 @@ pub(crate) struct AudioMetadata {
     pub(crate) album: String,
 }
 
+impl AudioMetadata {
+    /// Compares artist, title and album ignoring case and surrounding whitespace.
+    pub(crate) fn is_same_track(&self, other: &AudioMetadata) -> bool {
+        eq_ignore_case(&self.artist, &other.artist)
+            && eq_ignore_case(&self.title, &other.title)
+            && eq_ignore_case(&self.album, &other.album)
+    }
+
+    /// Whether the album of a found track matches the requested one. Users often know
+    /// the artist and title only, so any album matches if none is requested.
+    pub(crate) fn matches_album(&self, album: &str) -> bool {
+        self.album.trim().is_empty() || eq_ignore_case(&self.album, album)
+    }
+
+    /// Whether the track is a single, i.e. has no album of its own.
+    pub(crate) fn is_single(&self) -> bool {
+        self.album.trim().is_empty() || eq_ignore_case(&self.album, &self.title)
+    }
+
+    /// Queries to search for the releases with the track, the album ones first.
+    /// Singles are searched in the discographies only, as there is no album to search for.
+    /// With `exact_phrases`, the artist and album are quoted to be matched as whole phrases.
+    pub(crate) fn get_search_queries(&self, exact_phrases: bool) -> Vec<String> {
+        let phrase = |text: &str| {
+            if exact_phrases {
+                format!("\"{}\"", text.replace('"', ""))
+            } else {
+                text.to_string()
+            }
+        };
+        let album_query = |artist: &str| {
+            if exact_phrases {
+                format!("{} {}", phrase(artist), phrase(&self.album))
+            } else {
+                format!("{} - {}", artist, self.album)
+            }
+        };
+        let is_single = self.is_single();
+        let mut queries = vec![];
+
+        if !is_single {
+            queries.push(album_query(&self.artist));
+        }
+        queries.push(format!("{} дискография", phrase(&self.artist)));
+        queries.push(format!("{} discography", phrase(&self.artist)));
+        queries.push(format!("{} дискографія", phrase(&self.artist)));
+
+        for artist in get_artist_name_variants(&self.artist) {
+            if !is_single {
+                queries.push(album_query(&artist));
+            }
+            queries.push(format!("{} дискография", phrase(&artist)));
+            queries.push(format!("{} discography", phrase(&artist)));
+        }
+
+        queries
+    }
+
+    /// File name `{artist} - {title}.{ext}` to upload the audio file at the path under,
+    /// keeping its extension. Returns `None` if there is no title to name the file after.
+    pub(crate) fn get_upload_file_name(&self, path_to_audio_file: &str) -> Option<String> {
+        let title = to_safe_filename(&self.title);
+        if title.is_empty() {
+            return None;
+        }
+
+        let name = match to_safe_filename(&self.artist) {
+            artist if artist.is_empty() => title,
+            artist => format!("{} - {}", artist, title),
+        };
+
+        match std::path::Path::new(path_to_audio_file)
+            .extension()
+            .and_then(|ext| ext.to_str())
+        {
+            Some(ext) => Some(format!("{}.{}", name, ext)),
+            None => Some(name),
+        }
+    }
+}
+
 impl std::fmt::Display for AudioMetadata {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{} - {} ({})", self.artist, self.title, self.album)
@@ -129,17 +223,33 @@ impl std::fmt::Display for TorrentId {
     }
 }
 
+#[derive(Eq, PartialEq, Clone, Copy, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum AudioFormat {
+    Flac,
+    Mp3,
+    Alac,
+    Aac,
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub(crate) struct TopicData {
     pub(crate) topic_id: TopicId,
     pub(crate) download_id: DownloadId,
     pub(crate) title: String,
+    #[serde(default)]
+    pub(crate) format: Option<AudioFormat>,
+    #[serde(default)]
+    pub(crate) year: Option<u16>,
 }
 
 #[derive(Clone, PartialEq, Debug)]
 pub(crate) enum TorrentStatus {
     Downloading,
+    Paused,
     Complete,
+    /// Torrent client gave up on the torrent, e.g. the tracker rejected it or the disk is full.
+    Error(String),
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -152,55 +262,162 @@ pub(crate) struct Torrent {
 pub(crate) struct TrackRequestProcessingContext {
     pub(crate) metadata: AudioMetadata,
     pub(crate) options: CreateRequestOptions,
-    pub(crate) target_channel_id: RadioManagerChannelId,
+    /// Channel the track is added to. Without one, the track is only uploaded to the library.
+    pub(crate) target_channel_id: Option<RadioManagerChannelId>,
+    /// Local audio file of the upload only request, which skips search and download.
+    #[serde(default)]
+    pub(crate) upload_file_path: Option<String>,
+    /// Free-text query of the search request, whose track is the best matching file
+    /// of the found torrents. Metadata is taken from the tags of that file.
+    #[serde(default)]
+    pub(crate) search_query: Option<String>,
+    /// Unix timestamp in seconds.
+    #[serde(default)]
+    pub(crate) created_at: u64,
+    /// Unix timestamp in seconds of the last status change.
+    #[serde(default)]
+    pub(crate) updated_at: u64,
+    /// Unix timestamp in seconds the request is given up at if still processing.
+    #[serde(default)]
+    pub(crate) deadline: Option<u64>,
 }
 
 impl TrackRequestProcessingContext {
     pub(crate) fn new(
         metadata: AudioMetadata,
         options: CreateRequestOptions,
-        target_channel_id: RadioManagerChannelId,
+        target_channel_id: Option<RadioManagerChannelId>,
     ) -> Self {
+        let now = unix_timestamp();
+
         Self {
             metadata,
             options,
             target_channel_id,
+            upload_file_path: None,
+            search_query: None,
+            created_at: now,
+            updated_at: now,
+            deadline: None,
+        }
+    }
+
+    pub(crate) fn initial_state(&self) -> TrackRequestProcessingState {
+        TrackRequestProcessingState {
+            path_to_downloaded_file: self.upload_file_path.clone(),
+            skip_channel_add: self.target_channel_id.is_none(),
+            ..TrackRequestProcessingState::default()
+        }
+    }
+
+    /// Indexes of the torrent files to download: the best match of the search query,
+    /// or the files matching the requested title.
+    pub(crate) fn select_files(&self, filepaths: &[String]) -> Vec<usize> {
+        let track_indexes =
+            (0..filepaths.len()).filter(|index| is_track_file(&filepaths[*index], filepaths));
+
+        match &self.search_query {
+            Some(query) => find_best_matching_file(track_indexes.collect(), filepaths, query)
+                .into_iter()
+                .collect(),
+            None => {
+                let matching_indexes = track_indexes
+                    .filter(|index| {
+                        self.options
+                            .pre_download_match
+                            .matches_filename(&filepaths[*index], &self.metadata.title)
+                    })
+                    .collect();
+
+                prefer_exact_matches_across_discs(matching_indexes, filepaths, &self.metadata.title)
+            }
         }
     }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub(crate) struct TrackRequestProcessingState {
+    /// Set once the library has been searched for the requested track, before searching
+    /// for the topics, so nothing is downloaded if the track has been uploaded already.
+    #[serde(default)]
+    pub(crate) library_searched: bool,
     pub(crate) topics_queue: Option<Vec<TopicData>>,
+    /// Topic the current torrent comes from, to download it again if the torrent is gone.
+    #[serde(default)]
+    pub(crate) current_topic: Option<TopicData>,
     pub(crate) current_torrent_data: Option<Vec<u8>>,
+    /// Info hash of the current torrent, to record the files it yielded once downloaded.
+    #[serde(default)]
+    pub(crate) current_info_hash: Option<String>,
     pub(crate) current_torrent_id: Option<TorrentId>,
+    /// Unix timestamp in seconds of adding the current torrent to the torrent client.
+    #[serde(default)]
+    pub(crate) download_started_at: Option<u64>,
+    #[serde(default)]
+    pub(crate) dead_topic_ids: DeadTopics,
     pub(crate) path_to_downloaded_file: Option<String>,
+    /// Set before the file is sent to the radio manager. The upload might have succeeded
+    /// even if its response has been lost, so the library is checked before uploading again.
+    #[serde(default)]
+    pub(crate) upload_in_progress: bool,
     pub(crate) radio_manager_track_id: Option<RadioManagerTrackId>,
     pub(crate) radio_manager_link_id: Option<RadioManagerLinkId>,
+    /// Set for requests without a target channel, which finish once the track is uploaded.
+    #[serde(default)]
+    pub(crate) skip_channel_add: bool,
+    /// Consecutive failed attempts of the current step, kept to survive a restart.
+    #[serde(default)]
+    pub(crate) step_retries: u32,
+    /// Queries tried while filling the topics queue, to tell why nothing has been found.
+    #[serde(default)]
+    pub(crate) search_attempts: Vec<SearchAttempt>,
+    /// First topic with the album as a single audio image and a `.cue` sheet,
+    /// reported if no topic has the requested track as a separate file.
+    #[serde(default)]
+    pub(crate) image_release_topic: Option<TopicData>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SearchAttempt {
+    pub(crate) query: String,
+    pub(crate) results_count: usize,
 }
 
 impl TrackRequestProcessingState {
     pub(crate) fn get_step(&self) -> TrackRequestProcessingStep {
-        if self.topics_queue.is_none() {
+        // Track might be found in the library before downloading anything,
+        // so the radio manager fields take precedence over the download ones.
+        // Likewise, the file of the upload only request is there from the start.
+        // Requests without a channel are done as soon as the track is in the library.
+        if self.radio_manager_link_id.is_some()
+            || (self.radio_manager_track_id.is_some() && self.skip_channel_add)
+        {
+            TrackRequestProcessingStep::Finish
+        } else if self.radio_manager_track_id.is_some() {
+            TrackRequestProcessingStep::AddToRadioManagerChannel
+        } else if self.path_to_downloaded_file.is_some() {
+            TrackRequestProcessingStep::UploadToRadioManager
+        } else if self.topics_queue.is_none() && !self.library_searched {
+            TrackRequestProcessingStep::FindInLibrary
+        } else if self.topics_queue.is_none() {
             TrackRequestProcessingStep::GetTopicsIntoQueue
-        } else if self.current_torrent_data.is_none() {
-            TrackRequestProcessingStep::DownloadNextTorrentFile
         } else if self.current_torrent_id.is_none() {
-            TrackRequestProcessingStep::Download
-        } else if self.path_to_downloaded_file.is_none() {
-            TrackRequestProcessingStep::CheckDownloadStatus
-        } else if self.radio_manager_track_id.is_none() {
-            TrackRequestProcessingStep::UploadToRadioManager
-        } else if self.radio_manager_link_id.is_none() {
-            TrackRequestProcessingStep::AddToRadioManagerChannel
+            // Torrent file data is only kept until the torrent is added to the torrent client.
+            if self.current_torrent_data.is_none() {
+                TrackRequestProcessingStep::DownloadNextTorrentFile
+            } else {
+                TrackRequestProcessingStep::Download
+            }
         } else {
-            TrackRequestProcessingStep::Finish
+            TrackRequestProcessingStep::CheckDownloadStatus
         }
     }
 }
 
 #[derive(Debug, PartialEq)]
 pub(crate) enum TrackRequestProcessingStep {
+    FindInLibrary,
     GetTopicsIntoQueue,
     DownloadNextTorrentFile,
     Download,
@@ -216,10 +433,147 @@ pub(crate) enum TrackRequestProcessingStatus {
     NotFound,
     Failed,
     Finished,
+    /// Track has only been found in an image release, which has to be split manually.
+    RequiresSplitting,
+}
+
+/// Status the listed requests are filtered by.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RequestStatusFilter {
+    Processing,
+    NotFound,
+    Failed,
+    Finished,
+    RequiresSplitting,
+}
+
+impl RequestStatusFilter {
+    fn matches(&self, status: &TrackRequestProcessingStatus) -> bool {
+        matches!(
+            (self, status),
+            (Self::Processing, TrackRequestProcessingStatus::Processing)
+                | (Self::NotFound, TrackRequestProcessingStatus::NotFound)
+                | (
+                    Self::RequiresSplitting,
+                    TrackRequestProcessingStatus::RequiresSplitting
+                )
+                | (Self::Failed, TrackRequestProcessingStatus::Failed)
+                | (Self::Finished, TrackRequestProcessingStatus::Finished)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RequestFilter {
+    pub(crate) status: Option<RequestStatusFilter>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RequestsPage {
+    pub(crate) limit: usize,
+    pub(crate) offset: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RequestSummary {
+    pub(crate) request_id: RequestId,
+    pub(crate) metadata: AudioMetadata,
+    pub(crate) target_channel_id: Option<RadioManagerChannelId>,
+    pub(crate) status: TrackRequestProcessingStatus,
+    pub(crate) created_at: u64,
+    pub(crate) updated_at: u64,
+    /// Unix timestamp in seconds the request is given up at, only while it's processing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) deadline: Option<u64>,
+}
+
+/// Status of a request along with the details telling how it's going.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RequestStatusReport {
+    pub(crate) status: TrackRequestProcessingStatus,
+    /// Unix timestamp in seconds the request is given up at, only while it's processing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) deadline: Option<u64>,
+    /// Queries tried before giving up, only if the track hasn't been found.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) search_attempts: Vec<SearchAttempt>,
+}
+
+/// Aggregate numbers of the requests of a user.
+#[derive(Debug, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RequestStats {
+    pub(crate) processing: usize,
+    pub(crate) not_found: usize,
+    pub(crate) failed: usize,
+    pub(crate) finished: usize,
+    pub(crate) requires_splitting: usize,
+    /// Tracks added to the channels by the finished requests.
+    pub(crate) tracks_added: usize,
+    /// Average seconds from creating a request to finishing it, if any has finished.
+    pub(crate) average_completion_secs: Option<u64>,
+}
+
+/// Request whose track hasn't been found, kept for curators to source the track manually.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NotFoundRecord {
+    pub(crate) metadata: AudioMetadata,
+    pub(crate) target_channel_id: Option<RadioManagerChannelId>,
+    /// Unix timestamp in seconds.
+    pub(crate) created_at: u64,
+    #[serde(default)]
+    pub(crate) search_attempts: Vec<SearchAttempt>,
+}
+
+/// Where the track of a finished request came from, kept for audits.
+/// Tracks found in the library have no topic, torrent or file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ProvenanceRecord {
+    pub(crate) metadata: AudioMetadata,
+    pub(crate) target_channel_id: Option<RadioManagerChannelId>,
+    pub(crate) topic_id: Option<TopicId>,
+    pub(crate) info_hash: Option<String>,
+    /// Path of the uploaded file.
+    pub(crate) file_path: Option<String>,
+    pub(crate) radio_manager_track_id: Option<RadioManagerTrackId>,
+    /// Unix timestamp in seconds.
+    pub(crate) created_at: u64,
+    /// Unix timestamp in seconds.
+    pub(crate) finished_at: u64,
+}
+
+/// Request created for the client-provided idempotency key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct IdempotencyRecord {
+    pub(crate) request_id: RequestId,
+    /// Unix timestamp in seconds.
+    pub(crate) created_at: u64,
+}
+
+/// Files already downloaded from a torrent, reused by later requests for the same torrent.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DownloadedTorrentRecord {
+    /// Paths in the torrent client's filesystem.
+    pub(crate) files: Vec<String>,
+}
+
+/// Torrent of a finished request that is left seeding until its removal time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SeedingTorrentRecord {
+    pub(crate) request_id: RequestId,
+    /// Unix timestamp in seconds.
+    pub(crate) remove_at: u64,
 }
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct RadioManagerChannelTrack {
+    /// Not known if the radio manager doesn't report it.
+    pub(crate) track_id: Option<RadioManagerTrackId>,
     pub(crate) album: String,
     pub(crate) artist: String,
     pub(crate) title: String,
@@ -245,6 +599,12 @@ pub(crate) trait StateStorageTrait {
         request_id: &RequestId,
         state: &TrackRequestProcessingState,
     ) -> Result<(), StateStorageError>;
+    async fn update_context(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        ctx: &TrackRequestProcessingContext,
+    ) -> Result<(), StateStorageError>;
     async fn update_status(
         &self,
         user_id: &UserId,
@@ -280,7 +640,149 @@ pub(crate) trait StateStorageTrait {
         &self,
         user_id: &UserId,
     ) -> Result<HashMap<RequestId, TrackRequestProcessingStatus>, StateStorageError>;
+    async fn get_all_contexts(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<RequestId, TrackRequestProcessingContext>, StateStorageError>;
     async fn get_all_tasks(&self) -> Result<Vec<(UserId, RequestId)>, StateStorageError>;
+    /// Users having at least one request status stored.
+    async fn get_status_owners(&self) -> Result<Vec<UserId>, StateStorageError>;
+    /// Returns the page of requests, most recently created first, along with the total number
+    /// of requests matching the filter. Requests dropped from the storage aren't listed.
+    async fn list_requests(
+        &self,
+        user_id: &UserId,
+        filter: &RequestFilter,
+        page: &RequestsPage,
+    ) -> Result<(Vec<RequestSummary>, usize), StateStorageError> {
+        let statuses = self.get_all_statuses(user_id).await?;
+        let mut requests = self
+            .get_all_contexts(user_id)
+            .await?
+            .into_iter()
+            .filter_map(|(request_id, ctx)| {
+                let status = statuses
+                    .get(&request_id)
+                    .cloned()
+                    .unwrap_or(TrackRequestProcessingStatus::Processing);
+
+                match &filter.status {
+                    Some(status_filter) if !status_filter.matches(&status) => None,
+                    _ => Some(RequestSummary {
+                        request_id,
+                        metadata: ctx.metadata,
+                        target_channel_id: ctx.target_channel_id,
+                        deadline: ctx
+                            .deadline
+                            .filter(|_| matches!(status, TrackRequestProcessingStatus::Processing)),
+                        status,
+                        created_at: ctx.created_at,
+                        updated_at: ctx.updated_at,
+                    }),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        requests.sort_by_key(|request| std::cmp::Reverse(request.created_at));
+        let total = requests.len();
+
+        Ok((
+            requests
+                .into_iter()
+                .skip(page.offset)
+                .take(page.limit)
+                .collect(),
+            total,
+        ))
+    }
+    async fn get_request_stats(&self, user_id: &UserId) -> Result<RequestStats, StateStorageError> {
+        let statuses = self.get_all_statuses(user_id).await?;
+        let mut stats = RequestStats::default();
+        let mut completion_secs = vec![];
+
+        for (request_id, ctx) in self.get_all_contexts(user_id).await? {
+            match statuses.get(&request_id) {
+                Some(TrackRequestProcessingStatus::Processing) | None => stats.processing += 1,
+                Some(TrackRequestProcessingStatus::NotFound) => stats.not_found += 1,
+                Some(TrackRequestProcessingStatus::Failed) => stats.failed += 1,
+                Some(TrackRequestProcessingStatus::RequiresSplitting) => {
+                    stats.requires_splitting += 1
+                }
+                Some(TrackRequestProcessingStatus::Finished) => {
+                    stats.finished += 1;
+                    stats.tracks_added += 1;
+                    // Requests stored before the timestamps were introduced have none.
+                    if ctx.created_at > 0 {
+                        completion_secs.push(ctx.updated_at.saturating_sub(ctx.created_at));
+                    }
+                }
+            }
+        }
+
+        if !completion_secs.is_empty() {
+            let total_secs: u64 = completion_secs.iter().sum();
+            stats.average_completion_secs = Some(total_secs / completion_secs.len() as u64);
+        }
+
+        Ok(stats)
+    }
+
+    async fn get_idempotency(
+        &self,
+        user_id: &UserId,
+        idempotency_key: &str,
+    ) -> Result<Option<IdempotencyRecord>, StateStorageError>;
+    async fn set_idempotency(
+        &self,
+        user_id: &UserId,
+        idempotency_key: &str,
+        record: &IdempotencyRecord,
+    ) -> Result<(), StateStorageError>;
+    async fn add_not_found(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        record: &NotFoundRecord,
+    ) -> Result<(), StateStorageError>;
+    async fn delete_not_found(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<(), StateStorageError>;
+    async fn get_downloaded_torrent(
+        &self,
+        info_hash: &str,
+    ) -> Result<Option<DownloadedTorrentRecord>, StateStorageError>;
+    async fn set_downloaded_torrent(
+        &self,
+        info_hash: &str,
+        record: &DownloadedTorrentRecord,
+    ) -> Result<(), StateStorageError>;
+    async fn add_seeding_torrent(
+        &self,
+        torrent_id: &TorrentId,
+        record: &SeedingTorrentRecord,
+    ) -> Result<(), StateStorageError>;
+    async fn get_all_seeding_torrents(
+        &self,
+    ) -> Result<HashMap<TorrentId, SeedingTorrentRecord>, StateStorageError>;
+    async fn delete_seeding_torrent(&self, torrent_id: &TorrentId)
+        -> Result<(), StateStorageError>;
+    async fn get_all_not_found(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<RequestId, NotFoundRecord>, StateStorageError>;
+    async fn set_provenance(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        record: &ProvenanceRecord,
+    ) -> Result<(), StateStorageError>;
+    async fn get_provenance(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<Option<ProvenanceRecord>, StateStorageError>;
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -301,6 +803,11 @@ impl std::fmt::Display for StateStorageError {
 #[async_trait]
 pub(crate) trait SearchProviderTrait {
     async fn find_all(&self, query: &str) -> Result<Vec<TopicData>, SearchProviderError>;
+    /// Returns `None` if there is no topic with the id.
+    async fn resolve_topic(
+        &self,
+        topic_id: &TopicId,
+    ) -> Result<Option<TopicData>, SearchProviderError>;
     async fn download_torrent(
         &self,
         download_id: &DownloadId,
@@ -310,6 +817,14 @@ pub(crate) trait SearchProviderTrait {
 #[derive(Debug, thiserror::Error)]
 pub(crate) struct SearchProviderError(pub(crate) Box<dyn std::error::Error>);
 
+impl SearchProviderError {
+    /// Whether the search provider couldn't be reached at all. Search providers mark such
+    /// errors by wrapping them into `UnreachableError`.
+    pub(crate) fn is_unreachable(&self) -> bool {
+        self.0.is::<UnreachableError>()
+    }
+}
+
 impl std::fmt::Display for SearchProviderError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -322,9 +837,23 @@ pub(crate) trait TorrentClientTrait {
         &self,
         torrent_file_data: Vec<u8>,
         selected_files_indexes: Vec<i32>,
+        request_id: &RequestId,
+        download_dir: Option<&str>,
     ) -> Result<TorrentId, TorrentClientError>;
-    async fn get_torrent(&self, torrent_id: &TorrentId) -> Result<Torrent, TorrentClientError>;
+    /// Returns `None` if the torrent client doesn't have the torrent, e.g. it has been removed.
+    async fn get_torrent(
+        &self,
+        torrent_id: &TorrentId,
+    ) -> Result<Option<Torrent>, TorrentClientError>;
     async fn delete_torrent(&self, torrent_id: &TorrentId) -> Result<(), TorrentClientError>;
+    /// Torrents added by the bot, along with the requests they were added for.
+    async fn get_request_torrents(
+        &self,
+    ) -> Result<Vec<(TorrentId, Vec<RequestId>)>, TorrentClientError>;
+    async fn pause_torrents(&self, torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError>;
+    async fn resume_torrents(&self, torrent_ids: &[TorrentId]) -> Result<(), TorrentClientError>;
+    /// Fails if the torrent client is unreachable or rejects the credentials.
+    async fn check_session(&self) -> Result<(), TorrentClientError>;
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -338,10 +867,12 @@ impl std::fmt::Display for TorrentClientError {
 
 #[async_trait]
 pub(crate) trait RadioManagerClientTrait {
+    /// Uploads the file under `file_name`, or under its own name if not set.
     async fn upload_audio_track(
         &self,
         user_id: &UserId,
         path_to_audio_file: &str,
+        file_name: Option<&str>,
     ) -> Result<RadioManagerTrackId, RadioManagerClientError>;
     async fn add_track_to_channel_playlist(
         &self,
@@ -353,6 +884,11 @@ pub(crate) trait RadioManagerClientTrait {
         &self,
         channel_id: &RadioManagerChannelId,
     ) -> Result<Vec<RadioManagerChannelTrack>, RadioManagerClientError>;
+    async fn find_track(
+        &self,
+        user_id: &UserId,
+        metadata: &AudioMetadata,
+    ) -> Result<Option<RadioManagerTrackId>, RadioManagerClientError>;
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -364,12 +900,166 @@ impl std::fmt::Display for RadioManagerClientError {
     }
 }
 
+#[async_trait]
+pub(crate) trait MetadataServiceTrait {
+    async fn get_audio_metadata(
+        &self,
+        path_to_audio_file: &str,
+    ) -> Result<Option<AudioMetadata>, MetadataServiceError>;
+    /// Returns `false` if tags of the audio format are not supported.
+    async fn set_audio_metadata(
+        &self,
+        path_to_audio_file: &str,
+        metadata: &AudioMetadata,
+    ) -> Result<bool, MetadataServiceError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) struct MetadataServiceError(pub(crate) Box<dyn std::error::Error>);
+
+impl std::fmt::Display for MetadataServiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Source of the current time, so timeouts can be tested without waiting for them.
+#[async_trait]
+pub(crate) trait ClockTrait {
+    /// Unix timestamp in seconds.
+    fn now(&self) -> u64;
+    async fn sleep(&self, duration: Duration);
+}
+
+/// How long a replayed create request with the same idempotency key returns the same request.
+const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Drops the current torrent and marks its topic as dead, so the next topic is downloaded.
+fn abandon_current_torrent(state: &mut TrackRequestProcessingState, max_dead_topics: usize) {
+    state.current_torrent_id.take();
+    state.current_torrent_data.take();
+    state.current_info_hash.take();
+    state.download_started_at.take();
+    if let Some(topic) = state.current_topic.take() {
+        state.dead_topic_ids.insert(topic.topic_id, max_dead_topics);
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Wall-clock time and real delays.
+pub(crate) struct SystemClock;
+
+#[async_trait]
+impl ClockTrait for SystemClock {
+    fn now(&self) -> u64 {
+        unix_timestamp()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        actix_rt::time::sleep(duration).await
+    }
+}
+
 pub(crate) struct TrackRequestProcessor {
     state_storage: Arc<dyn StateStorageTrait + Send + Sync + 'static>,
     search_provider: Arc<dyn SearchProviderTrait + Send + Sync + 'static>,
     torrent_client: Arc<dyn TorrentClientTrait + Send + Sync + 'static>,
     radio_manager_client: Arc<dyn RadioManagerClientTrait + Send + Sync + 'static>,
-    download_directory: String,
+    metadata_service: Arc<dyn MetadataServiceTrait + Send + Sync + 'static>,
+    download_directories: DownloadDirectories,
+    max_download_bytes: Option<u64>,
+    // Torrents not complete within the timeout are given up on, waiting forever if not set.
+    download_timeout: Option<Duration>,
+    // Requests not finished within the timeout since their creation are given up on,
+    // processing them for as long as it takes if not set.
+    request_timeout: Option<Duration>,
+    // Writes the requested artist, title and album into the file tags before uploading.
+    // Only library copies are tagged, as the torrent's own file is still seeded and reused.
+    normalize_tags: bool,
+    // Uploads files as `{artist} - {title}.{ext}` rather than under their often meaningless
+    // names in the torrent, like `track02.mp3`.
+    name_uploads: bool,
+    // Checks that the track shows up on the channel after adding it, adding it once again
+    // if it doesn't, as the radio manager occasionally drops added tracks silently.
+    verify_channel_add: bool,
+    matching_file_preference: MatchingFilePreference,
+    poll_intervals: PollIntervals,
+    step_retries: StepRetries,
+    // Retries of downloading a torrent file within its step, so a hiccup of the search
+    // provider doesn't waste the search. The topic is skipped once these run out.
+    torrent_file_retries: StepRetries,
+    // Dead topics kept in the state of a request, the ones that died first are forgotten.
+    max_dead_topics: usize,
+    // Torrents of finished requests are kept seeding for this long before they're removed
+    // by `remove_seeded_torrents`, removing them as soon as the request finishes if not set.
+    seed_duration: Option<Duration>,
+    clock: Arc<dyn ClockTrait + Send + Sync + 'static>,
+    // Limits the number of torrents downloading at the same time, unlimited if not set.
+    download_queue: Option<DownloadQueue>,
+    // Limits the number of files uploading to the radio manager at the same time,
+    // so imports of large albums don't overwhelm it or saturate the upload link.
+    upload_permits: Semaphore,
+    create_request_lock: Mutex<()>,
+    // In-memory copy of the request statuses, so reading them doesn't hit the state storage.
+    // Statuses of a user are loaded from the state storage once, on first access.
+    status_index: Mutex<HashMap<UserId, HashMap<RequestId, TrackRequestProcessingStatus>>>,
+}
+
+/// Delays between polls while a track request is being processed.
+#[derive(Clone, Debug)]
+pub(crate) struct PollIntervals {
+    /// Delay between two consecutive processing steps.
+    pub(crate) step: Duration,
+    /// Delay between two checks of the torrent download status.
+    pub(crate) download: Duration,
+}
+
+/// How a step failed with a transient error is retried before the request is marked as failed.
+#[derive(Clone, Debug)]
+pub(crate) struct StepRetries {
+    pub(crate) max_retries: u32,
+    /// Delay before the first retry, doubled on each subsequent one.
+    pub(crate) backoff: Duration,
+}
+
+impl StepRetries {
+    fn delay(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+    }
+}
+
+impl Default for StepRetries {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+/// What the processing loop should do after a step has been handled.
+#[derive(Debug, PartialEq)]
+enum StepOutcome {
+    /// The step made progress, proceed to the next one.
+    Continue,
+    /// The step is waiting on something external, run it again after the delay.
+    RetryAfter(Duration),
+}
+
+impl Default for PollIntervals {
+    fn default() -> Self {
+        Self {
+            step: Duration::from_secs(1),
+            download: Duration::from_secs(5),
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -390,13 +1080,224 @@ pub(crate) enum ProcessRequestError {
     RadioManagerError(#[from] RadioManagerClientError),
     #[error(transparent)]
     TorrentParserError(#[from] TorrentParserError),
+    #[error(transparent)]
+    MetadataServiceError(#[from] MetadataServiceError),
     #[error("Request track has not been found")]
     TrackNotFound,
+    #[error("Request track has only been found in an image release which requires splitting")]
+    RequiresSplitting,
+    #[error("Request state is inconsistent: {0} is not defined")]
+    InconsistentState(&'static str),
+    #[error("Track has not shown up on the channel after adding it")]
+    MissingOnChannel,
+    #[error("Unable to copy downloaded file into the library: {0}")]
+    LibraryError(#[source] std::io::Error),
+    #[error("Request has not finished before its deadline")]
+    DeadlineExceeded,
+}
+
+impl ProcessRequestError {
+    /// Whether retrying the request is pointless, e.g. the search provider rejected the
+    /// credentials. Dependencies mark such errors by wrapping them into `UnrecoverableError`.
+    pub(crate) fn is_unrecoverable(&self) -> bool {
+        let source: &(dyn std::error::Error + 'static) = match self {
+            ProcessRequestError::SearchProviderError(error) => error.0.as_ref(),
+            ProcessRequestError::DownloaderError(error) => error.0.as_ref(),
+            ProcessRequestError::RadioManagerError(error) => error.0.as_ref(),
+            ProcessRequestError::MetadataServiceError(error) => error.0.as_ref(),
+            _ => return false,
+        };
+
+        source.is::<UnrecoverableError>()
+    }
+
+    /// Whether the failed step might succeed if run again, e.g. a dependency responded with 5xx.
+    pub(crate) fn is_transient(&self) -> bool {
+        match self {
+            ProcessRequestError::SearchProviderError(_)
+            | ProcessRequestError::DownloaderError(_)
+            | ProcessRequestError::RadioManagerError(_)
+            | ProcessRequestError::MetadataServiceError(_) => !self.is_unrecoverable(),
+            _ => false,
+        }
+    }
+}
+
+/// Error of a dependency that will not go away by retrying the request.
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+pub(crate) struct UnrecoverableError(pub(crate) Box<dyn std::error::Error>);
+
+/// Error of a dependency which couldn't be connected to, e.g. the connection has been refused.
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+pub(crate) struct UnreachableError(pub(crate) Box<dyn std::error::Error>);
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum RetryRequestError {
+    #[error(transparent)]
+    StateStorageError(#[from] StateStorageError),
+    #[error("Request has not been found")]
+    RequestNotFound,
+    #[error("Request is neither failed nor not found")]
+    NotFailed,
+}
+
+/// How closely a file has to match the title of the requested track.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TitleMatch {
+    /// File name has every word of the title, in any order, ignoring case and punctuation.
+    Loose,
+    /// File name contains the title, ignoring case.
+    #[default]
+    Contains,
+    /// File name contains the title and the tags of the file match the title and the artist.
+    /// Files without tags are accepted. Tags can only be read once the file is downloaded,
+    /// so this is the same as `Contains` before downloading.
+    Tags,
+}
+
+impl TitleMatch {
+    pub(crate) fn matches_filename(&self, filepath: &str, title: &str) -> bool {
+        match self {
+            TitleMatch::Loose => contains_words_in_filename_ignore_case(filepath, title),
+            TitleMatch::Contains | TitleMatch::Tags => {
+                contains_in_filename_ignore_case(filepath, title)
+            }
+        }
+    }
+}
+
+/// Which of the downloaded files matching the title is uploaded when there are several,
+/// e.g. the studio and the live versions of the track.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) enum MatchingFilePreference {
+    /// The first one in the torrent.
+    #[default]
+    First,
+    /// The one with the shortest path, which is less likely to have a suffix like `(Live)`.
+    ShortestPath,
+    /// The one named exactly after the title, apart from the track number and the artist.
+    ExactTitle,
+    /// The one in a folder whose name contains the text, ignoring case, e.g. `Studio`.
+    Folder(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid matching file preference {0:?}, expected `first`, `shortest_path`, `exact_title` or `folder:<name>`")]
+pub(crate) struct InvalidMatchingFilePreference(String);
+
+impl std::str::FromStr for MatchingFilePreference {
+    type Err = InvalidMatchingFilePreference;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "first" => Ok(Self::First),
+            "shortest_path" => Ok(Self::ShortestPath),
+            "exact_title" => Ok(Self::ExactTitle),
+            value => match value.strip_prefix("folder:").map(str::trim) {
+                Some(folder) if !folder.is_empty() => Ok(Self::Folder(folder.to_string())),
+                _ => Err(InvalidMatchingFilePreference(value.to_string())),
+            },
+        }
+    }
+}
+
+impl MatchingFilePreference {
+    /// Picks one of the files matching the title, the first one if none is preferred.
+    pub(crate) fn pick<'a>(&self, files: &'a [String], title: &str) -> Option<&'a String> {
+        let preferred = match self {
+            Self::First => None,
+            Self::ShortestPath => files.iter().min_by_key(|path| path.chars().count()),
+            Self::ExactTitle => files.iter().find(|path| is_exact_title_match(path, title)),
+            Self::Folder(folder) => {
+                let folder = folder.to_lowercase();
+
+                files.iter().find(|path| {
+                    std::path::Path::new(path)
+                        .parent()
+                        .into_iter()
+                        .flat_map(|parent| parent.components())
+                        .any(|component| {
+                            component
+                                .as_os_str()
+                                .to_string_lossy()
+                                .to_lowercase()
+                                .contains(&folder)
+                        })
+                })
+            }
+        };
+
+        preferred.or_else(|| files.first())
+    }
 }
 
+/// Files are matched twice: torrents are picked by their file lists before downloading,
+/// then the downloaded files are checked again. A loose first check doesn't reject
+/// releases with differently spelled titles, while a strict second one keeps out wrong tracks.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub(crate) struct CreateRequestOptions {
-    pub(crate) validate_metadata: bool,
+    /// Match of the files in the torrent file list, deciding what to download.
+    #[serde(default)]
+    pub(crate) pre_download_match: TitleMatch,
+    /// Match of the downloaded files, deciding which one to upload.
+    #[serde(default)]
+    pub(crate) post_download_match: TitleMatch,
+    /// Releases in these formats are tried first, the ones in other formats after them.
+    #[serde(default)]
+    pub(crate) preferred_formats: Vec<AudioFormat>,
+    /// Releases of this year are tried first.
+    #[serde(default)]
+    pub(crate) preferred_year: Option<u16>,
+    /// Earlier releases are tried before later ones, e.g. the original before remasters.
+    #[serde(default)]
+    pub(crate) prefer_original: bool,
+    /// Topic picked by the user, downloaded instead of searching for the album.
+    #[serde(default)]
+    pub(crate) topic_id: Option<TopicId>,
+    /// Requests created before priorities were introduced are manual ones.
+    #[serde(default)]
+    pub(crate) priority: RequestPriority,
+    /// Artist and album are searched as exact phrases, so names made of common words,
+    /// like `Air - Moon Safari`, don't match every release mentioning one of them.
+    #[serde(default)]
+    pub(crate) exact_phrase_search: bool,
+}
+
+/// Requests of higher priority take free download slots first.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RequestPriority {
+    /// Track suggested for the channel.
+    Suggestion,
+    /// Track explicitly requested by the user.
+    #[default]
+    Manual,
+}
+
+impl CreateRequestOptions {
+    /// Moves the releases in the preferred formats, then the ones of the preferred year,
+    /// then the earliest ones if the original is preferred, to the front. Otherwise keeps
+    /// the order of the search results.
+    pub(crate) fn sort_topics_by_preference(&self, topics: &mut [TopicData]) {
+        topics.sort_by_key(|topic| {
+            let format_mismatch = !self.preferred_formats.is_empty()
+                && !topic
+                    .format
+                    .is_some_and(|format| self.preferred_formats.contains(&format));
+            let year_mismatch = self.preferred_year.is_some() && topic.year != self.preferred_year;
+            let year = match self.prefer_original {
+                true => topic.year.unwrap_or(u16::MAX),
+                false => 0,
+            };
+
+            (format_mismatch, year_mismatch, year)
+        });
+    }
 }
 
 impl TrackRequestProcessor {
@@ -405,27 +1306,173 @@ impl TrackRequestProcessor {
         search_provider: Arc<dyn SearchProviderTrait + Send + Sync + 'static>,
         torrent_client: Arc<dyn TorrentClientTrait + Send + Sync + 'static>,
         radio_manager_client: Arc<dyn RadioManagerClientTrait + Send + Sync + 'static>,
-        download_directory: String,
+        metadata_service: Arc<dyn MetadataServiceTrait + Send + Sync + 'static>,
+        download_directories: DownloadDirectories,
+        max_download_bytes: Option<u64>,
     ) -> Self {
         Self {
             state_storage,
             search_provider,
             torrent_client,
             radio_manager_client,
-            download_directory,
+            metadata_service,
+            download_directories,
+            max_download_bytes,
+            download_timeout: None,
+            request_timeout: None,
+            normalize_tags: false,
+            name_uploads: false,
+            verify_channel_add: false,
+            matching_file_preference: MatchingFilePreference::default(),
+            poll_intervals: PollIntervals::default(),
+            step_retries: StepRetries::default(),
+            torrent_file_retries: StepRetries {
+                max_retries: 2,
+                backoff: Duration::from_secs(1),
+            },
+            max_dead_topics: 100,
+            seed_duration: None,
+            clock: Arc::new(SystemClock),
+            download_queue: None,
+            upload_permits: Semaphore::new(2),
+            create_request_lock: Mutex::new(()),
+            status_index: Mutex::new(HashMap::new()),
         }
     }
 
-    #[tracing::instrument(skip(self))]
-    pub(crate) async fn create_request(
-        &self,
-        user_id: &UserId,
-        track_metadata: &AudioMetadata,
-        options: &CreateRequestOptions,
-        target_channel_id: &RadioManagerChannelId,
-    ) -> Result<RequestId, CreateRequestError> {
-        debug!(
-            ?target_channel_id,
+    pub(crate) fn download_directories(&self) -> &DownloadDirectories {
+        &self.download_directories
+    }
+
+    pub(crate) fn with_poll_intervals(self, poll_intervals: PollIntervals) -> Self {
+        Self {
+            poll_intervals,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_step_retries(self, step_retries: StepRetries) -> Self {
+        Self {
+            step_retries,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_torrent_file_retries(self, torrent_file_retries: StepRetries) -> Self {
+        Self {
+            torrent_file_retries,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_max_dead_topics(self, max_dead_topics: usize) -> Self {
+        Self {
+            max_dead_topics,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_seed_duration(self, seed_duration: Option<Duration>) -> Self {
+        Self {
+            seed_duration,
+            ..self
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_clock(self, clock: Arc<dyn ClockTrait + Send + Sync + 'static>) -> Self {
+        Self { clock, ..self }
+    }
+
+    pub(crate) fn with_download_timeout(self, download_timeout: Option<Duration>) -> Self {
+        Self {
+            download_timeout,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_request_timeout(self, request_timeout: Option<Duration>) -> Self {
+        Self {
+            request_timeout,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_tag_normalization(self, normalize_tags: bool) -> Self {
+        Self {
+            normalize_tags,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_upload_naming(self, name_uploads: bool) -> Self {
+        Self {
+            name_uploads,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_channel_add_verification(self, verify_channel_add: bool) -> Self {
+        Self {
+            verify_channel_add,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_matching_file_preference(
+        self,
+        matching_file_preference: MatchingFilePreference,
+    ) -> Self {
+        Self {
+            matching_file_preference,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_max_concurrent_uploads(self, max_concurrent_uploads: usize) -> Self {
+        Self {
+            upload_permits: Semaphore::new(max_concurrent_uploads.max(1)),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_max_active_downloads(self, max_active_downloads: Option<usize>) -> Self {
+        Self {
+            download_queue: max_active_downloads.map(DownloadQueue::new),
+            ..self
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn create_request(
+        &self,
+        user_id: &UserId,
+        track_metadata: &AudioMetadata,
+        options: &CreateRequestOptions,
+        target_channel_id: Option<&RadioManagerChannelId>,
+    ) -> Result<RequestId, CreateRequestError> {
+        let _guard = self.create_request_lock.lock().await;
+
+        // Request for a picked topic downloads that topic, so it can't be served by
+        // a request searching for the track.
+        let in_flight_request_id = match options.topic_id {
+            Some(_) => None,
+            None => {
+                self.find_in_flight_request(user_id, track_metadata, target_channel_id)
+                    .await?
+            }
+        };
+
+        if let Some(request_id) = in_flight_request_id {
+            info!(
+                ?target_channel_id,
+                "Track request {} for {} is already in progress", request_id, track_metadata
+            );
+            return Ok(request_id);
+        }
+
+        debug!(
+            ?target_channel_id,
             "Creating the new track request - {}", track_metadata
         );
 
@@ -433,16 +1480,10 @@ impl TrackRequestProcessor {
         let ctx = TrackRequestProcessingContext::new(
             track_metadata.clone(),
             options.clone(),
-            target_channel_id.clone(),
+            target_channel_id.cloned(),
         );
-        let state = TrackRequestProcessingState::default();
 
-        self.state_storage
-            .create_context(user_id, &request_id, ctx)
-            .await?;
-        self.state_storage
-            .create_state(user_id, &request_id, state)
-            .await?;
+        self.save_new_request(user_id, &request_id, ctx).await?;
 
         info!(
             ?target_channel_id,
@@ -452,95 +1493,791 @@ impl TrackRequestProcessor {
         Ok(request_id)
     }
 
+    /// Creates the request for the audio file already available locally,
+    /// so it's only uploaded to the radio manager and added to the channel.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn create_upload_request(
+        &self,
+        user_id: &UserId,
+        path_to_file: &str,
+        target_channel_id: Option<&RadioManagerChannelId>,
+    ) -> Result<RequestId, CreateRequestError> {
+        let request_id = RequestId(Uuid::new_v4());
+        let ctx = TrackRequestProcessingContext {
+            upload_file_path: Some(path_to_file.to_string()),
+            ..TrackRequestProcessingContext::new(
+                AudioMetadata::default(),
+                CreateRequestOptions {
+                    pre_download_match: TitleMatch::Loose,
+                    post_download_match: TitleMatch::Contains,
+                    preferred_formats: vec![],
+                    preferred_year: None,
+                    prefer_original: false,
+                    topic_id: None,
+                    priority: RequestPriority::Manual,
+                    exact_phrase_search: false,
+                },
+                target_channel_id.cloned(),
+            )
+        };
+
+        self.save_new_request(user_id, &request_id, ctx).await?;
+
+        info!(
+            ?target_channel_id,
+            "Created new upload request {} for {}", request_id, path_to_file
+        );
+
+        Ok(request_id)
+    }
+
+    /// Creates the request for a free-text query, e.g. `robert miles children`,
+    /// downloading the best matching file of the top ranked topic.
     #[tracing::instrument(skip(self))]
+    pub(crate) async fn create_search_request(
+        &self,
+        user_id: &UserId,
+        query: &str,
+        target_channel_id: Option<&RadioManagerChannelId>,
+    ) -> Result<RequestId, CreateRequestError> {
+        let request_id = RequestId(Uuid::new_v4());
+        let ctx = TrackRequestProcessingContext {
+            search_query: Some(query.to_string()),
+            ..TrackRequestProcessingContext::new(
+                AudioMetadata::default(),
+                CreateRequestOptions {
+                    pre_download_match: TitleMatch::Loose,
+                    post_download_match: TitleMatch::Contains,
+                    preferred_formats: vec![],
+                    preferred_year: None,
+                    prefer_original: false,
+                    topic_id: None,
+                    priority: RequestPriority::Manual,
+                    exact_phrase_search: false,
+                },
+                target_channel_id.cloned(),
+            )
+        };
+
+        self.save_new_request(user_id, &request_id, ctx).await?;
+
+        info!(
+            ?target_channel_id,
+            "Created new search request {} for \"{}\"", request_id, query
+        );
+
+        Ok(request_id)
+    }
+
+    async fn save_new_request(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        ctx: TrackRequestProcessingContext,
+    ) -> Result<(), StateStorageError> {
+        let ctx = TrackRequestProcessingContext {
+            deadline: self
+                .request_timeout
+                .map(|request_timeout| ctx.created_at + request_timeout.as_secs()),
+            ..ctx
+        };
+        let state = ctx.initial_state();
+
+        self.state_storage
+            .create_context(user_id, request_id, ctx)
+            .await?;
+        self.state_storage
+            .create_state(user_id, request_id, state)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns the request created for the idempotency key, unless the key has expired.
+    pub(crate) async fn find_idempotent_request(
+        &self,
+        user_id: &UserId,
+        idempotency_key: &str,
+    ) -> Result<Option<RequestId>, StateStorageError> {
+        let record = match self
+            .state_storage
+            .get_idempotency(user_id, idempotency_key)
+            .await?
+        {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+
+        if self.clock.now().saturating_sub(record.created_at) > IDEMPOTENCY_KEY_TTL.as_secs() {
+            return Ok(None);
+        }
+
+        Ok(Some(record.request_id))
+    }
+
+    pub(crate) async fn save_idempotency_key(
+        &self,
+        user_id: &UserId,
+        idempotency_key: &str,
+        request_id: &RequestId,
+    ) -> Result<(), StateStorageError> {
+        let record = IdempotencyRecord {
+            request_id: request_id.clone(),
+            created_at: self.clock.now(),
+        };
+
+        self.state_storage
+            .set_idempotency(user_id, idempotency_key, &record)
+            .await
+    }
+
+    async fn find_in_flight_request(
+        &self,
+        user_id: &UserId,
+        track_metadata: &AudioMetadata,
+        target_channel_id: Option<&RadioManagerChannelId>,
+    ) -> Result<Option<RequestId>, StateStorageError> {
+        let contexts = self.state_storage.get_all_contexts(user_id).await?;
+        let statuses = self.get_statuses(user_id).await?;
+
+        // Context is kept when request finishes or fails, so the status has to be checked.
+        Ok(contexts
+            .into_iter()
+            .filter(|(request_id, _)| {
+                !matches!(
+                    statuses.get(request_id),
+                    Some(TrackRequestProcessingStatus::Failed)
+                        | Some(TrackRequestProcessingStatus::NotFound)
+                        | Some(TrackRequestProcessingStatus::RequiresSplitting)
+                        | Some(TrackRequestProcessingStatus::Finished)
+                )
+            })
+            .find(|(_, ctx)| {
+                &ctx.metadata == track_metadata
+                    && ctx.target_channel_id.as_ref() == target_channel_id
+            })
+            .map(|(request_id, _)| request_id))
+    }
+
+    // Every log emitted while processing the request, including ones from the step
+    // handlers and the clients, inherits `user_id` and `request_id` from this span.
+    #[tracing::instrument(skip_all, fields(user_id = **user_id, %request_id))]
     pub(crate) async fn process_request(
         &self,
         user_id: &UserId,
         request_id: &RequestId,
+    ) -> Result<(), ProcessRequestError> {
+        let result = self.run_request(user_id, request_id).await;
+
+        // Download slot might still be taken if the request has failed while downloading.
+        self.release_download_slot(request_id);
+
+        result
+    }
+
+    async fn run_request(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
     ) -> Result<(), ProcessRequestError> {
         debug!("Starting processing the track request {}", request_id);
 
-        let ctx = self.state_storage.load_context(user_id, request_id).await?;
+        let mut ctx = self.state_storage.load_context(user_id, request_id).await?;
         let mut state = self.state_storage.load_state(user_id, request_id).await?;
 
-        self.state_storage
-            .update_status(
-                user_id,
-                request_id,
-                &TrackRequestProcessingStatus::Processing,
-            )
-            .await?;
-
-        // TODO Check if the file already exists in library.
+        self.set_status(
+            user_id,
+            request_id,
+            &TrackRequestProcessingStatus::Processing,
+        )
+        .await?;
 
         while !matches!(state.get_step(), TrackRequestProcessingStep::Finish) {
-            if let Err(error) = self
-                .handle_next_step(user_id, request_id, &ctx, &mut state)
+            if ctx
+                .deadline
+                .is_some_and(|deadline| self.clock.now() >= deadline)
+            {
+                let error = ProcessRequestError::DeadlineExceeded;
+                self.handle_processing_error(user_id, request_id, &error)
+                    .await?;
+
+                return Err(error);
+            }
+
+            // The step runs on a copy of the state, so a failed step can be retried
+            // from the same state it has started with.
+            let mut next_state = state.clone();
+            let delay = match self
+                .handle_next_step(user_id, request_id, &mut ctx, &mut next_state)
                 .await
             {
-                match error {
-                    ProcessRequestError::TrackNotFound => {
-                        self.state_storage
-                            .update_status(
-                                user_id,
-                                request_id,
-                                &TrackRequestProcessingStatus::NotFound,
-                            )
-                            .await?;
-                    }
-                    _ => {
-                        self.state_storage
-                            .update_status(
-                                user_id,
-                                request_id,
-                                &TrackRequestProcessingStatus::Failed,
-                            )
-                            .await?;
+                Ok(outcome) => {
+                    state = next_state;
+                    state.step_retries = 0;
+
+                    match outcome {
+                        StepOutcome::Continue => self.poll_intervals.step,
+                        StepOutcome::RetryAfter(delay) => delay,
                     }
                 }
+                Err(error)
+                    if error.is_transient()
+                        && state.step_retries < self.step_retries.max_retries =>
+                {
+                    state.step_retries += 1;
+                    // Failed upload might still have reached the radio manager.
+                    state.upload_in_progress |= next_state.upload_in_progress;
+                    warn!(
+                        ?error,
+                        retry = state.step_retries,
+                        "Processing step failed, retrying..."
+                    );
+
+                    self.step_retries.delay(state.step_retries)
+                }
+                Err(error) => {
+                    self.handle_processing_error(user_id, request_id, &error)
+                        .await?;
 
-                return Err(error);
+                    return Err(error);
+                }
             };
             self.state_storage
                 .update_state(user_id, request_id, &state)
                 .await?;
-            actix_rt::time::sleep(Duration::from_secs(1)).await;
+
+            self.clock.sleep(delay).await;
         }
 
-        info!("Track request {} processing finished", request_id);
+        info!("Track request {} processing finished", request_id);
+
+        if let Some(torrent_id) = &state.current_torrent_id {
+            self.release_torrent(request_id, torrent_id).await?;
+        }
+
+        self.state_storage
+            .set_provenance(
+                user_id,
+                request_id,
+                &ProvenanceRecord {
+                    metadata: ctx.metadata,
+                    target_channel_id: ctx.target_channel_id,
+                    topic_id: state.current_topic.map(|topic| topic.topic_id),
+                    info_hash: state.current_info_hash,
+                    file_path: state.path_to_downloaded_file,
+                    radio_manager_track_id: state.radio_manager_track_id,
+                    created_at: ctx.created_at,
+                    finished_at: self.clock.now(),
+                },
+            )
+            .await?;
+        self.set_status(user_id, request_id, &TrackRequestProcessingStatus::Finished)
+            .await?;
+        // Context is kept for the request history.
+        self.state_storage.delete_state(user_id, request_id).await?;
+
+        Ok(())
+    }
+
+    /// Removes the torrent of the finished request or leaves it seeding for `seed_duration`.
+    async fn release_torrent(
+        &self,
+        request_id: &RequestId,
+        torrent_id: &TorrentId,
+    ) -> Result<(), ProcessRequestError> {
+        match self.seed_duration {
+            Some(seed_duration) => {
+                let remove_at = self.clock.now() + seed_duration.as_secs();
+                self.state_storage
+                    .add_seeding_torrent(
+                        torrent_id,
+                        &SeedingTorrentRecord {
+                            request_id: request_id.clone(),
+                            remove_at,
+                        },
+                    )
+                    .await?;
+                debug!(%torrent_id, remove_at, "Leaving torrent seeding");
+            }
+            None => self.remove_torrent(request_id, torrent_id).await,
+        }
+
+        Ok(())
+    }
+
+    /// Removes the torrents whose seeding time is over, returning how many were removed.
+    pub(crate) async fn remove_seeded_torrents(&self) -> Result<usize, ProcessRequestError> {
+        let now = self.clock.now();
+        let mut removed = 0;
+
+        for (torrent_id, record) in self.state_storage.get_all_seeding_torrents().await? {
+            if record.remove_at > now {
+                continue;
+            }
+
+            self.remove_torrent(&record.request_id, &torrent_id).await;
+            self.state_storage
+                .delete_seeding_torrent(&torrent_id)
+                .await?;
+            removed += 1;
+        }
+
+        Ok(removed)
+    }
+
+    /// Removes the torrents added by the bot for requests which don't exist anymore,
+    /// e.g. deleted while the bot was down, returning how many were removed.
+    pub(crate) async fn remove_orphaned_torrents(&self) -> Result<usize, ProcessRequestError> {
+        let mut request_ids: HashSet<RequestId> = self
+            .state_storage
+            .get_all_tasks()
+            .await?
+            .into_iter()
+            .map(|(_, request_id)| request_id)
+            .collect();
+        // Finished requests keep their contexts, along with the torrents left seeding.
+        for user_id in self.state_storage.get_status_owners().await? {
+            request_ids.extend(
+                self.state_storage
+                    .get_all_contexts(&user_id)
+                    .await?
+                    .into_keys(),
+            );
+        }
+
+        let mut removed = 0;
+
+        for (torrent_id, torrent_request_ids) in self.torrent_client.get_request_torrents().await? {
+            if torrent_request_ids
+                .iter()
+                .any(|request_id| request_ids.contains(request_id))
+            {
+                continue;
+            }
+
+            info!(%torrent_id, "Removing torrent of the requests which don't exist anymore");
+            self.torrent_client.delete_torrent(&torrent_id).await?;
+            removed += 1;
+        }
+
+        Ok(removed)
+    }
+
+    /// Removes the torrent with its data unless another unfinished request has reused it.
+    /// Failures are only logged, as the request it belongs to has finished anyway.
+    async fn remove_torrent(&self, request_id: &RequestId, torrent_id: &TorrentId) {
+        let tasks = match self.state_storage.get_all_tasks().await {
+            Ok(tasks) => tasks,
+            Err(error) => {
+                warn!(?error, %torrent_id, "Unable to check whether torrent is still in use");
+                return;
+            }
+        };
+
+        for (user_id, other_request_id) in tasks {
+            if other_request_id == *request_id {
+                continue;
+            }
+
+            let in_use = self
+                .state_storage
+                .load_state(&user_id, &other_request_id)
+                .await
+                .is_ok_and(|state| state.current_torrent_id.as_ref() == Some(torrent_id));
+            if in_use {
+                debug!(%torrent_id, "Torrent is still in use, keeping it");
+                return;
+            }
+        }
+
+        match self.torrent_client.delete_torrent(torrent_id).await {
+            Ok(()) => debug!(%torrent_id, "Torrent removed"),
+            Err(error) => warn!(?error, %torrent_id, "Unable to remove torrent"),
+        }
+    }
+
+    async fn handle_processing_error(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        error: &ProcessRequestError,
+    ) -> Result<(), StateStorageError> {
+        match error {
+            ProcessRequestError::TrackNotFound => {
+                self.set_status(user_id, request_id, &TrackRequestProcessingStatus::NotFound)
+                    .await?;
+
+                let ctx = self.state_storage.load_context(user_id, request_id).await?;
+                let state = self.state_storage.load_state(user_id, request_id).await?;
+                self.state_storage
+                    .add_not_found(
+                        user_id,
+                        request_id,
+                        &NotFoundRecord {
+                            metadata: ctx.metadata,
+                            target_channel_id: ctx.target_channel_id,
+                            created_at: self.clock.now(),
+                            search_attempts: state.search_attempts,
+                        },
+                    )
+                    .await?;
+            }
+            ProcessRequestError::RequiresSplitting => {
+                self.set_status(
+                    user_id,
+                    request_id,
+                    &TrackRequestProcessingStatus::RequiresSplitting,
+                )
+                .await?;
+            }
+            error if error.is_unrecoverable() => {
+                self.set_status(user_id, request_id, &TrackRequestProcessingStatus::Failed)
+                    .await?;
+
+                // Nothing to retry, so drop the request instead of keeping it for retry.
+                warn!(?error, "Track request failed with unrecoverable error");
+                self.state_storage.delete_state(user_id, request_id).await?;
+                self.state_storage
+                    .delete_context(user_id, request_id)
+                    .await?;
+            }
+            _ => {
+                self.set_status(user_id, request_id, &TrackRequestProcessingStatus::Failed)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn get_processing_requests(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<RequestId, TrackRequestProcessingStatus>, ProcessRequestError> {
+        let statuses = self.get_statuses(user_id).await?;
+
+        Ok(statuses)
+    }
+
+    /// Statuses of the requests of the user, along with the deadlines of the processing ones
+    /// and the queries tried for the ones whose track hasn't been found.
+    pub(crate) async fn get_status_reports(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<RequestId, RequestStatusReport>, ProcessRequestError> {
+        let mut statuses = self.get_statuses(user_id).await?;
+        let contexts = self.state_storage.get_all_contexts(user_id).await?;
+        let mut not_found = self.state_storage.get_all_not_found(user_id).await?;
+
+        // Requests not having started yet have no status stored.
+        for request_id in contexts.keys() {
+            statuses
+                .entry(request_id.clone())
+                .or_insert(TrackRequestProcessingStatus::Processing);
+        }
+
+        Ok(statuses
+            .into_iter()
+            .map(|(request_id, status)| {
+                let deadline = contexts
+                    .get(&request_id)
+                    .and_then(|ctx| ctx.deadline)
+                    .filter(|_| matches!(status, TrackRequestProcessingStatus::Processing));
+
+                let search_attempts = not_found
+                    .remove(&request_id)
+                    .filter(|_| matches!(status, TrackRequestProcessingStatus::NotFound))
+                    .map(|record| record.search_attempts)
+                    .unwrap_or_default();
+
+                (
+                    request_id,
+                    RequestStatusReport {
+                        status,
+                        deadline,
+                        search_attempts,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Reads the statuses of all the users into memory, so they aren't read from the state
+    /// storage on first access.
+    pub(crate) async fn load_status_index(&self) -> Result<(), StateStorageError> {
+        let mut loaded_statuses = HashMap::new();
+
+        for user_id in self.state_storage.get_status_owners().await? {
+            let statuses = self.state_storage.get_all_statuses(&user_id).await?;
+            loaded_statuses.insert(user_id, statuses);
+        }
+
+        let mut status_index = self.status_index.lock().await;
+        for (user_id, statuses) in loaded_statuses {
+            // Statuses updated while loading are newer than the ones read.
+            status_index.entry(user_id).or_insert(statuses);
+        }
+
+        Ok(())
+    }
+
+    async fn get_statuses(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<RequestId, TrackRequestProcessingStatus>, StateStorageError> {
+        if let Some(statuses) = self.status_index.lock().await.get(user_id) {
+            return Ok(statuses.clone());
+        }
+
+        let statuses = self.state_storage.get_all_statuses(user_id).await?;
+
+        Ok(self
+            .status_index
+            .lock()
+            .await
+            .entry(user_id.clone())
+            .or_insert(statuses)
+            .clone())
+    }
+
+    /// Requests whose track hasn't been found, most recent first.
+    pub(crate) async fn get_not_found_requests(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<(RequestId, NotFoundRecord)>, ProcessRequestError> {
+        let mut records = self
+            .state_storage
+            .get_all_not_found(user_id)
+            .await?
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        records.sort_by_key(|(_, record)| std::cmp::Reverse(record.created_at));
+
+        Ok(records)
+    }
+
+    /// Where the track of the finished request came from, if it has finished.
+    pub(crate) async fn get_provenance(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<Option<ProvenanceRecord>, ProcessRequestError> {
+        Ok(self
+            .state_storage
+            .get_provenance(user_id, request_id)
+            .await?)
+    }
+
+    pub(crate) async fn get_request_stats(
+        &self,
+        user_id: &UserId,
+    ) -> Result<RequestStats, ProcessRequestError> {
+        let stats = self.state_storage.get_request_stats(user_id).await?;
+
+        Ok(stats)
+    }
+
+    pub(crate) async fn list_requests(
+        &self,
+        user_id: &UserId,
+        filter: &RequestFilter,
+        page: &RequestsPage,
+    ) -> Result<(Vec<RequestSummary>, usize), ProcessRequestError> {
+        let requests = self
+            .state_storage
+            .list_requests(user_id, filter, page)
+            .await?;
+
+        Ok(requests)
+    }
+
+    async fn set_status(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        status: &TrackRequestProcessingStatus,
+    ) -> Result<(), StateStorageError> {
+        self.state_storage
+            .update_status(user_id, request_id, status)
+            .await?;
+
+        if let Ok(mut ctx) = self.state_storage.load_context(user_id, request_id).await {
+            ctx.updated_at = self.clock.now();
+            self.state_storage
+                .update_context(user_id, request_id, &ctx)
+                .await?;
+        }
+
+        // The index is loaded at startup, so users missing from it had no statuses before.
+        self.status_index
+            .lock()
+            .await
+            .entry(user_id.clone())
+            .or_default()
+            .insert(request_id.clone(), status.clone());
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn pause_downloads(
+        &self,
+        user_id: &UserId,
+    ) -> Result<usize, ProcessRequestError> {
+        let torrent_ids = self.get_downloading_torrent_ids(user_id).await?;
+
+        if !torrent_ids.is_empty() {
+            self.torrent_client.pause_torrents(&torrent_ids).await?;
+        }
+
+        info!("Paused {} torrent(s)", torrent_ids.len());
+
+        Ok(torrent_ids.len())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn resume_downloads(
+        &self,
+        user_id: &UserId,
+    ) -> Result<usize, ProcessRequestError> {
+        let torrent_ids = self.get_downloading_torrent_ids(user_id).await?;
+
+        if !torrent_ids.is_empty() {
+            self.torrent_client.resume_torrents(&torrent_ids).await?;
+        }
+
+        info!("Resumed {} torrent(s)", torrent_ids.len());
+
+        Ok(torrent_ids.len())
+    }
+
+    async fn get_downloading_torrent_ids(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<TorrentId>, ProcessRequestError> {
+        let statuses = self.get_statuses(user_id).await?;
+        let mut torrent_ids = vec![];
+
+        for (request_id, status) in statuses {
+            if !matches!(status, TrackRequestProcessingStatus::Processing) {
+                continue;
+            }
+
+            let state = self.state_storage.load_state(user_id, &request_id).await?;
+
+            if let TrackRequestProcessingStep::CheckDownloadStatus = state.get_step() {
+                torrent_ids.extend(state.current_torrent_id);
+            }
+        }
+
+        Ok(torrent_ids)
+    }
+
+    /// Drops the request along with its torrent, unless another request has reused it.
+    /// Its task must have been stopped, so nothing writes the state back.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn delete_request(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+    ) -> Result<(), ProcessRequestError> {
+        let state = self.state_storage.load_state(user_id, request_id).await?;
+        if let Some(torrent_id) = &state.current_torrent_id {
+            self.remove_torrent(request_id, torrent_id).await;
+        }
+        self.release_download_slot(request_id);
 
-        self.state_storage
-            .update_status(user_id, request_id, &TrackRequestProcessingStatus::Finished)
-            .await?;
         self.state_storage.delete_state(user_id, request_id).await?;
         self.state_storage
             .delete_context(user_id, request_id)
             .await?;
+        self.state_storage
+            .delete_status(user_id, request_id)
+            .await?;
+
+        if let Some(statuses) = self.status_index.lock().await.get_mut(user_id) {
+            statuses.remove(request_id);
+        }
+
+        info!("Track request {} has been deleted", request_id);
 
         Ok(())
     }
 
-    pub(crate) async fn get_processing_requests(
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn reset_request(
         &self,
         user_id: &UserId,
-    ) -> Result<HashMap<RequestId, TrackRequestProcessingStatus>, ProcessRequestError> {
-        let statuses = self.state_storage.get_all_statuses(user_id).await?;
+        request_id: &RequestId,
+    ) -> Result<(), RetryRequestError> {
+        let statuses = self.get_statuses(user_id).await?;
+
+        // Tracks might have been uploaded to the tracker since the request has been not found.
+        match statuses.get(request_id) {
+            Some(TrackRequestProcessingStatus::Failed)
+            | Some(TrackRequestProcessingStatus::NotFound) => (),
+            Some(_) => return Err(RetryRequestError::NotFailed),
+            None => return Err(RetryRequestError::RequestNotFound),
+        }
 
-        Ok(statuses)
+        // Context is kept on failure, so the original metadata is still there.
+        let ctx = match self.state_storage.load_context(user_id, request_id).await {
+            Ok(ctx) => ctx,
+            Err(error) => {
+                error!(?error, "Unable to load context of the failed request");
+                return Err(RetryRequestError::RequestNotFound);
+            }
+        };
+
+        // Dead topics would fail the same way again, so they are skipped on retry as well.
+        let dead_topic_ids = match self.state_storage.load_state(user_id, request_id).await {
+            Ok(state) => state.dead_topic_ids,
+            Err(_) => DeadTopics::default(),
+        };
+
+        // Created anew, as the state of a request failed with an unrecoverable error is gone.
+        self.state_storage
+            .create_state(
+                user_id,
+                request_id,
+                TrackRequestProcessingState {
+                    dead_topic_ids,
+                    ..ctx.initial_state()
+                },
+            )
+            .await?;
+        self.state_storage
+            .delete_not_found(user_id, request_id)
+            .await?;
+        self.set_status(
+            user_id,
+            request_id,
+            &TrackRequestProcessingStatus::Processing,
+        )
+        .await?;
+
+        info!("Track request {} has been reset for retry", request_id);
+
+        Ok(())
     }
 
+    #[tracing::instrument(skip_all, fields(step = ?state.get_step()))]
     async fn handle_next_step(
         &self,
         user_id: &UserId,
         request_id: &RequestId,
-        ctx: &TrackRequestProcessingContext,
+        ctx: &mut TrackRequestProcessingContext,
         state: &mut TrackRequestProcessingState,
-    ) -> Result<(), ProcessRequestError> {
+    ) -> Result<StepOutcome, ProcessRequestError> {
         let step = state.get_step();
 
         debug!("Running processing step: {:?}", step);
 
         match step {
+            TrackRequestProcessingStep::FindInLibrary => {
+                self.find_in_library(user_id, ctx, state).await?;
+            }
             TrackRequestProcessingStep::GetTopicsIntoQueue => {
                 self.get_topics_into_queue(user_id, request_id, ctx, state)
                     .await?;
@@ -549,13 +2286,16 @@ impl TrackRequestProcessor {
                 self.download_next_torrent_file(user_id, ctx, state).await?;
             }
             TrackRequestProcessingStep::Download => {
-                self.download(user_id, ctx, state).await?;
+                return self.download(user_id, request_id, ctx, state).await;
             }
             TrackRequestProcessingStep::CheckDownloadStatus => {
-                self.check_download_status(user_id, ctx, state).await?;
+                return self
+                    .check_download_status(user_id, request_id, ctx, state)
+                    .await;
             }
             TrackRequestProcessingStep::UploadToRadioManager => {
-                self.upload_to_radio_manager(user_id, ctx, state).await?;
+                self.upload_to_radio_manager(user_id, request_id, ctx, state)
+                    .await?;
             }
             TrackRequestProcessingStep::AddToRadioManagerChannel => {
                 self.add_to_radio_manager_channel(user_id, ctx, state)
@@ -564,6 +2304,30 @@ impl TrackRequestProcessor {
             TrackRequestProcessingStep::Finish => (),
         }
 
+        Ok(StepOutcome::Continue)
+    }
+
+    async fn find_in_library(
+        &self,
+        user_id: &UserId,
+        ctx: &TrackRequestProcessingContext,
+        state: &mut TrackRequestProcessingState,
+    ) -> Result<(), ProcessRequestError> {
+        // Search requests don't know their track until it's downloaded.
+        if ctx.search_query.is_none() {
+            if let Some(track_id) = self
+                .radio_manager_client
+                .find_track(user_id, &ctx.metadata)
+                .await?
+            {
+                info!(%track_id, "Requested track already exists in the library");
+
+                state.radio_manager_track_id.replace(track_id);
+            }
+        }
+
+        state.library_searched = true;
+
         Ok(())
     }
 
@@ -574,30 +2338,55 @@ impl TrackRequestProcessor {
         ctx: &TrackRequestProcessingContext,
         state: &mut TrackRequestProcessingState,
     ) -> Result<(), ProcessRequestError> {
-        let queries = vec![
-            format!("{} - {}", ctx.metadata.artist, ctx.metadata.album),
-            format!("{} дискография", ctx.metadata.artist),
-            format!("{} discography", ctx.metadata.artist),
-            format!("{} дискографія", ctx.metadata.artist),
-        ];
+        if let Some(topic_id) = &ctx.options.topic_id {
+            let topic = self.search_provider.resolve_topic(topic_id).await?;
+
+            match &topic {
+                Some(topic) => info!("Using topic {} picked by the user", topic.title),
+                None => warn!(%topic_id, "Topic picked by the user does not exist"),
+            }
+
+            state.topics_queue.replace(topic.into_iter().collect());
+
+            return Ok(());
+        }
+
+        let queries = match &ctx.search_query {
+            Some(query) => vec![query.clone()],
+            None => ctx
+                .metadata
+                .get_search_queries(ctx.options.exact_phrase_search),
+        };
 
         let mut found_results = vec![];
+        let mut search_attempts = vec![];
 
         for query in queries {
             let mut results = self.search_provider.find_all(&query).await?;
 
             info!("Searching for \"{}\": {} result(s)", query, results.len());
 
+            search_attempts.push(SearchAttempt {
+                results_count: results.len(),
+                query,
+            });
             found_results.append(&mut results);
         }
 
-        found_results.dedup_by_key(|topic| *topic.topic_id);
+        // Queries for artist name variants often return the same topics, so duplicates
+        // aren't necessarily adjacent. The first occurrence wins to keep the query order.
+        let mut seen_topic_ids = HashSet::new();
+        found_results.retain(|topic| seen_topic_ids.insert(*topic.topic_id));
+        found_results.retain(|topic| !state.dead_topic_ids.contains(&topic.topic_id));
+
+        ctx.options.sort_topics_by_preference(&mut found_results);
 
         found_results.reverse();
 
         info!("Found {} unique result(s)", found_results.len());
 
         state.topics_queue.replace(found_results);
+        state.search_attempts = search_attempts;
 
         Ok(())
     }
@@ -610,6 +2399,9 @@ impl TrackRequestProcessor {
     ) -> Result<(), ProcessRequestError> {
         let topic = match state.topics_queue.as_mut().and_then(Vec::pop) {
             Some(topic) => topic,
+            None if state.image_release_topic.is_some() => {
+                return Err(ProcessRequestError::RequiresSplitting);
+            }
             None => {
                 return Err(ProcessRequestError::TrackNotFound);
             }
@@ -620,124 +2412,497 @@ impl TrackRequestProcessor {
             topic.download_id, topic.title
         );
 
-        let torrent_data = self
-            .search_provider
-            .download_torrent(&topic.download_id)
-            .await?;
+        let torrent_data = match self.download_torrent_file(&topic).await {
+            Ok(torrent_data) => torrent_data,
+            Err(error) if error.is_transient() => {
+                warn!(
+                    ?error,
+                    "Unable to download torrent file {}, trying the next topic...",
+                    topic.download_id
+                );
+                state
+                    .dead_topic_ids
+                    .insert(topic.topic_id, self.max_dead_topics);
+                return Ok(());
+            }
+            Err(error) => return Err(error),
+        };
         let files_in_torrent = get_files(&torrent_data)?;
+        let release_layout = get_release_layout(&files_in_torrent);
+        debug!(?release_layout, "Torrent file downloaded");
 
-        if files_in_torrent
-            .into_iter()
-            .any(|filepath| contains_in_filename_ignore_case(&filepath, &ctx.metadata.title))
-        {
+        if !ctx.select_files(&files_in_torrent).is_empty() {
             info!("Downloaded torrent file seems to have the requested track...");
+            let info_hash = get_info_hash(&torrent_data)?;
+
+            if let Some(filepath) = self.find_already_downloaded_file(&info_hash, ctx).await? {
+                info!("Reusing already downloaded file: {}", filepath);
+                let filepath = self.copy_into_library(filepath).await?;
+                state.path_to_downloaded_file.replace(filepath);
+                return Ok(());
+            }
+
+            state.current_info_hash.replace(info_hash);
             state.current_torrent_data.replace(torrent_data);
+            state.current_topic.replace(topic);
+        } else if release_layout == ReleaseLayout::CueImage {
+            info!(
+                "Torrent {} is an image release, trying the next one...",
+                topic.title
+            );
+            state.image_release_topic.get_or_insert(topic);
         }
 
         Ok(())
     }
 
+    async fn download_torrent_file(
+        &self,
+        topic: &TopicData,
+    ) -> Result<Vec<u8>, ProcessRequestError> {
+        let mut retry = 0;
+
+        loop {
+            let error = match self
+                .search_provider
+                .download_torrent(&topic.download_id)
+                .await
+            {
+                Ok(torrent_data) => return Ok(torrent_data),
+                Err(error) => ProcessRequestError::from(error),
+            };
+
+            if !error.is_transient() || retry >= self.torrent_file_retries.max_retries {
+                return Err(error);
+            }
+
+            retry += 1;
+            let delay = self.torrent_file_retries.delay(retry);
+            warn!(
+                ?error,
+                retry,
+                ?delay,
+                "Unable to download torrent file {}, retrying...",
+                topic.download_id
+            );
+            self.clock.sleep(delay).await;
+        }
+    }
+
     async fn download(
         &self,
-        _user_id: &UserId,
+        user_id: &UserId,
+        request_id: &RequestId,
         ctx: &TrackRequestProcessingContext,
         state: &mut TrackRequestProcessingState,
-    ) -> Result<(), ProcessRequestError> {
-        let torrent_data = state
-            .current_torrent_data
-            .clone()
-            .take()
-            .expect("current_torrent_data should be defined");
+    ) -> Result<StepOutcome, ProcessRequestError> {
+        if !self.acquire_download_slot(request_id, ctx.options.priority) {
+            debug!("All download slots are taken, waiting for one to free up...");
+            return Ok(StepOutcome::RetryAfter(self.poll_intervals.download));
+        }
 
-        let files_in_torrent = get_files(&torrent_data)?;
-        let selected_files: Vec<_> = files_in_torrent
+        let torrent_data =
+            state
+                .current_torrent_data
+                .take()
+                .ok_or(ProcessRequestError::InconsistentState(
+                    "current_torrent_data",
+                ))?;
+
+        let files_in_torrent = get_files_with_sizes(&torrent_data)?;
+        let filepaths: Vec<_> = files_in_torrent
+            .iter()
+            .map(|(filepath, _)| filepath.clone())
+            .collect();
+        let matching_indexes = ctx.select_files(&filepaths);
+        let selected_size: u64 = matching_indexes
+            .iter()
+            .map(|index| files_in_torrent[*index].1)
+            .sum();
+        let selected_files: Vec<_> = matching_indexes
             .into_iter()
-            .enumerate()
-            .filter_map(|(index, filepath)| {
-                if contains_in_filename_ignore_case(&filepath, &ctx.metadata.title) {
-                    Some(index as i32)
-                } else {
-                    None
-                }
-            })
+            .map(|index| index as i32)
             .collect();
 
-        debug!("Adding torrent to the torrent client...");
+        // Transmission downloads every file when none is selected, so a torrent
+        // without the requested track is skipped and the next topic will be tried.
+        if selected_files.is_empty() {
+            warn!("None of the torrent files match the requested track");
+            abandon_current_torrent(state, self.max_dead_topics);
+            self.release_download_slot(request_id);
+            return Ok(StepOutcome::Continue);
+        }
+
+        if let Some(max_download_bytes) = self.max_download_bytes {
+            if selected_size > max_download_bytes {
+                // Topic is marked as tried, so neither a retry nor reuse picks it up again.
+                warn!(
+                    selected_size,
+                    max_download_bytes, "Selected files exceed the download size limit"
+                );
+                abandon_current_torrent(state, self.max_dead_topics);
+                self.release_download_slot(request_id);
+                return Ok(StepOutcome::Continue);
+            }
+        }
+
+        let download_dir =
+            self.download_directories
+                .get_request_download_dir(user_id, request_id, &ctx.metadata);
+
+        // Fails fast on a dead torrent client rather than somewhere in the middle of adding.
+        self.torrent_client.check_session().await?;
+
+        debug!(?download_dir, "Adding torrent to the torrent client...");
         let torrent_id = self
             .torrent_client
-            .add_torrent(torrent_data, selected_files)
+            .add_torrent(
+                torrent_data,
+                selected_files,
+                request_id,
+                download_dir.as_deref(),
+            )
             .await?;
 
         info!(%torrent_id, "Started downloading the torrent contents...");
 
         state.current_torrent_id.replace(torrent_id);
+        state.download_started_at.replace(self.clock.now());
 
-        Ok(())
+        Ok(StepOutcome::Continue)
+    }
+
+    /// Takes a download slot for the request, unless it already has one.
+    /// Returns `false` if all the slots are taken or due to requests ahead in the queue.
+    fn acquire_download_slot(&self, request_id: &RequestId, priority: RequestPriority) -> bool {
+        match &self.download_queue {
+            Some(download_queue) => download_queue.try_acquire(request_id, priority),
+            None => true,
+        }
+    }
+
+    fn release_download_slot(&self, request_id: &RequestId) {
+        if let Some(download_queue) = &self.download_queue {
+            download_queue.release(request_id);
+        }
     }
 
     async fn check_download_status(
         &self,
         _user_id: &UserId,
+        request_id: &RequestId,
         ctx: &TrackRequestProcessingContext,
         state: &mut TrackRequestProcessingState,
-    ) -> Result<(), ProcessRequestError> {
+    ) -> Result<StepOutcome, ProcessRequestError> {
         let torrent_id = state
             .current_torrent_id
             .clone()
             .take()
-            .expect("current_torrent_id should be defined");
+            .ok_or(ProcessRequestError::InconsistentState("current_torrent_id"))?;
 
         debug!("Checking the download status of the torrent file...");
 
-        let torrent = self.torrent_client.get_torrent(&torrent_id).await?;
+        // Slots aren't persisted, so a download resumed after restart takes a free one, if any.
+        if let Some(download_queue) = &self.download_queue {
+            download_queue.try_acquire_free(request_id);
+        }
 
-        if !matches!(torrent.status, TorrentStatus::Complete) {
-            // Still downloading? Check again in 5 secs...
-            actix_rt::time::sleep(Duration::from_secs(5)).await;
+        let torrent = match self.torrent_client.get_torrent(&torrent_id).await? {
+            Some(torrent) => torrent,
+            None => {
+                // Torrent has been removed from the torrent client, e.g. while the bot was down.
+                warn!(%torrent_id, "Torrent is gone, downloading it again...");
+                self.release_download_slot(request_id);
+
+                state.current_torrent_id.take();
+                state.download_started_at.take();
+                if let Some(topic) = state.current_topic.take() {
+                    state.topics_queue.get_or_insert_with(Vec::new).push(topic);
+                }
 
-            return Ok(());
+                return Ok(StepOutcome::Continue);
+            }
+        };
+
+        if matches!(torrent.status, TorrentStatus::Paused) {
+            debug!(%torrent_id, "Torrent is paused");
+        }
+
+        if let TorrentStatus::Error(error) = &torrent.status {
+            warn!(%torrent_id, error, "Torrent failed to download, trying the next one...");
+            self.remove_torrent(request_id, &torrent_id).await;
+            self.release_download_slot(request_id);
+            abandon_current_torrent(state, self.max_dead_topics);
+
+            return Ok(StepOutcome::Continue);
+        }
+
+        // Paused torrents are not timed out, as they are not expected to make any progress.
+        if matches!(torrent.status, TorrentStatus::Downloading) && self.is_download_timed_out(state)
+        {
+            warn!(%torrent_id, "Torrent has not completed in time, trying the next one...");
+            self.remove_torrent(request_id, &torrent_id).await;
+            self.release_download_slot(request_id);
+            abandon_current_torrent(state, self.max_dead_topics);
+
+            return Ok(StepOutcome::Continue);
+        }
+
+        if !matches!(torrent.status, TorrentStatus::Complete) {
+            // Still downloading or paused? Check again later...
+            return Ok(StepOutcome::RetryAfter(self.poll_intervals.download));
         }
 
         debug!(%torrent_id, "Download complete");
 
-        for filepath in torrent.files {
-            if contains_in_filename_ignore_case(&filepath, &ctx.metadata.title) {
-                info!("Found matching file: {}", filepath);
-                state.path_to_downloaded_file.replace(filepath);
-                return Ok(());
+        self.release_download_slot(request_id);
+
+        let track_files = torrent
+            .files
+            .iter()
+            .filter(|filepath| is_track_file(filepath, &torrent.files))
+            .cloned()
+            .collect();
+
+        if let Some(filepath) = self.find_matching_file(track_files, ctx).await? {
+            info!("Found matching file: {}", filepath);
+            // Info hash is kept for the provenance of the request.
+            if let Some(info_hash) = &state.current_info_hash {
+                self.record_downloaded_file(info_hash, &filepath).await?;
             }
+            let filepath = self.copy_into_library(filepath).await?;
+            state.path_to_downloaded_file.replace(filepath);
+            return Ok(StepOutcome::Continue);
         }
 
         warn!("Downloaded torrent does not have the requested audio track");
 
-        state.current_torrent_id.take();
-        state.current_torrent_data.take();
+        // Topic is not tried again, e.g. after the request is reset.
+        self.remove_torrent(request_id, &torrent_id).await;
+        abandon_current_torrent(state, self.max_dead_topics);
+
+        Ok(StepOutcome::Continue)
+    }
+
+    async fn find_matching_file(
+        &self,
+        files: Vec<String>,
+        ctx: &TrackRequestProcessingContext,
+    ) -> Result<Option<String>, ProcessRequestError> {
+        if let Some(query) = &ctx.search_query {
+            let indexes = (0..files.len()).collect();
+
+            return Ok(
+                find_best_matching_file(indexes, &files, query).map(|index| files[index].clone())
+            );
+        }
+
+        let title_match = ctx.options.post_download_match;
+        let mut matching_files = vec![];
+
+        for filepath in files {
+            if !title_match.matches_filename(&filepath, &ctx.metadata.title) {
+                continue;
+            }
+
+            if title_match == TitleMatch::Tags
+                && !self.has_matching_metadata(&filepath, ctx).await?
+            {
+                debug!("File {} does not match the requested metadata", filepath);
+                continue;
+            }
+
+            matching_files.push(filepath);
+        }
+
+        let filepath = self
+            .matching_file_preference
+            .pick(&matching_files, &ctx.metadata.title)
+            .cloned();
+
+        if let (Some(filepath), true) = (&filepath, matching_files.len() > 1) {
+            let alternatives: Vec<_> = matching_files
+                .iter()
+                .filter(|file| *file != filepath)
+                .collect();
+            info!(
+                ?alternatives,
+                preference = ?self.matching_file_preference,
+                "Picked {} of {} matching files",
+                filepath,
+                matching_files.len()
+            );
+        }
+
+        Ok(filepath)
+    }
+
+    // Files might have been removed since, so only the ones still on disk are reused.
+    async fn find_already_downloaded_file(
+        &self,
+        info_hash: &str,
+        ctx: &TrackRequestProcessingContext,
+    ) -> Result<Option<String>, ProcessRequestError> {
+        let record = match self.state_storage.get_downloaded_torrent(info_hash).await? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+
+        let mut existing_files = vec![];
+        for filepath in record.files {
+            let full_path_to_file = self.download_directories.to_local_path(&filepath);
+            if tokio::fs::metadata(&full_path_to_file).await.is_ok() {
+                existing_files.push(filepath);
+            }
+        }
+
+        self.find_matching_file(existing_files, ctx).await
+    }
+
+    async fn record_downloaded_file(
+        &self,
+        info_hash: &str,
+        filepath: &str,
+    ) -> Result<(), ProcessRequestError> {
+        let mut record = self
+            .state_storage
+            .get_downloaded_torrent(info_hash)
+            .await?
+            .unwrap_or_default();
+
+        if !record.files.iter().any(|file| file == filepath) {
+            record.files.push(filepath.to_string());
+            self.state_storage
+                .set_downloaded_torrent(info_hash, &record)
+                .await?;
+        }
 
         Ok(())
     }
 
+    /// Copies the downloaded file into the library directory, if any, and returns the path
+    /// it's uploaded from. The torrent keeps its own file, so it can still be seeded.
+    async fn copy_into_library(&self, filepath: String) -> Result<String, ProcessRequestError> {
+        let library_path = match self.download_directories.to_library_path(&filepath) {
+            Some(library_path) => library_path,
+            None => return Ok(filepath),
+        };
+        let full_path_to_file = self.download_directories.to_local_path(&filepath);
+
+        if let Some(library_subdir) = Path::new(&library_path).parent() {
+            tokio::fs::create_dir_all(library_subdir)
+                .await
+                .map_err(ProcessRequestError::LibraryError)?;
+        }
+        tokio::fs::copy(&full_path_to_file, &library_path)
+            .await
+            .map_err(ProcessRequestError::LibraryError)?;
+
+        info!(library_path, "Downloaded file copied into the library");
+
+        Ok(library_path)
+    }
+
+    fn is_download_timed_out(&self, state: &TrackRequestProcessingState) -> bool {
+        match (self.download_timeout, state.download_started_at) {
+            (Some(download_timeout), Some(download_started_at)) => {
+                self.clock.now().saturating_sub(download_started_at) >= download_timeout.as_secs()
+            }
+            _ => false,
+        }
+    }
+
+    async fn has_matching_metadata(
+        &self,
+        path: &str,
+        ctx: &TrackRequestProcessingContext,
+    ) -> Result<bool, ProcessRequestError> {
+        let full_path_to_file = self.download_directories.to_local_path(path);
+
+        let metadata = match self
+            .metadata_service
+            .get_audio_metadata(&full_path_to_file)
+            .await?
+        {
+            Some(metadata) => metadata,
+            None => {
+                warn!(
+                    full_path_to_file,
+                    "Unable to validate file without metadata"
+                );
+                return Ok(true);
+            }
+        };
+
+        Ok(contains_ignore_case(&metadata.title, &ctx.metadata.title)
+            && contains_ignore_case(&metadata.artist, &ctx.metadata.artist))
+    }
+
     async fn upload_to_radio_manager(
         &self,
         user_id: &UserId,
-        _ctx: &TrackRequestProcessingContext,
+        request_id: &RequestId,
+        ctx: &mut TrackRequestProcessingContext,
         state: &mut TrackRequestProcessingState,
     ) -> Result<(), ProcessRequestError> {
-        let path = state
-            .path_to_downloaded_file
-            .clone()
-            .take()
-            .expect("path_to_downloaded_file should be defined");
+        let path = state.path_to_downloaded_file.clone().take().ok_or(
+            ProcessRequestError::InconsistentState("path_to_downloaded_file"),
+        )?;
+
+        let full_path_to_file = self.download_directories.to_local_path(&path);
+
+        // Upload and search requests carry no metadata to write. Without a library directory
+        // the file is the torrent's own, which must stay intact for seeding and reuse.
+        if self.normalize_tags
+            && self.download_directories.library_dir.is_some()
+            && !ctx.metadata.title.is_empty()
+        {
+            self.write_requested_tags(&full_path_to_file, &ctx.metadata)
+                .await;
+        }
+
+        if ctx.search_query.is_some() {
+            self.take_metadata_from_tags(user_id, request_id, &full_path_to_file, ctx)
+                .await?;
+        }
+
+        if state.upload_in_progress {
+            if let Some(track_id) = self
+                .find_uploaded_track(user_id, &full_path_to_file, ctx)
+                .await?
+            {
+                info!(%track_id, "Audio track has already been uploaded to radio manager");
+                state.radio_manager_track_id.replace(track_id);
+                return Ok(());
+            }
+        }
 
-        let full_path_to_file = format!("{}/{}", self.download_directory, path);
+        let file_name = match self.name_uploads {
+            true => ctx.metadata.get_upload_file_name(&full_path_to_file),
+            false => None,
+        };
+
+        state.upload_in_progress = true;
+        self.state_storage
+            .update_state(user_id, request_id, state)
+            .await?;
+
+        // Permit is released once the upload is done or has failed.
+        let _permit = self
+            .upload_permits
+            .acquire()
+            .await
+            .expect("Upload semaphore should never be closed");
 
         info!(
             full_path_to_file,
+            ?file_name,
             "Uploading audio track to radio manager..."
         );
 
         let track_id = self
             .radio_manager_client
-            .upload_audio_track(user_id, &full_path_to_file)
+            .upload_audio_track(user_id, &full_path_to_file, file_name.as_deref())
             .await?;
 
         state.radio_manager_track_id.replace(track_id);
@@ -745,30 +2910,136 @@ impl TrackRequestProcessor {
         Ok(())
     }
 
+    /// Looks the file up in the library by its tags, which the radio manager takes
+    /// the metadata of uploaded tracks from.
+    async fn find_uploaded_track(
+        &self,
+        user_id: &UserId,
+        full_path_to_file: &str,
+        ctx: &TrackRequestProcessingContext,
+    ) -> Result<Option<RadioManagerTrackId>, ProcessRequestError> {
+        let metadata = match self
+            .metadata_service
+            .get_audio_metadata(full_path_to_file)
+            .await?
+        {
+            Some(metadata) => metadata,
+            None if !ctx.metadata.title.is_empty() => ctx.metadata.clone(),
+            None => {
+                warn!(
+                    full_path_to_file,
+                    "Unable to tell if file without metadata has been uploaded"
+                );
+                return Ok(None);
+            }
+        };
+
+        Ok(self
+            .radio_manager_client
+            .find_track(user_id, &metadata)
+            .await?)
+    }
+
+    /// Saves the metadata of the file found for the search request, so the request
+    /// history tells which track has been added.
+    async fn take_metadata_from_tags(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        full_path_to_file: &str,
+        ctx: &mut TrackRequestProcessingContext,
+    ) -> Result<(), ProcessRequestError> {
+        match self
+            .metadata_service
+            .get_audio_metadata(full_path_to_file)
+            .await?
+        {
+            Some(metadata) => {
+                info!("Search request resolved to {}", metadata);
+                ctx.metadata = metadata;
+                self.state_storage
+                    .update_context(user_id, request_id, ctx)
+                    .await?;
+            }
+            None => warn!(full_path_to_file, "Audio file found by search has no tags"),
+        }
+
+        Ok(())
+    }
+
+    // A badly labeled track is still better than no track, so failures are only logged.
+    async fn write_requested_tags(&self, full_path_to_file: &str, metadata: &AudioMetadata) {
+        match self
+            .metadata_service
+            .set_audio_metadata(full_path_to_file, metadata)
+            .await
+        {
+            Ok(true) => debug!(full_path_to_file, "Audio file tags normalized"),
+            Ok(false) => debug!(
+                full_path_to_file,
+                "Tags of the audio format are not supported"
+            ),
+            Err(error) => warn!(
+                ?error,
+                full_path_to_file, "Unable to normalize audio file tags"
+            ),
+        }
+    }
+
     async fn add_to_radio_manager_channel(
         &self,
         user_id: &UserId,
         ctx: &TrackRequestProcessingContext,
         state: &mut TrackRequestProcessingState,
     ) -> Result<(), ProcessRequestError> {
-        let track_id = state
-            .radio_manager_track_id
-            .clone()
-            .take()
-            .expect("radio_manager_track_id should be defined");
+        let track_id = state.radio_manager_track_id.clone().take().ok_or(
+            ProcessRequestError::InconsistentState("radio_manager_track_id"),
+        )?;
+        let channel_id = ctx
+            .target_channel_id
+            .as_ref()
+            .ok_or(ProcessRequestError::InconsistentState("target_channel_id"))?;
 
         info!(
             "Adding uploaded audio track to the radio manager channel {}...",
-            ctx.target_channel_id
+            channel_id
         );
 
-        let link_id = self
+        let mut link_id = self
             .radio_manager_client
-            .add_track_to_channel_playlist(user_id, &track_id, &ctx.target_channel_id)
+            .add_track_to_channel_playlist(user_id, &track_id, channel_id)
             .await?;
 
+        if self.verify_channel_add && !self.is_on_channel(&track_id, channel_id).await? {
+            warn!(%track_id, "Track is missing on the channel after adding it, adding it again...");
+
+            link_id = self
+                .radio_manager_client
+                .add_track_to_channel_playlist(user_id, &track_id, channel_id)
+                .await?;
+
+            if !self.is_on_channel(&track_id, channel_id).await? {
+                return Err(ProcessRequestError::MissingOnChannel);
+            }
+        }
+
         state.radio_manager_link_id.replace(link_id);
 
         Ok(())
     }
+
+    async fn is_on_channel(
+        &self,
+        track_id: &RadioManagerTrackId,
+        channel_id: &RadioManagerChannelId,
+    ) -> Result<bool, ProcessRequestError> {
+        let tracks = self
+            .radio_manager_client
+            .get_channel_tracks(channel_id)
+            .await?;
+
+        Ok(tracks
+            .iter()
+            .any(|track| track.track_id.as_ref() == Some(track_id)))
+    }
 }
@@ -1,15 +1,46 @@
-use crate::services::torrent_parser::{get_files_count, TorrentParserError};
+use crate::services::torrent_parser::{get_files_count, get_info_hash, TorrentParserError};
 use async_lock::Mutex;
 use base64::{engine::general_purpose::STANDARD, Engine};
+use std::collections::BTreeSet;
+use tracing::info;
 use transmission_rpc::types::{
     BasicAuth, Id, RpcResponse, Torrent, TorrentAction, TorrentAddArgs, TorrentAddedOrDuplicate,
-    TorrentSetArgs,
+    TorrentGetField, TorrentSetArgs,
 };
 use transmission_rpc::TransClient;
 
+/// Prefix of the label attached to every torrent added by the bot, followed by the request id.
+pub(crate) const REQUEST_LABEL_PREFIX: &str = "channel-bot:";
+
 pub(crate) struct TransmissionClient {
     client: Mutex<TransClient>,
     download_dir: String,
+    speed_limits: SpeedLimits,
+}
+
+/// Speed limits in KB/s applied to every added torrent. `None` or `0` means unlimited.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SpeedLimits {
+    pub(crate) download_kbps: Option<u32>,
+    pub(crate) upload_kbps: Option<u32>,
+}
+
+impl SpeedLimits {
+    pub(crate) fn is_unlimited(&self) -> bool {
+        self.download_kbps.unwrap_or_default() == 0 && self.upload_kbps.unwrap_or_default() == 0
+    }
+}
+
+/// Whether the TLS certificate of an HTTPS endpoint is verified.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum CertificateVerification {
+    #[default]
+    Verify,
+    /// Accepts any certificate, e.g. a self-signed one of a home setup behind a reverse proxy.
+    /// The connection is then open to man-in-the-middle attacks, which expose the RPC
+    /// credentials and let anyone in between control the torrents, so this is only
+    /// acceptable within a trusted network.
+    AcceptInvalid,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -32,21 +63,68 @@ impl TransmissionClient {
         username: Option<String>,
         password: Option<String>,
         download_dir: String,
+        speed_limits: SpeedLimits,
+        certificate_verification: CertificateVerification,
     ) -> Self {
         let url = (&url).parse().unwrap();
-        let client = match (username, password) {
-            (Some(user), Some(password)) => {
-                TransClient::with_auth(url, BasicAuth { user, password })
-            }
-            _ => TransClient::new(url),
-        };
+        let http_client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(
+                certificate_verification == CertificateVerification::AcceptInvalid,
+            )
+            .build()
+            .expect("Unable to build HTTP client for Transmission");
+        let mut client = TransClient::new_with_client(url, http_client);
+        if let (Some(user), Some(password)) = (username, password) {
+            client.set_auth(BasicAuth { user, password });
+        }
 
         Self {
             client: Mutex::new(client),
             download_dir,
+            speed_limits,
         }
     }
 
+    pub(crate) fn download_dir(&self) -> &str {
+        &self.download_dir
+    }
+
+    pub(crate) fn speed_limits(&self) -> &SpeedLimits {
+        &self.speed_limits
+    }
+
+    pub(crate) async fn set_speed_limit(
+        &self,
+        torrent_id: &i64,
+        down_kbps: Option<u32>,
+        up_kbps: Option<u32>,
+    ) -> Result<()> {
+        let down_kbps = down_kbps.unwrap_or_default();
+        let up_kbps = up_kbps.unwrap_or_default();
+
+        let RpcResponse { result, .. } = self
+            .client
+            .lock()
+            .await
+            .torrent_set(
+                TorrentSetArgs {
+                    download_limit: Some(down_kbps as i32),
+                    download_limited: Some(down_kbps > 0),
+                    upload_limit: Some(up_kbps as i32),
+                    upload_limited: Some(up_kbps > 0),
+                    ..TorrentSetArgs::default()
+                },
+                Some(vec![Id::Id(*torrent_id)]),
+            )
+            .await?;
+
+        if result != "success" {
+            return Err(TransmissionClientError::ErroneousResult(result));
+        }
+
+        Ok(())
+    }
+
     pub(crate) async fn select_files(&self, torrent_id: &i64, file_indexes: &[i32]) -> Result<()> {
         let id = Id::Id(*torrent_id);
 
@@ -71,7 +149,147 @@ impl TransmissionClient {
         Ok(())
     }
 
-    pub(crate) async fn add(&self, torrent_file_content: Vec<u8>) -> Result<i64> {
+    /// Adds the torrent and selects the given files for download. If the torrent is
+    /// already present in Transmission, it's reused and the given files are added to
+    /// its existing selection, so files wanted by other requests stay selected.
+    pub(crate) async fn add_or_reuse(
+        &self,
+        torrent_file_content: Vec<u8>,
+        file_indexes: &[i32],
+        label: &str,
+        download_dir: Option<&str>,
+    ) -> Result<i64> {
+        let info_hash = get_info_hash(&torrent_file_content)?;
+
+        let torrent_id = match self.get_by_hash(&info_hash).await? {
+            Some(torrent) => {
+                let torrent_id = torrent.id.ok_or(TransmissionClientError::NotFound)?;
+                info!(
+                    torrent_id,
+                    info_hash, "Reusing torrent already added to transmission"
+                );
+
+                let mut wanted: BTreeSet<i32> = torrent
+                    .wanted
+                    .unwrap_or_default()
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, wanted)| *wanted != 0)
+                    .map(|(index, _)| index as i32)
+                    .collect();
+                wanted.extend(file_indexes);
+
+                self.select_files(&torrent_id, &wanted.into_iter().collect::<Vec<_>>())
+                    .await?;
+
+                let mut labels = torrent.labels.unwrap_or_default();
+                if !labels.iter().any(|l| l == label) {
+                    labels.push(label.to_string());
+                    self.set_labels(&torrent_id, labels).await?;
+                }
+
+                torrent_id
+            }
+            None => {
+                let torrent_id = self.add(torrent_file_content, label, download_dir).await?;
+                self.select_files(&torrent_id, file_indexes).await?;
+
+                torrent_id
+            }
+        };
+
+        Ok(torrent_id)
+    }
+
+    pub(crate) async fn get_by_hash(&self, info_hash: &str) -> Result<Option<Torrent>> {
+        let RpcResponse { result, arguments } = self
+            .client
+            .lock()
+            .await
+            .torrent_get(
+                Some(vec![
+                    TorrentGetField::Id,
+                    TorrentGetField::HashString,
+                    TorrentGetField::Wanted,
+                    TorrentGetField::Labels,
+                ]),
+                Some(vec![Id::Hash(info_hash.to_string())]),
+            )
+            .await?;
+
+        if result != "success" {
+            return Err(TransmissionClientError::ErroneousResult(result));
+        }
+
+        Ok(arguments.torrents.into_iter().next())
+    }
+
+    /// Torrents having the label. Label ending with `*` matches any label starting with
+    /// the rest of it, e.g. `channel-bot:*` matches the labels of all the requests.
+    pub(crate) async fn get_by_label(&self, label: &str) -> Result<Vec<Torrent>> {
+        let RpcResponse { result, arguments } = self
+            .client
+            .lock()
+            .await
+            .torrent_get(
+                Some(vec![
+                    TorrentGetField::Id,
+                    TorrentGetField::Name,
+                    TorrentGetField::Labels,
+                ]),
+                None,
+            )
+            .await?;
+
+        if result != "success" {
+            return Err(TransmissionClientError::ErroneousResult(result));
+        }
+
+        let matches = |l: &String| match label.strip_suffix('*') {
+            Some(prefix) => l.starts_with(prefix),
+            None => l == label,
+        };
+
+        Ok(arguments
+            .torrents
+            .into_iter()
+            .filter(|torrent| {
+                torrent
+                    .labels
+                    .as_ref()
+                    .is_some_and(|labels| labels.iter().any(matches))
+            })
+            .collect())
+    }
+
+    async fn set_labels(&self, torrent_id: &i64, labels: Vec<String>) -> Result<()> {
+        let RpcResponse { result, .. } = self
+            .client
+            .lock()
+            .await
+            .torrent_set(
+                TorrentSetArgs {
+                    labels: Some(labels),
+                    ..TorrentSetArgs::default()
+                },
+                Some(vec![Id::Id(*torrent_id)]),
+            )
+            .await?;
+
+        if result != "success" {
+            return Err(TransmissionClientError::ErroneousResult(result));
+        }
+
+        Ok(())
+    }
+
+    /// Adds the torrent into `download_dir`, or into the default download directory if not set.
+    pub(crate) async fn add(
+        &self,
+        torrent_file_content: Vec<u8>,
+        label: &str,
+        download_dir: Option<&str>,
+    ) -> Result<i64> {
         let files_count = get_files_count(&torrent_file_content)?;
         let metainfo = STANDARD.encode(torrent_file_content);
 
@@ -81,9 +299,10 @@ impl TransmissionClient {
             .await
             .torrent_add(TorrentAddArgs {
                 metainfo: Some(metainfo.clone()),
-                download_dir: Some(self.download_dir.clone()),
+                download_dir: Some(download_dir.unwrap_or(&self.download_dir).to_string()),
                 // Initialize new torrent with disabling download of any files.
                 files_unwanted: Some((0..files_count as i32).collect()),
+                labels: Some(vec![label.to_string()]),
                 ..TorrentAddArgs::default()
             })
             .await?;
@@ -100,6 +319,44 @@ impl TransmissionClient {
         Ok(torrent.id.unwrap())
     }
 
+    pub(crate) async fn pause(&self, torrent_id: &i64) -> Result<()> {
+        self.perform_action(TorrentAction::Stop, &[*torrent_id])
+            .await
+    }
+
+    pub(crate) async fn resume(&self, torrent_id: &i64) -> Result<()> {
+        self.perform_action(TorrentAction::Start, &[*torrent_id])
+            .await
+    }
+
+    pub(crate) async fn pause_all(&self, torrent_ids: &[i64]) -> Result<()> {
+        for torrent_id in torrent_ids {
+            self.pause(torrent_id).await?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn resume_all(&self, torrent_ids: &[i64]) -> Result<()> {
+        for torrent_id in torrent_ids {
+            self.resume(torrent_id).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn perform_action(&self, action: TorrentAction, torrent_ids: &[i64]) -> Result<()> {
+        let ids = torrent_ids.iter().map(|id| Id::Id(*id)).collect();
+        let RpcResponse { result, .. } =
+            self.client.lock().await.torrent_action(action, ids).await?;
+
+        if result != "success" {
+            return Err(TransmissionClientError::ErroneousResult(result));
+        }
+
+        Ok(())
+    }
+
     pub(crate) async fn remove(&self, torrent_id: &i64) -> Result<()> {
         let RpcResponse { result, .. } = self
             .client
@@ -148,11 +405,10 @@ impl TransmissionClient {
         maybe_torrent.ok_or(TransmissionClientError::NotFound)
     }
 
-    pub(crate) async fn check_connection(&self) -> Result<()> {
-        let RpcResponse {
-            result,
-            arguments: _,
-        } = self.client.lock().await.torrent_get(None, None).await?;
+    /// Checks that Transmission is up and accepts the credentials with a lightweight
+    /// `session-get` request.
+    pub(crate) async fn session_check(&self) -> Result<()> {
+        let RpcResponse { result, .. } = self.client.lock().await.session_get().await?;
 
         if result != "success" {
             return Err(TransmissionClientError::ErroneousResult(result));
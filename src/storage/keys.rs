@@ -0,0 +1,71 @@
+use crate::types::UserId;
+
+const STATE_SUFFIX: &str = "-state";
+const CONTEXT_SUFFIX: &str = "-ctx";
+const STATUS_SUFFIX: &str = "-status";
+const IDEMPOTENCY_SUFFIX: &str = "-idempotency";
+const NOT_FOUND_SUFFIX: &str = "-notfound";
+const PROVENANCE_SUFFIX: &str = "-provenance";
+
+/// Set of the `{user_id}/{request_id}` pairs of the requests having a state, i.e. the ones
+/// that might still need processing, so they can be found at startup without a full scan.
+pub(crate) const TASKS_INDEX: &str = "tasks.idx";
+
+/// Downloaded torrents are shared by all users, with info hashes as keys.
+pub(crate) const DOWNLOADED_TORRENTS_PREFIX: &str = "downloaded-torrents";
+
+/// Torrents left seeding after their requests have finished, with torrent ids as keys.
+pub(crate) const SEEDING_TORRENTS_PREFIX: &str = "seeding-torrents";
+
+pub(crate) fn state_prefix(user_id: &UserId) -> String {
+    format!("{}{}", user_id, STATE_SUFFIX)
+}
+
+pub(crate) fn context_prefix(user_id: &UserId) -> String {
+    format!("{}{}", user_id, CONTEXT_SUFFIX)
+}
+
+pub(crate) fn status_prefix(user_id: &UserId) -> String {
+    format!("{}{}", user_id, STATUS_SUFFIX)
+}
+
+pub(crate) fn idempotency_prefix(user_id: &UserId) -> String {
+    format!("{}{}", user_id, IDEMPOTENCY_SUFFIX)
+}
+
+pub(crate) fn not_found_prefix(user_id: &UserId) -> String {
+    format!("{}{}", user_id, NOT_FOUND_SUFFIX)
+}
+
+pub(crate) fn provenance_prefix(user_id: &UserId) -> String {
+    format!("{}{}", user_id, PROVENANCE_SUFFIX)
+}
+
+/// Returns the owner of the states stored under the prefix,
+/// or `None` if the prefix doesn't hold states.
+pub(crate) fn parse_state_prefix(prefix: &str) -> Option<UserId> {
+    prefix
+        .strip_suffix(STATE_SUFFIX)
+        .and_then(|user_id| user_id.parse::<u64>().ok())
+        .map(UserId)
+}
+
+/// Returns the owner of the statuses stored under the prefix,
+/// or `None` if the prefix doesn't hold statuses.
+pub(crate) fn parse_status_prefix(prefix: &str) -> Option<UserId> {
+    prefix
+        .strip_suffix(STATUS_SUFFIX)
+        .and_then(|user_id| user_id.parse::<u64>().ok())
+        .map(UserId)
+}
+
+pub(crate) fn tasks_index_entry(user_id: &UserId, request_id: &str) -> String {
+    format!("{}/{}", user_id, request_id)
+}
+
+/// Returns the owner and the request id of the tasks index entry.
+pub(crate) fn parse_tasks_index_entry(entry: &str) -> Option<(UserId, &str)> {
+    let (user_id, request_id) = entry.split_once('/')?;
+
+    Some((UserId(user_id.parse::<u64>().ok()?), request_id))
+}
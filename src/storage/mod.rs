@@ -1 +1,2 @@
+pub(crate) mod keys;
 pub(crate) mod on_disk;
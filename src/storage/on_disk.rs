@@ -1,15 +1,47 @@
-use std::collections::HashMap;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
 use std::path::Path;
 use tokio::fs::create_dir_all;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+fn encode_value(value: &str) -> Result<Vec<u8>, std::io::Error> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(value.as_bytes())?;
+    encoder.finish()
+}
+
+// Values written before compression was introduced are stored as plain text,
+// so only the gzip magic decides whether the content has to be decompressed.
+fn decode_value(bytes: Vec<u8>) -> Result<String, std::io::Error> {
+    if !bytes.starts_with(&GZIP_MAGIC) {
+        return String::from_utf8(bytes)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error));
+    }
+
+    let mut value = String::new();
+    GzDecoder::new(bytes.as_slice()).read_to_string(&mut value)?;
+
+    Ok(value)
+}
 
 pub(crate) struct OnDiskStorage {
     path: String,
+    // Sets are read and written as a whole, so concurrent changes must not interleave.
+    sets_lock: Mutex<()>,
 }
 
 impl OnDiskStorage {
     pub(crate) fn create(path: String) -> Self {
-        Self { path }
+        Self {
+            path,
+            sets_lock: Mutex::new(()),
+        }
     }
 
     pub(crate) async fn get(
@@ -19,8 +51,8 @@ impl OnDiskStorage {
     ) -> Result<Option<String>, std::io::Error> {
         let path = format!("{}/{}/{}", self.path, prefix, key);
 
-        match tokio::fs::read_to_string(path).await {
-            Ok(value) => Ok(Some(value)),
+        match tokio::fs::read(path).await {
+            Ok(bytes) => Ok(Some(decode_value(bytes)?)),
             Err(error) if matches!(error.kind(), std::io::ErrorKind::NotFound) => Ok(None),
             Err(error) => Err(error),
         }
@@ -41,13 +73,31 @@ impl OnDiskStorage {
 
         while let Some(dir) = dir_reader.next_entry().await? {
             let filename = dir.file_name().to_str().unwrap_or_default().to_string();
-            let content = tokio::fs::read_to_string(format!("{}/{}", path, filename)).await?;
-            map.insert(filename, content);
+            let bytes = tokio::fs::read(format!("{}/{}", path, filename)).await?;
+            map.insert(filename, decode_value(bytes)?);
         }
 
         Ok(map)
     }
 
+    /// Keys under the prefix, without reading their values.
+    pub(crate) async fn get_keys(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        let path = format!("{}/{}", self.path, prefix);
+
+        let mut dir_reader = match tokio::fs::read_dir(&path).await {
+            Ok(reader) => reader,
+            Err(_) => return Ok(vec![]),
+        };
+
+        let mut keys = vec![];
+
+        while let Some(dir) = dir_reader.next_entry().await? {
+            keys.push(dir.file_name().to_str().unwrap_or_default().to_string());
+        }
+
+        Ok(keys)
+    }
+
     pub(crate) async fn get_prefixes(&self) -> Result<Vec<String>, std::io::Error> {
         let mut dir_reader = match tokio::fs::read_dir(&self.path).await {
             Ok(reader) => reader,
@@ -83,7 +133,7 @@ impl OnDiskStorage {
             .open(path)
             .await?;
 
-        file.write_all(value.as_bytes()).await?;
+        file.write_all(&encode_value(value)?).await?;
 
         Ok(())
     }
@@ -95,4 +145,108 @@ impl OnDiskStorage {
 
         Ok(())
     }
+
+    /// Members of the set stored in a single file, one per line,
+    /// or `None` if the set has never been saved.
+    pub(crate) async fn get_set(
+        &self,
+        name: &str,
+    ) -> Result<Option<HashSet<String>>, std::io::Error> {
+        let _guard = self.sets_lock.lock().await;
+
+        self.read_set(name).await
+    }
+
+    pub(crate) async fn save_set(
+        &self,
+        name: &str,
+        members: &HashSet<String>,
+    ) -> Result<(), std::io::Error> {
+        let _guard = self.sets_lock.lock().await;
+
+        self.write_set(name, members).await
+    }
+
+    /// Adds the member to the set, unless the set has never been saved,
+    /// as its other members are not known then.
+    pub(crate) async fn add_to_set(&self, name: &str, member: &str) -> Result<(), std::io::Error> {
+        let _guard = self.sets_lock.lock().await;
+
+        if let Some(mut members) = self.read_set(name).await? {
+            if members.insert(member.to_string()) {
+                self.write_set(name, &members).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn remove_from_set(
+        &self,
+        name: &str,
+        member: &str,
+    ) -> Result<(), std::io::Error> {
+        let _guard = self.sets_lock.lock().await;
+
+        if let Some(mut members) = self.read_set(name).await? {
+            if members.remove(member) {
+                self.write_set(name, &members).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn read_set(&self, name: &str) -> Result<Option<HashSet<String>>, std::io::Error> {
+        let path = format!("{}/{}", self.path, name);
+
+        match tokio::fs::read_to_string(path).await {
+            Ok(content) => Ok(Some(
+                content
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(String::from)
+                    .collect(),
+            )),
+            Err(error) if matches!(error.kind(), std::io::ErrorKind::NotFound) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Written into a temporary file first, so the set is never left half written.
+    async fn write_set(&self, name: &str, members: &HashSet<String>) -> Result<(), std::io::Error> {
+        let path = format!("{}/{}", self.path, name);
+        let tmp_path = format!("{}.tmp", path);
+        let content: String = members
+            .iter()
+            .map(|member| format!("{}\n", member))
+            .collect();
+
+        create_dir_all(&self.path).await?;
+        tokio::fs::write(&tmp_path, content).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding_and_decoding_value() {
+        let value = r#"{"topics_queue":null}"#;
+        let encoded = encode_value(value).unwrap();
+
+        assert!(encoded.starts_with(&GZIP_MAGIC));
+        assert_eq!(value, decode_value(encoded).unwrap());
+    }
+
+    #[test]
+    fn test_decoding_plain_text_value() {
+        let value = r#"{"topics_queue":null}"#;
+
+        assert_eq!(value, decode_value(value.as_bytes().to_vec()).unwrap());
+    }
 }
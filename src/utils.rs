@@ -2,9 +2,156 @@ pub(crate) fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
     haystack.to_lowercase().contains(&needle.to_lowercase())
 }
 
+pub(crate) fn eq_ignore_case(left: &str, right: &str) -> bool {
+    left.trim().to_lowercase() == right.trim().to_lowercase()
+}
+
 pub(crate) fn contains_in_filename_ignore_case(filepath: &str, needle: &str) -> bool {
     match filepath.split(std::path::MAIN_SEPARATOR_STR).last() {
         Some(filename) => contains_ignore_case(&filename, needle),
         None => false,
     }
 }
+
+/// Lowercase words of the text: apostrophes are dropped and other punctuation separates words.
+fn get_words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .chars()
+        .filter(|c| !matches!(c, '\'' | '’'))
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .map(String::from)
+        .collect()
+}
+
+/// Whether every word of the needle is in the file name, in any order,
+/// so "Winter's Sunset (Remastered)" matches "Winters Sunset".
+pub(crate) fn contains_words_in_filename_ignore_case(filepath: &str, needle: &str) -> bool {
+    let filename_words = match filepath.split(std::path::MAIN_SEPARATOR_STR).last() {
+        Some(filename) => get_words(filename),
+        None => return false,
+    };
+    let needle_words = get_words(needle);
+
+    !needle_words.is_empty()
+        && needle_words
+            .iter()
+            .all(|word| filename_words.contains(word))
+}
+
+/// Makes the text usable as a file name on any file system: path separators, characters
+/// reserved on Windows and control characters are replaced with `_`, whitespace is collapsed
+/// into single spaces, and leading and trailing dots and spaces are dropped.
+pub(crate) fn to_safe_filename(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_whitespace() => ' ',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches('.')
+        .trim()
+        .to_string()
+}
+
+const ARTIST_SEPARATORS: [&str; 7] = ["feat.", "feat", "ft.", "featuring", "&", "vs", "vs."];
+
+/// Returns alternative spellings of the artist name that are more likely to match torrent titles:
+/// the primary artist without featured ones, and the name without leading "The".
+/// The original name is not included.
+pub(crate) fn get_artist_name_variants(artist: &str) -> Vec<String> {
+    let words = artist.split_whitespace().collect::<Vec<_>>();
+    let primary_artist_len = words
+        .iter()
+        .position(|word| ARTIST_SEPARATORS.contains(&word.to_lowercase().as_str()))
+        .unwrap_or(words.len());
+    let primary_artist = &words[..primary_artist_len];
+
+    let mut variants = vec![primary_artist.join(" ")];
+
+    if primary_artist.len() > 1 && primary_artist[0].eq_ignore_ascii_case("the") {
+        variants.push(primary_artist[1..].join(" "));
+    }
+
+    let mut unique_variants: Vec<String> = vec![];
+    for variant in variants {
+        if !variant.is_empty()
+            && !eq_ignore_case(&variant, artist)
+            && !unique_variants.contains(&variant)
+        {
+            unique_variants.push(variant);
+        }
+    }
+
+    unique_variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_getting_artist_name_variants() {
+        assert_eq!(get_artist_name_variants("The Beatles"), vec!["Beatles"]);
+        assert_eq!(
+            get_artist_name_variants("Armin van Buuren feat. Trevor Guthrie"),
+            vec!["Armin van Buuren"]
+        );
+        assert_eq!(
+            get_artist_name_variants("The Chemical Brothers ft. Beck"),
+            vec!["The Chemical Brothers", "Chemical Brothers"]
+        );
+        assert_eq!(get_artist_name_variants("Simon & Garfunkel"), vec!["Simon"]);
+        assert_eq!(
+            get_artist_name_variants("Armin van Buuren vs Sophie Ellis-Bextor"),
+            vec!["Armin van Buuren"]
+        );
+        assert!(get_artist_name_variants("Ted Irens").is_empty());
+        assert!(get_artist_name_variants("The").is_empty());
+    }
+
+    #[test]
+    fn test_containing_words_in_filename() {
+        assert!(contains_words_in_filename_ignore_case(
+            "Ted Irens/07. Ted Irens - Winter's Sunset.flac",
+            "Winters Sunset"
+        ));
+        assert!(contains_words_in_filename_ignore_case(
+            "05. Dreamland-Trip (Remastered).flac",
+            "dreamland trip"
+        ));
+        assert!(!contains_words_in_filename_ignore_case(
+            "Dreamland Trip/01. Rider.flac",
+            "Dreamland Trip"
+        ));
+        assert!(!contains_words_in_filename_ignore_case(
+            "01. Rider.flac",
+            ""
+        ));
+    }
+
+    #[test]
+    fn test_converting_to_safe_filename() {
+        assert_eq!(
+            to_safe_filename("Ted Irens - Life @ Mirror"),
+            "Ted Irens - Life @ Mirror"
+        );
+        assert_eq!(
+            to_safe_filename("AC/DC: Back\\In|Black"),
+            "AC_DC_ Back_In_Black"
+        );
+        assert_eq!(to_safe_filename("What?*\"<>"), "What_____");
+        assert_eq!(
+            to_safe_filename("  ..Tab\tand\nnewline.. "),
+            "Tab and newline"
+        );
+        assert_eq!(to_safe_filename("Line\u{0}Break"), "Line_Break");
+        assert_eq!(to_safe_filename("..."), "");
+    }
+}